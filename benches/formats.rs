@@ -0,0 +1,153 @@
+//! Serialization and deserialization throughput of both formats, run with `cargo bench`.
+
+use std::collections::BTreeMap;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Wide {
+    a: u8,
+    b: u16,
+    c: u32,
+    d: u64,
+    e: i8,
+    f: i16,
+    g: i32,
+    h: i64,
+    i: f32,
+    j: f64,
+    k: bool,
+    l: char,
+    m: Option<u32>,
+    n: Option<u32>,
+    o: (u8, u16, u32),
+    p: [u64; 4],
+    q: String,
+    r: Vec<u8>,
+    s: Unit,
+    t: Kind,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Unit;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Kind {
+    Small,
+    Big { size: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Node {
+    value: u32,
+    children: BTreeMap<String, Node>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Document {
+    title: String,
+    body: String,
+    tags: Vec<String>,
+}
+
+fn wide() -> Vec<Wide> {
+    (0..1000)
+        .map(|i| Wide {
+            a: i as u8,
+            b: i as u16,
+            c: i,
+            d: u64::from(i) << 32,
+            e: (i as i8).wrapping_neg(),
+            f: -(i as i16),
+            g: -(i as i32),
+            h: -i64::from(i) << 32,
+            i: i as f32 / 3.0,
+            j: f64::from(i) / 7.0,
+            k: i % 2 == 0,
+            l: char::from_u32(0x41 + i % 26).unwrap(),
+            m: Some(i),
+            n: None,
+            o: (1, 2, 3),
+            p: [4; 4],
+            q: format!("wide {}", i),
+            r: vec![i as u8; 16],
+            s: Unit,
+            t: if i % 3 == 0 {
+                Kind::Small
+            } else {
+                Kind::Big { size: i.into() }
+            },
+        })
+        .collect()
+}
+
+fn numbers() -> Vec<u64> {
+    (0..100_000).map(|i| i * 0x9E37_79B9).collect()
+}
+
+fn tree(depth: u32) -> Node {
+    let children = match depth {
+        0 => BTreeMap::new(),
+        _ => (0..4)
+            .map(|i| (format!("child {}", i), tree(depth - 1)))
+            .collect(),
+    };
+    Node {
+        value: depth,
+        children,
+    }
+}
+
+fn documents() -> Vec<Document> {
+    (0..1000)
+        .map(|i| Document {
+            title: format!("Document number {}", i),
+            body: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(i % 20 + 1),
+            tags: (0..i % 8).map(|t| format!("tag-{}", t)).collect(),
+        })
+        .collect()
+}
+
+fn bench_payload<T>(c: &mut Criterion, name: &str, value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    let mut group = c.benchmark_group(name);
+
+    let compact = serde_bin::compact::to_bytes(value).unwrap();
+    let any = serde_bin::any::to_bytes(value).unwrap();
+    assert_eq!(
+        &serde_bin::compact::from_bytes::<T>(&compact).unwrap(),
+        value
+    );
+    assert_eq!(&serde_bin::any::from_bytes::<T>(&any).unwrap(), value);
+
+    group.throughput(Throughput::Bytes(compact.len() as u64));
+    group.bench_function(BenchmarkId::new("serialize", "compact"), |b| {
+        b.iter(|| serde_bin::compact::to_bytes(value).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("deserialize", "compact"), |b| {
+        b.iter(|| serde_bin::compact::from_bytes::<T>(&compact).unwrap())
+    });
+
+    group.throughput(Throughput::Bytes(any.len() as u64));
+    group.bench_function(BenchmarkId::new("serialize", "any"), |b| {
+        b.iter(|| serde_bin::any::to_bytes(value).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("deserialize", "any"), |b| {
+        b.iter(|| serde_bin::any::from_bytes::<T>(&any).unwrap())
+    });
+
+    group.finish();
+}
+
+fn formats(c: &mut Criterion) {
+    bench_payload(c, "wide_struct", &wide());
+    bench_payload(c, "large_vec_u64", &numbers());
+    bench_payload(c, "nested_map", &tree(6));
+    bench_payload(c, "strings", &documents());
+}
+
+criterion_group!(benches, formats);
+criterion_main!(benches);
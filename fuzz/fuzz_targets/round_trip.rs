@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_bin::any::value::Value;
+
+fuzz_target!(|value: Value<'static>| {
+    serde_bin::fuzz::round_trip(&value);
+});
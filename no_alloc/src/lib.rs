@@ -0,0 +1,144 @@
+//! Tests of the crate built without `alloc`, run with `cargo test` from this directory,
+//! and with `cargo test --features alloc` for the crate built with `alloc` but without `std`.
+#![no_std]
+
+#[cfg(all(test, feature = "alloc"))]
+extern crate alloc;
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_bin::any::{from_bytes, from_bytes_with_config, get_serialized_size, to_buff, Tag};
+    use serde_bin::{
+        compact, tagged, BuffWriter, DeserializerConfig, EncryptingWriter, EndOfBuff, Error,
+        ExtendWriter, XorCipher,
+    };
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+    struct TestStruct<'a> {
+        a: usize,
+        b: &'a str,
+    }
+
+    #[test]
+    fn test_serialize_deserialize_struct_buff() {
+        let value = TestStruct { a: 56, b: "Hello" };
+
+        let mut buff = [0; 32];
+        let written = to_buff(&value, &mut buff).unwrap();
+
+        assert_eq!(written.len(), get_serialized_size(&value).unwrap());
+
+        let res: TestStruct = from_bytes(&written).unwrap();
+
+        assert_eq!(res, value);
+    }
+
+    struct UnsizedSeq<'a>(&'a [u16]);
+
+    impl<'a> Serialize for UnsizedSeq<'a> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            // a filtered iterator does not give an exact size hint
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    #[test]
+    fn test_serialize_unsized_seq_buff() {
+        const VEC: [u16; 3] = [3, 7, 1];
+        let value = UnsizedSeq(&VEC);
+
+        let mut buff = [0; 32];
+        let written = to_buff(&value, &mut buff).unwrap();
+
+        assert_eq!(written.first().copied(), Some(Tag::UnsizedSeq.into()));
+        assert_eq!(written.last().copied(), Some(Tag::UnsizedSeqEnd.into()));
+
+        // an array can't be decoded from a sequence without `lenient_sequences`
+        let config = DeserializerConfig::new().lenient_sequences(true);
+        let res: [u16; 3] = from_bytes_with_config(&written, config).unwrap();
+
+        assert_eq!(res, VEC);
+    }
+
+    // the writers available without `std`
+
+    #[test]
+    fn test_buff_writer() {
+        let value = (7u8, "Hello");
+        let mut buff = [0; 32];
+        let mut writer = BuffWriter::new(&mut buff);
+        let written = compact::to_writer(&value, &mut writer).unwrap();
+        let written = written + tagged::to_writer(&value, &mut writer).unwrap();
+        let compact_len = compact::get_serialized_size(&value).unwrap();
+        assert_eq!(written, compact_len + get_serialized_size(&value).unwrap());
+
+        let (res, rest) = compact::from_bytes_partial::<(u8, &str)>(&buff[..written]).unwrap();
+        assert_eq!(res, value);
+        assert_eq!(from_bytes::<(u8, &str)>(rest), Ok(value));
+
+        let mut buff = [0; 8];
+        let mut writer = BuffWriter::new(&mut buff);
+        assert_eq!(
+            compact::to_writer(&value, &mut writer),
+            Err(Error::WriterError(EndOfBuff))
+        );
+    }
+
+    // fixed capacity container, as `Vec` needs `alloc`
+    #[derive(Default)]
+    struct Bytes32 {
+        bytes: [u8; 32],
+        len: usize,
+    }
+
+    impl Extend<u8> for Bytes32 {
+        fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+            for byte in iter {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_extend_writer() {
+        let value = (7u8, "Hello");
+        let mut writer = ExtendWriter(Bytes32::default());
+        tagged::to_writer(&value, &mut writer).unwrap();
+        let container = writer.into_inner();
+        assert_eq!(
+            from_bytes::<(u8, &str)>(&container.bytes[..container.len]),
+            Ok(value)
+        );
+    }
+
+    #[test]
+    fn test_encrypting_writer() {
+        let value = (7u8, "Hello");
+        let mut buff = [0; 32];
+        let mut writer = BuffWriter::new(&mut buff);
+        let mut writer = EncryptingWriter::new(&mut writer, XorCipher::new(b"key"));
+        let written = compact::to_writer(&value, &mut writer).unwrap();
+
+        let mut plain = [0; 32];
+        let plain_len = compact::to_writer(&value, &mut BuffWriter::new(&mut plain)).unwrap();
+        assert_eq!(written, plain_len);
+        assert_ne!(buff[..written], plain[..written]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_vec_writer() {
+        let value = (7u8, "Hello");
+        let mut output = alloc::vec::Vec::new();
+        let written = compact::to_writer(&value, &mut output).unwrap();
+        let written = written + tagged::to_writer(&value, &mut output).unwrap();
+        assert_eq!(written, output.len());
+        let compact_len = compact::get_serialized_size(&value).unwrap();
+        assert_eq!(compact::to_bytes(&value).unwrap(), output[..compact_len]);
+    }
+}
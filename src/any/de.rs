@@ -1,14 +1,45 @@
 use serde::{
-    de::{self, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor},
-    serde_if_integer128, Deserialize,
+    de::{
+        self,
+        value::{BorrowedBytesDeserializer, BorrowedStrDeserializer},
+        DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+        VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, serde_if_integer128, Deserialize,
 };
 
+use core::fmt::{self, Display};
+use core::marker::PhantomData;
+
 use crate::{
-    error::{Error as Err, NoWriterError, Result},
+    canonical::{check_key_order, CanonicalRule},
+    compact,
+    compat::unknown_variant_index,
+    config::{DeserializerConfig, NumberCoercion, StrCoercion},
+    de::{check_message_len, for_each_result, narrow_len, ForEachElement},
+    error::{Error as Err, ForEachError, NoWriterError, Result},
+    is_unsized_string_end,
+    progress::{NoProgress, ProgressCallback, ProgressTracker},
+    trace::TraceStep,
+    write::Crc32,
     UNSIZED_STRING_END_MARKER,
 };
 
-use super::{Tag, TagParsingError};
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::{borrow::Cow, string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use crate::trace::{Trace, TraceEvent};
+#[cfg(feature = "alloc")]
+use crate::unborrowed::Unborrowed;
+
+use super::extension::{self, ExtensionAccess};
+use super::raw::RAW_VALUE_TOKEN;
+use super::{field_hash, ChildCount, PayloadKind, Tag, TagParsingError};
 
 type Error = Err<NoWriterError>;
 
@@ -40,65 +71,674 @@ macro_rules! check_tag {
 
 macro_rules! implement_number {
     ($fn_name:ident, $visitor_fn_name:ident, $t:ident, $expected_tag:pat, $expected:expr) => {
-        fn $fn_name<V>(self, visitor: V) -> Result<V::Value>
+        fn $fn_name<V>(mut self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
         {
-            check_tag!($expected_tag, self.pop_tag()?, $expected);
-            let bytes = self.pop_n()?;
-            visitor.$visitor_fn_name($t::from_be_bytes(bytes))
+            self.unwrap_some()?;
+            match self.pop_tag()? {
+                $expected_tag => {
+                    let bytes = self.pop_n()?;
+                    visitor.$visitor_fn_name($t::from_be_bytes(bytes))
+                }
+                got => {
+                    let value: $t = self.coerce_number(got, $expected)?;
+                    visitor.$visitor_fn_name(value)
+                }
+            }
         }
     };
 }
 
-pub struct Deserializer<'de> {
+/// Integer decoded from any integer tag, for `NumberCoercion`.
+#[derive(Clone, Copy)]
+enum Integer {
+    Signed(WidestSigned),
+    Unsigned(WidestUnsigned),
+}
+
+#[cfg(not(no_integer128))]
+type WidestSigned = i128;
+#[cfg(not(no_integer128))]
+type WidestUnsigned = u128;
+#[cfg(no_integer128)]
+type WidestSigned = i64;
+#[cfg(no_integer128)]
+type WidestUnsigned = u64;
+
+impl Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integer::Signed(n) => Display::fmt(n, f),
+            Integer::Unsigned(n) => Display::fmt(n, f),
+        }
+    }
+}
+
+/// Numbers that can be decoded from a number of another type.
+trait CoercedNumber: Sized {
+    /// Bits count and signedness, `None` for floats.
+    const INTEGER: Option<(u32, bool)>;
+
+    fn from_integer(value: Integer) -> Option<Self>;
+
+    fn from_f32(_value: f32) -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! implement_coerced_integer {
+    ($($t:ident: $signed:expr),*) => {
+        $(
+            impl CoercedNumber for $t {
+                const INTEGER: Option<(u32, bool)> = Some(($t::BITS, $signed));
+
+                fn from_integer(value: Integer) -> Option<Self> {
+                    match value {
+                        Integer::Signed(n) => n.try_into().ok(),
+                        Integer::Unsigned(n) => n.try_into().ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+implement_coerced_integer!(
+    i8: true, i16: true, i32: true, i64: true,
+    u8: false, u16: false, u32: false, u64: false
+);
+
+#[cfg(not(no_integer128))]
+implement_coerced_integer!(i128: true, u128: false);
+
+impl CoercedNumber for f32 {
+    const INTEGER: Option<(u32, bool)> = None;
+
+    fn from_integer(_value: Integer) -> Option<Self> {
+        None
+    }
+}
+
+impl CoercedNumber for f64 {
+    const INTEGER: Option<(u32, bool)> = None;
+
+    fn from_integer(_value: Integer) -> Option<Self> {
+        None
+    }
+
+    fn from_f32(value: f32) -> Option<Self> {
+        Some(value.into())
+    }
+}
+
+pub struct Deserializer<'de, P = NoProgress> {
     input: &'de [u8],
+    config: DeserializerConfig,
+    remaining_elements: Option<usize>,
+    remaining_depth: Option<usize>,
+    input_len: usize,
+    // checksum of the innermost container, only used with `container_checksums`
+    crc: Crc32,
+    progress: ProgressTracker<P>,
+    // fields given to `DeserializerConfig::projection`, taken by the outermost struct if it is not in a container
+    projection: Option<&'static [&'static str]>,
+    // number of unsized sequences and maps being read, the input ending inside one fails with `Error::NeedMoreData`
+    unsized_depth: usize,
+    // last steps with `DeserializerConfig::trace`
+    #[cfg(feature = "alloc")]
+    trace: Option<Trace>,
+    // number of calls of the `Deserializer` trait in progress, only counted with a trace
+    #[cfg(feature = "alloc")]
+    calls: usize,
 }
 
 pub fn from_bytes<'a, T>(input: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer { input };
-    let t = T::deserialize(&mut deserializer)?;
-    let len = deserializer.input.len();
-    (len == 0).then_some(t).ok_or(Error::TrailingBytes(len))
+    from_bytes_seed(PhantomData, input)
+}
+
+/// Fail with `Error::MessageTooLarge` if the input is longer than `max_len` bytes, before decoding anything,
+/// then deserialize it like `from_bytes`.
+///
+/// This rejects an oversized frame up front, `DeserializerConfig::max_total_elements` bounds the work done while decoding.
+pub fn from_bytes_with_limit<'a, T>(input: &'a [u8], max_len: usize) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    check_message_len(input, max_len)?;
+    from_bytes(input)
+}
+
+pub fn from_bytes_seed<'a, S>(seed: S, input: &'a [u8]) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    from_bytes_seed_with_config(seed, input, DeserializerConfig::new())
+}
+
+pub fn from_bytes_with_config<'a, T>(input: &'a [u8], config: DeserializerConfig) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_seed_with_config(PhantomData, input, config)
+}
+
+pub fn from_bytes_seed_with_config<'a, S>(
+    seed: S,
+    input: &'a [u8],
+    config: DeserializerConfig,
+) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    let mut deserializer = Deserializer::with_config(input, config);
+    let res = seed
+        .deserialize(&mut deserializer)
+        .and_then(|t| deserializer.end().map(|_| t));
+    #[cfg(feature = "alloc")]
+    let res = res.map_err(|err| {
+        let err = err.with_format_hint(input, crate::Format::Tagged);
+        match deserializer.trace.take() {
+            Some(trace) if err.trace().is_none() => err.with_trace(trace),
+            _ => err,
+        }
+    });
+    res
+}
+
+/// Deserialize a value with `DeserializerConfig::canonical`, rejecting any other encoding than the one
+/// written by `to_bytes_canonical`.
+pub fn from_bytes_canonical<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_with_config(input, DeserializerConfig::new().canonical(true))
+}
+
+/// Deserialize a sequence, calling `f` with each element as soon as it is decoded instead of collecting them,
+/// to process a large sequence without holding all of it.
+///
+/// The first error returned by `f` stops the decoding and is returned as `ForEachError::Callback`.
+pub fn for_each_element<'a, T, E, F>(
+    input: &'a [u8],
+    f: F,
+) -> core::result::Result<(), ForEachError<E>>
+where
+    T: Deserialize<'a>,
+    F: FnMut(T) -> core::result::Result<(), E>,
+{
+    let mut error = None;
+    let res = from_bytes_seed(ForEachElement::new(f, &mut error), input);
+    for_each_result(res, error)
+}
+
+/// Deserialize the values at the given ranges of the input, in the order of the ranges, as written by `to_bytes_batch`.
+///
+/// Fails with `Error::Eof` if a range is not in the input.
+#[cfg(feature = "alloc")]
+pub fn from_bytes_batch<'a, T>(input: &'a [u8], ranges: &[Range<usize>]) -> Result<Vec<T>>
+where
+    T: Deserialize<'a>,
+{
+    ranges
+        .iter()
+        .map(|range| {
+            input
+                .get(range.clone())
+                .ok_or(Error::Eof)
+                .and_then(from_bytes)
+        })
+        .collect()
+}
+
+/// Deserialize a value at the start of the input, returning it with the bytes left after it.
+pub fn from_bytes_partial<'a, T>(input: &'a [u8]) -> Result<(T, &'a [u8])>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(input);
+    let value = T::deserialize(&mut deserializer)?;
+    Ok((value, deserializer.remaining()))
+}
+
+/// Deserialize a struct with `DeserializerConfig::projection`, only decoding the given fields.
+pub fn from_bytes_projected<'a, T>(input: &'a [u8], fields: &'static [&'static str]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_with_config(input, DeserializerConfig::new().projection(fields))
+}
+
+/// Return `true` if the input holds exactly one well-formed value.
+#[cfg(feature = "alloc")]
+pub(crate) fn is_single_value(input: &[u8]) -> bool {
+    let mut deserializer = Deserializer::new(input);
+    deserializer.skip_value().is_ok() && deserializer.input.is_empty()
+}
+
+/// Walk over the single value of the input, returning the checksum of the enclosing container updated with the bytes it covers.
+pub(super) fn walk_raw_value(input: &[u8], parent_crc: Option<Crc32>) -> Result<Option<Crc32>> {
+    let config = DeserializerConfig::new().container_checksums(parent_crc.is_some());
+    let mut deserializer = Deserializer::with_config(input, config);
+    if let Some(crc) = parent_crc {
+        deserializer.crc = crc;
+    }
+    deserializer.skip_value()?;
+    deserializer.end()?;
+    Ok(parent_crc.map(|_| deserializer.crc))
 }
 
 impl<'de> Deserializer<'de> {
-    fn pop_tag(&mut self) -> Result<Tag> {
-        let [byte] = self.pop_n()?;
-        let tag = byte.try_into()?;
+    pub fn new(input: &'de [u8]) -> Self {
+        Self::with_config(input, DeserializerConfig::new())
+    }
+
+    pub fn with_config(input: &'de [u8], config: DeserializerConfig) -> Self {
+        Deserializer {
+            input,
+            config,
+            remaining_elements: config.max_total_elements,
+            remaining_depth: config.max_depth,
+            input_len: input.len(),
+            crc: Crc32::new(),
+            progress: ProgressTracker::new(),
+            projection: config.projection,
+            unsized_depth: 0,
+            #[cfg(feature = "alloc")]
+            trace: config.trace.map(Trace::new),
+            #[cfg(feature = "alloc")]
+            calls: 0,
+        }
+    }
+
+    /// Call `callback` with the number of bytes read so far, at most once every `interval` bytes.
+    /// Returning `ControlFlow::Break(())` stops the deserialization with `Error::Cancelled`.
+    pub fn with_progress<P: ProgressCallback>(
+        self,
+        interval: usize,
+        callback: P,
+    ) -> Deserializer<'de, P> {
+        Deserializer {
+            input: self.input,
+            config: self.config,
+            remaining_elements: self.remaining_elements,
+            remaining_depth: self.remaining_depth,
+            input_len: self.input_len,
+            crc: self.crc,
+            progress: ProgressTracker::with_callback(interval, callback),
+            projection: self.projection,
+            unsized_depth: self.unsized_depth,
+            #[cfg(feature = "alloc")]
+            trace: self.trace,
+            #[cfg(feature = "alloc")]
+            calls: self.calls,
+        }
+    }
+}
+
+impl<'de, P: ProgressCallback> Deserializer<'de, P> {
+    pub fn config(&self) -> &DeserializerConfig {
+        &self.config
+    }
+
+    /// Input not consumed yet, to read the next value of a stream.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.input
+    }
+
+    /// Erase the type of the deserializer, to give it to `erased_serde::deserialize`.
+    /// The erased deserializer reads a single value, call this again for the next one of a stream.
+    ///
+    /// The errors are then `erased_serde::Error`s with the message of the error of this crate.
+    /// The input is not checked to be fully consumed, see `remaining`.
+    #[cfg(feature = "erased")]
+    pub fn erased(&mut self) -> impl erased_serde::Deserializer<'de> + '_ {
+        <dyn erased_serde::Deserializer>::erase(self)
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if let Some(remaining) = self.remaining_depth.as_mut() {
+            *remaining = remaining.checked_sub(1).ok_or(Error::DepthLimitExceeded)?;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        if let Some(remaining) = self.remaining_depth.as_mut() {
+            *remaining += 1;
+        }
+    }
+
+    /// Run `f` one nesting level deeper.
+    /// Run a call of the `Deserializer` trait, attaching the trace of `DeserializerConfig::trace`
+    /// to the error leaving the outermost call.
+    fn traced<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        #[cfg(feature = "alloc")]
+        if self.trace.is_some() {
+            self.calls += 1;
+            let res = f(self);
+            self.calls -= 1;
+            return match res {
+                Err(err) if self.calls == 0 => Err(self.attach_trace(err)),
+                res => res,
+            };
+        }
+        f(self)
+    }
+
+    /// Attach a copy of the trace to the error, if it doesn't have one already.
+    #[cfg(feature = "alloc")]
+    fn attach_trace(&self, err: Error) -> Error {
+        match &self.trace {
+            Some(trace) if err.trace().is_none() => err.with_trace(trace.clone()),
+            _ => err,
+        }
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.enter()?;
+        let res = f(self);
+        self.leave();
+        res
+    }
+
+    fn consume_element(&mut self) -> Result<()> {
+        if let Some(remaining) = self.remaining_elements.as_mut() {
+            *remaining = remaining
+                .checked_sub(1)
+                .ok_or(Error::ElementBudgetExceeded)?;
+        }
+        Ok(())
+    }
+
+    /// Start the checksum of a container whose header has been popped, returning the checksum of the enclosing container.
+    fn open_container(&mut self) -> Option<Crc32> {
+        // only a struct outside of any container is projected
+        self.projection = None;
+        self.config
+            .container_checksums
+            .then(|| core::mem::replace(&mut self.crc, Crc32::new()))
+    }
+
+    /// Pop and verify the checksum of a container after its last element.
+    fn close_container(&mut self, parent_crc: Option<Crc32>) -> Result<()> {
+        let Some(parent_crc) = parent_crc else {
+            return Ok(());
+        };
+        let offset = self.offset();
+        let crc = core::mem::replace(&mut self.crc, parent_crc).finish();
+        if u32::from_be_bytes(self.pop_n()?) != crc {
+            return Err(Error::ChecksumMismatch { offset });
+        }
+        Ok(())
+    }
+
+    /// Pop the `Some` tag wrapping a value when the target is not an option, if `DeserializerConfig::unwrap_some` is enabled.
+    fn unwrap_some(&mut self) -> Result<()> {
+        if self.config.unwrap_some && self.peek_tag()? == Tag::Some {
+            self.pop_tag()?;
+        }
+        Ok(())
+    }
+
+    /// Fail if the input is not fully consumed.
+    pub(super) fn end(&self) -> Result<()> {
+        match self.input.len() {
+            0 => Ok(()),
+            len => Err(Error::TrailingBytes(len)),
+        }
+    }
+
+    pub(super) fn pop_tag(&mut self) -> Result<Tag> {
+        let (tag, rest) = self.read_tag()?;
+        self.record(self.offset(), TraceStep::Tag(tag));
+        self.check_canonical_tag(tag)?;
+        self.check_integer_tag(tag, rest)?;
+        self.consume(self.input.len() - rest.len())?;
         Ok(tag)
     }
 
-    fn peek_tag(&mut self) -> Result<Tag> {
-        let byte = self.input.first().copied().ok_or(Error::Eof)?;
-        let tag = byte.try_into()?;
+    pub(super) fn peek_tag(&mut self) -> Result<Tag> {
+        let (tag, _) = self.read_tag()?;
+        self.check_canonical_tag(tag)?;
         Ok(tag)
     }
 
+    /// Parse the next tag, with the bytes of `DeserializerConfig::tag_map` if there is one.
+    fn read_tag(&mut self) -> Result<(Tag, &'de [u8])> {
+        let res = match self.config.tag_map {
+            Some(map) => map.read(self.input),
+            None => Tag::read(self.input),
+        };
+        if res.is_err() {
+            self.record(
+                self.offset(),
+                TraceStep::InvalidTag(self.input.first().copied()),
+            );
+        }
+        res.map_err(|err| match err {
+            Error::Eof => self.eof(),
+            err => err,
+        })
+    }
+
+    /// Record a step with `DeserializerConfig::trace`.
+    fn record(&mut self, offset: usize, step: TraceStep) {
+        #[cfg(feature = "alloc")]
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEvent { offset, step });
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = (offset, step);
+    }
+
+    /// Offset of the remaining input in the whole input.
+    fn offset(&self) -> usize {
+        self.input_len - self.input.len()
+    }
+
+    /// Reject the tags of the values encoded without their length with `canonical`.
+    fn check_canonical_tag(&self, tag: Tag) -> Result<()> {
+        let rule = match tag {
+            _ if !self.config.canonical => return Ok(()),
+            Tag::NullTerminatedString => CanonicalRule::UnsizedString,
+            Tag::UnsizedSeq => CanonicalRule::UnsizedSeq,
+            Tag::UnsizedMap => CanonicalRule::UnsizedMap,
+            _ => return Ok(()),
+        };
+        Err(Error::NonCanonical {
+            rule,
+            offset: self.offset(),
+        })
+    }
+
+    /// With `strict_integers`, check that an integer is tagged with the narrowest tag holding its value.
+    fn check_integer_tag(&self, tag: Tag, payload: &[u8]) -> Result<()> {
+        if !self.config.strict_integers {
+            return Ok(());
+        }
+        match narrowest_integer_tag(tag, payload) {
+            Some(narrowest) if narrowest != tag => Err(Error::NonCanonicalInteger {
+                tag,
+                narrowest,
+                offset: self.offset(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read a map key with `read`, and with `canonical` check that it comes after the previous key.
+    fn map_key<T>(
+        &mut self,
+        previous_key: &mut Option<&'de [u8]>,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if !self.config.canonical {
+            return read(self);
+        }
+        let input = self.input;
+        let offset = self.offset();
+        let value = read(self)?;
+        let key = &input[..input.len() - self.input.len()];
+        if !check_key_order(*previous_key, key) {
+            return Err(Error::NonCanonical {
+                rule: CanonicalRule::UnsortedMapKeys,
+                offset,
+            });
+        }
+        *previous_key = Some(key);
+        Ok(value)
+    }
+
+    /// Error of the input ending too soon, `Error::NeedMoreData` inside an unsized sequence or map
+    /// as more bytes could still complete it.
+    fn eof(&self) -> Error {
+        match self.unsized_depth {
+            0 => Error::Eof,
+            _ => Error::NeedMoreData,
+        }
+    }
+
+    /// Skip the elements of an unsized sequence or map up to its end marker, consuming it.
+    fn skip_to_unsized_seq_end(&mut self) -> Result<()> {
+        while self.peek_tag()? != Tag::UnsizedSeqEnd {
+            self.skip_value()?;
+        }
+        self.pop_tag().map(drop)
+    }
+
     fn pop_slice(&mut self, len: usize) -> Result<&'de [u8]> {
         if self.input.len() < len {
-            return Err(Error::Eof);
+            return Err(self.eof());
         }
+        self.consume(len)
+    }
+
+    fn consume(&mut self, len: usize) -> Result<&'de [u8]> {
         let (bytes, rem) = self.input.split_at(len);
+        if self.config.container_checksums {
+            self.crc.update(bytes);
+        }
         self.input = rem;
+        self.progress.advance(len)?;
         Ok(bytes)
     }
 
-    fn pop_n<const N: usize>(&mut self) -> Result<[u8; N]> {
+    pub(super) fn pop_n<const N: usize>(&mut self) -> Result<[u8; N]> {
         let bytes = self.pop_slice(N)?;
         let mut buff = [0; N];
         buff.copy_from_slice(bytes);
         Ok(buff)
     }
 
-    fn pop_usize(&mut self) -> Result<usize> {
+    /// Pop the 1 byte length of a tuple, struct or variant, which can't announce more elements than
+    /// the bytes left as each element starts with its tag.
+    pub(super) fn pop_elements_len(&mut self) -> Result<usize> {
+        let offset = self.offset();
+        let [len] = self.pop_n()?;
+        // fits in any `usize`, which has at least 16 bits
+        let len = usize::from(len);
+        self.record(offset, TraceStep::Length(len));
+        let remaining = self.input.len();
+        if len > remaining {
+            // like `eof`, more bytes can still complete an unsized sequence
+            return Err(match self.unsized_depth {
+                0 => Error::LengthExceedsInput { len, remaining },
+                _ => Error::NeedMoreData,
+            });
+        }
+        Ok(len)
+    }
+
+    /// Pop an extension value, giving its tag and its payload.
+    fn pop_extension(&mut self) -> Result<(u8, &'de [u8])> {
+        let tag = self.input.first().copied();
+        check_tag!(Tag::Extension, self.pop_tag()?, "Extension");
+        let len = self.pop_usize()?;
+        Ok((tag.unwrap(), self.pop_slice(len)?))
+    }
+
+    pub(super) fn pop_usize(&mut self) -> Result<usize> {
+        let offset = self.offset();
         let bytes = self.pop_n()?;
-        u64::from_be_bytes(bytes)
-            .try_into()
-            .map_err(|_| Error::InvalidSize)
+        let len = narrow_len(u64::from_be_bytes(bytes))?;
+        self.record(offset, TraceStep::Length(len));
+        Ok(len)
+    }
+
+    /// Pop an integer of any type, the tag has already been popped.
+    fn pop_integer(&mut self, tag: Tag) -> Result<Option<(Integer, u32)>> {
+        let integer = match tag {
+            Tag::I8 => (Integer::Signed(i8::from_be_bytes(self.pop_n()?).into()), 8),
+            Tag::I16 => (
+                Integer::Signed(i16::from_be_bytes(self.pop_n()?).into()),
+                16,
+            ),
+            Tag::I32 => (
+                Integer::Signed(i32::from_be_bytes(self.pop_n()?).into()),
+                32,
+            ),
+            Tag::I64 => (
+                Integer::Signed(i64::from_be_bytes(self.pop_n()?).into()),
+                64,
+            ),
+            #[cfg(not(no_integer128))]
+            Tag::I128 => (Integer::Signed(i128::from_be_bytes(self.pop_n()?)), 128),
+            Tag::U8 => (
+                Integer::Unsigned(u8::from_be_bytes(self.pop_n()?).into()),
+                8,
+            ),
+            Tag::U16 => (
+                Integer::Unsigned(u16::from_be_bytes(self.pop_n()?).into()),
+                16,
+            ),
+            Tag::U32 => (
+                Integer::Unsigned(u32::from_be_bytes(self.pop_n()?).into()),
+                32,
+            ),
+            Tag::U64 => (
+                Integer::Unsigned(u64::from_be_bytes(self.pop_n()?).into()),
+                64,
+            ),
+            #[cfg(not(no_integer128))]
+            Tag::U128 => (Integer::Unsigned(u128::from_be_bytes(self.pop_n()?)), 128),
+            _ => return Ok(None),
+        };
+        Ok(Some(integer))
+    }
+
+    /// Decode a number whose tag is not the one of the requested type, following `DeserializerConfig::number_coercion`.
+    fn coerce_number<T: CoercedNumber>(&mut self, tag: Tag, expected: &'static str) -> Result<T> {
+        let coercion = self.config.number_coercion;
+        if coercion == NumberCoercion::Exact {
+            unexpected_tag!(expected, tag);
+        }
+        if tag == Tag::F32 {
+            return match T::from_f32(f32::from_be_bytes(self.pop_n()?)) {
+                Some(value) => Ok(value),
+                None => unexpected_tag!(expected, tag),
+            };
+        }
+        let Some((bits, signed)) = T::INTEGER else {
+            unexpected_tag!(expected, tag)
+        };
+        let Some((value, encoded_bits)) = self.pop_integer(tag)? else {
+            unexpected_tag!(expected, tag)
+        };
+        let widening = match value {
+            Integer::Signed(_) => signed && encoded_bits <= bits,
+            Integer::Unsigned(_) if signed => encoded_bits < bits,
+            Integer::Unsigned(_) => encoded_bits <= bits,
+        };
+        if coercion == NumberCoercion::Widen && !widening {
+            unexpected_tag!(expected, tag);
+        }
+        T::from_integer(value).ok_or_else(|| {
+            de::Error::custom(format_args!("{} is out of range for {}", value, expected))
+        })
     }
 
     fn parse_str_inner(&mut self, len: usize) -> Result<&'de str> {
@@ -111,8 +751,8 @@ impl<'de> Deserializer<'de> {
         let len = self
             .input
             .windows(UNSIZED_STRING_END_MARKER.len())
-            .position(|bytes| bytes == UNSIZED_STRING_END_MARKER)
-            .ok_or(Error::Eof)?;
+            .position(is_unsized_string_end)
+            .ok_or_else(|| self.eof())?;
         let s = self.parse_str_inner(len)?;
         self.pop_slice(UNSIZED_STRING_END_MARKER.len())?;
         Ok(s)
@@ -131,13 +771,214 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    fn skip_values(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.skip_value()?;
+        }
+        Ok(())
+    }
+
+    /// Walk over the next value using its tag, without decoding it.
+    ///
+    /// Skipping a nested value recurses through this function, `skip_children` and the loops over the children,
+    /// the work that doesn't recurse is kept in helpers out of their frames so deep values fit in the stack.
+    fn skip_value(&mut self) -> Result<()> {
+        let Some(count) = self.skip_header()? else {
+            return Ok(());
+        };
+        self.enter()?;
+        let res = self.skip_children(count);
+        self.leave();
+        res
+    }
+
+    /// Pop the tag of the next value with its payload, or with its header if it has children,
+    /// returning the count of the children.
+    #[inline(never)]
+    fn skip_header(&mut self) -> Result<Option<ChildCount>> {
+        let tag = self.pop_tag()?;
+        match tag.payload_kind() {
+            PayloadKind::Fixed(len) => self.pop_slice(len).map(|_| None),
+            PayloadKind::LengthPrefixed => {
+                let len = self.pop_usize()?;
+                self.pop_slice(len).map(|_| None)
+            }
+            PayloadKind::NullTerminated => self.parse_unknown_len_str().map(|_| None),
+            PayloadKind::TaggedChildren { header, count } => {
+                self.pop_slice(header)?;
+                Ok(Some(count))
+            }
+            PayloadKind::EndMarker => {
+                unexpected_tag!("Any tag other than end of sequence", tag)
+            }
+        }
+    }
+
+    fn skip_children(&mut self, count: ChildCount) -> Result<()> {
+        let len = match count {
+            ChildCount::Fixed(count) => return self.skip_values(count),
+            ChildCount::U64PairsLen => return self.skip_map(),
+            ChildCount::UntilEndMarker => return self.skip_unsized_children(),
+            _ => self.pop_children_len(count)?,
+        };
+        let parent_crc = self.open_container();
+        for _ in 0..len {
+            self.skip_value()?;
+        }
+        self.close_container(parent_crc)
+    }
+
+    /// Pop the number of elements of a sequence, tuple or struct.
+    #[inline(never)]
+    fn pop_children_len(&mut self, count: ChildCount) -> Result<usize> {
+        match count {
+            ChildCount::U8Len => self.pop_elements_len(),
+            _ => self.pop_usize(),
+        }
+    }
+
+    fn skip_map(&mut self) -> Result<()> {
+        let len = self.pop_usize()?;
+        let parent_crc = self.open_container();
+        let mut previous_key = None;
+        for _ in 0..len {
+            self.map_key(&mut previous_key, Self::skip_value)?;
+            self.skip_value()?;
+        }
+        self.close_container(parent_crc)
+    }
+
+    fn skip_unsized_children(&mut self) -> Result<()> {
+        let parent_crc = self.open_container();
+        self.unsized_depth += 1;
+        let res = self.skip_to_unsized_seq_end();
+        self.unsized_depth -= 1;
+        res?;
+        self.close_container(parent_crc)
+    }
+
+    /// Skip the content of a variant, the tag and the variant index have already been popped.
+    fn skip_variant_payload(&mut self, tag: Tag) -> Result<()> {
+        match tag.payload_kind() {
+            // unit variant, nothing after the index
+            PayloadKind::Fixed(_) => Ok(()),
+            PayloadKind::TaggedChildren { count, .. } => self.skip_children(count),
+            _ => unexpected_tag!("Enum", tag),
+        }
+    }
+
+    /// Pop the header of a tuple or tuple struct, or of a sequence with the lenient sequences option.
+    fn tuple_deserializer(
+        &mut self,
+        tuple_tag: Tag,
+        expected: &'static str,
+        len: usize,
+    ) -> Result<SeqDeserializer<'_, 'de, P>> {
+        let lenient = self.config.lenient_sequences;
+        let (encoded_len, sized) = match self.pop_tag()? {
+            tag if tag == tuple_tag => {
+                let len = self.pop_elements_len()?;
+                (len, true)
+            }
+            Tag::Seq if lenient => (self.pop_usize()?, true),
+            Tag::UnsizedSeq if lenient => (self.count_unsized_elements()?, false),
+            got => unexpected_tag!(expected, got),
+        };
+        if len != encoded_len {
+            return Err(Err::SeqSizeMismatch {
+                expected: len,
+                got: encoded_len,
+            });
+        }
+        if sized {
+            Ok(SeqDeserializer::new_with_len(self, len))
+        } else {
+            Ok(SeqDeserializer::new_unsized(self))
+        }
+    }
+
+    /// Count the elements of an unsized sequence without consuming them, the `UnsizedSeq` tag has already been popped.
+    fn count_unsized_elements(&self) -> Result<usize> {
+        let mut de = Deserializer::with_config(self.input, self.config);
+        de.remaining_depth = self.remaining_depth;
+        de.input_len = self.input_len;
+        de.unsized_depth = self.unsized_depth + 1;
+        // the elements are read again after counting them, without reporting the progress
+        #[cfg(feature = "alloc")]
+        {
+            de.trace = None;
+        }
+        let mut count = 0;
+        while de.peek_tag()? != Tag::UnsizedSeqEnd {
+            de.skip_value()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn parse_map<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.map_deserializer()?.visit_map(visitor)
+    }
+
+    fn map_deserializer(&mut self) -> Result<SeqDeserializer<'_, 'de, P>> {
+        Ok(match_tag! {
+            self.pop_tag()?, "Map",
+            Tag::Map => SeqDeserializer::new(self)?
+            Tag::UnsizedMap => SeqDeserializer::new_unsized(self)
+        })
+    }
+
+    /// Parse a struct written as a map by `SerializerConfig::sparse_structs` or `hashed_field_names`.
+    fn parse_struct_map<V>(
+        &mut self,
+        fields: &'static [&'static str],
+        projection: Option<Projection>,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let hashed_field_names = self.config.hashed_field_names;
+        let mut seq_des = self.map_deserializer()?;
+        if hashed_field_names {
+            seq_des.hashed_fields = Some(fields);
+        }
+        seq_des.projection = projection;
+        seq_des.visit_map(visitor)
+    }
+
+    fn check_variant_len(&mut self, len: usize) -> Result<()> {
+        let encoded_len = self.pop_elements_len()?;
+        if len != encoded_len {
+            return Err(Err::SeqSizeMismatch {
+                expected: len,
+                got: encoded_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the number of fields of a struct, fewer fields are accepted with `DeserializerConfig::missing_fields`.
+    fn check_fields_len(&self, len: usize, encoded_len: usize) -> Result<()> {
+        if len == encoded_len || (encoded_len < len && self.config.missing_fields) {
+            return Ok(());
+        }
+        Err(Err::SeqSizeMismatch {
+            expected: len,
+            got: encoded_len,
+        })
+    }
+
     fn parse_tuple<V>(&mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         check_tag!(Tag::Tuple, self.pop_tag()?, "Tuple");
-        let [len] = self.pop_n()?;
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len.into()))
+        let len = self.pop_elements_len()?;
+        SeqDeserializer::new_with_len(self, len).visit_seq(visitor)
     }
 
     fn parse_tuple_struct<V>(&mut self, visitor: V) -> Result<V::Value>
@@ -145,8 +986,8 @@ impl<'de> Deserializer<'de> {
         V: Visitor<'de>,
     {
         check_tag!(Tag::TupleStruct, self.pop_tag()?, "TupleStruct");
-        let [len] = self.pop_n()?;
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len.into()))
+        let len = self.pop_elements_len()?;
+        SeqDeserializer::new_with_len(self, len).visit_seq(visitor)
     }
 
     fn parse_struct<V>(&mut self, visitor: V) -> Result<V::Value>
@@ -154,22 +995,104 @@ impl<'de> Deserializer<'de> {
         V: Visitor<'de>,
     {
         check_tag!(Tag::Struct, self.pop_tag()?, "Struct");
-        let de = StructDeserializer::new(self)?;
-        visitor.visit_map(de)
+        StructDeserializer::new(self)?.visit_map(visitor)
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+macro_rules! forward_traced {
+    ($($fn_name:ident($($arg:ident: $t:ty),*);)*) => {
+        $(
+            fn $fn_name<V>(self, $($arg: $t,)* visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.traced(|de| Untraced(de).$fn_name($($arg,)* visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, P: ProgressCallback> de::Deserializer<'de> for &mut Deserializer<'de, P> {
     type Error = Error;
 
     fn is_human_readable(&self) -> bool {
         false
     }
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    forward_traced! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    serde_if_integer128! {
+        forward_traced! {
+            deserialize_i128();
+            deserialize_u128();
+        }
+    }
+}
+
+/// The `Deserializer` trait implemented on the deserializer, without attaching the trace to the errors.
+///
+/// `&mut Deserializer` forwards each call to it, attaching the trace of `DeserializerConfig::trace`
+/// to the error leaving the outermost call, as the nested values are deserialized through `&mut Deserializer` too.
+struct Untraced<'a, 'de, P>(&'a mut Deserializer<'de, P>);
+
+impl<'a, 'de, P> core::ops::Deref for Untraced<'a, 'de, P> {
+    type Target = Deserializer<'de, P>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a, 'de, P> core::ops::DerefMut for Untraced<'a, 'de, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<'de, P: ProgressCallback> de::Deserializer<'de> for Untraced<'_, 'de, P> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if self.config.deny_any {
+            return Err(Error::AnyDenied);
+        }
         let tag = self.peek_tag()?;
         match tag {
             Tag::None | Tag::Some => self.deserialize_option(visitor),
@@ -190,18 +1113,34 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Tag::Unit => self.deserialize_unit(visitor),
             Tag::UnitStruct => self.deserialize_unit_struct("", visitor),
             Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
-                self.deserialize_enum("", &[], visitor)
+                self.pop_tag()?;
+                self.nested(|de| {
+                    visitor.visit_enum(VariantDeserializer {
+                        de,
+                        tag,
+                        any_payload: true,
+                    })
+                })
             }
             Tag::NewTypeStruct => self.deserialize_newtype_struct("", visitor),
             Tag::Seq | Tag::UnsizedSeq => self.deserialize_seq(visitor),
             Tag::Tuple => self.parse_tuple(visitor),
             Tag::TupleStruct => self.parse_tuple_struct(visitor),
             Tag::Map | Tag::UnsizedMap => self.deserialize_map(visitor),
+            Tag::Struct if self.config.structs_as_sequences => {
+                self.pop_tag()?;
+                let len = self.pop_elements_len()?;
+                SeqDeserializer::new_with_len(self.0, len).visit_seq(visitor)
+            }
             Tag::Struct => self.parse_struct(visitor),
             #[cfg(not(no_integer128))]
             Tag::I128 => self.deserialize_i128(visitor),
             #[cfg(not(no_integer128))]
             Tag::U128 => self.deserialize_u128(visitor),
+            Tag::Extension => {
+                let (tag, payload) = self.pop_extension()?;
+                visitor.visit_map(ExtensionAccess::new(tag, payload))
+            }
             Tag::UnsizedSeqEnd => Err(Error::TagParsingError(TagParsingError::unexpected(
                 "Any tag other than end of sequence",
                 Tag::UnsizedSeqEnd,
@@ -209,10 +1148,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         }
     }
 
-    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_bool<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.unwrap_some()?;
         match_tag! {
             self.pop_tag()?, "Boolean",
             Tag::BoolFalse => visitor.visit_bool(false)
@@ -236,10 +1176,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         implement_number!(deserialize_u128, visit_u128, u128, Tag::U128, "u128");
     }
 
-    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_char<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.unwrap_some()?;
         let len = match_tag! {
             self.pop_tag()?, "char",
             Tag::Char1 => 1
@@ -248,22 +1189,40 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Tag::Char4 => 4
         };
         let bytes = self.pop_slice(len)?;
-        // bytes is at least 1 byte, so the decoded &str is not empty,
-        // unwraping would be ok but from my test it is not optimised away,
-        // unwrap_unchecked could be use but I try to keep it unsafe-free, so unwrap_or_default it is
-        let c = core::str::from_utf8(bytes)?
-            .chars()
-            .next()
-            .unwrap_or_default();
-        visitor.visit_char(c)
+        // the tag gives the UTF-8 length of the char, so the bytes must be valid UTF-8 decoding to exactly one char,
+        // several ASCII bytes would otherwise be silently truncated to the first one.
+        let mut chars = core::str::from_utf8(bytes).unwrap_or_default().chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => {
+                let mut buff = [0; 4];
+                buff[4 - len..].copy_from_slice(bytes);
+                Err(Error::InvalidChar(u32::from_be_bytes(buff)))
+            }
+        }
     }
 
-    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_str<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let s = self.parse_str()?;
-        visitor.visit_borrowed_str(s)
+        self.unwrap_some()?;
+        let coercion = self.config.str_coercion;
+        if coercion == StrCoercion::Exact || self.peek_tag()? != Tag::ByteArray {
+            let s = self.parse_str()?;
+            return visitor.visit_borrowed_str(s);
+        }
+        self.pop_tag()?;
+        let len = self.pop_usize()?;
+        let bytes = self.pop_slice(len)?;
+        match coercion {
+            #[cfg(feature = "alloc")]
+            StrCoercion::Lossy => match String::from_utf8_lossy(bytes) {
+                Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                Cow::Owned(s) => visitor.visit_string(s),
+            },
+            _ => visitor.visit_borrowed_str(core::str::from_utf8(bytes)?),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -273,13 +1232,21 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_str(visitor)
     }
 
-    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        check_tag!(Tag::ByteArray, self.pop_tag()?, "ByteArray");
-        let len = self.pop_usize()?;
-        let bytes = self.pop_slice(len)?;
+        self.unwrap_some()?;
+        let lenient = self.config.str_coercion != StrCoercion::Exact;
+        let bytes = match self.pop_tag()? {
+            Tag::ByteArray => {
+                let len = self.pop_usize()?;
+                self.pop_slice(len)?
+            }
+            Tag::String if lenient => self.parse_known_len_str()?.as_bytes(),
+            Tag::NullTerminatedString if lenient => self.parse_unknown_len_str()?.as_bytes(),
+            got => unexpected_tag!("ByteArray", got),
+        };
         visitor.visit_borrowed_bytes(bytes)
     }
 
@@ -290,71 +1257,103 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_bytes(visitor)
     }
 
-    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_option<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         match_tag! {
             self.pop_tag()?, "Option",
             Tag::None => visitor.visit_none()
-            Tag::Some => visitor.visit_some(self)
+            Tag::Some => self.nested(|de| visitor.visit_some(de))
         }
     }
 
-    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_unit<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.unwrap_some()?;
         check_tag!(Tag::Unit, self.pop_tag()?, "Unit");
         visitor.visit_unit()
     }
 
-    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(mut self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.unwrap_some()?;
         check_tag!(Tag::UnitStruct, self.pop_tag()?, "UnitStruct");
         visitor.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(mut self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == RAW_VALUE_TOKEN {
+            let input = self.input;
+            self.skip_value()?;
+            let len = input.len() - self.input.len();
+            return visitor.visit_borrowed_bytes(&input[..len]);
+        }
+        self.unwrap_some()?;
+        if let Some(extension) = extension::find_by_name(self.config.extensions, name) {
+            if self.peek_tag()? == Tag::Extension {
+                let (tag, payload) = self.pop_extension()?;
+                if tag != extension.tag() {
+                    return Err(de::Error::invalid_value(
+                        Unexpected::Unsigned(tag.into()),
+                        &"the tag of the extension",
+                    ));
+                }
+                #[cfg(feature = "alloc")]
+                if let Some((_, decode)) = extension.codec() {
+                    let mut content = Vec::new();
+                    decode(payload, &mut content)?;
+                    let mut de = compact::Deserializer::new(&content);
+                    let value = visitor.visit_newtype_struct(Unborrowed::new(&mut de))?;
+                    de.end()?;
+                    return Ok(value);
+                }
+                let mut de = compact::Deserializer::new(payload);
+                let value = visitor.visit_newtype_struct(&mut de)?;
+                de.end()?;
+                return Ok(value);
+            }
+        }
         check_tag!(Tag::NewTypeStruct, self.pop_tag()?, "NewTypeStruct");
-        visitor.visit_newtype_struct(self)
+        self.nested(|de| visitor.visit_newtype_struct(de))
     }
 
-    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let seq_des = match_tag! {
-            self.pop_tag()?, "Sequence",
-            Tag::Seq => SeqDeserializer::new(self)?
-            Tag::UnsizedSeq => SeqDeserializer::new_unsized(self)
+        self.unwrap_some()?;
+        let lenient = self.config.lenient_sequences;
+        let seq_des = match self.pop_tag()? {
+            Tag::Seq => SeqDeserializer::new(self.0)?,
+            Tag::UnsizedSeq => SeqDeserializer::new_unsized(self.0),
+            Tag::Tuple | Tag::TupleStruct if lenient => {
+                let len = self.pop_elements_len()?;
+                SeqDeserializer::new_with_len(self.0, len)
+            }
+            got => unexpected_tag!("Sequence", got),
         };
-        visitor.visit_seq(seq_des)
+        seq_des.visit_seq(visitor)
     }
 
-    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(mut self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        check_tag!(Tag::Tuple, self.pop_tag()?, "Tuple");
-        let [encoded_len] = self.pop_n()?;
-        let encoded_len: usize = encoded_len.into();
-        if len != encoded_len {
-            return Err(Err::SeqSizeMismatch {
-                expected: len,
-                got: encoded_len,
-            });
-        }
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        self.unwrap_some()?;
+        self.tuple_deserializer(Tag::Tuple, "Tuple", len)?
+            .visit_seq(visitor)
     }
 
     fn deserialize_tuple_struct<V>(
-        self,
+        mut self,
         _name: &'static str,
         len: usize,
         visitor: V,
@@ -362,32 +1361,21 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        check_tag!(Tag::TupleStruct, self.pop_tag()?, "TupleStruct");
-        let [encoded_len] = self.pop_n()?;
-        let encoded_len: usize = encoded_len.into();
-        if len != encoded_len {
-            return Err(Err::SeqSizeMismatch {
-                expected: len,
-                got: encoded_len,
-            });
-        }
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        self.unwrap_some()?;
+        self.tuple_deserializer(Tag::TupleStruct, "TupleStruct", len)?
+            .visit_seq(visitor)
     }
 
-    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let seq_des = match_tag! {
-            self.pop_tag()?, "Map",
-            Tag::Map => SeqDeserializer::new(self)?
-            Tag::UnsizedMap => SeqDeserializer::new_unsized(self)
-        };
-        visitor.visit_map(seq_des)
+        self.unwrap_some()?;
+        self.parse_map(visitor)
     }
 
     fn deserialize_struct<V>(
-        self,
+        mut self,
         _name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
@@ -395,43 +1383,93 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        check_tag!(Tag::Struct, self.pop_tag()?, "Struct");
+        self.unwrap_some()?;
+        let projection = self
+            .projection
+            .take()
+            .map(|projected| Projection { fields, projected });
         let len = fields.len();
-        let [encoded_len] = self.pop_n()?;
-        let encoded_len: usize = encoded_len.into();
-        if len != encoded_len {
-            return Err(Err::SeqSizeMismatch {
-                expected: len,
-                got: encoded_len,
-            });
+        let tag = self.peek_tag()?;
+        if self.config.lenient_sequences
+            && matches!(
+                tag,
+                Tag::Seq | Tag::UnsizedSeq | Tag::Tuple | Tag::TupleStruct
+            )
+        {
+            // the fields are given in order, like the compact format
+            let tuple_tag = match tag {
+                Tag::Tuple | Tag::TupleStruct => tag,
+                _ => Tag::Struct,
+            };
+            return self
+                .tuple_deserializer(tuple_tag, "Struct", len)?
+                .visit_seq(visitor);
+        }
+        if matches!(self.peek_tag()?, Tag::Map | Tag::UnsizedMap) {
+            // written by `SerializerConfig::sparse_structs` or `hashed_field_names`
+            return self.parse_struct_map(fields, projection, visitor);
         }
-        visitor.visit_map(StructDeserializer::new_with_len(self, len))
+        check_tag!(Tag::Struct, self.pop_tag()?, "Struct");
+        let encoded_len = self.pop_elements_len()?;
+        self.check_fields_len(len, encoded_len)?;
+        let mut struct_des = StructDeserializer::new_with_len(self.0, encoded_len);
+        struct_des.projection = projection;
+        struct_des.visit_map(visitor)
     }
 
     fn deserialize_enum<V>(
-        self,
+        mut self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        check_tag!(
-            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant,
-            self.peek_tag()?,
-            "Enum"
-        );
-        visitor.visit_enum(self)
+        self.unwrap_some()?;
+        match_tag! {
+            self.peek_tag()?, "Enum",
+            tag @ (Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant) => {
+                if let Some(fallback) = unknown_variant_index(variants) {
+                    let index = self.input.get(1..5).ok_or_else(|| self.eof())?;
+                    if u32::from_be_bytes(index.try_into().unwrap()) >= fallback {
+                        let input = self.input;
+                        self.skip_value()?;
+                        let raw = &input[..input.len() - self.input.len()];
+                        return visitor.visit_enum(UnknownVariantDeserializer { fallback, raw });
+                    }
+                }
+                self.pop_tag()?;
+                self.nested(|de| {
+                    visitor.visit_enum(VariantDeserializer {
+                        de,
+                        tag,
+                        any_payload: false,
+                    })
+                })
+            }
+            // unit variant serialized by name
+            Tag::String | Tag::NullTerminatedString => {
+                if self.config.canonical {
+                    return Err(Error::NonCanonical {
+                        rule: CanonicalRule::VariantByName,
+                        offset: self.offset(),
+                    });
+                }
+                let variant = self.parse_str()?;
+                visitor.visit_enum(BorrowedStrDeserializer::new(variant))
+            }
+        }
     }
 
-    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_identifier<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         match_tag! {
             self.pop_tag()?, "Identifier",
-            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
+            // the variant index of an enum, or the field index of a struct with `SerializerConfig::sparse_structs`
+            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant | Tag::U32 => {
                 let bytes = self.pop_n()?;
                 visitor.visit_u32(u32::from_be_bytes(bytes))
             }
@@ -442,57 +1480,149 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         }
     }
 
-    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        if self.config.deny_any {
+            return Err(Error::AnyDenied);
+        }
+        self.skip_value()?;
+        visitor.visit_unit()
     }
 }
 
-struct SeqDeserializer<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct SeqDeserializer<'a, 'de: 'a, P> {
+    de: &'a mut Deserializer<'de, P>,
+    // `None` for an unsized sequence or map until its end marker is read
     remaining: Option<usize>,
+    parent_crc: Option<Crc32>,
+    // bytes of the previous key of a map, only kept with `canonical`
+    previous_key: Option<&'de [u8]>,
+    // fields of a struct written as a map with `hashed_field_names`, matched with the `U32` keys
+    hashed_fields: Option<&'static [&'static str]>,
+    // fields to decode with `DeserializerConfig::projection`, the entries of the other fields are skipped
+    projection: Option<Projection>,
 }
 
-impl<'a, 'de> SeqDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Result<Self> {
+impl<'a, 'de, P: ProgressCallback> SeqDeserializer<'a, 'de, P> {
+    fn new(de: &'a mut Deserializer<'de, P>) -> Result<Self> {
         let len = de.pop_usize()?;
         Ok(Self::new_with_len(de, len))
     }
 
-    fn new_with_len(de: &'a mut Deserializer<'de>, len: usize) -> Self {
+    fn new_with_len(de: &'a mut Deserializer<'de, P>, len: usize) -> Self {
         Self {
+            parent_crc: de.open_container(),
             de,
             remaining: Some(len),
+            previous_key: None,
+            hashed_fields: None,
+            projection: None,
         }
     }
 
-    fn new_unsized(de: &'a mut Deserializer<'de>) -> Self {
+    fn new_unsized(de: &'a mut Deserializer<'de, P>) -> Self {
+        de.unsized_depth += 1;
         Self {
+            parent_crc: de.open_container(),
             de,
             remaining: None,
+            previous_key: None,
+            hashed_fields: None,
+            projection: None,
         }
     }
-}
 
-impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
-    type Error = Error;
+    fn visit_seq<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.enter()?;
+        let value = visitor.visit_seq(&mut self);
+        self.de.leave();
+        let value = value?;
+        self.end(1)?;
+        Ok(value)
+    }
 
-    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    fn visit_map<V>(mut self, visitor: V) -> Result<V::Value>
     where
-        T: de::DeserializeSeed<'de>,
+        V: Visitor<'de>,
     {
+        self.de.enter()?;
+        let value = visitor.visit_map(&mut self);
+        self.de.leave();
+        let value = value?;
+        self.end(2)?;
+        Ok(value)
+    }
+
+    /// Check if an element is left and count it, consuming the end marker of an unsized sequence.
+    fn next_element(&mut self) -> Result<bool> {
         if let Some(remaining) = self.remaining.as_mut() {
             if *remaining == 0 {
-                return Ok(None);
+                return Ok(false);
             }
             *remaining -= 1;
-        } else if let Tag::UnsizedSeqEnd = self.de.peek_tag()? {
+        } else if self.de.peek_tag()? == Tag::UnsizedSeqEnd {
             self.de.pop_tag()?;
-            return Ok(None);
+            self.unsized_end_read();
+            return Ok(false);
+        }
+        self.de.consume_element()?;
+        Ok(true)
+    }
+
+    /// Read the key of a field of a struct written as a map, as the index of the field.
+    fn field_index(&mut self, fields: &'static [&'static str]) -> Result<u32> {
+        if self.hashed_fields.is_some() && self.de.peek_tag()? == Tag::U32 {
+            self.de.pop_tag()?;
+            let hash = u32::from_be_bytes(self.de.pop_n()?);
+            return hashed_field_index(fields, hash);
+        }
+        self.de
+            .map_key(&mut self.previous_key, |de| u32::deserialize(de))
+    }
+
+    // the end marker is consumed, nothing is left to read or skip
+    fn unsized_end_read(&mut self) {
+        self.remaining = Some(0);
+        self.de.unsized_depth -= 1;
+    }
+
+    /// Skip the elements the visitor did not read, so the input is left at the end of the sequence.
+    fn end(mut self, values_per_element: usize) -> Result<()> {
+        match self.remaining {
+            Some(remaining) => self.de.skip_values(remaining * values_per_element)?,
+            None => {
+                self.de.skip_to_unsized_seq_end()?;
+                self.unsized_end_read();
+            }
+        }
+        self.de.close_container(self.parent_crc)
+    }
+}
+
+impl<P> Drop for SeqDeserializer<'_, '_, P> {
+    fn drop(&mut self) {
+        // left before the end marker by an error
+        if self.remaining.is_none() {
+            self.de.unsized_depth -= 1;
         }
+    }
+}
+
+impl<'de, 'a, P: ProgressCallback> SeqAccess<'de> for SeqDeserializer<'a, 'de, P> {
+    type Error = Error;
 
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if !self.next_element()? {
+            return Ok(None);
+        }
         seed.deserialize(&mut *self.de).map(Some)
     }
 
@@ -501,14 +1631,40 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for SeqDeserializer<'a, 'de> {
+impl<'de, 'a, P: ProgressCallback> MapAccess<'de> for SeqDeserializer<'a, 'de, P> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: de::DeserializeSeed<'de>,
     {
-        self.next_element_seed(seed)
+        if !self.next_element()? {
+            return Ok(None);
+        }
+        if let Some(projection) = self.projection {
+            loop {
+                let index = self.field_index(projection.fields)?;
+                if projection.keeps(index) {
+                    return seed.deserialize(FieldIndexDeserializer(index)).map(Some);
+                }
+                self.de.skip_value()?;
+                if !self.next_element()? {
+                    return Ok(None);
+                }
+            }
+        }
+        if let Some(fields) = self.hashed_fields {
+            // the keys follow the order of the fields, not the canonical order
+            if self.de.peek_tag()? == Tag::U32 {
+                self.de.pop_tag()?;
+                let hash = u32::from_be_bytes(self.de.pop_n()?);
+                let index = hashed_field_index(fields, hash)?;
+                return seed.deserialize(FieldIndexDeserializer(index)).map(Some);
+            }
+        }
+        self.de
+            .map_key(&mut self.previous_key, |de| seed.deserialize(de))
+            .map(Some)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
@@ -523,7 +1679,13 @@ impl<'de, 'a> MapAccess<'de> for SeqDeserializer<'a, 'de> {
     }
 }
 
-impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
+// Give the bytes of a variant unknown to the enum to its fallback variant holding an `UnknownVariant`.
+struct UnknownVariantDeserializer<'de> {
+    fallback: u32,
+    raw: &'de [u8],
+}
+
+impl<'de> EnumAccess<'de> for UnknownVariantDeserializer<'de> {
     type Error = Error;
     type Variant = Self;
 
@@ -531,79 +1693,335 @@ impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let val = seed.deserialize(&mut *self)?;
+        let de: de::value::U32Deserializer<Error> = self.fallback.into_deserializer();
+        let val = seed.deserialize(de)?;
         Ok((val, self))
     }
 }
 
-impl<'a, 'de> VariantAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> VariantAccess<'de> for UnknownVariantDeserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        // check_tag!(Tag::UnitVariant, self, "UnitVariant");
-        Ok(())
+        Err(de::Error::invalid_type(
+            Unexpected::UnitVariant,
+            &"a newtype variant holding an UnknownVariant",
+        ))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(BorrowedBytesDeserializer::new(self.raw))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            Unexpected::TupleVariant,
+            &"a newtype variant holding an UnknownVariant",
+        ))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            Unexpected::StructVariant,
+            &"a newtype variant holding an UnknownVariant",
+        ))
+    }
+}
+
+struct VariantDeserializer<'a, 'de: 'a, P> {
+    de: &'a mut Deserializer<'de, P>,
+    tag: Tag,
+    // given by `deserialize_any`, the payload of any variant can then be read as a newtype,
+    // so visitors that don't know the variants such as `Value` can decode it
+    any_payload: bool,
+}
+
+impl<'de, 'a, P: ProgressCallback> EnumAccess<'de> for VariantDeserializer<'a, 'de, P> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let index = u32::from_be_bytes(self.de.pop_n()?);
+        let de: de::value::U32Deserializer<Error> = index.into_deserializer();
+        let val = seed.deserialize(de)?;
+        Ok((val, self))
+    }
+}
+
+impl<'de, 'a, P: ProgressCallback> VariantAccess<'de> for VariantDeserializer<'a, 'de, P> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        // `#[serde(other)]` variants are unit variants standing for any unknown variant,
+        // so the payload of the actual variant is skipped.
+        self.de.skip_variant_payload(self.tag)
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
     where
         T: de::DeserializeSeed<'de>,
     {
-        // check_tag!(Tag::NewTypeVariant, self, "NewTypeVariant");
-        seed.deserialize(self)
+        if self.any_payload && self.tag != Tag::NewTypeVariant {
+            return seed.deserialize(VariantPayloadDeserializer {
+                de: self.de,
+                tag: self.tag,
+            });
+        }
+        check_tag!(Tag::NewTypeVariant, self.tag, "NewTypeVariant");
+        seed.deserialize(self.de)
     }
 
     fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        // check_tag!(Tag::TupleVariant, self, "TupleVariant");
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        check_tag!(Tag::TupleVariant, self.tag, "TupleVariant");
+        self.de.check_variant_len(len)?;
+        SeqDeserializer::new_with_len(self.de, len).visit_seq(visitor)
     }
 
     fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        // check_tag!(Tag::StructVariant, self, "StructVariant");
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, fields.len()))
+        if self.tag == Tag::NewTypeVariant {
+            // written by `SerializerConfig::sparse_structs` or `hashed_field_names`
+            return self.de.parse_struct_map(fields, None, visitor);
+        }
+        check_tag!(Tag::StructVariant, self.tag, "StructVariant");
+        let encoded_len = self.de.pop_elements_len()?;
+        self.de.check_fields_len(fields.len(), encoded_len)?;
+        SeqDeserializer::new_with_len(self.de, encoded_len).visit_seq(visitor)
     }
 }
 
-struct StructDeserializer<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+/// Payload of a unit, tuple or struct variant given to `deserialize_any`, as a unit, a sequence or a struct,
+/// like `Value::parse` decodes it.
+struct VariantPayloadDeserializer<'a, 'de: 'a, P> {
+    de: &'a mut Deserializer<'de, P>,
+    tag: Tag,
+}
+
+impl<'de, 'a, P: ProgressCallback> de::Deserializer<'de>
+    for VariantPayloadDeserializer<'a, 'de, P>
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.tag {
+            Tag::TupleVariant => {
+                let len = self.de.pop_elements_len()?;
+                SeqDeserializer::new_with_len(self.de, len).visit_seq(visitor)
+            }
+            Tag::StructVariant if self.de.config.structs_as_sequences => {
+                let len = self.de.pop_elements_len()?;
+                SeqDeserializer::new_with_len(self.de, len).visit_seq(visitor)
+            }
+            Tag::StructVariant => StructDeserializer::new(self.de)?.visit_map(visitor),
+            _ => visitor.visit_unit(),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Narrowest tag of the same signedness holding the integer at the start of `payload`,
+/// `None` if the tag is not an integer or the payload is too short.
+fn narrowest_integer_tag(tag: Tag, payload: &[u8]) -> Option<Tag> {
+    let signed = match tag {
+        Tag::I8 | Tag::I16 | Tag::I32 | Tag::I64 | Tag::I128 => true,
+        Tag::U8 | Tag::U16 | Tag::U32 | Tag::U64 | Tag::U128 => false,
+        _ => return None,
+    };
+    let PayloadKind::Fixed(len) = tag.payload_kind() else {
+        return None;
+    };
+    let bytes = payload.get(..len)?;
+    // sign extended to 16 bytes
+    let fill = if signed && bytes[0] & 0x80 != 0 {
+        0xFF
+    } else {
+        0
+    };
+    let mut buff = [fill; 16];
+    buff[16 - len..].copy_from_slice(bytes);
+    let narrowest = if signed {
+        let value = i128::from_be_bytes(buff);
+        if i8::try_from(value).is_ok() {
+            Tag::I8
+        } else if i16::try_from(value).is_ok() {
+            Tag::I16
+        } else if i32::try_from(value).is_ok() {
+            Tag::I32
+        } else if i64::try_from(value).is_ok() {
+            Tag::I64
+        } else {
+            Tag::I128
+        }
+    } else {
+        let value = u128::from_be_bytes(buff);
+        if u8::try_from(value).is_ok() {
+            Tag::U8
+        } else if u16::try_from(value).is_ok() {
+            Tag::U16
+        } else if u32::try_from(value).is_ok() {
+            Tag::U32
+        } else if u64::try_from(value).is_ok() {
+            Tag::U64
+        } else {
+            Tag::U128
+        }
+    };
+    Some(narrowest)
+}
+
+/// Index of the field with the `field_hash` of its name equal to `hash`,
+/// or the number of fields for an unknown field.
+fn hashed_field_index(fields: &'static [&'static str], hash: u32) -> Result<u32> {
+    let mut matching = (0..)
+        .zip(fields)
+        .filter(|(_, name)| field_hash(name) == hash);
+    match (matching.next(), matching.next()) {
+        (Some((_, first)), Some((_, second))) => Err(Error::FieldHashCollision { first, second }),
+        (Some((index, _)), None) => Ok(index),
+        _ => Ok(fields.len() as u32),
+    }
+}
+
+/// Index of a struct field, given as a `u32` to identifiers like the index of an enum variant.
+///
+/// `deserialize_any` still gives it as a `u64`, as the content buffered by serde for untagged enums
+/// and flattened fields only accepts `u8` and `u64` identifiers.
+struct FieldIndexDeserializer(u32);
+
+impl<'de> de::Deserializer<'de> for FieldIndexDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(self.0.into())
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.0)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum ignored_any
+    }
+}
+
+/// Fields of the struct given to `DeserializerConfig::projection`.
+#[derive(Clone, Copy)]
+struct Projection {
+    fields: &'static [&'static str],
+    projected: &'static [&'static str],
+}
+
+impl Projection {
+    fn keeps(self, index: u32) -> bool {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.fields.get(index))
+            .is_some_and(|field| self.projected.contains(field))
+    }
+}
+
+struct StructDeserializer<'a, 'de: 'a, P> {
+    de: &'a mut Deserializer<'de, P>,
     remaining: usize,
-    current_index: u64,
+    current_index: u32,
+    parent_crc: Option<Crc32>,
+    // with `DeserializerConfig::projection`, the other fields are skipped
+    projection: Option<Projection>,
 }
 
-impl<'a, 'de> StructDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Result<Self> {
-        let [len] = de.pop_n()?;
-        Ok(Self::new_with_len(de, len.into()))
+impl<'a, 'de, P: ProgressCallback> StructDeserializer<'a, 'de, P> {
+    fn new(de: &'a mut Deserializer<'de, P>) -> Result<Self> {
+        let len = de.pop_elements_len()?;
+        Ok(Self::new_with_len(de, len))
     }
 
-    fn new_with_len(de: &'a mut Deserializer<'de>, len: usize) -> Self {
+    fn new_with_len(de: &'a mut Deserializer<'de, P>, len: usize) -> Self {
         Self {
+            parent_crc: de.open_container(),
             de,
             remaining: len,
             current_index: 0,
+            projection: None,
         }
     }
+
+    fn visit_map<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.enter()?;
+        let value = visitor.visit_map(&mut self);
+        self.de.leave();
+        let value = value?;
+        // skip the fields the visitor did not read
+        self.de.skip_values(self.remaining)?;
+        self.de.close_container(self.parent_crc)?;
+        Ok(value)
+    }
 }
 
-impl<'de, 'a> MapAccess<'de> for StructDeserializer<'a, 'de> {
+impl<'de, 'a, P: ProgressCallback> MapAccess<'de> for StructDeserializer<'a, 'de, P> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: de::DeserializeSeed<'de>,
     {
-        if self.remaining == 0 {
-            return Ok(None);
+        loop {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            let index = self.current_index;
+            self.remaining -= 1;
+            self.current_index += 1;
+            match self.projection {
+                Some(projection) if !projection.keeps(index) => self.de.skip_value()?,
+                _ => break,
+            }
         }
 
-        let de = self.current_index.into_deserializer();
-        self.remaining -= 1;
-        self.current_index += 1;
+        self.de.consume_element()?;
+        let de = FieldIndexDeserializer(self.current_index - 1);
 
         seed.deserialize(de).map(Some)
     }
@@ -612,7 +2030,11 @@ impl<'de, 'a> MapAccess<'de> for StructDeserializer<'a, 'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
+        let index = (self.current_index - 1) as usize;
+        self.de.record(self.de.offset(), TraceStep::Field(index));
+        let tag = self.de.peek_tag().ok();
         seed.deserialize(&mut *self.de)
+            .map_err(|err| err.in_field(index, tag))
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -0,0 +1,257 @@
+//! Pull parser giving the values of the `any` format as a flat stream of events, without building them.
+
+use serde::Deserialize;
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use super::value::{Number, Value};
+use super::{Deserializer, PayloadKind, Tag, TagParsingError};
+use crate::error::{Error, Result};
+
+/// Step of the traversal of a value by `Events`.
+///
+/// The containers give their elements between a start and an end event, the entries of maps as the events
+/// of the key followed by the events of the value. `Some` and `Variant` are followed by the events of their content,
+/// and newtype structs are transparent, only their content is given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'de> {
+    /// Unit and unit structs.
+    Unit,
+    Bool(bool),
+    Number(Number),
+    Char(char),
+    Str(&'de str),
+    Bytes(&'de [u8]),
+    /// Value of an `Extension`, with its tag and its payload.
+    Extension(u8, &'de [u8]),
+    None,
+    /// Start of the content of an `Option`.
+    Some,
+    /// Start of a sequence, tuple or tuple struct, with the number of elements if it is known.
+    StartSeq(Option<usize>),
+    EndSeq,
+    /// Start of a map, with the number of entries if it is known.
+    StartMap(Option<usize>),
+    EndMap,
+    /// Start of a struct, with its number of fields, given in order as they are not named in the `any` format.
+    StartStruct(usize),
+    EndStruct,
+    /// Start of an enum variant with its index, followed by its content: `Unit` for unit variants,
+    /// the value of newtype variants, a sequence for tuple variants and a struct for struct variants.
+    Variant(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Seq,
+    Map,
+    Struct,
+}
+
+// Container being walked, with the number of values left to read, `None` until the end marker for unsized ones.
+#[derive(Debug)]
+struct Frame {
+    container: Container,
+    remaining: Option<usize>,
+}
+
+/// Iterator over the events of a single value of the `any` format, borrowing the strings and bytes from the input.
+///
+/// Only a frame per open container is kept, so walking a large document costs no allocation per value.
+/// The iteration stops at the first error, and fails with `Error::TrailingBytes` if bytes are left after the value.
+///
+/// ```
+/// use serde_bin::any::{self, Event, Events};
+/// use serde_bin::any::value::Number;
+///
+/// let bytes = any::to_bytes(&(1u8, "a")).unwrap();
+/// let events: Vec<Event> = Events::new(&bytes).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(
+///     events,
+///     [Event::StartSeq(Some(2)), Event::Number(Number::U8(1)), Event::Str("a"), Event::EndSeq]
+/// );
+/// ```
+pub struct Events<'de> {
+    de: Deserializer<'de>,
+    stack: Vec<Frame>,
+    // start of the content of a unit, tuple or struct variant, given after the `Variant` event
+    pending: Option<Event<'de>>,
+    done: bool,
+}
+
+impl<'de> Events<'de> {
+    pub fn new(input: &'de [u8]) -> Self {
+        Events {
+            de: Deserializer::new(input),
+            stack: Vec::new(),
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Number of containers started and not ended yet.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Input not consumed yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.de.remaining()
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event<'de>>> {
+        if let Some(event) = self.pending.take() {
+            return self.emit(event).map(Some);
+        }
+        match self.stack.last() {
+            Some(frame) => {
+                let at_end = match frame.remaining {
+                    Some(remaining) => remaining == 0,
+                    None => match self.de.peek_tag() {
+                        Ok(tag) => tag == Tag::UnsizedSeqEnd,
+                        Err(Error::Eof) => return Err(Error::NeedMoreData),
+                        Err(err) => return Err(err),
+                    },
+                };
+                if at_end {
+                    return self.end_container().map(Some);
+                }
+            }
+            None if self.done => {
+                self.de.end()?;
+                return Ok(None);
+            }
+            None => {}
+        }
+
+        let mut tag = self.de.peek_tag()?;
+        while tag == Tag::NewTypeStruct {
+            self.de.pop_tag()?;
+            tag = self.de.peek_tag()?;
+        }
+        if !matches!(
+            tag.payload_kind(),
+            PayloadKind::TaggedChildren { .. } | PayloadKind::EndMarker
+        ) {
+            let event = self.leaf()?;
+            return self.emit(event).map(Some);
+        }
+        self.de.pop_tag()?;
+        let event = match tag {
+            Tag::Some => Event::Some,
+            Tag::NewTypeVariant => Event::Variant(u32::from_be_bytes(self.de.pop_n()?)),
+            Tag::TupleVariant | Tag::StructVariant => {
+                let index = u32::from_be_bytes(self.de.pop_n()?);
+                let len = self.de.pop_elements_len()?;
+                self.pending = Some(match tag {
+                    Tag::TupleVariant => Event::StartSeq(Some(len)),
+                    _ => Event::StartStruct(len),
+                });
+                Event::Variant(index)
+            }
+            Tag::Seq => Event::StartSeq(Some(self.de.pop_usize()?)),
+            Tag::Tuple | Tag::TupleStruct => Event::StartSeq(Some(self.de.pop_elements_len()?)),
+            Tag::UnsizedSeq => Event::StartSeq(None),
+            Tag::Map => Event::StartMap(Some(self.de.pop_usize()?)),
+            Tag::UnsizedMap => Event::StartMap(None),
+            Tag::Struct => Event::StartStruct(self.de.pop_elements_len()?),
+            _ => {
+                return Err(
+                    TagParsingError::unexpected("Any tag other than end of sequence", tag).into(),
+                )
+            }
+        };
+        self.emit(event).map(Some)
+    }
+
+    // Decode a value without children, the unit variants are given as their index followed by a unit.
+    fn leaf(&mut self) -> Result<Event<'de>> {
+        if self.de.peek_tag()? == Tag::UnitVariant {
+            self.de.pop_tag()?;
+            self.pending = Some(Event::Unit);
+            return Ok(Event::Variant(u32::from_be_bytes(self.de.pop_n()?)));
+        }
+        Ok(match Value::deserialize(&mut self.de)? {
+            Value::Unit => Event::Unit,
+            Value::Bool(v) => Event::Bool(v),
+            Value::Number(v) => Event::Number(v),
+            Value::Char(v) => Event::Char(v),
+            Value::String(v) => Event::Str(v),
+            Value::Bytes(v) => Event::Bytes(v),
+            Value::Extension(tag, payload) => Event::Extension(tag, payload),
+            Value::Option(None) => Event::None,
+            value => unreachable!("not a leaf borrowed from the input: {:?}", value),
+        })
+    }
+
+    /// Update the open containers for the event before giving it.
+    fn emit(&mut self, event: Event<'de>) -> Result<Event<'de>> {
+        let frame = match event {
+            Event::StartSeq(len) => Frame {
+                container: Container::Seq,
+                remaining: len,
+            },
+            Event::StartMap(len) => Frame {
+                container: Container::Map,
+                // a key and a value per entry
+                remaining: len
+                    .map(|len| len.checked_mul(2).ok_or(Error::InvalidSize))
+                    .transpose()?,
+            },
+            Event::StartStruct(len) => Frame {
+                container: Container::Struct,
+                remaining: Some(len),
+            },
+            // followed by their content
+            Event::Some | Event::Variant(_) => return Ok(event),
+            _ => {
+                self.value_done();
+                return Ok(event);
+            }
+        };
+        self.stack.push(frame);
+        Ok(event)
+    }
+
+    fn end_container(&mut self) -> Result<Event<'de>> {
+        let frame = self.stack.pop().unwrap();
+        if frame.remaining.is_none() {
+            self.de.pop_tag()?;
+        }
+        self.value_done();
+        Ok(match frame.container {
+            Container::Seq => Event::EndSeq,
+            Container::Map => Event::EndMap,
+            Container::Struct => Event::EndStruct,
+        })
+    }
+
+    // A value is complete, count it in its container.
+    fn value_done(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame {
+                remaining: Some(remaining),
+                ..
+            }) => *remaining -= 1,
+            Some(_) => {}
+            None => self.done = true,
+        }
+    }
+}
+
+impl<'de> Iterator for Events<'de> {
+    type Item = Result<Event<'de>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.next_event().transpose();
+        if matches!(event, Some(Err(_))) {
+            // the input can't be walked past an error
+            self.stack.clear();
+            self.pending = None;
+            self.done = true;
+            self.de = Deserializer::new(&[]);
+        }
+        event
+    }
+}
@@ -0,0 +1,216 @@
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+
+use serde::de::value::{BorrowedBytesDeserializer, BorrowedStrDeserializer};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+#[cfg(feature = "alloc")]
+use serde::Serialize;
+
+#[cfg(feature = "alloc")]
+use crate::bytes::Bytes;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Tags reserved for the extensions, an extension value is its tag followed by a `u64` length
+/// and that many bytes of payload, so the extensions unknown to a deserializer can be skipped.
+pub const EXTENSION_TAGS: RangeInclusive<u8> = 200..=255;
+
+/// Name of the newtype variant used by `Value::Extension` to ask the `any` serializer to write an extension,
+/// with the tag as the variant index.
+pub(crate) const EXTENSION_TOKEN: &str = "$serde_bin::private::Extension";
+
+/// Newtype struct encoded with its own tag in the `any` format.
+///
+/// The newtype struct is identified by the name given to `serialize_newtype_struct` and `deserialize_newtype_struct`,
+/// the name of the type for a derived implementation. Once registered with `SerializerConfig::extensions`,
+/// its content is encoded with the compact format, without the tags of its fields, and written as the payload
+/// of an extension value with the given tag. It is read back the same way when the deserializer is given the same
+/// extensions with `DeserializerConfig::extensions`. An extension registered with `Extension::with_codec`
+/// passes the compact encoding through its encoder before writing the payload, and the payload through its
+/// decoder before reading it back. Other deserializers give it to `deserialize_any` as a map
+/// with a single entry, from a private name to the tag and the payload, which `Value` decodes as a `Value::Extension`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extension {
+    tag: u8,
+    name: &'static str,
+    #[cfg(feature = "alloc")]
+    codec: Option<(ExtensionEncoder, ExtensionDecoder)>,
+}
+
+/// Writes to `payload` the payload of an extension from the compact encoding of its content.
+#[cfg(feature = "alloc")]
+pub type ExtensionEncoder = fn(content: &[u8], payload: &mut Vec<u8>) -> crate::Result<()>;
+
+/// Writes to `content` the compact encoding of the content of an extension from its payload.
+#[cfg(feature = "alloc")]
+pub type ExtensionDecoder = fn(payload: &[u8], content: &mut Vec<u8>) -> crate::Result<()>;
+
+impl Extension {
+    /// Panics if `tag` is not in `EXTENSION_TAGS`, at compile time for a constant.
+    pub const fn new(tag: u8, name: &'static str) -> Self {
+        assert!(
+            tag >= *EXTENSION_TAGS.start(),
+            "extension tags start at 200"
+        );
+        Extension {
+            tag,
+            name,
+            #[cfg(feature = "alloc")]
+            codec: None,
+        }
+    }
+
+    /// Same as `new`, with the payload produced by `encode` and read back by `decode`
+    /// instead of the compact encoding of the content.
+    ///
+    /// Values borrowing from the input can't be read from a decoded payload.
+    #[cfg(feature = "alloc")]
+    pub const fn with_codec(
+        tag: u8,
+        name: &'static str,
+        encode: ExtensionEncoder,
+        decode: ExtensionDecoder,
+    ) -> Self {
+        let mut extension = Extension::new(tag, name);
+        extension.codec = Some((encode, decode));
+        extension
+    }
+
+    pub const fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) const fn codec(&self) -> Option<(ExtensionEncoder, ExtensionDecoder)> {
+        self.codec
+    }
+}
+
+pub(crate) fn find_by_name<'a>(extensions: &'a [Extension], name: &str) -> Option<&'a Extension> {
+    extensions.iter().find(|extension| extension.name == name)
+}
+
+/// Extension value given to serializers by `Value`.
+#[cfg(feature = "alloc")]
+pub(crate) struct ExtensionValue<'a> {
+    pub(crate) tag: u8,
+    pub(crate) payload: &'a [u8],
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Serialize for ExtensionValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_newtype_variant(
+            EXTENSION_TOKEN,
+            self.tag.into(),
+            EXTENSION_TOKEN,
+            &Bytes(self.payload),
+        )
+    }
+}
+
+/// Extension value given to `deserialize_any` as a map with a single entry,
+/// from `EXTENSION_TOKEN` to a sequence of the tag and the payload.
+pub(crate) struct ExtensionAccess<'de, E> {
+    payload: Option<ExtensionPayload<'de, E>>,
+}
+
+impl<'de, E> ExtensionAccess<'de, E> {
+    pub(crate) fn new(tag: u8, payload: &'de [u8]) -> Self {
+        ExtensionAccess {
+            payload: Some(ExtensionPayload {
+                tag,
+                payload,
+                next: 0,
+                error: PhantomData,
+            }),
+        }
+    }
+}
+
+impl<'de, E: de::Error> MapAccess<'de> for ExtensionAccess<'de, E> {
+    type Error = E;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, E>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.payload.is_none() {
+            return Ok(None);
+        }
+        seed.deserialize(BorrowedStrDeserializer::new(EXTENSION_TOKEN))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, E>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.payload.take() {
+            Some(payload) => seed.deserialize(payload),
+            None => Err(de::Error::custom("value of an extension already given")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.payload.iter().len())
+    }
+}
+
+struct ExtensionPayload<'de, E> {
+    tag: u8,
+    payload: &'de [u8],
+    // index of the next element, the tag then the payload
+    next: usize,
+    error: PhantomData<E>,
+}
+
+impl<'de, E: de::Error> SeqAccess<'de> for ExtensionPayload<'de, E> {
+    type Error = E;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, E>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.next += 1;
+        match self.next {
+            1 => seed.deserialize(self.tag.into_deserializer()).map(Some),
+            2 => seed
+                .deserialize(BorrowedBytesDeserializer::new(self.payload))
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(2usize.saturating_sub(self.next))
+    }
+}
+
+impl<'de, E: de::Error> de::Deserializer<'de> for ExtensionPayload<'de, E> {
+    type Error = E;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
@@ -1,19 +1,48 @@
 use core::fmt::Display;
 
-use crate::Error;
+use crate::{Error, Write};
 
 mod de;
+#[cfg(feature = "alloc")]
+mod events;
+mod extension;
+mod raw;
+mod resync;
 mod ser;
+mod stats;
+mod tag_map;
 
 #[cfg(feature = "alloc")]
 pub mod value;
 
-pub use de::{from_bytes, Deserializer};
 #[cfg(feature = "alloc")]
-pub use ser::to_bytes;
-#[cfg(feature = "std")]
-pub use ser::to_writer;
-pub use ser::{get_serialized_size, to_buff, Serializer};
+pub use de::from_bytes_batch;
+#[cfg(feature = "alloc")]
+pub(crate) use de::is_single_value;
+pub use de::{
+    for_each_element, from_bytes, from_bytes_canonical, from_bytes_partial, from_bytes_projected,
+    from_bytes_seed, from_bytes_seed_with_config, from_bytes_with_config, from_bytes_with_limit,
+    Deserializer,
+};
+#[cfg(feature = "alloc")]
+pub use events::{Event, Events};
+pub use extension::{Extension, EXTENSION_TAGS};
+#[cfg(feature = "alloc")]
+pub use extension::{ExtensionDecoder, ExtensionEncoder};
+pub use raw::{RawBytes, RawValue};
+pub use resync::resync;
+pub use ser::{
+    get_serialized_size, get_serialized_size_and_crc, to_array, to_array_ref, to_buff, to_writer,
+    to_writer_complete, Serializer, Writer,
+};
+#[cfg(feature = "alloc")]
+pub use ser::{to_bytes, to_bytes_batch, to_bytes_canonical, to_hybrid, to_writer_batch};
+#[cfg(feature = "erased")]
+pub use ser::{to_bytes_erased, to_writer_erased};
+#[cfg(feature = "pool")]
+pub use ser::{to_local_pooled_bytes, to_pooled_bytes};
+pub use stats::{tag_stats, TagCount, TagStats};
+pub use tag_map::TagMap;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 #[repr(u8)]
@@ -56,9 +85,184 @@ pub enum Tag {
     StructVariant = 35,
     I128 = 36,
     U128 = 37,
+    /// Any byte of `EXTENSION_TAGS`, the tag of an `Extension`, so this is the first of them.
+    ///
+    /// The actual byte is given by `Kind::Extension`.
+    Extension = 200,
+}
+
+/// What follows a tag in the encoded data.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PayloadKind {
+    /// A fixed number of bytes, 0 for the tags that are the whole value.
+    Fixed(usize),
+    /// A `u64` length, followed by that many bytes.
+    LengthPrefixed,
+    /// Bytes until the `UNSIZED_STRING_END_MARKER`, the marker included.
+    NullTerminated,
+    /// `header` bytes (the variant index for variants) followed by child values, each starting with its own tag.
+    TaggedChildren { header: usize, count: ChildCount },
+    /// End of an unsized sequence or map, nothing follows.
+    EndMarker,
+}
+
+/// Number of child values of a tag with a `PayloadKind::TaggedChildren` payload.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ChildCount {
+    Fixed(usize),
+    /// A `u8` count of children follows the header.
+    U8Len,
+    /// A `u64` count of children follows the header.
+    U64Len,
+    /// A `u64` count of key-value pairs follows the header, so twice as many children.
+    U64PairsLen,
+    /// Children until a `Tag::UnsizedSeqEnd`.
+    UntilEndMarker,
+}
+
+/// Read the `u64` length of a `PayloadKind::LengthPrefixed` payload or a `ChildCount::U64Len` count at the start
+/// of the input, returning it with the rest of the input, for the walks over the tags outside of the deserializer.
+fn read_len(input: &[u8]) -> crate::Result<(usize, &[u8])> {
+    let bytes = input.get(..8).ok_or(Error::Eof)?;
+    let len = u64::from_be_bytes(bytes.try_into().unwrap());
+    Ok((crate::de::narrow_len(len)?, &input[8..]))
+}
+
+/// Coarse type of an encoded value, returned by `peek_type`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Kind {
+    Integer,
+    Float,
+    Bool,
+    Char,
+    String,
+    Bytes,
+    /// Sequences of known or unknown length, tuples and tuple structs.
+    Seq,
+    Map,
+    Struct,
+    /// Any kind of enum variant, with its index.
+    EnumVariant(u32),
+    /// An `Extension`, with its tag.
+    Extension(u8),
+    Option,
+    /// Unit and unit structs.
+    Unit,
+}
+
+/// 32-bit FNV-1a hash of a field name, the key of the field with `SerializerConfig::hashed_field_names`.
+pub const fn field_hash(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut hash = 0x811c_9dc5u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    hash
+}
+
+/// Return the tag of the value at the start of `input`.
+pub fn peek_tag(input: &[u8]) -> crate::Result<Tag> {
+    Tag::read(input).map(|(tag, _)| tag)
+}
+
+/// Return the type of the value at the start of `input` without decoding it,
+/// only its tag is read, and the variant index for enums.
+///
+/// Newtype structs are transparent, the type of their content is returned.
+pub fn peek_type(mut input: &[u8]) -> crate::Result<Kind> {
+    loop {
+        let (tag, rest) = Tag::read(input)?;
+        let kind = match tag {
+            Tag::None | Tag::Some => Kind::Option,
+            Tag::BoolFalse | Tag::BoolTrue => Kind::Bool,
+            Tag::I8 | Tag::I16 | Tag::I32 | Tag::I64 | Tag::I128 => Kind::Integer,
+            Tag::U8 | Tag::U16 | Tag::U32 | Tag::U64 | Tag::U128 => Kind::Integer,
+            Tag::F32 | Tag::F64 => Kind::Float,
+            Tag::Char1 | Tag::Char2 | Tag::Char3 | Tag::Char4 => Kind::Char,
+            Tag::String | Tag::NullTerminatedString => Kind::String,
+            Tag::ByteArray => Kind::Bytes,
+            Tag::Unit | Tag::UnitStruct => Kind::Unit,
+            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
+                let index = rest.get(..4).ok_or(Error::Eof)?;
+                Kind::EnumVariant(u32::from_be_bytes(index.try_into().unwrap()))
+            }
+            Tag::NewTypeStruct => {
+                input = rest;
+                continue;
+            }
+            Tag::Seq | Tag::UnsizedSeq | Tag::Tuple | Tag::TupleStruct => Kind::Seq,
+            Tag::Map | Tag::UnsizedMap => Kind::Map,
+            Tag::Struct => Kind::Struct,
+            Tag::Extension => Kind::Extension(input[0]),
+            Tag::UnsizedSeqEnd => {
+                return Err(
+                    TagParsingError::unexpected("Any tag other than end of sequence", tag).into(),
+                )
+            }
+        };
+        return Ok(kind);
+    }
 }
 
 impl Tag {
+    /// Parse the tag at the start of `input`, and return it with the rest of the input.
+    pub fn read(input: &[u8]) -> crate::Result<(Tag, &[u8])> {
+        let (&byte, rest) = input.split_first().ok_or(Error::Eof)?;
+        Ok((byte.try_into()?, rest))
+    }
+
+    pub fn write<W: Write>(self, writer: &mut W) -> crate::Result<usize, W::Error> {
+        writer.write_byte(self.into()).map_err(Into::into)
+    }
+
+    pub fn payload_kind(self) -> PayloadKind {
+        match self {
+            Tag::None | Tag::BoolFalse | Tag::BoolTrue | Tag::Unit | Tag::UnitStruct => {
+                PayloadKind::Fixed(0)
+            }
+            Tag::I8 | Tag::U8 | Tag::Char1 => PayloadKind::Fixed(1),
+            Tag::I16 | Tag::U16 | Tag::Char2 => PayloadKind::Fixed(2),
+            Tag::Char3 => PayloadKind::Fixed(3),
+            Tag::I32 | Tag::U32 | Tag::F32 | Tag::Char4 | Tag::UnitVariant => PayloadKind::Fixed(4),
+            Tag::I64 | Tag::U64 | Tag::F64 => PayloadKind::Fixed(8),
+            Tag::I128 | Tag::U128 => PayloadKind::Fixed(16),
+            Tag::String | Tag::ByteArray | Tag::Extension => PayloadKind::LengthPrefixed,
+            Tag::NullTerminatedString => PayloadKind::NullTerminated,
+            Tag::Some | Tag::NewTypeStruct => PayloadKind::TaggedChildren {
+                header: 0,
+                count: ChildCount::Fixed(1),
+            },
+            Tag::NewTypeVariant => PayloadKind::TaggedChildren {
+                header: 4,
+                count: ChildCount::Fixed(1),
+            },
+            Tag::TupleVariant | Tag::StructVariant => PayloadKind::TaggedChildren {
+                header: 4,
+                count: ChildCount::U8Len,
+            },
+            Tag::Tuple | Tag::TupleStruct | Tag::Struct => PayloadKind::TaggedChildren {
+                header: 0,
+                count: ChildCount::U8Len,
+            },
+            Tag::Seq => PayloadKind::TaggedChildren {
+                header: 0,
+                count: ChildCount::U64Len,
+            },
+            Tag::Map => PayloadKind::TaggedChildren {
+                header: 0,
+                count: ChildCount::U64PairsLen,
+            },
+            Tag::UnsizedSeq | Tag::UnsizedMap => PayloadKind::TaggedChildren {
+                header: 0,
+                count: ChildCount::UntilEndMarker,
+            },
+            Tag::UnsizedSeqEnd => PayloadKind::EndMarker,
+        }
+    }
+
     pub fn encode_char(c: char, buff: &mut [u8]) -> (Self, &[u8]) {
         let bytes = c.encode_utf8(buff).as_bytes();
         let tag = match bytes.len() {
@@ -77,6 +281,8 @@ pub enum TagParsingError {
     #[cfg(no_integer128)]
     Integer128,
     InvalidTag(u8),
+    /// Byte unknown to the `TagMap` given to `DeserializerConfig::tag_map`, the counterpart of `InvalidTag`.
+    UnmappedTag(u8),
     UnexpectedTag {
         expected: &'static str,
         got: Tag,
@@ -97,7 +303,11 @@ impl Display for TagParsingError {
                 f.write_str("This platform doesn't support 128 bits integers.")
             }
             TagParsingError::InvalidTag(tag) => f.write_fmt(format_args!(
-                "Invalid tag for data type: expected byte beetween 0 and 31 included, got {}",
+                "Invalid tag for data type: expected byte beetween 0 and 37 or 200 and 255 included, got {}",
+                tag
+            )),
+            TagParsingError::UnmappedTag(tag) => f.write_fmt(format_args!(
+                "Invalid tag for data type: {} is not in the tag map of the deserializer",
                 tag
             )),
             TagParsingError::UnexpectedTag { expected, got } => {
@@ -154,6 +364,7 @@ impl TryFrom<u8> for Tag {
             37 => Ok(Tag::U128),
             #[cfg(no_integer128)]
             37 | 36 => Err(TagParsingError::Integer128),
+            200..=255 => Ok(Tag::Extension),
             tag => Err(TagParsingError::InvalidTag(tag)),
         }
     }
@@ -174,10 +385,20 @@ impl<We> From<TagParsingError> for Error<We> {
 #[cfg(all(test, feature = "test-utils"))]
 mod tests {
 
-    use crate::any::value::Value;
+    use crate::any::value::{self, DebugLimits, Number, OwnedPathSegment, PathSegment, Value};
+    use crate::test_fixtures::{
+        assert_borrow_own_symmetry, Empty, HeadAndRest, Tagged, WithPhantom, EMPTY_COLLECTIONS,
+    };
+    use crate::{
+        ByteBuf, Bytes, DeserializerConfig, NumberCoercion, SerializerConfig, StrCoercion,
+        DEFAULT_MAX_DEPTH,
+    };
+    use core::ops::ControlFlow;
 
     use super::*;
+    use core::marker::PhantomData;
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
     struct TestStruct {
@@ -193,6 +414,375 @@ mod tests {
         Struct { a: f64, b: Vec<u16> },
     }
 
+    #[test]
+    fn test_peek_type() {
+        use Kind::*;
+        let table = [
+            (Tag::None, Option),
+            (Tag::Some, Option),
+            (Tag::BoolFalse, Bool),
+            (Tag::BoolTrue, Bool),
+            (Tag::I8, Integer),
+            (Tag::I16, Integer),
+            (Tag::I32, Integer),
+            (Tag::I64, Integer),
+            (Tag::U8, Integer),
+            (Tag::U16, Integer),
+            (Tag::U32, Integer),
+            (Tag::U64, Integer),
+            (Tag::F32, Float),
+            (Tag::F64, Float),
+            (Tag::Char1, Char),
+            (Tag::Char2, Char),
+            (Tag::Char3, Char),
+            (Tag::Char4, Char),
+            (Tag::String, String),
+            (Tag::NullTerminatedString, String),
+            (Tag::ByteArray, Bytes),
+            (Tag::Unit, Unit),
+            (Tag::UnitStruct, Unit),
+            (Tag::UnitVariant, EnumVariant(0x01020304)),
+            // the type of the content, a `u8`
+            (Tag::NewTypeStruct, Integer),
+            (Tag::NewTypeVariant, EnumVariant(0x01020304)),
+            (Tag::Seq, Seq),
+            (Tag::UnsizedSeq, Seq),
+            (Tag::Tuple, Seq),
+            (Tag::TupleStruct, Seq),
+            (Tag::TupleVariant, EnumVariant(0x01020304)),
+            (Tag::Map, Map),
+            (Tag::UnsizedMap, Map),
+            (Tag::Struct, Struct),
+            (Tag::StructVariant, EnumVariant(0x01020304)),
+            (Tag::I128, Integer),
+            (Tag::U128, Integer),
+            (Tag::Extension, Kind::Extension(200)),
+        ];
+        // every tag but `UnsizedSeqEnd`
+        assert_eq!(table.len(), 38);
+
+        for (tag, kind) in table {
+            // a variant index, or a `u8` tag for newtype structs, the rest is never read
+            let bytes = [tag.into(), 1, 2, 3, 4];
+            let bytes = match tag {
+                Tag::NewTypeStruct => [tag.into(), Tag::U8.into(), 0, 0, 0],
+                _ => bytes,
+            };
+            assert_eq!(peek_tag(&bytes), Ok(tag));
+            assert_eq!(peek_type(&bytes), Ok(kind), "{:?}", tag);
+        }
+
+        assert!(peek_type(&[Tag::UnsizedSeqEnd.into()]).is_err());
+        assert_eq!(
+            peek_tag(&[Tag::UnsizedSeqEnd.into()]),
+            Ok(Tag::UnsizedSeqEnd)
+        );
+        assert_eq!(
+            peek_type(&[38]),
+            Err(Error::TagParsingError(TagParsingError::InvalidTag(38)))
+        );
+        assert_eq!(peek_type(&[]), Err(Error::Eof));
+        assert_eq!(peek_type(&[Tag::UnitVariant.into(), 0, 0]), Err(Error::Eof));
+        assert_eq!(peek_type(&[Tag::NewTypeStruct.into()]), Err(Error::Eof));
+
+        let bytes = to_bytes(&(TestStruct { a: 1, b: "".into() }, TestEnum::Unit)).unwrap();
+        assert_eq!(peek_type(&bytes), Ok(Seq));
+        assert_eq!(peek_type(&bytes[2..]), Ok(Struct));
+    }
+
+    #[test]
+    fn test_resync() {
+        assert_eq!(resync(&[]), None);
+        assert_eq!(resync(&[38, 0xFF, Tag::UnsizedSeqEnd.into()]), None);
+        assert_eq!(resync(&[0xFF, Tag::U8.into(), 5]), Some(1));
+        // not enough bytes for the payload
+        assert_eq!(resync(&[Tag::U64.into(), 1, 2]), Some(1));
+        assert_eq!(resync(&[Tag::U16.into(), 0xFF]), None);
+        assert_eq!(resync(&[Tag::Char2.into(), 0xFF, 0xFF]), None);
+        // a length longer than the input
+        let mut bytes = vec![Tag::Seq.into()];
+        bytes.extend(100u64.to_be_bytes());
+        bytes.push(Tag::Unit.into());
+        // skip the `Seq` tag, the first byte of the length is a `None` tag
+        assert_eq!(resync(&bytes), Some(1));
+        // the first child must have a valid tag
+        bytes[1..9].copy_from_slice(&1u64.to_be_bytes());
+        assert_eq!(resync(&bytes), Some(0));
+        bytes[9] = 199;
+        assert_eq!(resync(&bytes), Some(1));
+    }
+
+    #[test]
+    fn test_resync_long_strings() {
+        // null terminated strings without their end, each position is only probed over a few bytes
+        let bytes = [Tag::NullTerminatedString.into(), 0xFF].repeat(1 << 16);
+        assert_eq!(resync(&bytes), None);
+
+        // strings longer than the probe are accepted from their first bytes
+        let mut bytes = vec![0xFF, Tag::NullTerminatedString.into()];
+        bytes.extend("a".repeat(5000).as_bytes());
+        bytes.extend(crate::UNSIZED_STRING_END_MARKER);
+        assert_eq!(resync(&bytes), Some(1));
+        let content = "a".repeat(1023) + &"é".repeat(1000);
+        let bytes = to_bytes(&content).unwrap();
+        assert_eq!(resync(&bytes), Some(0));
+    }
+
+    #[test]
+    fn test_resync_stream() {
+        let records: Vec<TestStruct> = (0..5)
+            .map(|a| TestStruct {
+                a,
+                b: format!("record {}", a),
+            })
+            .collect();
+        let mut bytes = Vec::new();
+        let mut corrupted = 0..0;
+        for (i, record) in records.iter().enumerate() {
+            let start = bytes.len();
+            bytes.extend(to_bytes(record).unwrap());
+            if i == 2 {
+                // the tag of the first field
+                bytes[start + 2] = 0xFF;
+                corrupted = start..bytes.len();
+            }
+        }
+
+        let mut input = &bytes[..];
+        let mut decoded = Vec::new();
+        let mut skipped = 0;
+        while !input.is_empty() {
+            let mut deserializer = Deserializer::new(input);
+            match TestStruct::deserialize(&mut deserializer) {
+                Ok(record) => {
+                    decoded.push(record);
+                    input = deserializer.remaining();
+                }
+                Err(_) => {
+                    let offset = resync(&input[1..]).map_or(input.len(), |offset| offset + 1);
+                    skipped += offset;
+                    input = &input[offset..];
+                }
+            }
+        }
+
+        assert_eq!(skipped, corrupted.len());
+        let mut expected = records;
+        expected.remove(2);
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_tag_payload_kinds() {
+        use ChildCount as C;
+        use PayloadKind as P;
+
+        const fn children(header: usize, count: ChildCount) -> PayloadKind {
+            PayloadKind::TaggedChildren { header, count }
+        }
+
+        let expected = [
+            (Tag::None, P::Fixed(0)),
+            (Tag::Some, children(0, C::Fixed(1))),
+            (Tag::BoolFalse, P::Fixed(0)),
+            (Tag::BoolTrue, P::Fixed(0)),
+            (Tag::I8, P::Fixed(1)),
+            (Tag::I16, P::Fixed(2)),
+            (Tag::I32, P::Fixed(4)),
+            (Tag::I64, P::Fixed(8)),
+            (Tag::U8, P::Fixed(1)),
+            (Tag::U16, P::Fixed(2)),
+            (Tag::U32, P::Fixed(4)),
+            (Tag::U64, P::Fixed(8)),
+            (Tag::F32, P::Fixed(4)),
+            (Tag::F64, P::Fixed(8)),
+            (Tag::Char1, P::Fixed(1)),
+            (Tag::Char2, P::Fixed(2)),
+            (Tag::Char3, P::Fixed(3)),
+            (Tag::Char4, P::Fixed(4)),
+            (Tag::String, P::LengthPrefixed),
+            (Tag::NullTerminatedString, P::NullTerminated),
+            (Tag::ByteArray, P::LengthPrefixed),
+            (Tag::Unit, P::Fixed(0)),
+            (Tag::UnitStruct, P::Fixed(0)),
+            (Tag::UnitVariant, P::Fixed(4)),
+            (Tag::NewTypeStruct, children(0, C::Fixed(1))),
+            (Tag::NewTypeVariant, children(4, C::Fixed(1))),
+            (Tag::Seq, children(0, C::U64Len)),
+            (Tag::UnsizedSeq, children(0, C::UntilEndMarker)),
+            (Tag::UnsizedSeqEnd, P::EndMarker),
+            (Tag::Tuple, children(0, C::U8Len)),
+            (Tag::TupleStruct, children(0, C::U8Len)),
+            (Tag::TupleVariant, children(4, C::U8Len)),
+            (Tag::Map, children(0, C::U64PairsLen)),
+            (Tag::UnsizedMap, children(0, C::UntilEndMarker)),
+            (Tag::Struct, children(0, C::U8Len)),
+            (Tag::StructVariant, children(4, C::U8Len)),
+            (Tag::I128, P::Fixed(16)),
+            (Tag::U128, P::Fixed(16)),
+        ];
+
+        for (byte, (tag, kind)) in expected.into_iter().enumerate() {
+            let input = [byte as u8, 42];
+            assert_eq!(Tag::read(&input), Ok((tag, &input[1..])));
+            assert_eq!(tag.payload_kind(), kind, "{:?}", tag);
+
+            let mut written = Vec::new();
+            assert_eq!(tag.write(&mut written).unwrap(), 1);
+            assert_eq!(written, [byte as u8]);
+        }
+
+        assert_eq!(Tag::read(&[]), Err(Error::Eof));
+        assert_eq!(
+            Tag::read(&[expected.len() as u8]),
+            Err(TagParsingError::InvalidTag(expected.len() as u8).into())
+        );
+        assert_eq!(
+            Tag::read(&[199]),
+            Err(TagParsingError::InvalidTag(199).into())
+        );
+        for byte in EXTENSION_TAGS {
+            assert_eq!(Tag::read(&[byte]), Ok((Tag::Extension, &[][..])));
+        }
+        // the byte of the extension is given by `peek_type`
+        assert_eq!(u8::from(Tag::Extension), *EXTENSION_TAGS.start());
+        assert_eq!(
+            peek_type(&[255, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Ok(Kind::Extension(255))
+        );
+        assert_eq!(Tag::Extension.payload_kind(), P::LengthPrefixed);
+    }
+
+    // Length of the value at the start of `input`, using only the tags.
+    fn encoded_len(input: &[u8]) -> usize {
+        let (tag, rest) = Tag::read(input).unwrap();
+        let read_u64 = |bytes: &[u8]| u64::from_be_bytes(bytes[..8].try_into().unwrap()) as usize;
+        let payload_len = match tag.payload_kind() {
+            PayloadKind::Fixed(len) => len,
+            PayloadKind::LengthPrefixed => 8 + read_u64(rest),
+            PayloadKind::NullTerminated => {
+                let marker = crate::UNSIZED_STRING_END_MARKER;
+                rest.windows(marker.len())
+                    .position(|w| w == marker)
+                    .unwrap()
+                    + marker.len()
+            }
+            PayloadKind::TaggedChildren { header, count } => {
+                let (mut len, children) = match count {
+                    ChildCount::Fixed(count) => (header, count),
+                    ChildCount::U8Len => (header + 1, rest[header] as usize),
+                    ChildCount::U64Len => (header + 8, read_u64(&rest[header..])),
+                    ChildCount::U64PairsLen => (header + 8, 2 * read_u64(&rest[header..])),
+                    ChildCount::UntilEndMarker => {
+                        let mut len = header;
+                        while rest[len] != Tag::UnsizedSeqEnd.into() {
+                            len += encoded_len(&rest[len..]);
+                        }
+                        (len + 1, 0)
+                    }
+                };
+                for _ in 0..children {
+                    len += encoded_len(&rest[len..]);
+                }
+                len
+            }
+            PayloadKind::EndMarker => panic!("unexpected end marker"),
+        };
+        1 + payload_len
+    }
+
+    #[test]
+    fn test_tag_payload_walk() {
+        let mut map = BTreeMap::new();
+        map.insert(1u8, Some(TestEnum::Tuple(1.5, "a".into())));
+        map.insert(2, None);
+        map.insert(3, Some(TestEnum::Struct { a: 2.0, b: vec![3] }));
+        let value = (
+            map,
+            UnsizedSeq(&[1u16, 2]),
+            TestEnum::Unit,
+            TestEnum::NewType(4),
+            'é',
+            -5i128,
+            ByteBuf::from(vec![6; 3]),
+        );
+
+        let mut bytes = to_bytes(&value).unwrap();
+        bytes.push(0xFF);
+        assert_eq!(encoded_len(&bytes), bytes.len() - 1);
+    }
+
+    #[test]
+    fn test_tag_stats() {
+        let value = (
+            TestStruct {
+                a: 1,
+                b: "foo".into(),
+            },
+            UnsizedSeq(&[1u16, 2]),
+            Some(TestEnum::NewType(4)),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let stats = tag_stats(&bytes).unwrap();
+        assert_eq!(
+            stats.get(Tag::String),
+            TagCount {
+                count: 1,
+                overhead: 9,
+                payload: 3
+            }
+        );
+        assert_eq!(
+            stats.get(Tag::U16),
+            TagCount {
+                count: 2,
+                overhead: 2,
+                payload: 4
+            }
+        );
+        assert_eq!(stats.get(Tag::Map), TagCount::default());
+        assert_eq!(stats.total().total(), bytes.len());
+        assert_eq!(stats.iter().count(), 10);
+        let expected = "   count   overhead    payload      total  tag
+       1          9          3         12  String
+       1          1          8          9  U64
+       2          2          4          6  U16
+       1          1          4          5  NewTypeVariant
+       1          1          1          2  U8
+       1          2          0          2  Tuple
+       1          2          0          2  Struct
+       1          1          0          1  Some
+       1          1          0          1  UnsizedSeq
+       1          1          0          1  UnsizedSeqEnd
+      11         21         20         41  total";
+        assert_eq!(stats.to_string(), expected);
+
+        let bytes = to_bytes(&(vec![Color::Red], "a".repeat(10))).unwrap();
+        let stats = tag_stats(&bytes).unwrap();
+        assert_eq!(stats.get(Tag::UnitVariant).payload, 4);
+        assert_eq!(stats.get(Tag::Seq).overhead, 9);
+        assert_eq!(stats.total().total(), bytes.len());
+
+        assert_eq!(
+            tag_stats(&bytes[..bytes.len() - 1]),
+            Err(Error::LengthExceedsInput {
+                len: 10,
+                remaining: 9
+            })
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(tag_stats(&trailing), Err(Error::TrailingBytes(1)));
+        let mut nested = vec![Tag::Some.into(); DEFAULT_MAX_DEPTH + 1];
+        nested.push(Tag::None.into());
+        assert_eq!(tag_stats(&nested), Err(Error::DepthLimitExceeded));
+        nested.remove(0);
+        assert_eq!(
+            tag_stats(&nested).unwrap().get(Tag::Some).count,
+            DEFAULT_MAX_DEPTH
+        );
+    }
+
     #[test]
     fn test_serialize_struct() {
         const N: usize = 56;
@@ -239,6 +829,156 @@ mod tests {
         assert_eq!(t, value);
     }
 
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+    struct TestStructBorrowed<'a> {
+        a: usize,
+        b: &'a str,
+    }
+
+    #[test]
+    fn test_to_array() {
+        let value = TestStructBorrowed { a: 56, b: "Hello" };
+        let size = get_serialized_size(&value).unwrap();
+        assert_eq!(size, 25);
+
+        // exact fit
+        let (buff, len) = to_array::<25, _>(&value).unwrap();
+        assert_eq!(len, size);
+        assert_eq!(from_bytes::<TestStructBorrowed>(&buff), Ok(value));
+
+        let (buff, len) = to_array::<32, _>(&value).unwrap();
+        assert_eq!(len, size);
+        assert_eq!(buff[len..], [0; 7]);
+        assert_eq!(from_bytes::<TestStructBorrowed>(&buff[..len]), Ok(value));
+
+        assert_eq!(
+            to_array::<24, _>(&value),
+            Err(Error::WriterError(crate::EndOfBuff))
+        );
+
+        let mut buff = [0; 25];
+        assert_eq!(to_array_ref(&value, &mut buff), Ok(25));
+        assert_eq!(buff, to_array::<25, _>(&value).unwrap().0);
+    }
+
+    struct UnsizedSeq<'a, T>(&'a [T]);
+
+    impl<'a, T: Serialize> Serialize for UnsizedSeq<'a, T> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            // a filtered iterator does not give an exact size hint
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    #[test]
+    fn test_serialize_empty_collections() {
+        for empty in EMPTY_COLLECTIONS {
+            let bytes = to_bytes(&(&empty, 5u8)).unwrap();
+            let expected_tag = match (empty.map, empty.len) {
+                (false, Some(_)) => Tag::Seq,
+                (false, None) => Tag::UnsizedSeq,
+                (true, Some(_)) => Tag::Map,
+                (true, None) => Tag::UnsizedMap,
+            };
+            assert_eq!(bytes[2], expected_tag.into());
+            match empty.len {
+                Some(_) => assert_eq!(bytes[3..11], 0u64.to_be_bytes()),
+                None => assert_eq!(bytes[3], Tag::UnsizedSeqEnd.into()),
+            }
+
+            if empty.map {
+                let res: (BTreeMap<String, u8>, u8) = from_bytes(&bytes).unwrap();
+                assert_eq!(res, (BTreeMap::new(), 5));
+                let res: (Value, u8) = from_bytes(&bytes).unwrap();
+                assert!(matches!(res, (Value::Map(map), 5) if map.is_empty()));
+            } else {
+                let res: (Vec<u8>, u8) = from_bytes(&bytes).unwrap();
+                assert_eq!(res, (vec![], 5));
+                let res: (Value, u8) = from_bytes(&bytes).unwrap();
+                assert_eq!(res, (Value::Array(vec![]), 5));
+            }
+            assert_eq!(Value::parse(&bytes), from_bytes(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_unsized_seq_need_more_data() {
+        const VEC: [u16; 3] = [3, 7, 1];
+        let bytes = to_bytes(&UnsizedSeq(&VEC)).unwrap();
+        let truncated = &bytes[..bytes.len() - 1];
+
+        assert_eq!(from_bytes::<Vec<u16>>(truncated), Err(Error::NeedMoreData));
+        let config = DeserializerConfig::new().lenient_sequences(true);
+        assert_eq!(
+            from_bytes_with_config::<[u16; 3]>(truncated, config),
+            Err(Error::NeedMoreData)
+        );
+        assert_eq!(
+            from_bytes::<serde::de::IgnoredAny>(truncated).map(|_| ()),
+            Err(Error::NeedMoreData)
+        );
+        assert_eq!(Value::parse(truncated), Err(Error::NeedMoreData));
+        // inside an element too
+        for len in 1..bytes.len() {
+            let truncated = &bytes[..len];
+            assert_eq!(from_bytes::<Vec<u16>>(truncated), Err(Error::NeedMoreData));
+            assert_eq!(
+                from_bytes::<serde::de::IgnoredAny>(truncated).map(|_| ()),
+                Err(Error::NeedMoreData)
+            );
+            assert_eq!(Value::parse(truncated), Err(Error::NeedMoreData));
+        }
+        // or inside the header of a tuple
+        let tuples = [(1u32, 2u32, 3u32), (4, 5, 6)];
+        let bytes = to_bytes(&UnsizedSeq(&tuples)).unwrap();
+        for len in 1..bytes.len() {
+            let truncated = &bytes[..len];
+            assert_eq!(
+                from_bytes::<Vec<(u32, u32, u32)>>(truncated),
+                Err(Error::NeedMoreData),
+                "{}",
+                len
+            );
+            assert_eq!(
+                from_bytes::<serde::de::IgnoredAny>(truncated).map(|_| ()),
+                Err(Error::NeedMoreData),
+                "{}",
+                len
+            );
+        }
+        // through the field of a struct
+        #[derive(Serialize)]
+        struct Unsized<'a> {
+            values: UnsizedSeq<'a, u16>,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Values {
+            _values: Vec<u16>,
+        }
+        let nested = to_bytes(&Unsized {
+            values: UnsizedSeq(&VEC),
+        })
+        .unwrap();
+        let err = from_bytes::<Values>(&nested[..nested.len() - 2]).unwrap_err();
+        assert_eq!(err.root_cause(), &Error::NeedMoreData);
+        // a sized sequence can't be completed
+        let sized = to_bytes(&VEC.to_vec()).unwrap();
+        assert_eq!(
+            from_bytes::<Vec<u16>>(&sized[..sized.len() - 1]),
+            Err(Error::Eof)
+        );
+        // an unknown tag where the end marker is expected is still malformed
+        let mut malformed = truncated.to_vec();
+        malformed.push(255);
+        assert!(matches!(
+            from_bytes::<Vec<u16>>(&malformed),
+            Err(Error::TagParsingError(_))
+        ));
+    }
+
     #[test]
     fn test_serialize_enum_unit() {
         let value = TestEnum::Unit;
@@ -282,6 +1022,7 @@ mod tests {
         let vt = [variant_tag]
             .into_iter()
             .chain(variant_index_bytes)
+            .chain([2])
             .chain([f32_tag])
             .chain(fbytes)
             .chain([string_tag])
@@ -295,6 +1036,7 @@ mod tests {
         //  [
         //      28,                           variant tag
         //      0, 0, 0, 2,                   variant index
+        //      2,                            fields count, since 0.2.0
         //      12,                           F32 tag
         //      65, 68, 204, 205,             NUM
         //      18,                           String tag
@@ -329,6 +1071,7 @@ mod tests {
         let vt = [variant_tag]
             .into_iter()
             .chain(variant_index_bytes)
+            .chain([2])
             .chain([num_tag])
             .chain(fbytes)
             .chain([seq_tag])
@@ -341,6 +1084,7 @@ mod tests {
         //  [
         //      31,                                   variant tag
         //      0, 0, 0, 3,                           variant index
+        //      2,                                    fields count, since 0.2.0
         //      13,                                   F64 tag
         //      64, 69, 15, 190, 118, 200, 180, 57,   f64
         //      25,                                   Seq tag
@@ -463,6 +1207,49 @@ mod tests {
         assert_eq!(c, res);
     }
 
+    #[test]
+    fn test_deserialize_invalid_char() {
+        // valid UTF-8, but 2 chars instead of one
+        let bytes = [Tag::Char2.into(), b'a', b'b'];
+        assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(0x6162)));
+
+        let bytes = [Tag::Char4.into(), b'a', 0xC3, 0xAE, b'b'];
+        assert_eq!(
+            from_bytes::<char>(&bytes),
+            Err(Error::InvalidChar(0x61C3AE62))
+        );
+
+        // a valid char followed by the NUL padding of a longer tag
+        let bytes = [Tag::Char2.into(), b'a', 0];
+        assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(0x6100)));
+
+        // not UTF-8, with the raw bytes
+        let bytes = [Tag::Char2.into(), 0xC3, b'a'];
+        assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(0xC361)));
+        let bytes = [Tag::Char1.into(), 0xFF];
+        assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(0xFF)));
+
+        // the UTF-8 length of the char is longer than the tag
+        let bytes = [Tag::Char2.into(), 0xE2, 0x82];
+        assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(0xE282)));
+        let bytes = [Tag::Char3.into(), 0xF0, 0x92, 0x80];
+        assert_eq!(
+            from_bytes::<char>(&bytes),
+            Err(Error::InvalidChar(0xF09280))
+        );
+
+        // the UTF-8 length of the char is shorter than the tag, with the continuation byte of nothing
+        let bytes = [Tag::Char3.into(), 0xC3, 0xAE, 0x80];
+        assert_eq!(
+            from_bytes::<char>(&bytes),
+            Err(Error::InvalidChar(0xC3AE80))
+        );
+
+        // through deserialize_any
+        let bytes = [Tag::Char2.into(), b'a', b'b'];
+        assert_eq!(from_bytes::<Value>(&bytes), Err(Error::InvalidChar(0x6162)));
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     #[serde(untagged)]
     enum UntaggedEnum {
@@ -494,6 +1281,182 @@ mod tests {
         assert_eq!(value, res);
     }
 
+    // identifier only accepting a `u32`, like the one of some hand written `Deserialize`
+    #[derive(Debug, PartialEq)]
+    struct Identifier(u32);
+
+    impl<'de> Deserialize<'de> for Identifier {
+        fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            struct IdentifierVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for IdentifierVisitor {
+                type Value = Identifier;
+
+                fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                    f.write_str("an u32 identifier")
+                }
+
+                fn visit_u32<E>(self, v: u32) -> core::result::Result<Self::Value, E> {
+                    Ok(Identifier(v))
+                }
+            }
+
+            deserializer.deserialize_identifier(IdentifierVisitor)
+        }
+    }
+
+    // identifiers of the fields of a struct or of an enum variant
+    #[derive(Debug, PartialEq)]
+    struct Identifiers(Vec<Identifier>);
+
+    struct IdentifiersVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for IdentifiersVisitor {
+        type Value = Identifiers;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a struct or an enum")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> core::result::Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            let mut keys = Vec::new();
+            while let Some(key) = map.next_key()? {
+                keys.push(key);
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+            Ok(Identifiers(keys))
+        }
+
+        fn visit_enum<A>(self, data: A) -> core::result::Result<Self::Value, A::Error>
+        where
+            A: serde::de::EnumAccess<'de>,
+        {
+            use serde::de::VariantAccess;
+            let (variant, data) = data.variant()?;
+            data.newtype_variant::<u8>()?;
+            Ok(Identifiers(vec![variant]))
+        }
+    }
+
+    #[derive(Serialize)]
+    enum Choice {
+        _A,
+        B(u8),
+    }
+
+    #[test]
+    fn test_u32_identifiers() {
+        use serde::Deserializer as _;
+
+        let bytes = to_bytes(&TestStruct {
+            a: 1,
+            b: "foo".into(),
+        })
+        .unwrap();
+        let res = Deserializer::new(&bytes)
+            .deserialize_struct("TestStruct", &["a", "b"], IdentifiersVisitor)
+            .unwrap();
+        assert_eq!(res, Identifiers(vec![Identifier(0), Identifier(1)]));
+        // the content buffered by untagged enums only accepts `u64` identifiers
+        let res: UntaggedEnum =
+            from_bytes(&to_bytes(&UntaggedEnum::Struct { num: 12 }).unwrap()).unwrap();
+        assert_eq!(res, UntaggedEnum::Struct { num: 12 });
+
+        let bytes = to_bytes(&Choice::B(3)).unwrap();
+        let res = Deserializer::new(&bytes)
+            .deserialize_enum("Choice", &["_A", "B"], IdentifiersVisitor)
+            .unwrap();
+        assert_eq!(res, Identifiers(vec![Identifier(1)]));
+
+        let bytes = crate::compact::to_bytes(&Choice::B(3)).unwrap();
+        let res = crate::compact::Deserializer::new(&bytes)
+            .deserialize_enum("Choice", &["_A", "B"], IdentifiersVisitor)
+            .unwrap();
+        assert_eq!(res, Identifiers(vec![Identifier(1)]));
+    }
+
+    #[test]
+    fn test_deny_any() {
+        let config = DeserializerConfig::new().deny_any(true);
+
+        let value = (
+            TestStruct {
+                a: 1,
+                b: "foo".into(),
+            },
+            vec![
+                TestEnum::Unit,
+                TestEnum::NewType(2),
+                TestEnum::Tuple(3.5, "bar".into()),
+                TestEnum::Struct {
+                    a: 4.5,
+                    b: vec![5, 6],
+                },
+            ],
+            Some('c'),
+            BTreeMap::from([(7u8, ByteBuf(vec![8, 9]))]),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let res: (
+            TestStruct,
+            Vec<TestEnum>,
+            Option<char>,
+            BTreeMap<u8, ByteBuf>,
+        ) = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res, value);
+
+        let bytes = to_bytes(&UntaggedEnum::Struct { num: 12 }).unwrap();
+        assert!(from_bytes::<UntaggedEnum>(&bytes).is_ok());
+        let err = from_bytes_with_config::<UntaggedEnum>(&bytes, config).unwrap_err();
+        assert_eq!(err, Error::AnyDenied);
+        let err = from_bytes_with_config::<Value>(&bytes, config).unwrap_err();
+        assert_eq!(err, Error::AnyDenied);
+
+        let bytes = to_bytes(&value.0).unwrap();
+        let err = from_bytes_with_config::<serde::de::IgnoredAny>(&bytes, config).unwrap_err();
+        assert_eq!(err, Error::AnyDenied);
+    }
+
+    #[test]
+    fn test_unwrap_some() {
+        let config = DeserializerConfig::new().unwrap_some(true);
+
+        let bytes = to_bytes(&Some(5u32)).unwrap();
+        assert!(from_bytes::<u32>(&bytes).is_err());
+        assert_eq!(from_bytes_with_config::<u32>(&bytes, config), Ok(5));
+        // an option target still gets the option
+        assert_eq!(
+            from_bytes_with_config::<Option<u32>>(&bytes, config),
+            Ok(Some(5))
+        );
+
+        let value = (
+            Some(TestStruct {
+                a: 1,
+                b: "foo".into(),
+            }),
+            Some(TestEnum::NewType(2)),
+            Some(vec![3u8, 4]),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let res: (TestStruct, TestEnum, Vec<u8>) = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res.0, value.0.unwrap());
+        assert_eq!(res.1, value.1.unwrap());
+        assert_eq!(res.2, value.2.unwrap());
+
+        // only a single `Some` is unwrapped, and `None` has no value to give
+        let bytes = to_bytes(&Some(Some(5u32))).unwrap();
+        assert!(from_bytes_with_config::<u32>(&bytes, config).is_err());
+        let bytes = to_bytes(&None::<u32>).unwrap();
+        assert!(from_bytes_with_config::<u32>(&bytes, config).is_err());
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct TestBorrow<'a, 'b> {
         name: &'a str,
@@ -639,6 +1602,35 @@ mod tests {
         assert_eq!(value, res);
     }
 
+    fn cancel_after_header(bytes: usize) -> ControlFlow<()> {
+        if bytes > 3 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn test_progress_cancel() {
+        let value = TestDisplay {
+            name: "john",
+            age: 42,
+        };
+        let full = to_bytes(&value).unwrap();
+
+        // cancelled while writing the string given to `collect_str`
+        let mut v: Vec<u8> = Vec::new();
+        let err = value
+            .serialize(&mut Serializer::new(&mut v).with_progress(1, cancel_after_header))
+            .unwrap_err();
+        assert!(matches!(err, Error::Cancelled));
+        assert!(full.starts_with(&v) && v.len() < full.len());
+
+        let mut deserializer = Deserializer::new(&full).with_progress(1, cancel_after_header);
+        let err = TestDisplay::deserialize(&mut deserializer).unwrap_err();
+        assert_eq!(err.root_cause(), &Error::Cancelled);
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     #[serde(tag = "t", content = "c")]
     enum AdjTaggedEnum {
@@ -647,8 +1639,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    // should panic because adjacently tagged enums don't support u64 identifier like other struct-like types.
     fn test_serialize_deserialize_adj_tagged_enum_variant1() {
         let value = AdjTaggedEnum::NewType("john".into());
 
@@ -665,7 +1655,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_serialize_deserialize_adj_tagged_enum_variant2() {
         let value = AdjTaggedEnum::Struct { num: 12 };
 
@@ -680,4 +1669,2725 @@ mod tests {
 
         assert_eq!(value, res);
     }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(tag = "type")]
+    enum InternallyTaggedEnum {
+        Unit,
+        NewType(TestStruct),
+        Struct { num: usize, name: String },
+    }
+
+    #[test]
+    fn test_serialize_deserialize_internally_tagged_enum() {
+        let config = DeserializerConfig::new().structs_as_sequences(true);
+        let values = vec![
+            InternallyTaggedEnum::Unit,
+            InternallyTaggedEnum::NewType(TestStruct {
+                a: 1,
+                b: "foo".into(),
+            }),
+            InternallyTaggedEnum::Struct {
+                num: 12,
+                name: "bar".into(),
+            },
+        ];
+        for value in values.iter() {
+            let bytes = to_bytes(value).unwrap();
+            // the tag is the first field
+            assert_eq!(bytes[0], Tag::Struct.into());
+            assert_eq!(bytes[2], Tag::String.into());
+
+            let res: InternallyTaggedEnum = from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(&res, value);
+
+            // the field names are not encoded, so the tag can't be found in a map
+            let err = from_bytes::<InternallyTaggedEnum>(&bytes).unwrap_err();
+            assert_eq!(err, Error::Message("missing field `type`".into()));
+        }
+
+        let bytes = to_bytes(&(values, 5u8)).unwrap();
+        let res: (Vec<InternallyTaggedEnum>, u8) = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res.0.len(), 3);
+        assert_eq!(res.1, 5);
+
+        // other types are not affected
+        let value = TestStruct {
+            a: 3,
+            b: "baz".into(),
+        };
+        let bytes = to_bytes(&value).unwrap();
+        let res: TestStruct = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res, value);
+        let res: Value = from_bytes_with_config(&bytes, config).unwrap();
+        assert!(matches!(res, Value::Array(values) if values.len() == 2));
+    }
+
+    #[test]
+    fn test_borrowed_owned_str() {
+        assert_borrow_own_symmetry(Tagged, &"Hello", &String::from("Hello"));
+        assert_borrow_own_symmetry(Tagged, &"", &String::new());
+
+        let bytes = to_bytes(&String::from("Hello")).unwrap();
+        let res: &str = from_bytes(&bytes).unwrap();
+        assert_eq!(res, "Hello");
+    }
+
+    #[test]
+    fn test_borrowed_owned_bytes() {
+        assert_borrow_own_symmetry(Tagged, &Bytes(b"Hello"), &ByteBuf(b"Hello".to_vec()));
+        assert_borrow_own_symmetry(Tagged, &Bytes(&[]), &ByteBuf(Vec::new()));
+
+        let bytes = to_bytes(&ByteBuf(b"Hello".to_vec())).unwrap();
+        let res: Bytes = from_bytes(&bytes).unwrap();
+        assert_eq!(res, Bytes(b"Hello"));
+        assert_eq!(bytes.first().copied(), Some(Tag::ByteArray.into()));
+    }
+
+    #[test]
+    fn test_borrowed_owned_slice() {
+        let slice: &[u16] = &[3, 7, 1, 8];
+        assert_borrow_own_symmetry(Tagged, &slice, &slice.to_vec());
+        let strs: &[&str] = &["a", "bc"];
+        assert_borrow_own_symmetry(Tagged, &strs, &vec![String::from("a"), String::from("bc")]);
+        assert_borrow_own_symmetry(Tagged, &&[0u8; 0][..], &Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_max_total_elements() {
+        // 10 outer elements + 10 * 3 inner elements
+        let value: Vec<Vec<u8>> = vec![vec![1, 2, 3]; 10];
+        let bytes = to_bytes(&value).unwrap();
+
+        let config = DeserializerConfig::new().max_total_elements(39);
+        let mut deserializer = Deserializer::with_config(&bytes, config);
+        let res = Vec::<Vec<u8>>::deserialize(&mut deserializer);
+
+        assert_eq!(res, Err(Error::ElementBudgetExceeded));
+
+        let config = DeserializerConfig::new().max_total_elements(40);
+        let mut deserializer = Deserializer::with_config(&bytes, config);
+        let res = Vec::<Vec<u8>>::deserialize(&mut deserializer);
+
+        assert_eq!(res, Ok(value));
+    }
+
+    #[test]
+    fn test_deserializer_config() {
+        // depth 2, 10 outer elements + 10 * 3 inner elements
+        let value: Vec<Vec<u8>> = vec![vec![1, 2, 3]; 10];
+        let bytes = to_bytes(&value).unwrap();
+
+        let config = DeserializerConfig::new()
+            .max_total_elements(39)
+            .max_depth(2);
+        let res = from_bytes_with_config::<Vec<Vec<u8>>>(&bytes, config);
+        assert_eq!(res, Err(Error::ElementBudgetExceeded));
+
+        let config = config.max_total_elements(40);
+        let res = from_bytes_with_config::<Vec<Vec<u8>>>(&bytes, config);
+        assert_eq!(res, Ok(value));
+    }
+
+    #[test]
+    fn test_default_max_depth() {
+        let mut bytes = vec![Tag::Some.into(); 1000];
+        bytes.push(Tag::Unit.into());
+        assert_eq!(from_bytes::<Value>(&bytes), Err(Error::DepthLimitExceeded));
+
+        // skipped values are limited too
+        let mut bytes = vec![Tag::Seq.into()];
+        bytes.extend(1u64.to_be_bytes());
+        for _ in 0..1000 {
+            bytes.push(Tag::Seq.into());
+            bytes.extend(1u64.to_be_bytes());
+        }
+        bytes.push(Tag::Unit.into());
+        let res = from_bytes::<serde::de::IgnoredAny>(&bytes);
+        assert_eq!(res.unwrap_err(), Error::DepthLimitExceeded);
+
+        let config = DeserializerConfig::new().max_depth(1002);
+        assert!(from_bytes_with_config::<serde::de::IgnoredAny>(&bytes, config).is_ok());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Position {
+        coords: Vec<f32>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct FixedPosition {
+        coords: [f32; 3],
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Rgb(u16, u16, u16);
+
+    fn lenient<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> crate::Result<T> {
+        let config = DeserializerConfig::new().lenient_sequences(true);
+        let mut deserializer = Deserializer::with_config(bytes, config);
+        T::deserialize(&mut deserializer)
+    }
+
+    fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+        *value == T::default()
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    #[serde(default)]
+    struct Sparse {
+        #[serde(skip_serializing_if = "is_default")]
+        a: u32,
+        #[serde(skip_serializing_if = "is_default")]
+        b: String,
+        #[serde(skip_serializing_if = "is_default")]
+        c: Vec<u8>,
+        #[serde(skip_serializing_if = "is_default")]
+        d: Option<i16>,
+        e: u8,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum SparseEnum {
+        Struct {
+            #[serde(default, skip_serializing_if = "is_default")]
+            a: u32,
+            b: u8,
+        },
+    }
+
+    #[test]
+    fn test_sparse_structs() {
+        fn to_bytes<T: Serialize>(value: &T, config: SerializerConfig) -> Vec<u8> {
+            let mut v: Vec<u8> = Vec::new();
+            value
+                .serialize(&mut Serializer::with_config(&mut v, config))
+                .unwrap();
+            v
+        }
+
+        let config = SerializerConfig::new().sparse_structs(true);
+        let value = Sparse {
+            c: vec![1, 2],
+            e: 3,
+            ..Default::default()
+        };
+        let bytes = to_bytes(&value, config);
+        // only the fields 2 and 4 are written
+        let map: BTreeMap<u32, Value> = from_bytes(&bytes).unwrap();
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(from_bytes::<Sparse>(&bytes), Ok(value));
+        // map header, then the keys tagged as u32 before the values
+        assert_eq!(bytes.len(), 9 + (5 + 13) + (5 + 2));
+
+        let value = Sparse::default();
+        let bytes = to_bytes(&value, config);
+        assert_eq!(from_bytes::<Sparse>(&bytes), Ok(value));
+
+        // every field set, deserialized with the canonical mode
+        let value = Sparse {
+            a: 1,
+            b: "foo".into(),
+            c: vec![4],
+            d: Some(-1),
+            e: 2,
+        };
+        let bytes = to_bytes(&value, config.canonical(true));
+        assert_eq!(from_bytes_canonical::<Sparse>(&bytes), Ok(value));
+
+        for value in [
+            SparseEnum::Struct { a: 0, b: 1 },
+            SparseEnum::Struct { a: 2, b: 3 },
+        ] {
+            let bytes = to_bytes(&value, config);
+            assert_eq!(from_bytes::<SparseEnum>(&bytes), Ok(value));
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Device {
+        id: u32,
+        name: String,
+        level: u8,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct DeviceReordered {
+        level: u8,
+        #[serde(default)]
+        battery: Option<u8>,
+        name: String,
+        id: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct DeviceRenamed {
+        id: u32,
+        label: String,
+        level: u8,
+    }
+
+    // "costarring" and "liquid" have the same FNV-1a hash
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Colliding {
+        costarring: u8,
+        #[serde(skip_serializing_if = "is_default")]
+        liquid: u8,
+    }
+
+    #[test]
+    fn test_hashed_field_names() {
+        let de_config = DeserializerConfig::new().hashed_field_names(true);
+        fn to_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+            let config = SerializerConfig::new().hashed_field_names(true);
+            let mut v: Vec<u8> = Vec::new();
+            value
+                .serialize(&mut Serializer::with_config(&mut v, config))
+                .unwrap();
+            v
+        }
+
+        let device = Device {
+            id: 7,
+            name: "probe".into(),
+            level: 3,
+        };
+        let bytes = to_bytes(&device);
+        // map header, then each key is the hash of the name of the field tagged as u32
+        assert_eq!(bytes[0], Tag::Map.into());
+        assert_eq!(bytes[9], Tag::U32.into());
+        assert_eq!(bytes[10..14], field_hash("id").to_be_bytes());
+        assert_eq!(
+            from_bytes_with_config::<Device>(&bytes, de_config),
+            Ok(device)
+        );
+
+        // the fields can be reordered and added
+        let reordered: DeviceReordered = from_bytes_with_config(&bytes, de_config).unwrap();
+        assert_eq!(
+            reordered,
+            DeviceReordered {
+                level: 3,
+                battery: None,
+                name: "probe".into(),
+                id: 7,
+            }
+        );
+        // and removed, the unknown hashes are skipped
+        let reordered = DeviceReordered {
+            battery: Some(80),
+            ..reordered
+        };
+        let device: Device = from_bytes_with_config(&to_bytes(&reordered), de_config).unwrap();
+        assert_eq!(device.name, "probe");
+
+        // but not renamed
+        let err = from_bytes_with_config::<DeviceRenamed>(&bytes, de_config).unwrap_err();
+        assert!(err.to_string().contains("missing field `label`"), "{}", err);
+
+        // struct variants are newtype variants holding the map
+        let value = SparseEnum::Struct { a: 2, b: 3 };
+        assert_eq!(
+            from_bytes_with_config::<SparseEnum>(&to_bytes(&value), de_config),
+            Ok(value)
+        );
+    }
+
+    #[test]
+    fn test_hashed_field_names_collision() {
+        assert_eq!(field_hash("costarring"), field_hash("liquid"));
+        let collision = Error::FieldHashCollision {
+            first: "costarring",
+            second: "liquid",
+        };
+
+        let config = SerializerConfig::new().hashed_field_names(true);
+        let mut v: Vec<u8> = Vec::new();
+        let res = Colliding::default().serialize(&mut Serializer::with_config(&mut v, config));
+        // found even when the second field is skipped
+        assert!(matches!(
+            res,
+            Err(Error::FieldHashCollision {
+                first: "costarring",
+                second: "liquid"
+            })
+        ));
+
+        // the deserializer finds it in the fields of the struct
+        let mut bytes = vec![Tag::Map.into()];
+        bytes.extend(1u64.to_be_bytes());
+        bytes.push(Tag::U32.into());
+        bytes.extend(field_hash("liquid").to_be_bytes());
+        bytes.extend([Tag::U8.into(), 1]);
+        let config = DeserializerConfig::new().hashed_field_names(true);
+        assert_eq!(
+            from_bytes_with_config::<Colliding>(&bytes, config),
+            Err(collision)
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+    struct Decimal((i64, u8));
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Price {
+        amount: Decimal,
+        currency: String,
+    }
+
+    const EXTENSIONS: &[Extension] = &[Extension::new(200, "Decimal")];
+
+    #[test]
+    fn test_extensions() {
+        let ser_config = SerializerConfig::new().extensions(EXTENSIONS);
+        let de_config = DeserializerConfig::new().extensions(EXTENSIONS);
+        let price = Price {
+            amount: Decimal((1234, 2)),
+            currency: "EUR".into(),
+        };
+        let mut bytes: Vec<u8> = Vec::new();
+        price
+            .serialize(&mut Serializer::with_config(&mut bytes, ser_config))
+            .unwrap();
+
+        // struct header, then the tag of the extension, the length and the content in the compact format
+        let payload = crate::compact::to_bytes(&(1234i64, 2u8)).unwrap();
+        assert_eq!(bytes[2], 200);
+        assert_eq!(bytes[3..11], (payload.len() as u64).to_be_bytes());
+        assert_eq!(bytes[11..11 + payload.len()], payload);
+        assert_eq!(peek_type(&bytes[2..]), Ok(Kind::Extension(200)));
+        assert_eq!(from_bytes_with_config(&bytes, de_config), Ok(price));
+
+        // the newtype structs written without the extension are still accepted
+        let plain = to_bytes(&Decimal((-1, 0))).unwrap();
+        assert_eq!(
+            from_bytes_with_config(&plain, de_config),
+            Ok(Decimal((-1, 0)))
+        );
+
+        // the tag must be the one of the registered extension
+        const OTHER: &[Extension] = &[Extension::new(201, "Decimal")];
+        let other = DeserializerConfig::new().extensions(OTHER);
+        assert!(from_bytes_with_config::<Price>(&bytes, other).is_err());
+        assert!(from_bytes::<Price>(&bytes).is_err());
+
+        // the payload must be fully read
+        let mut bytes = vec![200];
+        bytes.extend(10u64.to_be_bytes());
+        bytes.extend(crate::compact::to_bytes(&(1234i64, 2u8, 0u8)).unwrap());
+        assert_eq!(
+            from_bytes_with_config::<Decimal>(&bytes, de_config),
+            Err(Error::TrailingBytes(1))
+        );
+    }
+
+    // the mantissa of a `Decimal` on 4 bytes, then its scale
+    fn encode_decimal(content: &[u8], payload: &mut Vec<u8>) -> crate::Result<()> {
+        let (mantissa, scale): (i64, u8) = crate::compact::from_bytes(content)?;
+        let mantissa = i32::try_from(mantissa).map_err(|_| Error::InvalidSize)?;
+        payload.extend(mantissa.to_be_bytes());
+        payload.push(scale);
+        Ok(())
+    }
+
+    fn decode_decimal(payload: &[u8], content: &mut Vec<u8>) -> crate::Result<()> {
+        let [a, b, c, d, scale] = payload.try_into().map_err(|_| Error::InvalidSize)?;
+        let mantissa = i32::from_be_bytes([a, b, c, d]);
+        *content = crate::compact::to_bytes(&(i64::from(mantissa), scale)).unwrap();
+        Ok(())
+    }
+
+    // the bytes of the string in reverse order
+    fn reverse(input: &[u8], output: &mut Vec<u8>) -> crate::Result<()> {
+        output.extend(input.iter().rev());
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Label(String);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct BorrowedLabel<'a>(&'a str);
+
+    const CODECS: &[Extension] = &[
+        Extension::with_codec(210, "Decimal", encode_decimal, decode_decimal),
+        Extension::with_codec(255, "Label", reverse, reverse),
+        Extension::with_codec(255, "BorrowedLabel", reverse, reverse),
+    ];
+
+    #[test]
+    fn test_extension_codecs() {
+        let ser_config = SerializerConfig::new().extensions(CODECS);
+        let de_config = DeserializerConfig::new().extensions(CODECS);
+        let decimal = Decimal((-1234, 2));
+        let mut bytes: Vec<u8> = Vec::new();
+        decimal
+            .serialize(&mut Serializer::with_config(&mut bytes, ser_config))
+            .unwrap();
+
+        // the tag of the extension, the length and the payload given by the encoder
+        let mut expected = vec![210];
+        expected.extend(5u64.to_be_bytes());
+        expected.extend((-1234i32).to_be_bytes());
+        expected.push(2);
+        assert_eq!(bytes, expected);
+        assert_eq!(peek_type(&bytes), Ok(Kind::Extension(210)));
+        assert_eq!(from_bytes_with_config(&bytes, de_config), Ok(decimal));
+
+        // the tag is kept by `Value`, which writes the extension back unchanged
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(value, Value::Extension(210, &bytes[9..]));
+        assert_eq!(to_bytes(&value).unwrap(), bytes);
+
+        // errors of the encoder and the decoder are returned as is
+        let mut bytes: Vec<u8> = Vec::new();
+        let err = Decimal((i64::MAX, 0))
+            .serialize(&mut Serializer::with_config(&mut bytes, ser_config))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidSize));
+        let mut bytes = vec![210];
+        bytes.extend(4u64.to_be_bytes());
+        bytes.extend([0; 4]);
+        assert_eq!(
+            from_bytes_with_config::<Decimal>(&bytes, de_config),
+            Err(Error::InvalidSize)
+        );
+
+        // strings are read from the decoded payload
+        let label = Label("bottle".into());
+        let mut bytes: Vec<u8> = Vec::new();
+        label
+            .serialize(&mut Serializer::with_config(&mut bytes, ser_config))
+            .unwrap();
+        assert_eq!(bytes[0], 255);
+        let mut content = crate::compact::to_bytes(&label.0).unwrap();
+        content.reverse();
+        assert_eq!(bytes[9..], content);
+        assert_eq!(from_bytes_with_config(&bytes, de_config), Ok(label));
+
+        // but can't be borrowed from it
+        let borrowed = BorrowedLabel("bottle");
+        let mut bytes: Vec<u8> = Vec::new();
+        borrowed
+            .serialize(&mut Serializer::with_config(&mut bytes, ser_config))
+            .unwrap();
+        assert!(from_bytes_with_config::<BorrowedLabel>(&bytes, de_config).is_err());
+    }
+
+    #[test]
+    fn test_unknown_extensions() {
+        let mut bytes = vec![Tag::Tuple.into(), 2];
+        bytes.push(255);
+        bytes.extend(3u64.to_be_bytes());
+        bytes.extend([1, 2, 3]);
+        bytes.extend([Tag::U8.into(), 4]);
+
+        // skipped without knowing their content
+        assert_eq!(
+            from_bytes::<(serde::de::IgnoredAny, u8)>(&bytes),
+            Ok((serde::de::IgnoredAny, 4))
+        );
+        assert_eq!(resync(&bytes[2..]), Some(0));
+
+        // and kept as is by `Value`
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Extension(255, &[1, 2, 3]),
+                Value::Number(Number::U8(4))
+            ])
+        );
+        assert_eq!(Value::parse(&bytes), Ok(value.clone()));
+        // the array is written as a sequence
+        assert_eq!(to_bytes(&value).unwrap()[9..], bytes[2..]);
+        let owned = Value::OwnedExtension(255, vec![1, 2, 3]);
+        assert_eq!(to_bytes(&owned).unwrap(), bytes[2..14]);
+        assert_eq!(format!("{:?}", owned), "OwnedExtension(255, [1, 2, 3])");
+        assert_eq!(
+            format!("{:?}", value.compact_debug(DebugLimits::default())),
+            "Array [Extension(255, [1, 2, 3]), U8(4)]"
+        );
+
+        let events: Vec<Event> = Events::new(&bytes).collect::<crate::Result<_>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::StartSeq(Some(2)),
+                Event::Extension(255, &[1, 2, 3]),
+                Event::Number(Number::U8(4)),
+                Event::EndSeq
+            ]
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    struct Account {
+        name: String,
+        password: Option<String>,
+        level: u8,
+        token: Option<u64>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    enum Audit {
+        Login { account: Account, password: String },
+    }
+
+    fn hide_secrets(name: &'static str, field: &'static str) -> crate::FieldAction {
+        match (name, field) {
+            (_, "password") => crate::FieldAction::Redact,
+            ("Account", "token") => crate::FieldAction::Skip,
+            _ => crate::FieldAction::Keep,
+        }
+    }
+
+    #[test]
+    fn test_field_filter() {
+        let account = Account {
+            name: "admin".into(),
+            password: Some("hunter2".into()),
+            level: 3,
+            token: Some(42),
+        };
+        let hidden = Account {
+            password: None,
+            token: None,
+            ..account.clone()
+        };
+        let base = SerializerConfig::new().field_filter(hide_secrets);
+        for config in [
+            base,
+            base.sparse_structs(true),
+            base.hashed_field_names(true),
+            base.container_checksums(true),
+        ] {
+            let mut bytes: Vec<u8> = Vec::new();
+            let written = account
+                .serialize(&mut Serializer::with_config(&mut bytes, config))
+                .unwrap();
+            assert_eq!(written, bytes.len());
+            // the header counts the fields left
+            let keyed = config.sparse_structs || config.hashed_field_names;
+            if keyed {
+                assert_eq!(bytes[0], Tag::Map.into());
+                assert_eq!(bytes[1..9], 3u64.to_be_bytes());
+            } else {
+                assert_eq!(bytes[..2], [Tag::Struct.into(), 3]);
+            }
+            if keyed {
+                // the skipped field gets its default value
+                let de_config =
+                    DeserializerConfig::new().hashed_field_names(config.hashed_field_names);
+                assert_eq!(
+                    from_bytes_with_config(&bytes, de_config),
+                    Ok(hidden.clone())
+                );
+            }
+        }
+
+        // struct variants are filtered with the name of the enum
+        let audit = Audit::Login {
+            account: account.clone(),
+            password: "hunter2".into(),
+        };
+        for config in [base, base.sparse_structs(true)] {
+            let mut bytes: Vec<u8> = Vec::new();
+            audit
+                .serialize(&mut Serializer::with_config(&mut bytes, config))
+                .unwrap();
+            assert!(!bytes.windows(7).any(|w| w == b"hunter2"));
+            // both passwords are redacted
+            let value: Value = from_bytes(&bytes).unwrap();
+            let redacted = value.find_all(|value| *value == Value::Option(None));
+            assert_eq!(redacted.len(), 2, "{:?}", config);
+        }
+    }
+
+    // number of `History` decoded, to check that the fields left out by a projection are not decoded
+    static DECODED_HISTORIES: std::sync::atomic::AtomicUsize =
+        std::sync::atomic::AtomicUsize::new(0);
+
+    #[derive(Debug, Serialize, PartialEq, Default, Clone)]
+    #[serde(transparent)]
+    struct History(Vec<u32>);
+
+    impl<'de> Deserialize<'de> for History {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            DECODED_HISTORIES.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Vec::deserialize(deserializer).map(History)
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
+    #[serde(default)]
+    struct Record {
+        id: u64,
+        name: String,
+        history: History,
+        timestamp: i64,
+        labels: BTreeMap<String, String>,
+    }
+
+    // same fields without defaults
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct StrictRecord {
+        id: u64,
+        name: String,
+        history: Vec<u32>,
+        timestamp: i64,
+        labels: BTreeMap<String, String>,
+    }
+
+    #[test]
+    fn test_projection() {
+        let record = Record {
+            id: 7,
+            name: "sensor".into(),
+            history: History((0..100).collect()),
+            timestamp: -5,
+            labels: [("room".into(), "kitchen".into())].into(),
+        };
+        let projected = Record {
+            id: 7,
+            timestamp: -5,
+            ..Default::default()
+        };
+        let decoded = || DECODED_HISTORIES.load(std::sync::atomic::Ordering::SeqCst);
+        let base = SerializerConfig::new();
+        for config in [
+            base,
+            base.sparse_structs(true),
+            base.hashed_field_names(true),
+            base.container_checksums(true),
+        ] {
+            let mut bytes: Vec<u8> = Vec::new();
+            record
+                .serialize(&mut Serializer::with_config(&mut bytes, config))
+                .unwrap();
+            let de_config = DeserializerConfig::new()
+                .hashed_field_names(config.hashed_field_names)
+                .container_checksums(config.container_checksums);
+
+            let before = decoded();
+            let res = from_bytes_with_config(&bytes, de_config.projection(&["id", "timestamp"]));
+            assert_eq!(res, Ok(projected.clone()), "{:?}", config);
+            assert_eq!(decoded(), before, "{:?}", config);
+
+            // the elements of the skipped fields are not counted
+            let budget = de_config.max_total_elements(5);
+            assert_eq!(
+                from_bytes_with_config(&bytes, budget.projection(&["id", "timestamp"])),
+                Ok(projected.clone())
+            );
+            let err = from_bytes_with_config::<Record>(&bytes, budget).unwrap_err();
+            assert_eq!(err.root_cause(), &Error::ElementBudgetExceeded);
+
+            let before = decoded();
+            let res: Record = from_bytes_with_config(&bytes, de_config).unwrap();
+            assert_eq!(res, record);
+            assert_eq!(decoded(), before + 1);
+        }
+
+        let bytes = to_bytes(&record).unwrap();
+        assert_eq!(
+            from_bytes_projected(&bytes, &["name", "history"]),
+            Ok(Record {
+                name: "sensor".into(),
+                history: record.history.clone(),
+                ..Default::default()
+            })
+        );
+        // the fields left out must have a default
+        let err = from_bytes_projected::<StrictRecord>(&bytes, &["id", "timestamp"]).unwrap_err();
+        assert_eq!(err, Error::Message("missing field `name`".into()));
+
+        // only the top-level struct is projected
+        let records = vec![record.clone(), record.clone()];
+        let bytes = to_bytes(&records).unwrap();
+        let before = decoded();
+        assert_eq!(from_bytes_projected(&bytes, &["id"]), Ok(records));
+        assert_eq!(decoded(), before + 2);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Sample {
+        label: String,
+        values: Vec<u16>,
+    }
+
+    #[test]
+    fn test_trace() {
+        use crate::{TraceEvent, TraceStep};
+
+        let samples = vec![
+            Sample {
+                label: "a".into(),
+                values: vec![1],
+            },
+            Sample {
+                label: "b".into(),
+                values: vec![2, 3],
+            },
+        ];
+        let mut bytes = to_bytes(&samples).unwrap();
+        // the tag of the last value
+        let corrupted = bytes
+            .windows(3)
+            .position(|w| w == [Tag::U16.into(), 0, 3])
+            .unwrap();
+        bytes[corrupted] = 199;
+
+        let untraced = from_bytes::<Vec<Sample>>(&bytes).unwrap_err();
+        assert_eq!(untraced.trace(), None);
+
+        let config = DeserializerConfig::new().trace(4);
+        let err = from_bytes_with_config::<Vec<Sample>>(&bytes, config).unwrap_err();
+        assert_eq!(
+            err.root_cause(),
+            &Error::TagParsingError(TagParsingError::InvalidTag(199))
+        );
+        let trace = err.trace().unwrap();
+        // the seq of the values starts after its tag and the 8 bytes of its length, followed by a value of 3 bytes
+        let values = corrupted - 12;
+        let steps: Vec<TraceEvent> = trace.iter().copied().collect();
+        assert_eq!(
+            steps,
+            [
+                TraceEvent {
+                    offset: values,
+                    step: TraceStep::Tag(Tag::Seq)
+                },
+                TraceEvent {
+                    offset: values + 1,
+                    step: TraceStep::Length(2)
+                },
+                TraceEvent {
+                    offset: corrupted - 3,
+                    step: TraceStep::Tag(Tag::U16)
+                },
+                TraceEvent {
+                    offset: corrupted,
+                    step: TraceStep::InvalidTag(Some(199))
+                },
+            ]
+        );
+        assert_eq!(err.code(), 14);
+        // the trace is only shown by the alternate format
+        assert_eq!(err.to_string(), untraced.to_string());
+        let verbose = format!("{:#}", err);
+        let expected = format!(
+            "(trace: tag Seq at byte {}, length 2 at byte {}, tag U16 at byte {}, invalid tag 199 at byte {})",
+            values,
+            values + 1,
+            corrupted - 3,
+            corrupted
+        );
+        assert!(verbose.ends_with(&expected), "{}", verbose);
+
+        // the buffer keeps the last steps
+        let config = DeserializerConfig::new().trace(100);
+        let err = from_bytes_with_config::<Vec<Sample>>(&bytes, config).unwrap_err();
+        let trace = err.trace().unwrap();
+        assert!(trace.len() > 4 && trace.len() < 100);
+        assert_eq!(
+            trace.iter().next(),
+            Some(&TraceEvent {
+                offset: 0,
+                step: TraceStep::Tag(Tag::Seq)
+            })
+        );
+        assert_eq!(trace.last(), steps.last());
+
+        // a missing tag is recorded too
+        let err = from_bytes_with_config::<Vec<Sample>>(&bytes[..corrupted], config).unwrap_err();
+        assert_eq!(
+            err.trace().unwrap().last(),
+            Some(&TraceEvent {
+                offset: corrupted,
+                step: TraceStep::InvalidTag(None)
+            })
+        );
+
+        // the trace is attached to the errors of a deserializer driven by hand
+        let mut de = Deserializer::with_config(&bytes, DeserializerConfig::new().trace(4));
+        let err = Vec::<Sample>::deserialize(&mut de).unwrap_err();
+        let hand_steps: Vec<TraceEvent> = err.trace().unwrap().iter().copied().collect();
+        assert_eq!(hand_steps, steps);
+    }
+
+    #[derive(Serialize)]
+    enum Reading {
+        Missing,
+        Celsius(f32),
+        Range(i8, i8),
+        Labeled { label: &'static str },
+    }
+
+    #[derive(Serialize)]
+    struct Sensor {
+        id: u16,
+        name: Option<&'static str>,
+        readings: Vec<Reading>,
+        #[serde(serialize_with = "unsized_seq")]
+        raw: Vec<u8>,
+        tags: BTreeMap<&'static str, bool>,
+    }
+
+    fn unsized_seq<S: serde::Serializer>(values: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(values.iter().filter(|_| true))
+    }
+
+    #[test]
+    fn test_events() {
+        let sensor = Sensor {
+            id: 3,
+            name: Some("probe"),
+            readings: vec![
+                Reading::Missing,
+                Reading::Celsius(1.5),
+                Reading::Range(-1, 2),
+                Reading::Labeled { label: "ok" },
+            ],
+            raw: vec![9],
+            tags: [("hot", true)].into_iter().collect(),
+        };
+        let bytes = to_bytes(&sensor).unwrap();
+        let events: Vec<Event> = Events::new(&bytes).collect::<crate::Result<_>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::StartStruct(5),
+                Event::Number(Number::U16(3)),
+                Event::Some,
+                Event::Str("probe"),
+                Event::StartSeq(Some(4)),
+                Event::Variant(0),
+                Event::Unit,
+                Event::Variant(1),
+                Event::Number(Number::F32(1.5)),
+                Event::Variant(2),
+                Event::StartSeq(Some(2)),
+                Event::Number(Number::I8(-1)),
+                Event::Number(Number::I8(2)),
+                Event::EndSeq,
+                Event::Variant(3),
+                Event::StartStruct(1),
+                Event::Str("ok"),
+                Event::EndStruct,
+                Event::EndSeq,
+                Event::StartSeq(None),
+                Event::Number(Number::U8(9)),
+                Event::EndSeq,
+                Event::StartMap(Some(1)),
+                Event::Str("hot"),
+                Event::Bool(true),
+                Event::EndMap,
+                Event::EndStruct,
+            ]
+        );
+
+        // the depth follows the nesting boundaries
+        let mut events = Events::new(&bytes);
+        events.nth(4).unwrap().unwrap();
+        assert_eq!(events.depth(), 2);
+        assert_eq!(events.last().unwrap().unwrap(), Event::EndStruct);
+
+        let empty = to_bytes(&(Vec::<u8>::new(), ())).unwrap();
+        let events: Vec<Event> = Events::new(&empty).collect::<crate::Result<_>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::StartSeq(Some(2)),
+                Event::StartSeq(Some(0)),
+                Event::EndSeq,
+                Event::Unit,
+                Event::EndSeq
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_errors() {
+        let bytes = to_bytes(&(1u8, vec![2u8, 3])).unwrap();
+        let truncated = &bytes[..bytes.len() - 1];
+        let events: Vec<crate::Result<Event>> = Events::new(truncated).collect();
+        assert_eq!(events.last(), Some(&Err(Error::Eof)));
+        assert_eq!(events.len(), 5);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        let events: Vec<crate::Result<Event>> = Events::new(&trailing).collect();
+        assert_eq!(events.last(), Some(&Err(Error::TrailingBytes(1))));
+
+        // an unsized sequence without its end marker may still be completed
+        let mut unsized_seq = vec![Tag::UnsizedSeq.into(), Tag::U8.into(), 1];
+        assert_eq!(
+            Events::new(&unsized_seq).last(),
+            Some(Err(Error::NeedMoreData))
+        );
+        unsized_seq.push(Tag::UnsizedSeqEnd.into());
+        assert_eq!(Events::new(&unsized_seq).count(), 3);
+
+        // nothing is given after an error
+        let invalid = [Tag::UnsizedSeqEnd.into(), Tag::U8.into(), 1];
+        let mut events = Events::new(&invalid);
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn test_length_exceeds_input() {
+        let exceeds = Error::LengthExceedsInput {
+            len: 255,
+            remaining: 3,
+        };
+        let bytes = [Tag::Tuple.into(), 255, Tag::U8.into(), 1, Tag::Unit.into()];
+        assert_eq!(from_bytes::<Value>(&bytes).unwrap_err(), exceeds);
+        assert_eq!(Value::parse(&bytes).unwrap_err(), exceeds);
+        assert_eq!(
+            from_bytes::<serde::de::IgnoredAny>(&bytes).unwrap_err(),
+            exceeds
+        );
+
+        let bytes = [Tag::TupleVariant.into(), 0, 0, 0, 1, 255, 1, 2, 3];
+        assert_eq!(from_bytes::<Value>(&bytes).unwrap_err(), exceeds);
+        assert_eq!(Value::parse(&bytes).unwrap_err(), exceeds);
+        let bytes = [Tag::Struct.into(), 255, 1, 2, 3];
+        assert_eq!(from_bytes::<Value>(&bytes).unwrap_err(), exceeds);
+
+        // the length of an array is checked before its elements
+        let bytes = [Tag::Tuple.into(), 32, Tag::U8.into(), 1, Tag::U8.into()];
+        assert_eq!(
+            from_bytes::<[u8; 32]>(&bytes),
+            Err(Error::LengthExceedsInput {
+                len: 32,
+                remaining: 3
+            })
+        );
+        // a length that fits is still decoded element by element
+        let bytes = [Tag::Tuple.into(), 2, Tag::U8.into(), 1, Tag::U8.into()];
+        assert_eq!(from_bytes::<[u8; 2]>(&bytes), Err(Error::Eof));
+    }
+
+    #[test]
+    fn test_lenient_sequences() {
+        let bytes = to_bytes(&Position {
+            coords: vec![1.0, 2.0, 3.0],
+        })
+        .unwrap();
+        assert!(from_bytes::<FixedPosition>(&bytes).is_err());
+        assert_eq!(
+            lenient::<FixedPosition>(&bytes),
+            Ok(FixedPosition {
+                coords: [1.0, 2.0, 3.0]
+            })
+        );
+
+        let bytes = to_bytes(&UnsizedSeq(&[1u16, 2, 3])).unwrap();
+        assert_eq!(lenient::<Rgb>(&bytes), Ok(Rgb(1, 2, 3)));
+        assert_eq!(lenient::<(u16, u16, u16)>(&bytes), Ok((1, 2, 3)));
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Color {
+            r: u16,
+            g: u16,
+            b: u16,
+        }
+        assert!(from_bytes::<Color>(&bytes).is_err());
+        let color = Color { r: 1, g: 2, b: 3 };
+        assert_eq!(lenient::<Color>(&bytes), Ok(color));
+        let bytes = to_bytes(&vec![1u16, 2, 3]).unwrap();
+        assert_eq!(lenient::<[u16; 3]>(&bytes), Ok([1, 2, 3]));
+        assert_eq!(lenient::<Color>(&bytes), Ok(Color { r: 1, g: 2, b: 3 }));
+        let bytes = to_bytes(&vec![1u16, 2]).unwrap();
+        assert_eq!(
+            lenient::<Color>(&bytes),
+            Err(Error::SeqSizeMismatch {
+                expected: 3,
+                got: 2
+            })
+        );
+
+        // a struct written positionally as a tuple or a tuple struct
+        let bytes = to_bytes(&(1u16, 2u16, 3u16)).unwrap();
+        assert!(from_bytes::<Color>(&bytes).is_err());
+        assert_eq!(lenient::<Color>(&bytes), Ok(Color { r: 1, g: 2, b: 3 }));
+        let bytes = to_bytes(&Rgb(1, 2, 3)).unwrap();
+        assert_eq!(lenient::<Color>(&bytes), Ok(Color { r: 1, g: 2, b: 3 }));
+        let bytes = to_bytes(&(1u16, 2u16)).unwrap();
+        assert_eq!(
+            lenient::<Color>(&bytes),
+            Err(Error::SeqSizeMismatch {
+                expected: 3,
+                got: 2
+            })
+        );
+
+        let bytes = to_bytes(&Rgb(1, 2, 3)).unwrap();
+        assert!(from_bytes::<Vec<u16>>(&bytes).is_err());
+        assert_eq!(lenient::<Vec<u16>>(&bytes), Ok(vec![1, 2, 3]));
+        let bytes = to_bytes(&(1u8, 2u8)).unwrap();
+        assert_eq!(lenient::<Vec<u8>>(&bytes), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_lenient_sequences_len_mismatch() {
+        for (coords, got) in [(vec![1.0, 2.0], 2), (vec![1.0, 2.0, 3.0, 4.0], 4)] {
+            let bytes = to_bytes(&Position { coords }).unwrap();
+            let err = lenient::<FixedPosition>(&bytes).unwrap_err();
+            assert_eq!(
+                err.root_cause(),
+                &Error::SeqSizeMismatch { expected: 3, got }
+            );
+        }
+
+        let bytes = to_bytes(&UnsizedSeq(&[1u16, 2, 3, 4])).unwrap();
+        assert_eq!(
+            lenient::<Rgb>(&bytes),
+            Err(Error::SeqSizeMismatch {
+                expected: 3,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct UserV1 {
+            id: u32,
+            name: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct UserV2 {
+            id: u32,
+            name: String,
+            #[serde(default)]
+            admin: bool,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct UserV3 {
+            id: u32,
+            name: String,
+            admin: bool,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum EventV1 {
+            Login { id: u32 },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum EventV2 {
+            Login {
+                id: u32,
+                #[serde(default)]
+                attempts: u8,
+            },
+        }
+
+        let config = DeserializerConfig::new().missing_fields(true);
+        let v1 = UserV1 {
+            id: 3,
+            name: "foo".into(),
+        };
+        let bytes = to_bytes(&v1).unwrap();
+        assert_eq!(
+            from_bytes::<UserV2>(&bytes),
+            Err(Error::SeqSizeMismatch {
+                expected: 3,
+                got: 2
+            })
+        );
+        let v2 = UserV2 {
+            id: 3,
+            name: "foo".into(),
+            admin: false,
+        };
+        assert_eq!(from_bytes_with_config::<UserV2>(&bytes, config), Ok(v2));
+        let err = from_bytes_with_config::<UserV3>(&bytes, config).unwrap_err();
+        assert_eq!(err, Error::Message("missing field `admin`".into()));
+
+        // the struct can't have fewer fields than encoded
+        let v2 = UserV2 {
+            id: 3,
+            name: "foo".into(),
+            admin: true,
+        };
+        let bytes = to_bytes(&v2).unwrap();
+        assert_eq!(
+            from_bytes_with_config::<UserV1>(&bytes, config),
+            Err(Error::SeqSizeMismatch {
+                expected: 2,
+                got: 3
+            })
+        );
+
+        let bytes = to_bytes(&EventV1::Login { id: 9 }).unwrap();
+        assert!(from_bytes::<EventV2>(&bytes).is_err());
+        assert_eq!(
+            from_bytes_with_config::<EventV2>(&bytes, config),
+            Ok(EventV2::Login { id: 9, attempts: 0 })
+        );
+        // a value of the current version is read the same
+        let bytes = to_bytes(&EventV2::Login { id: 9, attempts: 2 }).unwrap();
+        assert_eq!(
+            from_bytes_with_config::<EventV2>(&bytes, config),
+            Ok(EventV2::Login { id: 9, attempts: 2 })
+        );
+    }
+
+    fn coerce<'de, T: Deserialize<'de>>(
+        bytes: &'de [u8],
+        coercion: NumberCoercion,
+    ) -> crate::Result<T> {
+        let config = DeserializerConfig::new().number_coercion(coercion);
+        from_bytes_with_config(bytes, config)
+    }
+
+    // `values` are the min, 0 and the max of `S`
+    fn check_number_coercion<S, D>(values: [S; 3])
+    where
+        S: Serialize + Copy,
+        D: TryFrom<S> + serde::de::DeserializeOwned + PartialEq + core::fmt::Debug,
+    {
+        let lossless = values.iter().all(|v| D::try_from(*v).is_ok());
+        let same_type = core::any::type_name::<S>() == core::any::type_name::<D>();
+        for value in values {
+            let bytes = to_bytes(&value).unwrap();
+            let fit = D::try_from(value).ok();
+            assert_eq!(coerce(&bytes, NumberCoercion::Fit).ok(), fit);
+            assert_eq!(
+                coerce(&bytes, NumberCoercion::Widen).ok(),
+                fit.filter(|_| lossless)
+            );
+            assert_eq!(
+                coerce::<D>(&bytes, NumberCoercion::Exact).is_ok(),
+                same_type
+            );
+        }
+    }
+
+    macro_rules! check_number_coercions {
+        ($($src:ident)*) => {
+            $(
+                check_number_coercions!(@to $src: i8 i16 i32 i64 i128 u8 u16 u32 u64 u128);
+            )*
+        };
+        (@to $src:ident: $($dst:ident)*) => {
+            $(
+                check_number_coercion::<$src, $dst>([$src::MIN, 0, $src::MAX]);
+            )*
+        };
+    }
+
+    #[test]
+    fn test_number_coercion() {
+        check_number_coercions!(i8 i16 i32 i64 i128 u8 u16 u32 u64 u128);
+
+        let bytes = to_bytes(&255u8).unwrap();
+        assert!(coerce::<i8>(&bytes, NumberCoercion::Widen).is_err());
+        let err = coerce::<i8>(&bytes, NumberCoercion::Fit).unwrap_err();
+        assert_eq!(err.to_string(), "255 is out of range for i8");
+        assert_eq!(coerce::<i16>(&bytes, NumberCoercion::Widen), Ok(255));
+
+        let bytes = to_bytes(&1.5f32).unwrap();
+        assert!(from_bytes::<f64>(&bytes).is_err());
+        assert_eq!(coerce::<f64>(&bytes, NumberCoercion::Widen), Ok(1.5));
+        assert_eq!(coerce::<f64>(&bytes, NumberCoercion::Fit), Ok(1.5));
+        assert!(coerce::<i64>(&bytes, NumberCoercion::Fit).is_err());
+
+        let bytes = to_bytes(&1.5f64).unwrap();
+        assert!(coerce::<f32>(&bytes, NumberCoercion::Fit).is_err());
+
+        let bytes = to_bytes(&1u8).unwrap();
+        assert!(coerce::<f32>(&bytes, NumberCoercion::Fit).is_err());
+        assert!(coerce::<f64>(&bytes, NumberCoercion::Fit).is_err());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct OldRecord {
+        id: u16,
+        score: f32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct NewRecord {
+        id: u32,
+        score: f64,
+    }
+
+    #[test]
+    fn test_number_coercion_struct() {
+        let bytes = to_bytes(&OldRecord { id: 7, score: 0.5 }).unwrap();
+        assert!(from_bytes::<NewRecord>(&bytes).is_err());
+        assert_eq!(
+            coerce(&bytes, NumberCoercion::Widen),
+            Ok(NewRecord { id: 7, score: 0.5 })
+        );
+    }
+
+    #[test]
+    fn test_strict_integers() {
+        let strict = DeserializerConfig::new().strict_integers(true);
+        let widen = strict.number_coercion(NumberCoercion::Widen);
+
+        let wide = [Tag::U64.into(), 0, 0, 0, 0, 0, 0, 0, 5];
+        assert_eq!(from_bytes::<u64>(&wide), Ok(5));
+        assert_eq!(
+            from_bytes_with_config::<u64>(&wide, strict),
+            Err(Error::NonCanonicalInteger {
+                tag: Tag::U64,
+                narrowest: Tag::U8,
+                offset: 0
+            })
+        );
+        let narrow = [Tag::U8.into(), 5];
+        assert_eq!(from_bytes_with_config::<u8>(&narrow, strict), Ok(5));
+        assert_eq!(from_bytes_with_config::<u64>(&narrow, widen), Ok(5));
+        let value: Value = from_bytes_with_config(&narrow, strict).unwrap();
+        assert_eq!(value, Value::Number(Number::U8(5)));
+
+        // the signedness is kept
+        let bytes = to_bytes(&(-1i32, 200i16, 100i8, u64::MAX, i128::MIN)).unwrap();
+        let err = from_bytes_with_config::<(i32, i16, i8, u64, i128)>(&bytes, strict).unwrap_err();
+        assert_eq!(
+            err,
+            Error::NonCanonicalInteger {
+                tag: Tag::I32,
+                narrowest: Tag::I8,
+                offset: 2
+            }
+        );
+        let bytes = to_bytes(&(-1i8, 200i16, 100i8, u64::MAX, i128::MIN)).unwrap();
+        let res = from_bytes_with_config::<(i32, i16, i8, u64, i128)>(&bytes, widen);
+        assert_eq!(res, Ok((-1, 200, 100, u64::MAX, i128::MIN)));
+
+        // the skipped values are checked too
+        let bytes = to_bytes(&(1u8, 2u32)).unwrap();
+        let err =
+            from_bytes_with_config::<(u8, serde::de::IgnoredAny)>(&bytes, strict).unwrap_err();
+        assert_eq!(
+            err,
+            Error::NonCanonicalInteger {
+                tag: Tag::U32,
+                narrowest: Tag::U8,
+                offset: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_tag_map() {
+        // a fork that numbered the sequences before the newtype variants
+        static FORK: TagMap = TagMap::current()
+            .with(25, Tag::Seq)
+            .with(26, Tag::NewTypeVariant);
+        let config = DeserializerConfig::new().tag_map(&FORK);
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Message {
+            Ping(u8),
+        }
+
+        let mut seq = to_bytes(&vec![1u16, 2]).unwrap();
+        assert_eq!(seq[0], Tag::Seq.into());
+        seq[0] = 25;
+        let mut variant = to_bytes(&Message::Ping(7)).unwrap();
+        assert_eq!(variant[0], Tag::NewTypeVariant.into());
+        variant[0] = 26;
+
+        assert_eq!(
+            from_bytes_with_config::<Vec<u16>>(&seq, config),
+            Ok(vec![1, 2])
+        );
+        assert_eq!(
+            from_bytes_with_config::<Message>(&variant, config),
+            Ok(Message::Ping(7))
+        );
+        let value: Value = from_bytes_with_config(&seq, config).unwrap();
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Number(Number::U16(1)),
+                Value::Number(Number::U16(2))
+            ])
+        );
+        assert!(from_bytes::<Vec<u16>>(&seq).is_err());
+
+        // the inputs of this crate decode the same with the map of its numbering
+        static CURRENT: TagMap = TagMap::current();
+        let bytes = to_bytes(&(Message::Ping(1), "a", Some(-3i64), [0.5f32])).unwrap();
+        assert_eq!(
+            from_bytes_with_config::<(Message, &str, Option<i64>, [f32; 1])>(
+                &bytes,
+                DeserializerConfig::new().tag_map(&CURRENT)
+            ),
+            Ok((Message::Ping(1), "a", Some(-3), [0.5]))
+        );
+
+        // a byte unknown to the map
+        static NO_UNIT: TagMap = TagMap::empty().with(1, Tag::Some);
+        let bytes = to_bytes(&Some(())).unwrap();
+        let err = from_bytes_with_config::<Option<()>>(
+            &bytes,
+            DeserializerConfig::new().tag_map(&NO_UNIT),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            Error::TagParsingError(TagParsingError::UnmappedTag(Tag::Unit.into()))
+        );
+        assert_eq!(
+            err.to_string(),
+            "Invalid tag for data type: 21 is not in the tag map of the deserializer"
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Named {
+        name: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct RawNamed<'a> {
+        #[serde(borrow)]
+        name: Bytes<'a>,
+    }
+
+    #[test]
+    fn test_str_coercion() {
+        let strict = DeserializerConfig::new();
+        let utf8 = DeserializerConfig::new().str_coercion(StrCoercion::Utf8);
+        let lossy = DeserializerConfig::new().str_coercion(StrCoercion::Lossy);
+
+        let bytes = to_bytes(&RawNamed {
+            name: Bytes("café".as_bytes()),
+        })
+        .unwrap();
+        let err = from_bytes_with_config::<Named>(&bytes, strict).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::TagParsingError(_)));
+        for config in [utf8, lossy] {
+            let res: Named = from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(res.name, "café");
+            let res: &str = from_bytes_with_config(&bytes[2..], config).unwrap();
+            assert_eq!(res, "café");
+        }
+
+        // latin-1 encoded
+        let bytes = to_bytes(&RawNamed {
+            name: Bytes(b"caf\xe9"),
+        })
+        .unwrap();
+        let err = from_bytes_with_config::<Named>(&bytes, utf8).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidStr(_)));
+        let res: Named = from_bytes_with_config(&bytes, lossy).unwrap();
+        assert_eq!(res.name, "caf\u{FFFD}");
+        // the replaced string can't be borrowed
+        assert!(from_bytes_with_config::<&str>(&bytes[2..], lossy).is_err());
+
+        // and the other way around
+        let bytes = to_bytes(&Named {
+            name: "café".into(),
+        })
+        .unwrap();
+        assert!(from_bytes_with_config::<RawNamed>(&bytes, strict).is_err());
+        let res: RawNamed = from_bytes_with_config(&bytes, utf8).unwrap();
+        assert_eq!(res.name, Bytes("café".as_bytes()));
+        let res: ByteBuf = from_bytes_with_config(&to_bytes(&"café").unwrap(), utf8).unwrap();
+        assert_eq!(res, ByteBuf("café".into()));
+    }
+
+    // newer version of `TestEnum`, with more variants
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum FutureTestEnum {
+        Unit,
+        NewType(u8),
+        Tuple(f32, String),
+        Struct { a: f64, b: Vec<u16> },
+        FutureUnit,
+        FutureNewType(Option<String>),
+        FutureTuple(u8, TestStruct),
+        FutureStruct { a: Vec<TestEnum>, b: (char, i64) },
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum FallbackTestEnum {
+        Unit,
+        NewType(u8),
+        Tuple(f32, String),
+        Struct {
+            a: f64,
+            b: Vec<u16>,
+        },
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[test]
+    fn test_unknown_variant_fallback() {
+        let value = vec![
+            FutureTestEnum::NewType(3),
+            FutureTestEnum::FutureUnit,
+            FutureTestEnum::FutureNewType(Some("new".into())),
+            FutureTestEnum::FutureTuple(
+                1,
+                TestStruct {
+                    a: 2,
+                    b: "b".into(),
+                },
+            ),
+            FutureTestEnum::FutureStruct {
+                a: vec![TestEnum::Unit, TestEnum::Tuple(1.0, "c".into())],
+                b: ('é', -4),
+            },
+            FutureTestEnum::Unit,
+        ];
+        let bytes = to_bytes(&value).unwrap();
+
+        let res: Vec<FallbackTestEnum> = from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            res,
+            [
+                FallbackTestEnum::NewType(3),
+                FallbackTestEnum::Unknown,
+                FallbackTestEnum::Unknown,
+                FallbackTestEnum::Unknown,
+                FallbackTestEnum::Unknown,
+                FallbackTestEnum::Unit,
+            ]
+        );
+
+        // without the fallback the unknown variant is still an error
+        let bytes = to_bytes(&FutureTestEnum::FutureTuple(
+            1,
+            TestStruct {
+                a: 2,
+                b: "b".into(),
+            },
+        ))
+        .unwrap();
+        assert!(from_bytes::<TestEnum>(&bytes).is_err());
+    }
+
+    // tuple variant of `len` fields
+    struct WideVariant(usize);
+
+    impl Serialize for WideVariant {
+        fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            use serde::ser::SerializeTupleVariant;
+            let mut variant = serializer.serialize_tuple_variant("Wide", 0, "Variant", self.0)?;
+            for _ in 0..self.0 {
+                variant.serialize_field(&0u8)?;
+            }
+            variant.end()
+        }
+    }
+
+    #[test]
+    fn test_too_many_fields() {
+        let bytes = to_bytes(&WideVariant(255)).unwrap();
+        assert_eq!(bytes[5], 255);
+        assert!(matches!(
+            to_bytes(&WideVariant(256)),
+            Err(Error::TooManyFields { len: 256 })
+        ));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+    enum Color {
+        Red,
+        Green,
+        Blue,
+    }
+
+    // same as `Color`, with reordered variants
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum ReorderedColor {
+        Blue,
+        Red,
+        Green,
+    }
+
+    #[test]
+    fn test_unit_variants_by_name() {
+        let value = vec![Color::Green, Color::Blue, Color::Red];
+
+        let config = SerializerConfig::new().unit_variants_by_name(true);
+        let mut v: Vec<u8> = Vec::new();
+        value
+            .serialize(&mut Serializer::with_config(&mut v, config))
+            .unwrap();
+
+        let variant_bytes = ["Green", "Blue", "Red"].into_iter().flat_map(|name| {
+            [Tag::String.into()]
+                .into_iter()
+                .chain((name.len() as u64).to_be_bytes())
+                .chain(name.bytes())
+        });
+        let expected = [Tag::Seq.into()]
+            .into_iter()
+            .chain(3u64.to_be_bytes())
+            .chain(variant_bytes)
+            .collect::<Vec<u8>>();
+        assert_eq!(v, expected);
+
+        let res: Vec<ReorderedColor> = from_bytes(&v).unwrap();
+        assert_eq!(
+            res,
+            [
+                ReorderedColor::Green,
+                ReorderedColor::Blue,
+                ReorderedColor::Red
+            ]
+        );
+
+        let res: Vec<Color> = from_bytes(&v).unwrap();
+        assert_eq!(res, value);
+
+        // by index, the reordering is not detected
+        let v = to_bytes(&value).unwrap();
+        let res: Vec<ReorderedColor> = from_bytes(&v).unwrap();
+        assert_eq!(
+            res,
+            [
+                ReorderedColor::Red,
+                ReorderedColor::Green,
+                ReorderedColor::Blue
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_map_keys() {
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct Point {
+            x: u8,
+            y: u8,
+        }
+
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        enum Side {
+            Left,
+        }
+
+        fn to_bytes_strict<T: Serialize>(
+            value: &T,
+            config: SerializerConfig,
+        ) -> crate::Result<Vec<u8>> {
+            let mut v = Vec::new();
+            value.serialize(&mut Serializer::with_config(
+                crate::write::VecWriter(&mut v),
+                config,
+            ))?;
+            Ok(v)
+        }
+
+        let config = SerializerConfig::new().strict_map_keys(true);
+        let value = std::collections::HashMap::from([(vec![1u8, 2], 3u32)]);
+        // accepted without the option
+        assert!(to_bytes(&value).is_ok());
+        assert_eq!(
+            to_bytes_strict(&value, config),
+            Err(Error::CompositeMapKey { tag: Tag::Seq })
+        );
+        let value = BTreeMap::from([((1u8, 2u8), 3u32)]);
+        assert_eq!(
+            to_bytes_strict(&value, config),
+            Err(Error::CompositeMapKey { tag: Tag::Tuple })
+        );
+        let value = BTreeMap::from([(BTreeMap::from([(1u8, 2u8)]), 3u32)]);
+        assert_eq!(
+            to_bytes_strict(&value, config),
+            Err(Error::CompositeMapKey { tag: Tag::Map })
+        );
+        let value = BTreeMap::from([(Point { x: 1, y: 2 }, 3u32)]);
+        assert_eq!(
+            to_bytes_strict(&value, config),
+            Err(Error::CompositeMapKey { tag: Tag::Struct })
+        );
+        // the canonical mode buffers the keys, they are still checked
+        let value = BTreeMap::from([(vec![1u8], 3u32)]);
+        assert_eq!(
+            to_bytes_strict(&value, config.canonical(true)),
+            Err(Error::CompositeMapKey { tag: Tag::Seq })
+        );
+
+        // scalar, string and unit variant keys are accepted, and only the keys are checked
+        let value = BTreeMap::from([("a", 1u8), ("b", 2u8)]);
+        assert_eq!(to_bytes_strict(&value, config).ok(), to_bytes(&value).ok());
+        let value = BTreeMap::from([(1u64, vec!['c']), (2u64, vec![])]);
+        assert_eq!(to_bytes_strict(&value, config).ok(), to_bytes(&value).ok());
+        let value = BTreeMap::from([(Side::Left, 1u8)]);
+        assert_eq!(to_bytes_strict(&value, config).ok(), to_bytes(&value).ok());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct WideStruct {
+        a: u8,
+        b: u16,
+        c: String,
+        d: bool,
+        e: u32,
+    }
+
+    #[test]
+    fn test_field_error_context() {
+        let value = WideStruct {
+            a: 1,
+            b: 2,
+            c: "c".into(),
+            d: true,
+            e: 5,
+        };
+        let mut bytes = to_bytes(&value).unwrap();
+        // struct tag + len, then tag + 1 byte for a, tag + 2 for b, tag + 8 + 1 for c
+        assert_eq!(bytes[17], Tag::BoolTrue.into());
+        bytes[17] = Tag::U8.into();
+
+        let err = from_bytes::<WideStruct>(&bytes).unwrap_err();
+        assert_eq!(
+            err,
+            Error::FieldError {
+                index: 3,
+                tag: Some(Tag::U8),
+                error: Box::new(Error::TagParsingError(TagParsingError::unexpected(
+                    "Boolean",
+                    Tag::U8
+                )))
+            }
+        );
+        assert!(err.to_string().starts_with("while decoding field 3 (U8): "));
+    }
+
+    #[test]
+    fn test_value_as_number() {
+        let value = (1u8, -2i16, 300u32, -4_000_000_000i64, u64::MAX, -6i128);
+        let bytes = to_bytes(&value).unwrap();
+
+        let Value::Array(values) = from_bytes(&bytes).unwrap() else {
+            panic!("expected an array");
+        };
+        let numbers: Vec<_> = values.iter().filter_map(Value::as_number).collect();
+        assert_eq!(numbers.len(), values.len());
+        assert!(numbers.iter().all(Number::is_integer));
+
+        let sum: i128 = numbers
+            .iter()
+            .map(|n| n.to_i128())
+            .sum::<Option<i128>>()
+            .unwrap();
+        assert_eq!(sum, 1 - 2 + 300 - 4_000_000_000 + u64::MAX as i128 - 6);
+
+        let bytes = to_bytes(&vec![1.5f32, -2.0]).unwrap();
+        let Value::Array(values) = from_bytes(&bytes).unwrap() else {
+            panic!("expected an array");
+        };
+        let number = values[0].as_number().unwrap();
+        assert!(number.is_float());
+        assert_eq!(number.to_i128(), None);
+        assert_eq!(
+            values
+                .iter()
+                .filter_map(Value::as_number)
+                .map(Number::to_f64)
+                .sum::<f64>(),
+            -0.5
+        );
+
+        assert_eq!(Value::Bool(true).as_number(), None);
+        assert_eq!(Number::U128(u128::MAX).to_i128(), None);
+    }
+
+    #[test]
+    fn test_value_as_typed_vec() {
+        let bytes = to_bytes(&vec![1u16, 300, u16::MAX]).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(value.as_u64_vec(), Some(vec![1, 300, 65535]));
+        assert_eq!(value.as_typed_vec::<i32>(), Some(vec![1, 300, 65535]));
+        // an element doesn't fit
+        assert_eq!(value.as_typed_vec::<u8>(), None);
+        assert_eq!(value.as_typed_vec::<f64>(), None);
+
+        let bytes = to_bytes(&(1u8, -2i8)).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(value.as_u64_vec(), None);
+        assert_eq!(value.as_typed_vec::<i64>(), Some(vec![1, -2]));
+        assert_eq!(value.as_typed_vec::<i128>(), Some(vec![1, -2]));
+
+        // mixed types
+        let bytes = to_bytes(&(1u8, "a", true)).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(value.as_u64_vec(), None);
+        assert_eq!(value.as_typed_vec::<&str>(), None);
+
+        let bytes = to_bytes(&Vec::<u32>::new()).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(value.as_u64_vec(), Some(vec![]));
+        assert_eq!(Value::Number(Number::U64(1)).as_u64_vec(), None);
+
+        let value = Value::Array(vec![
+            Value::Number(Number::U128(u128::MAX)),
+            Value::Number(Number::U8(1)),
+        ]);
+        assert_eq!(value.as_typed_vec::<u128>(), Some(vec![u128::MAX, 1]));
+        let value = Value::Array(vec![Value::String("a"), Value::OwnedString("b".into())]);
+        assert_eq!(value.as_typed_vec::<&str>(), Some(vec!["a", "b"]));
+        let value = Value::Array(vec![
+            Value::Number(Number::F32(0.5)),
+            Value::Number(Number::F64(2.0)),
+        ]);
+        assert_eq!(value.as_typed_vec::<f64>(), Some(vec![0.5, 2.0]));
+        assert_eq!(value.as_u64_vec(), None);
+        assert_eq!(bool::try_from(&Value::Unit), Err(value::ConversionError));
+    }
+
+    #[test]
+    fn test_value_to_compact_bytes() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), vec![Some(1i64), None, Some(-3)]);
+        map.insert("b".to_string(), vec![]);
+        let bytes = to_bytes(&map).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        let compact = value::to_compact_bytes(&value).unwrap();
+        assert_eq!(compact, crate::compact::to_bytes(&map).unwrap());
+        let res: BTreeMap<String, Vec<Option<i64>>> = crate::compact::from_bytes(&compact).unwrap();
+        assert_eq!(res, map);
+
+        let seq = vec![Some("foo".to_string()), None, Some(String::new())];
+        let bytes = to_bytes(&seq).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        let compact = value::to_compact_bytes(&value).unwrap();
+        let res: Vec<Option<String>> = crate::compact::from_bytes(&compact).unwrap();
+        assert_eq!(res, seq);
+
+        let bytes = to_bytes(&(42u16, Bytes(&[1, 2, 3]), 'c')).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        let compact = value::to_compact_bytes(&value).unwrap();
+        // the tuple is now an array, prefixed by its length
+        assert_eq!(compact[..8], 3u64.to_be_bytes());
+        let res: (u16, ByteBuf, char) = crate::compact::from_bytes(&compact[8..]).unwrap();
+        assert_eq!(res, (42, ByteBuf::from(vec![1, 2, 3]), 'c'));
+
+        // the struct is now a map of the field indexes, which the compact format can't read as a struct
+        let test_struct = TestStruct {
+            a: 1,
+            b: "b".into(),
+        };
+        let bytes = to_bytes(&test_struct).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        let compact = value::to_compact_bytes(&value).unwrap();
+        assert_eq!(compact[..8], 2u64.to_be_bytes());
+        assert!(crate::compact::from_bytes::<TestStruct>(&compact).is_err());
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Message<T> {
+        id: u8,
+        payload: T,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct RawMessage<'a> {
+        id: u8,
+        #[serde(borrow)]
+        payload: RawValue<'a>,
+    }
+
+    #[test]
+    fn test_raw_value() {
+        let payload = (
+            TestStruct {
+                a: 8,
+                b: "foo".into(),
+            },
+            vec![TestEnum::Unit, TestEnum::Tuple(1.5, "bar".into())],
+            UnsizedSeq(&[1u16, 2]),
+        );
+        let bytes = to_bytes(&Message {
+            id: 3,
+            payload: &payload,
+        })
+        .unwrap();
+        let res: RawMessage = from_bytes(&bytes).unwrap();
+        assert_eq!(res.id, 3);
+        let payload_bytes = to_bytes(&payload).unwrap();
+        assert_eq!(*res.payload, payload_bytes);
+        // the captured bytes are a suffix of the input
+        assert!(bytes.ends_with(&res.payload));
+
+        // splice them into a new message
+        let mut spliced = to_bytes(&Message { id: 4, payload: () }).unwrap();
+        spliced.pop();
+        spliced.extend_from_slice(&res.payload);
+        let res: Message<(TestStruct, Vec<TestEnum>, Vec<u16>)> = from_bytes(&spliced).unwrap();
+        assert_eq!(res.id, 4);
+        assert_eq!(res.payload.0, payload.0);
+        assert_eq!(res.payload.1, payload.1);
+        assert_eq!(res.payload.2, payload.2 .0);
+
+        // the value must be complete, it ends with the end marker of an unsized sequence
+        let err = from_bytes::<RawMessage>(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(err.root_cause(), &Error::NeedMoreData);
+        // the compact format can't find the end of a value
+        assert!(crate::compact::from_bytes::<RawValue>(&payload_bytes).is_err());
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        let payload = (
+            TestStruct {
+                a: 8,
+                b: "foo".into(),
+            },
+            vec![TestEnum::Unit, TestEnum::Tuple(1.5, "bar".into())],
+        );
+        let cached = to_bytes(&payload).unwrap();
+        let bytes = to_bytes(&Message {
+            id: 3,
+            payload: RawBytes(&cached),
+        })
+        .unwrap();
+        assert_eq!(
+            bytes,
+            to_bytes(&Message {
+                id: 3,
+                payload: &payload
+            })
+            .unwrap()
+        );
+        let res: Message<(TestStruct, Vec<TestEnum>)> = from_bytes(&bytes).unwrap();
+        assert_eq!(res.id, 3);
+        assert_eq!(res.payload.0, payload.0);
+        assert_eq!(res.payload.1, payload.1);
+
+        // a captured value can be forwarded
+        let raw: RawMessage = from_bytes(&bytes).unwrap();
+        let forwarded = to_bytes(&Message {
+            id: 4,
+            payload: RawBytes::from(raw.payload),
+        })
+        .unwrap();
+        assert_eq!(
+            forwarded,
+            to_bytes(&Message {
+                id: 4,
+                payload: &payload
+            })
+            .unwrap()
+        );
+
+        // the checksums of the enclosing containers cover the spliced bytes
+        let config = SerializerConfig::new().container_checksums(true);
+        let mut cached = Vec::new();
+        payload
+            .serialize(&mut Serializer::with_config(&mut cached, config))
+            .unwrap();
+        let mut bytes = Vec::new();
+        let message = Message {
+            id: 3,
+            payload: RawBytes(&cached),
+        };
+        message
+            .serialize(&mut Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        let mut expected = Vec::new();
+        let message = Message {
+            id: 3,
+            payload: &payload,
+        };
+        message
+            .serialize(&mut Serializer::with_config(&mut expected, config))
+            .unwrap();
+        assert_eq!(bytes, expected);
+        let de_config = DeserializerConfig::new().container_checksums(true);
+        let res: Message<(TestStruct, Vec<TestEnum>)> =
+            from_bytes_with_config(&bytes, de_config).unwrap();
+        assert_eq!(res.payload.0, payload.0);
+
+        // debug builds check that the bytes are exactly one value
+        let cached = to_bytes(&payload).unwrap();
+        let err = to_bytes(&RawBytes(&cached[..cached.len() - 1])).unwrap_err();
+        assert!(matches!(err, Error::Eof));
+        let err = to_bytes(&RawBytes(
+            &[cached.as_slice(), &[Tag::Unit.into()]].concat(),
+        ))
+        .unwrap_err();
+        assert!(matches!(err, Error::TrailingBytes(1)));
+
+        // other serializers write a byte array
+        let compact = crate::compact::to_bytes(&RawBytes(&cached)).unwrap();
+        assert_eq!(compact, crate::compact::to_bytes(&Bytes(&cached)).unwrap());
+    }
+
+    #[test]
+    fn test_non_zero() {
+        use core::num::{NonZeroI64, NonZeroU32, NonZeroU8};
+
+        let value = (
+            NonZeroU8::MAX,
+            NonZeroU32::new(42).unwrap(),
+            NonZeroI64::new(-1).unwrap(),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes, to_bytes(&(u8::MAX, 42u32, -1i64)).unwrap());
+        assert_eq!(
+            from_bytes::<(NonZeroU8, NonZeroU32, NonZeroI64)>(&bytes),
+            Ok(value)
+        );
+
+        let bytes = to_bytes(&(1u8, 42u32, 0i64)).unwrap();
+        let err = from_bytes::<(NonZeroU8, NonZeroU32, NonZeroI64)>(&bytes).unwrap_err();
+        assert!(matches!(err, Error::Message(msg) if msg.contains("nonzero")));
+        // the tag is still checked before the value
+        let bytes = to_bytes(&(1u8, 42u64, 1i64)).unwrap();
+        assert!(matches!(
+            from_bytes::<(NonZeroU8, NonZeroU32, NonZeroI64)>(&bytes),
+            Err(Error::TagParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn test_number_eq() {
+        let two_53 = 1u64 << 53;
+        // (a, b, a == b)
+        let table = [
+            (Number::U8(1), Number::U64(1), true),
+            (Number::I8(-1), Number::I64(-1), true),
+            (Number::I32(-1), Number::U32(u32::MAX), false),
+            (Number::I64(-1), Number::U64(u64::MAX), false),
+            (Number::U128(u128::MAX), Number::I128(-1), false),
+            (Number::U128(u128::MAX), Number::U128(u128::MAX), true),
+            (Number::I128(i128::MIN), Number::I128(i128::MIN), true),
+            (Number::U64(two_53), Number::F64(two_53 as f64), true),
+            (Number::U64(two_53 + 1), Number::F64(two_53 as f64), false),
+            (Number::I16(-3), Number::F32(-3.0), true),
+            (Number::U8(1), Number::F64(1.5), false),
+            (Number::U8(0), Number::F64(-0.0), true),
+            (Number::F32(-0.0), Number::F64(0.0), true),
+            (Number::F32(0.5), Number::F64(0.5), true),
+            (Number::F32(0.1), Number::F64(0.1), false),
+            (Number::F64(f64::NAN), Number::F64(f64::NAN), false),
+            (Number::U128(u128::MAX), Number::F64(f64::INFINITY), false),
+            (Number::U128(1 << 127), Number::F64(2f64.powi(127)), true),
+            (
+                Number::I128(i128::MIN),
+                Number::F64(-(2f64.powi(127))),
+                true,
+            ),
+        ];
+        for (a, b, eq) in table {
+            assert_eq!(a == b, eq, "{:?} == {:?}", a, b);
+            assert_eq!(b == a, eq, "{:?} == {:?}", b, a);
+        }
+
+        assert!(Number::U8(1).bitwise_eq(&Number::U8(1)));
+        assert!(!Number::U8(1).bitwise_eq(&Number::U64(1)));
+        assert!(Number::F64(f64::NAN).bitwise_eq(&Number::F64(f64::NAN)));
+        assert!(!Number::F64(0.0).bitwise_eq(&Number::F64(-0.0)));
+
+        // values from producers using different integer widths are equal
+        let small = to_bytes(&(1u8, vec![Some(-2i8)])).unwrap();
+        let large = to_bytes(&(1u64, vec![Some(-2i32)])).unwrap();
+        assert_ne!(small, large);
+        assert_eq!(
+            from_bytes::<Value>(&small).unwrap(),
+            from_bytes::<Value>(&large).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_value_compact_debug() {
+        let mut map = BTreeMap::new();
+        map.insert("long".to_string(), "é".repeat(40));
+        map.insert("short".to_string(), "abc".to_string());
+        let value = (
+            (0..100u32).collect::<Vec<_>>(),
+            ByteBuf((0..=255).collect()),
+            map,
+            Some(vec![vec![vec![1u8]]]),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+        let limits = DebugLimits::new()
+            .max_str_len(5)
+            .max_bytes_len(3)
+            .max_elements(3)
+            .max_depth(3);
+        assert_eq!(
+            format!("{:?}", value.compact_debug(limits)),
+            "Array [Array [U32(0), U32(1), U32(2), ... 100 elements], Bytes([0, 1, 2, ... 256 bytes]), \
+             Object {String(\"long\"):String(\"éé\"... 80 bytes),String(\"short\"):String(\"abc\")}, \
+             ... 4 elements]"
+        );
+        let value = value.try_into_array().unwrap().pop().unwrap();
+        assert_eq!(
+            format!("{:?}", value.compact_debug(limits)),
+            "Option Some(Array [Array [Array [... 1 elements]]])"
+        );
+        assert_eq!(
+            format!("{:?}", value.compact_debug(DebugLimits::new())),
+            format!("{:?}", value)
+        );
+    }
+
+    #[test]
+    fn test_value_parse() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), vec![Some(1i64), None, Some(-3)]);
+        map.insert("b".to_string(), vec![]);
+        let value = (
+            map,
+            TestStruct {
+                a: 8,
+                b: "foo".into(),
+            },
+            Bytes(&[1, 2]),
+            (),
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let expected: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(Value::parse(&bytes), Ok(expected));
+
+        let bytes = to_bytes(&UnsizedSeq(&[1u16, 2, 3])).unwrap();
+        let expected: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(Value::parse(&bytes), Ok(expected));
+
+        let bytes = to_bytes(&vec![
+            TestEnum::Unit,
+            TestEnum::NewType(3),
+            TestEnum::Tuple(1.5, "foo".into()),
+            TestEnum::Struct { a: 2.0, b: vec![4] },
+        ])
+        .unwrap();
+        let expected: Value = from_bytes(&bytes).unwrap();
+        assert_eq!(Value::parse(&bytes), Ok(expected));
+
+        let bytes = to_bytes(&vec![TestEnum::Unit, TestEnum::NewType(3)]).unwrap();
+        let Ok(Value::Array(values)) = Value::parse(&bytes) else {
+            panic!("expected an array");
+        };
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            value::to_compact_bytes(&values[1]).unwrap(),
+            [0, 0, 0, 1, 3]
+        );
+
+        let mut bytes = to_bytes(&1u8).unwrap();
+        bytes.push(0);
+        assert_eq!(Value::parse(&bytes), Err(Error::TrailingBytes(1)));
+        assert!(Value::parse(&[Tag::UnsizedSeqEnd.into()]).is_err());
+        assert!(Value::parse(&bytes[..1]).is_err());
+    }
+
+    // `Some(Some(...([[[...()]]])))`, nested `2 * depth` times
+    fn deep_nesting(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![u8::from(Tag::Some); depth];
+        for _ in 0..depth {
+            bytes.push(Tag::Seq.into());
+            bytes.extend(1u64.to_be_bytes());
+        }
+        bytes.push(Tag::Unit.into());
+        bytes
+    }
+
+    // Take a value from `deep_nesting` apart and return its depth, dropping it would recurse.
+    fn unnest(mut value: Value) -> usize {
+        let mut depth = 0;
+        loop {
+            value = match value {
+                Value::Option(Some(inner)) => *inner,
+                Value::Array(mut values) if values.len() == 1 => values.pop().unwrap(),
+                Value::Unit => return depth,
+                value => panic!("unexpected value {:?}", value),
+            };
+            depth += 1;
+        }
+    }
+
+    fn with_small_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_value_parse_deep_nesting() {
+        let bytes = deep_nesting(10_000);
+        let depth = with_small_stack(move || unnest(Value::parse(&bytes).unwrap()));
+        assert_eq!(depth, 20_000);
+    }
+
+    #[test]
+    fn test_value_try_into_map() {
+        let mut map = BTreeMap::new();
+        map.insert("a", vec![1u8, 2]);
+        map.insert("b", vec![]);
+        let bytes = to_bytes(&map).unwrap();
+        let value = Value::parse(&bytes).unwrap();
+
+        let res = value.try_into_map().unwrap();
+        assert_eq!(res.keys().collect::<Vec<_>>(), ["a", "b"]);
+        let values = res["a"].clone().try_into_array().unwrap();
+        assert_eq!(
+            values,
+            [Value::Number(Number::U8(1)), Value::Number(Number::U8(2))]
+        );
+        assert_eq!(res["b"].clone().try_into_array(), Ok(vec![]));
+
+        // owned and borrowed keys, the last value of a repeated key is kept
+        let map: value::ValueMap = [
+            (Value::OwnedString("a".into()), Value::Unit),
+            (Value::String("b"), Value::Bool(true)),
+            (Value::String("a"), Value::Bool(false)),
+        ]
+        .into_iter()
+        .collect();
+        let res = Value::Map(map).try_into_map().unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res["a"], Value::Bool(false));
+        assert_eq!(res["b"], Value::Bool(true));
+
+        // the value is given back on failure
+        let mut map = BTreeMap::new();
+        map.insert(1u32, "a");
+        let bytes = to_bytes(&map).unwrap();
+        let value = Value::parse(&bytes).unwrap();
+        assert_eq!(value.clone().try_into_map(), Err(value.clone()));
+        assert_eq!(value.clone().try_into_array(), Err(value));
+
+        let value = Value::Array(vec![Value::Unit]);
+        assert_eq!(value.clone().try_into_map(), Err(value));
+    }
+
+    // map serialized with its entries in the given order, keeping the repeated keys
+    struct OrderedMap<'a>(&'a [(&'a str, u8)]);
+
+    impl<'a> Serialize for OrderedMap<'a> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+        }
+    }
+
+    #[test]
+    fn test_value_map_entries() {
+        let entries = [("b", 1), ("a", 2), ("b", 3), ("c", 4)];
+        let bytes = to_bytes(&OrderedMap(&entries)).unwrap();
+        let value = Value::parse(&bytes).unwrap();
+        let Value::Map(map) = &value else {
+            panic!("expected a map, got {:?}", value);
+        };
+
+        let decoded: Vec<_> = map
+            .entries()
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let expected: Vec<_> = entries
+            .iter()
+            .map(|&(key, value)| (Value::String(key), Value::Number(Number::U8(value))))
+            .collect();
+        assert_eq!(decoded, expected);
+        // `get` only finds the first entry of a repeated key
+        assert_eq!(
+            map.get(&Value::String("b")),
+            Some(&Value::Number(Number::U8(1)))
+        );
+
+        // re-encoding keeps the order and the repeated keys
+        assert_eq!(to_bytes(&value).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_value_merge() {
+        fn map<'de>(entries: impl IntoIterator<Item = (&'de str, Value<'de>)>) -> Value<'de> {
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Value::String(key), value))
+                    .collect(),
+            )
+        }
+        let port = |port| Value::Number(Number::U16(port));
+        let features = |names: &[&'static str]| {
+            Value::Array(names.iter().map(|&name| Value::String(name)).collect())
+        };
+
+        let base = to_bytes(&map([
+            ("name", Value::String("app")),
+            (
+                "server",
+                map([("host", Value::String("localhost")), ("port", port(80))]),
+            ),
+            ("features", features(&["logs", "metrics"])),
+            ("limits", map([("users", Value::Number(Number::U32(10)))])),
+        ]))
+        .unwrap();
+        let overlay = to_bytes(&map([
+            (
+                "server",
+                map([("port", port(8080)), ("tls", Value::Bool(true))]),
+            ),
+            ("features", features(&["traces"])),
+            ("limits", Value::Option(None)),
+            ("debug", Value::Bool(false)),
+        ]))
+        .unwrap();
+        let base: Value = from_bytes(&base).unwrap();
+        let overlay: Value = from_bytes(&overlay).unwrap();
+
+        let merged = value::merge(base.clone(), overlay);
+        assert_eq!(
+            merged,
+            map([
+                ("name", Value::String("app")),
+                (
+                    "server",
+                    map([
+                        ("host", Value::String("localhost")),
+                        ("port", port(8080)),
+                        ("tls", Value::Bool(true)),
+                    ])
+                ),
+                // arrays and values that are not maps on both sides are replaced
+                ("features", features(&["traces"])),
+                ("limits", Value::Option(None)),
+                ("debug", Value::Bool(false)),
+            ])
+        );
+
+        // owned keys match the borrowed ones
+        let overlay = Value::Map(
+            [(Value::OwnedString("name".into()), Value::String("other"))]
+                .into_iter()
+                .collect(),
+        );
+        let merged = value::merge(base.clone(), overlay);
+        let Value::Map(merged) = merged else {
+            panic!("expected a map, got {:?}", merged);
+        };
+        assert_eq!(merged.len(), 4);
+        assert_eq!(
+            merged.get(&Value::String("name")),
+            Some(&Value::String("other"))
+        );
+
+        // a value that is not a map replaces the whole base
+        assert_eq!(value::merge(base, Value::Unit), Value::Unit);
+    }
+
+    #[test]
+    fn test_value_walk() {
+        let mut map = BTreeMap::new();
+        map.insert("a", vec![Some("foo"), None, Some("bar")]);
+        map.insert("b", vec![]);
+        let value = (map, TestEnum::Tuple(1.5, "baz".into()), 'c', "qux");
+        let bytes = to_bytes(&value).unwrap();
+        let mut value = Value::parse(&bytes).unwrap();
+
+        let mut strings = Vec::new();
+        let flow = value.walk(|path, value| {
+            if let Value::String(s) = value {
+                strings.push((path.clone(), *s));
+            }
+            ControlFlow::Continue(())
+        });
+        assert_eq!(flow, ControlFlow::Continue(()));
+        let key = Value::String("a");
+        let variant = Value::Number(Number::U32(2));
+        assert_eq!(
+            strings
+                .iter()
+                .map(|(path, s)| (path.segments(), *s))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    &[
+                        PathSegment::Index(0),
+                        PathSegment::Key(&key),
+                        PathSegment::Index(0),
+                        PathSegment::Some
+                    ][..],
+                    "foo"
+                ),
+                (
+                    &[
+                        PathSegment::Index(0),
+                        PathSegment::Key(&key),
+                        PathSegment::Index(2),
+                        PathSegment::Some
+                    ],
+                    "bar"
+                ),
+                (
+                    &[
+                        PathSegment::Index(1),
+                        PathSegment::Variant(&variant),
+                        PathSegment::Index(1)
+                    ],
+                    "baz"
+                ),
+                (&[PathSegment::Index(3)], "qux"),
+            ]
+        );
+        drop(strings);
+
+        // stop at the first string
+        let mut visited = 0;
+        let flow = value.walk(|_, value| {
+            visited += 1;
+            match value {
+                Value::String(_) => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(()),
+            }
+        });
+        assert_eq!(flow, ControlFlow::Break(()));
+        // root, map, "a" array, Some, "foo"
+        assert_eq!(visited, 5);
+
+        // replace the borrowed strings
+        let flow = value.walk_mut(|_, value| {
+            if let Value::String(s) = value {
+                *value = Value::OwnedString(s.to_uppercase());
+            }
+            ControlFlow::Continue(())
+        });
+        assert_eq!(flow, ControlFlow::Continue(()));
+        let mut owned = Vec::new();
+        let _ = value.walk(|path, value| {
+            assert!(!matches!(value, Value::String(_)));
+            if let Value::OwnedString(s) = value {
+                owned.push((path.len(), s.as_str()));
+            }
+            ControlFlow::Continue(())
+        });
+        assert_eq!(owned, [(4, "FOO"), (4, "BAR"), (3, "BAZ"), (1, "QUX")]);
+
+        // map keys are left as is
+        let Value::Array(values) = &value else {
+            panic!("expected an array");
+        };
+        let Value::Map(map) = &values[0] else {
+            panic!("expected a map");
+        };
+        let keys: Vec<_> = map.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, [&Value::String("a"), &Value::String("b")]);
+    }
+
+    #[test]
+    fn test_value_find() {
+        let mut map = BTreeMap::new();
+        map.insert(7u32, TestEnum::NewType(0xEF));
+        map.insert(
+            0xDEAD,
+            TestEnum::Struct {
+                a: 2.5,
+                b: vec![0xBEEF, 3],
+            },
+        );
+        let value = (Some(map), TestEnum::Tuple(1.0, "foo".into()));
+        let bytes = to_bytes(&value).unwrap();
+        let value = Value::parse(&bytes).unwrap();
+
+        let is_beef = |v: &Value| v.as_number().and_then(|n| n.to_i128()) == Some(0xBEEF);
+        let (path, found) = value.find(is_beef).unwrap();
+        assert_eq!(found, &Value::Number(Number::U16(0xBEEF)));
+        let key = Value::Number(Number::U32(0xDEAD));
+        let variant = Value::Number(Number::U32(3));
+        assert_eq!(
+            path.as_path().segments(),
+            [
+                PathSegment::Index(0),
+                PathSegment::Some,
+                PathSegment::Key(&key),
+                PathSegment::Variant(&variant),
+                PathSegment::Key(&Value::Number(Number::U64(1))),
+                PathSegment::Index(0),
+            ]
+        );
+        assert_eq!(path.last(), Some(&OwnedPathSegment::Index(0)));
+        assert_eq!(value.pointer(path.as_path().segments()), Some(found));
+
+        // the path doesn't borrow the value it was found in
+        let path = {
+            let other = Value::parse(&bytes).unwrap();
+            other.find(is_beef).unwrap().0
+        };
+        assert_eq!(value.pointer(path.as_path().segments()), Some(found));
+
+        // keys are only part of the paths
+        let is_dead = |v: &Value| v.as_number().and_then(|n| n.to_i128()) == Some(0xDEAD);
+        assert_eq!(value.find(is_dead), None);
+
+        let small = |v: &Value| matches!(v.as_number().and_then(|n| n.to_i128()), Some(0..=255));
+        let found = value.find_all(small);
+        let found: Vec<_> = found
+            .iter()
+            .map(|(path, v)| {
+                assert_eq!(value.pointer(path.as_path().segments()), Some(*v));
+                (path.len(), *v)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (4, &Value::Number(Number::U8(0xEF))),
+                (6, &Value::Number(Number::U16(3))),
+            ]
+        );
+
+        assert_eq!(value.pointer(&[]), Some(&value));
+        assert_eq!(value.pointer(&[PathSegment::Index(2)]), None);
+        assert_eq!(value.pointer(&[PathSegment::Some]), None);
+        let other_variant = Value::Number(Number::U32(1));
+        let path = [PathSegment::Index(1), PathSegment::Variant(&other_variant)];
+        assert_eq!(value.pointer(&path), None);
+    }
+
+    #[test]
+    fn test_value_walk_deep_nesting() {
+        let bytes = deep_nesting(10_000);
+        let depth = with_small_stack(move || {
+            let mut value = Value::parse(&bytes).unwrap();
+            let mut max_depth = 0;
+            let _ = value.walk_mut(|path, value| {
+                max_depth = path.len();
+                if path.len() == 20_000 {
+                    *value = Value::Option(Some(Box::new(Value::Unit)));
+                }
+                ControlFlow::Continue(())
+            });
+            assert_eq!(max_depth, 20_001);
+            unnest(value)
+        });
+        assert_eq!(depth, 20_001);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Point(u8, u8, u8);
+
+    // Reads only the first 2 fields of a `Point`.
+    #[derive(Debug, PartialEq)]
+    struct Prefix(u8, u8);
+
+    impl<'de> Deserialize<'de> for Prefix {
+        fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_tuple_struct("Point", 3, PrefixVisitor)
+        }
+    }
+
+    struct PrefixVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for PrefixVisitor {
+        type Value = Prefix;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a Point")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let a = seq.next_element()?.unwrap_or_default();
+            let b = seq.next_element()?.unwrap_or_default();
+            Ok(Prefix(a, b))
+        }
+    }
+
+    // Reads only the first element of a sequence.
+    #[derive(Debug, PartialEq)]
+    struct Head(u16);
+
+    impl<'de> Deserialize<'de> for Head {
+        fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_seq(HeadVisitor)
+        }
+    }
+
+    struct HeadVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for HeadVisitor {
+        type Value = Head;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            Ok(Head(seq.next_element()?.unwrap_or_default()))
+        }
+    }
+
+    #[test]
+    fn test_partial_seq_consumption() {
+        let bytes = to_bytes(&(Point(1, 2, 3), 4u8)).unwrap();
+        let res: (Prefix, u8) = from_bytes(&bytes).unwrap();
+        assert_eq!(res, (Prefix(1, 2), 4));
+
+        let value = (
+            vec![Point(1, 2, 3), Point(4, 5, 6)],
+            vec![7u16, 8, 9],
+            UnsizedSeq(&[10u16, 11, 12]),
+            13u8,
+        );
+        let bytes = to_bytes(&value).unwrap();
+        let res: (Vec<Prefix>, Head, Head, u8) = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            res,
+            (vec![Prefix(1, 2), Prefix(4, 5)], Head(7), Head(10), 13)
+        );
+    }
+
+    #[test]
+    fn test_seq_access_deserializer() {
+        let expected = HeadAndRest {
+            head: 1,
+            rest: vec![2, 3, 4],
+        };
+        let bytes = to_bytes(&(vec![1u32, 2, 3, 4], 5u8)).unwrap();
+        let res: (HeadAndRest, u8) = from_bytes(&bytes).unwrap();
+        assert_eq!(res, (expected, 5));
+
+        let bytes = to_bytes(&(1u32, 2u32, 3u32)).unwrap();
+        let config = DeserializerConfig::new().lenient_sequences(true);
+        let res: HeadAndRest = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res.rest, [2, 3]);
+    }
+
+    #[test]
+    fn test_phantom_data_and_empty_struct() {
+        let bytes = to_bytes(&PhantomData::<u32>).unwrap();
+        assert_eq!(bytes, [Tag::UnitStruct.into()]);
+        assert_eq!(from_bytes(&bytes), Ok(PhantomData::<u32>));
+
+        let bytes = to_bytes(&Empty {}).unwrap();
+        assert_eq!(bytes, [Tag::Struct.into(), 0]);
+        assert_eq!(from_bytes(&bytes), Ok(Empty {}));
+        let Value::Map(map) = from_bytes(&bytes).unwrap() else {
+            panic!("expected a map");
+        };
+        assert!(map.is_empty());
+
+        let value = WithPhantom {
+            a: 1,
+            marker: PhantomData,
+            b: Empty {},
+        };
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(from_bytes(&bytes), Ok(value));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Checked {
+        id: u32,
+        values: Vec<u16>,
+        variants: Vec<TestEnum>,
+        names: BTreeMap<u8, String>,
+    }
+
+    fn checked() -> Checked {
+        Checked {
+            id: 42,
+            values: vec![1, 2, 3],
+            variants: vec![
+                TestEnum::Unit,
+                TestEnum::NewType(4),
+                TestEnum::Tuple(5.5, "six".into()),
+                TestEnum::Struct {
+                    a: 7.5,
+                    b: vec![8, 9],
+                },
+            ],
+            names: [(10, "ten".into()), (11, "eleven".into())].into(),
+        }
+    }
+
+    fn to_bytes_with_checksums<T: Serialize>(value: &T) -> Vec<u8> {
+        let config = SerializerConfig::new().container_checksums(true);
+        let mut v: Vec<u8> = Vec::new();
+        value
+            .serialize(&mut Serializer::with_config(&mut v, config))
+            .unwrap();
+        v
+    }
+
+    #[test]
+    fn test_container_checksums() {
+        let config = DeserializerConfig::new().container_checksums(true);
+        let value = (checked(), UnsizedSeq(&[12u16, 13]), vec![Point(1, 2, 3)]);
+        let bytes = to_bytes_with_checksums(&value);
+
+        let res: (Checked, Vec<u16>, Vec<Point>) = from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res.0, value.0);
+        assert_eq!(res.1, [12, 13]);
+        assert_eq!(res.2, [Point(1, 2, 3)]);
+
+        // skipped values and unread elements are verified too
+        let res: (Checked, serde::de::IgnoredAny, Vec<Prefix>) =
+            from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(res.2, [Prefix(1, 2)]);
+        let bytes = to_bytes_with_checksums(&(&value.1, &value.2));
+        let res: Value = from_bytes_with_config(&bytes, config).unwrap();
+        assert!(matches!(res, Value::Array(_)));
+
+        // the encoding is different
+        let bytes = to_bytes_with_checksums(&value.0);
+        assert!(from_bytes::<Checked>(&bytes).is_err());
+        assert_ne!(bytes, to_bytes(&value.0).unwrap());
+    }
+
+    #[test]
+    fn test_container_checksums_corruption() {
+        let config = DeserializerConfig::new().container_checksums(true);
+        let bytes = to_bytes_with_checksums(&checked());
+
+        // struct header (2), id (5), `values` header (9) then its elements (3 * 3), the first element is 1
+        assert_eq!(bytes[16..19], [Tag::U16.into(), 0, 1]);
+
+        // without the checksums the value is silently changed
+        let mut corrupted = to_bytes(&checked()).unwrap();
+        corrupted[18] = 0;
+        let res: Checked = from_bytes(&corrupted).unwrap();
+        assert_eq!(res.values, [0, 2, 3]);
+
+        let mut corrupted = bytes.clone();
+        corrupted[18] = 0;
+        let err = from_bytes_with_config::<Checked>(&corrupted, config).unwrap_err();
+        assert!(matches!(err, Error::FieldError { index: 1, .. }));
+        assert_eq!(err.root_cause(), &Error::ChecksumMismatch { offset: 25 });
+
+        // every single bit flip is detected
+        for i in 0..bytes.len() * 8 {
+            let mut corrupted = bytes.clone();
+            corrupted[i / 8] ^= 1 << (i % 8);
+            let res = from_bytes_with_config::<Checked>(&corrupted, config);
+            assert!(res.is_err(), "bit {} flipped without error", i);
+        }
+    }
 }
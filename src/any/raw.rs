@@ -0,0 +1,89 @@
+use core::fmt;
+use core::ops::Deref;
+
+use serde::{de::Visitor, Deserialize, Serialize};
+
+use crate::bytes::Bytes;
+
+/// Name of the newtype struct used to ask the `any` deserializer for the raw bytes of the next value.
+pub(crate) const RAW_VALUE_TOKEN: &str = "$serde_bin::private::RawValue";
+
+/// Name of the newtype struct used to ask the `any` serializer to write the next byte array verbatim.
+pub(crate) const RAW_BYTES_TOKEN: &str = "$serde_bin::private::RawBytes";
+
+/// Bytes of one value of the `any` format, captured without being decoded.
+///
+/// The value is skipped by following its tags and the bytes are borrowed from the input,
+/// tag included, so they can be stored or forwarded and decoded later with `any::from_bytes`.
+///
+/// Only the `any` deserializer supports it, the compact format has no tags to find where a value ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawValue<'a>(pub &'a [u8]);
+
+impl<'a> Deref for RawValue<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+struct RawValueVisitor;
+
+impl<'de> Visitor<'de> for RawValueVisitor {
+    type Value = RawValue<'de>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the raw bytes of a value, only supported by the any format")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(RawValue(v))
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawValue<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(RAW_VALUE_TOKEN, RawValueVisitor)
+    }
+}
+
+/// Bytes of one already encoded value of the `any` format, written verbatim by the `any` serializer.
+///
+/// This is the serializing counterpart of `RawValue`, to splice a cached or forwarded value into a larger message
+/// without decoding and encoding it again. The bytes must contain exactly one value, tag included,
+/// encoded with the same `container_checksums` option as the message. This is checked in debug builds,
+/// and when `container_checksums` is enabled as the bytes are walked to update the checksums of the enclosing containers.
+///
+/// Other serializers serialize it as a byte array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawBytes<'a>(pub &'a [u8]);
+
+impl<'a> Deref for RawBytes<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a> From<RawValue<'a>> for RawBytes<'a> {
+    fn from(value: RawValue<'a>) -> Self {
+        RawBytes(value.0)
+    }
+}
+
+impl<'a> Serialize for RawBytes<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_BYTES_TOKEN, &Bytes(self.0))
+    }
+}
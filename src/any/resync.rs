@@ -0,0 +1,98 @@
+use super::{read_len, ChildCount, PayloadKind, Tag};
+use crate::{is_unsized_string_end, UNSIZED_STRING_END_MARKER};
+
+/// Return the offset of the first position of `input` where a value seems to start, to skip a corrupted region of a stream.
+///
+/// This is a heuristic, a position is accepted if:
+/// - it starts with a valid tag that is not `UnsizedSeqEnd`,
+/// - the length, variant index or children count following the tag fit in the rest of the input,
+/// - strings and chars are valid UTF-8, only the first 1024 bytes of the longer strings are checked
+///   and the end of a null terminated string is not searched past them,
+/// - the first child of a sequence, map, struct or tuple starts with a valid tag.
+///
+/// The value itself is not decoded, so a plausible position can still fail to deserialize, in which case
+/// the search should continue from the next byte. Call it with the input after the start of the value that failed,
+/// as the failing position can be plausible itself.
+pub fn resync(input: &[u8]) -> Option<usize> {
+    (0..input.len()).find(|&offset| is_plausible(&input[offset..]))
+}
+
+/// Number of bytes of a string inspected for each candidate position, so the search stays linear in the length
+/// of the input instead of going through the same long string again from every position inside it.
+const MAX_PROBE_LEN: usize = 1024;
+
+/// Return `true` if the bytes are valid UTF-8, apart from a char cut at the end if they are `truncated`.
+fn is_utf8_prefix(bytes: &[u8], truncated: bool) -> bool {
+    match core::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => truncated && err.error_len().is_none(),
+    }
+}
+
+fn is_plausible(input: &[u8]) -> bool {
+    let Ok((tag, rest)) = Tag::read(input) else {
+        return false;
+    };
+    match tag.payload_kind() {
+        PayloadKind::Fixed(len) => match rest.get(..len) {
+            Some(bytes) if matches!(tag, Tag::Char1 | Tag::Char2 | Tag::Char3 | Tag::Char4) => {
+                core::str::from_utf8(bytes).is_ok_and(|s| s.chars().count() == 1)
+            }
+            Some(_) => true,
+            None => false,
+        },
+        PayloadKind::LengthPrefixed => match read_len(rest) {
+            Ok((len, rest)) if len <= rest.len() => {
+                matches!(tag, Tag::ByteArray | Tag::Extension)
+                    || is_utf8_prefix(&rest[..len.min(MAX_PROBE_LEN)], len > MAX_PROBE_LEN)
+            }
+            _ => false,
+        },
+        PayloadKind::NullTerminated => {
+            // the marker may start on the last probed byte
+            let probed = rest
+                .len()
+                .min(MAX_PROBE_LEN + UNSIZED_STRING_END_MARKER.len() - 1);
+            let end = rest[..probed]
+                .windows(UNSIZED_STRING_END_MARKER.len())
+                .position(is_unsized_string_end);
+            match end {
+                Some(end) => core::str::from_utf8(&rest[..end]).is_ok(),
+                None if probed < rest.len() => is_utf8_prefix(&rest[..MAX_PROBE_LEN], true),
+                None => false,
+            }
+        }
+        PayloadKind::TaggedChildren { header, count } => {
+            let Some(rest) = rest.get(header..) else {
+                return false;
+            };
+            let (count, rest) = match count {
+                ChildCount::Fixed(count) => (Some(count), rest),
+                ChildCount::U8Len => match rest.split_first() {
+                    Some((&len, rest)) => (Some(len.into()), rest),
+                    None => return false,
+                },
+                ChildCount::U64Len => match read_len(rest) {
+                    Ok((len, rest)) => (Some(len), rest),
+                    Err(_) => return false,
+                },
+                ChildCount::U64PairsLen => match read_len(rest) {
+                    Ok((len, rest)) => (len.checked_mul(2), rest),
+                    Err(_) => return false,
+                },
+                ChildCount::UntilEndMarker => {
+                    return Tag::read(rest).is_ok();
+                }
+            };
+            match count {
+                Some(0) => true,
+                // every child takes at least one byte
+                Some(count) if count <= rest.len() => {
+                    matches!(Tag::read(rest), Ok((tag, _)) if tag != Tag::UnsizedSeqEnd)
+                }
+                _ => false,
+            }
+        }
+        PayloadKind::EndMarker => false,
+    }
+}
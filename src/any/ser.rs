@@ -3,25 +3,61 @@ use serde::{ser, serde_if_integer128, Serialize};
 #[cfg(feature = "std")]
 use std::io;
 
-use crate::error::{Error, Result};
-use crate::write::{BuffWriter, DummyWriter, EndOfBuff, Write};
+use crate::compact;
+use crate::config::SerializerConfig;
+use crate::error::{Error, Result, WriterError};
+#[cfg(feature = "pool")]
+use crate::pool::{BufferPool, PooledBytes};
+use crate::progress::{NoProgress, ProgressCallback, ProgressTracker};
+use crate::write::{BuffWriter, Crc32, DummyWriter, EndOfBuff, SizeAndCrcWriter, Write};
+#[cfg(feature = "alloc")]
+use crate::write::{HybridWriter, VecWriter};
 use crate::UNSIZED_STRING_END_MARKER;
 use core::fmt;
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::{string::ToString, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::ops::Range;
 
-use super::Tag;
+use super::de::walk_raw_value;
+use super::extension::{self, Extension, EXTENSION_TOKEN};
+use super::raw::RAW_BYTES_TOKEN;
+use super::{field_hash, ChildCount, PayloadKind, Tag};
+#[cfg(feature = "alloc")]
+use crate::canonical::BufferedValues;
+use crate::compat::UNKNOWN_VARIANT;
+#[cfg(feature = "alloc")]
+use crate::redact::{FieldAction, Filter};
 
-pub struct Serializer<T> {
+pub struct Serializer<T, P = NoProgress> {
     writer: T,
+    config: SerializerConfig,
+    // checksum of the innermost container, only used with `container_checksums`
+    crc: Crc32,
+    progress: ProgressTracker<P>,
+    // the next byte array is the content of a `RawBytes`
+    raw: bool,
+    // the next byte array is the payload of an extension with this tag, given by a `Value::Extension`
+    extension: Option<u8>,
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new(writer: W) -> Self {
-        Serializer { writer }
+        Self::with_config(writer, SerializerConfig::new())
+    }
+
+    pub fn with_config(writer: W, config: SerializerConfig) -> Self {
+        Serializer {
+            writer,
+            config,
+            crc: Crc32::new(),
+            progress: ProgressTracker::new(),
+            raw: false,
+            extension: None,
+        }
     }
 
     pub fn to_writer<T>(value: &T, writer: W) -> Result<usize, W::Error>
@@ -33,12 +69,54 @@ impl<W: Write> Serializer<W> {
         value.serialize(&mut serializer)
     }
 
-    fn write_byte(&mut self, byte: u8) -> Result<usize, W::Error> {
-        self.writer.write_byte(byte).map_err(Into::into)
+    /// Call `callback` with the number of bytes given to the writer so far, at most once every `interval` bytes.
+    /// Returning `ControlFlow::Break(())` stops the serialization with `Error::Cancelled`,
+    /// the writer then holds an incomplete value.
+    pub fn with_progress<P: ProgressCallback>(
+        self,
+        interval: usize,
+        callback: P,
+    ) -> Serializer<W, P> {
+        Serializer {
+            writer: self.writer,
+            config: self.config,
+            crc: self.crc,
+            progress: ProgressTracker::with_callback(interval, callback),
+            raw: self.raw,
+            extension: self.extension,
+        }
+    }
+}
+
+impl<W: Write, P: ProgressCallback> Serializer<W, P> {
+    pub fn config(&self) -> &SerializerConfig {
+        &self.config
     }
 
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, W::Error> {
-        self.writer.write_bytes(bytes).map_err(Into::into)
+        if self.config.container_checksums {
+            self.crc.update(bytes);
+        }
+        self.write_to_writer(bytes)
+    }
+
+    fn write_to_writer(&mut self, bytes: &[u8]) -> Result<usize, W::Error> {
+        let written_bytes = self.writer.write_bytes(bytes)?;
+        self.progress.advance(written_bytes)?;
+        Ok(written_bytes)
+    }
+
+    /// Write the bytes of an encoded value, the containers it holds have their own checksum.
+    fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<usize, W::Error> {
+        let checksums = self.config.container_checksums;
+        if checksums || cfg!(debug_assertions) {
+            let crc = walk_raw_value(bytes, checksums.then_some(self.crc))
+                .map_err(|err| err.map_writer_error(|err| match err {}))?;
+            if let Some(crc) = crc {
+                self.crc = crc;
+            }
+        }
+        self.write_to_writer(bytes)
     }
 
     fn write_byte_matrix(&mut self, bytes: &[&[u8]]) -> Result<usize, W::Error> {
@@ -49,7 +127,7 @@ impl<W: Write> Serializer<W> {
     }
 
     fn write_tag(&mut self, tag: Tag) -> Result<usize, W::Error> {
-        self.write_byte(tag.into())
+        self.write_bytes(&[tag.into()])
     }
 
     fn write_tag_then(&mut self, tag: Tag, bytes: &[u8]) -> Result<usize, W::Error> {
@@ -60,9 +138,73 @@ impl<W: Write> Serializer<W> {
         let len = bytes.len() as u64;
         self.write_byte_matrix(&[&[tag.into()], &len.to_be_bytes(), bytes])
     }
+
+    /// Write the content of a newtype struct registered as an extension with the compact format,
+    /// or with its encoder, after the tag of the extension and the length of the payload.
+    fn serialize_extension<T>(
+        &mut self,
+        extension: &Extension,
+        value: &T,
+    ) -> Result<usize, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let tag = extension.tag();
+        #[cfg(feature = "alloc")]
+        if let Some((encode, _)) = extension.codec() {
+            let mut content = Vec::new();
+            value
+                .serialize(&mut compact::Serializer::new(VecWriter(&mut content)))
+                .and_then(|_| {
+                    let mut payload = Vec::new();
+                    encode(&content, &mut payload).map(|()| payload)
+                })
+                .map_err(|err| err.map_writer_error(|never| match never {}))
+                .and_then(|payload| {
+                    let len = (payload.len() as u64).to_be_bytes();
+                    self.write_byte_matrix(&[&[tag], &len, &payload])
+                })
+        } else {
+            self.serialize_compact_extension(tag, value)
+        }
+        #[cfg(not(feature = "alloc"))]
+        self.serialize_compact_extension(tag, value)
+    }
+
+    fn serialize_compact_extension<T>(&mut self, tag: u8, value: &T) -> Result<usize, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let len = value
+            .serialize(&mut compact::Serializer::new(DummyWriter))
+            .map_err(|err| err.map_writer_error(|never| match never {}))?;
+        let wb = self.write_byte_matrix(&[&[tag], &(len as u64).to_be_bytes()])?;
+        let mut payload = PayloadWriter {
+            serializer: self,
+            error: None,
+        };
+        match value.serialize(&mut compact::Serializer::new(&mut payload)) {
+            Ok(written) => Ok(wb + written),
+            Err(Error::WriterError(PayloadWriteError)) => Err(payload.error.unwrap()),
+            Err(err) => Err(err.map_writer_error(|PayloadWriteError| unreachable!())),
+        }
+    }
+
+    /// Key of the fields of the structs, which are then serialized as maps.
+    fn struct_field_keys(&self) -> Option<FieldKeys> {
+        if self.config.hashed_field_names {
+            Some(FieldKeys::Hash)
+        } else if self.config.sparse_structs {
+            Some(FieldKeys::Index(0))
+        } else {
+            None
+        }
+    }
 }
 
-#[cfg(feature = "std")]
+/// Serialize the value into the writer, returning the number of bytes written.
+///
+/// Available without `std`, the writer can be a `BuffWriter` or, with `alloc`, a `&mut Vec<u8>`.
 pub fn to_writer<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
 where
     T: Serialize,
@@ -71,23 +213,133 @@ where
     Serializer::to_writer(value, writer)
 }
 
+/// Serialize a value behind an `erased_serde::Serialize` trait object into the writer,
+/// returning the number of bytes written. Gives the same bytes as `to_writer` with the value before its type was erased.
+#[cfg(feature = "erased")]
+pub fn to_writer_erased<W>(
+    value: &dyn erased_serde::Serialize,
+    writer: W,
+) -> Result<usize, W::Error>
+where
+    W: Write,
+{
+    erased_serde::serialize(value, &mut Serializer::new(writer))
+}
+
+/// Serialize the value then flush the writer, returning the number of bytes written.
+///
+/// Once it returns the writer holds exactly one complete value of that size and nothing is buffered,
+/// so the caller can close the frame containing it.
+pub fn to_writer_complete<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    let mut serializer = Serializer::new(writer);
+    let written_bytes = value.serialize(&mut serializer)?;
+    serializer.writer.flush()?;
+    Ok(written_bytes)
+}
+
+/// Writer of several values one after the other into the same sink, counting the bytes written.
+///
+/// Every value is serialized as if given alone to `to_writer`.
+///
+/// ```
+/// use serde_bin::any::{self, Writer};
+///
+/// let mut output = Vec::new();
+/// let mut writer = Writer::new(&mut output);
+/// writer.write(&1u8).unwrap();
+/// writer.write("a").unwrap();
+/// assert_eq!(writer.total(), output.len());
+/// ```
+pub struct Writer<W> {
+    serializer: Serializer<W>,
+    total: usize,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, SerializerConfig::new())
+    }
+
+    pub fn with_config(writer: W, config: SerializerConfig) -> Self {
+        Writer {
+            serializer: Serializer::with_config(writer, config),
+            total: 0,
+        }
+    }
+
+    /// Serialize the value after the ones already written, returning the number of bytes written for it.
+    pub fn write<T>(&mut self, value: &T) -> Result<usize, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serializer.crc = Crc32::new();
+        let written_bytes = value.serialize(&mut self.serializer)?;
+        self.total += written_bytes;
+        Ok(written_bytes)
+    }
+
+    /// Number of bytes written by all the calls to `write`.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn flush(&mut self) -> Result<(), W::Error> {
+        self.serializer.writer.flush().map_err(Into::into)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.serializer.writer
+    }
+}
+
 #[cfg(all(feature = "alloc", not(feature = "std")))]
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
     let mut output = Vec::new();
-    Serializer::to_writer(value, &mut output)?;
+    let mut writer = VecWriter(&mut output);
+    writer.reserve(get_serialized_size(value)?);
+    Serializer::to_writer(value, writer)?;
     Ok(output)
 }
 
+/// The value is serialized twice, first to compute its size, so the returned `Vec` is allocated once
+/// with the exact capacity.
 #[cfg(feature = "std")]
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>, io::Error>
+where
+    T: Serialize,
+{
+    let never = |err: Error<_>| err.map_writer_error(|err| match err {});
+    let mut output = Vec::new();
+    let mut writer = VecWriter(&mut output);
+    writer.reserve(get_serialized_size(value).map_err(never)?);
+    Serializer::to_writer(value, writer).map_err(never)?;
+    Ok(output)
+}
+
+/// Serialize a value behind an `erased_serde::Serialize` trait object into a new `Vec`.
+#[cfg(feature = "erased")]
+pub fn to_bytes_erased(value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    to_writer_erased(value, VecWriter(&mut output))?;
+    Ok(output)
+}
+
+/// Serialize the value with `SerializerConfig::canonical`, so equal values always give the same bytes.
+#[cfg(feature = "alloc")]
+pub fn to_bytes_canonical<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
     let mut output = Vec::new();
-    Serializer::to_writer(value, &mut output)?;
+    let config = SerializerConfig::new().canonical(true);
+    value.serialize(&mut Serializer::with_config(VecWriter(&mut output), config))?;
     Ok(output)
 }
 
@@ -100,6 +352,108 @@ where
     Ok(buff_writer)
 }
 
+/// Serialize the value into a new array, returning the array and the number of bytes written.
+///
+/// This is the simplest way to serialize without allocating:
+///
+/// ```
+/// let (buff, len) = serde_bin::any::to_array::<16, _>(&(1u8, 'c')).unwrap();
+/// let value: (u8, char) = serde_bin::any::from_bytes(&buff[..len]).unwrap();
+/// assert_eq!(value, (1, 'c'));
+/// ```
+pub fn to_array<const N: usize, T>(value: &T) -> Result<([u8; N], usize), EndOfBuff>
+where
+    T: Serialize,
+{
+    let mut buff = [0; N];
+    let len = to_array_ref(value, &mut buff)?;
+    Ok((buff, len))
+}
+
+/// Serialize the value into an existing array, returning the number of bytes written.
+pub fn to_array_ref<const N: usize, T>(value: &T, buff: &mut [u8; N]) -> Result<usize, EndOfBuff>
+where
+    T: Serialize,
+{
+    to_buff(value, buff).map(|writer| writer.len())
+}
+
+/// Serialize the values one after the other with a `Writer`, returning the range of the bytes of each of them,
+/// counted from the first byte written.
+///
+/// Any value can then be decoded alone with `from_bytes_batch`, without decoding the ones before it.
+#[cfg(feature = "alloc")]
+pub fn to_writer_batch<'a, W, T, I>(items: I, writer: W) -> Result<Vec<Range<usize>>, W::Error>
+where
+    W: Write,
+    T: 'a + ?Sized + Serialize,
+    I: IntoIterator<Item = &'a T>,
+{
+    let items = items.into_iter();
+    let mut ranges = Vec::with_capacity(items.size_hint().0);
+    let mut writer = Writer::new(writer);
+    for item in items {
+        let start = writer.total();
+        writer.write(item)?;
+        ranges.push(start..writer.total());
+    }
+    Ok(ranges)
+}
+
+/// Serialize the values one after the other into a new `Vec`, returning it with the range of the bytes of each value.
+///
+/// ```
+/// use serde_bin::tagged;
+///
+/// let (bytes, ranges) = tagged::to_bytes_batch(&["a", "bc", "def"]).unwrap();
+/// let values: Vec<&str> = tagged::from_bytes_batch(&bytes, &ranges[1..]).unwrap();
+/// assert_eq!(values, ["bc", "def"]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn to_bytes_batch<'a, T, I>(items: I) -> Result<(Vec<u8>, Vec<Range<usize>>)>
+where
+    T: 'a + ?Sized + Serialize,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut output = Vec::new();
+    let ranges = to_writer_batch(items, VecWriter(&mut output))?;
+    Ok((output, ranges))
+}
+
+/// Serialize the value into a buffer taken from the pool, given back to the pool when the returned bytes are dropped.
+#[cfg(feature = "pool")]
+pub fn to_pooled_bytes<'a, T>(value: &T, pool: &'a BufferPool) -> Result<PooledBytes<'a>>
+where
+    T: Serialize,
+{
+    let mut bytes = pool.take();
+    Serializer::to_writer(value, VecWriter(bytes.vec_mut()))?;
+    Ok(bytes)
+}
+
+/// Serialize the value into a buffer taken from a pool owned by the current thread, like `to_pooled_bytes`
+/// without sharing the pool between threads.
+#[cfg(feature = "pool")]
+pub fn to_local_pooled_bytes<T>(value: &T) -> Result<PooledBytes<'static>>
+where
+    T: Serialize,
+{
+    let mut bytes = BufferPool::take_local();
+    Serializer::to_writer(value, VecWriter(bytes.vec_mut()))?;
+    Ok(bytes)
+}
+
+/// Serialize the value into a `HybridWriter`, written inline if it takes at most `N` bytes.
+#[cfg(feature = "alloc")]
+pub fn to_hybrid<const N: usize, T>(value: &T) -> Result<HybridWriter<N>>
+where
+    T: Serialize,
+{
+    let mut writer = HybridWriter::new();
+    Serializer::to_writer(value, &mut writer)?;
+    Ok(writer)
+}
+
 pub fn get_serialized_size<T>(value: &T) -> Result<usize>
 where
     T: Serialize,
@@ -107,6 +461,16 @@ where
     Serializer::to_writer(value, DummyWriter)
 }
 
+/// Return the serialized size of the value and the CRC-32 of the serialized bytes, without storing them.
+pub fn get_serialized_size_and_crc<T>(value: &T) -> Result<(usize, u32)>
+where
+    T: Serialize,
+{
+    let mut writer = SizeAndCrcWriter::new();
+    Serializer::to_writer(value, &mut writer)?;
+    Ok(writer.finish())
+}
+
 macro_rules! implement_number {
     ($fn_name:ident, $t:ident, $tag:expr) => {
         fn $fn_name(self, value: $t) -> Result<Self::Ok, W::Error> {
@@ -115,18 +479,18 @@ macro_rules! implement_number {
     };
 }
 
-impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+impl<'a, W: Write, P: ProgressCallback> ser::Serializer for &'a mut Serializer<W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    type SerializeSeq = SeqSerializer<'a, W>;
-    type SerializeTuple = SeqSerializer<'a, W>;
-    type SerializeTupleStruct = SeqSerializer<'a, W>;
-    type SerializeTupleVariant = SeqSerializer<'a, W>;
-    type SerializeMap = SeqSerializer<'a, W>;
-    type SerializeStruct = SeqSerializer<'a, W>;
-    type SerializeStructVariant = SeqSerializer<'a, W>;
+    type SerializeSeq = SeqSerializer<'a, W, P>;
+    type SerializeTuple = SeqSerializer<'a, W, P>;
+    type SerializeTupleStruct = SeqSerializer<'a, W, P>;
+    type SerializeTupleVariant = SeqSerializer<'a, W, P>;
+    type SerializeMap = SeqSerializer<'a, W, P>;
+    type SerializeStruct = SeqSerializer<'a, W, P>;
+    type SerializeStructVariant = SeqSerializer<'a, W, P>;
 
     fn is_human_readable(&self) -> bool {
         false
@@ -164,6 +528,13 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, W::Error> {
+        if core::mem::take(&mut self.raw) {
+            return self.write_raw_bytes(v);
+        }
+        if let Some(tag) = self.extension.take() {
+            let len = v.len() as u64;
+            return self.write_byte_matrix(&[&[tag], &len.to_be_bytes(), v]);
+        }
         self.write_tag_then_seq(Tag::ByteArray, v)
     }
 
@@ -179,34 +550,52 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, W::Error> {
+        if self.config.unit_variants_by_name && !self.config.canonical {
+            return self.write_tag_then_seq(Tag::String, variant.as_bytes());
+        }
         self.write_tag_then(Tag::UnitVariant, &variant_index.to_be_bytes())
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        if name == RAW_BYTES_TOKEN {
+            self.raw = true;
+            return value.serialize(self);
+        }
+        if let Some(extension) = extension::find_by_name(self.config.extensions, name) {
+            return self.serialize_extension(extension, value);
+        }
         let mut wb = self.write_tag(Tag::NewTypeStruct)?;
         wb += value.serialize(self)?;
         Ok(wb)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        // the `UnknownVariant` already holds the tag and the index of the variant
+        if variant == UNKNOWN_VARIANT {
+            return value.serialize(self);
+        }
+        if variant == EXTENSION_TOKEN {
+            self.extension = Some(variant_index as u8);
+            return value.serialize(self);
+        }
         let mut wb = self.write_tag_then(Tag::NewTypeVariant, &variant_index.to_be_bytes())?;
         wb += value.serialize(self)?;
         Ok(wb)
@@ -219,6 +608,8 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
                 let written_bytes = self.write_tag_then(Tag::Seq, &len.to_be_bytes())?;
                 Ok(SeqSerializer::new(self, written_bytes, true))
             }
+            #[cfg(feature = "alloc")]
+            None if self.config.canonical => Ok(SeqSerializer::buffered(self, Tag::Seq)),
             None => {
                 let written_bytes = self.write_tag(Tag::UnsizedSeq)?;
                 Ok(SeqSerializer::new(self, written_bytes, false))
@@ -230,9 +621,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self.write_tag(Tag::None)
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let mut wb = self.write_tag(Tag::Some)?;
         wb += value.serialize(self)?;
@@ -240,7 +631,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, W::Error> {
-        let len: u8 = len as u8;
+        let len = fields_len(len)?;
         let wb = self.write_tag_then(Tag::Tuple, &len.to_be_bytes())?;
         Ok(SeqSerializer::new(self, wb, true))
     }
@@ -250,7 +641,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, W::Error> {
-        let len: u8 = len as u8;
+        let len = fields_len(len)?;
         let wb = self.write_tag_then(Tag::TupleStruct, &len.to_be_bytes())?;
         Ok(SeqSerializer::new(self, wb, true))
     }
@@ -260,13 +651,22 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         _name: &'static str,
         variant_index: u32,
         _variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant, W::Error> {
-        let wb = self.write_tag_then(Tag::TupleVariant, &variant_index.to_be_bytes())?;
+        let len = fields_len(len)?;
+        let wb = self.write_byte_matrix(&[
+            &[Tag::TupleVariant.into()],
+            &variant_index.to_be_bytes(),
+            &len.to_be_bytes(),
+        ])?;
         Ok(SeqSerializer::new(self, wb, true))
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, W::Error> {
+        #[cfg(feature = "alloc")]
+        if self.config.canonical {
+            return Ok(SeqSerializer::buffered(self, Tag::Map));
+        }
         match len {
             Some(len) => {
                 let len: u64 = len as u64;
@@ -282,79 +682,322 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, W::Error> {
-        let len = len as u8;
+        #[cfg(feature = "alloc")]
+        if let Some(filter) = self.config.field_filter {
+            return Ok(SeqSerializer::filtered(self, filter, name, None));
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = name;
+        if let Some(keys) = self.struct_field_keys() {
+            // the fields are written in the order of their declaration, so keyed by index they are also in the canonical order
+            let wb = self.write_tag_then(Tag::Map, &(len as u64).to_be_bytes())?;
+            return Ok(SeqSerializer::new(self, wb, true).keyed_by(keys));
+        }
+        let len = fields_len(len)?;
         let wb = self.write_tag_then(Tag::Struct, &len.to_be_bytes())?;
         Ok(SeqSerializer::new(self, wb, true))
     }
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
         _variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant, W::Error> {
-        let wb = self.write_tag_then(Tag::StructVariant, &variant_index.to_be_bytes())?;
+        #[cfg(feature = "alloc")]
+        if let Some(filter) = self.config.field_filter {
+            return Ok(SeqSerializer::filtered(
+                self,
+                filter,
+                name,
+                Some(variant_index),
+            ));
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = name;
+        if let Some(keys) = self.struct_field_keys() {
+            let wb = self.write_byte_matrix(&[
+                &[Tag::NewTypeVariant.into()],
+                &variant_index.to_be_bytes(),
+                &[Tag::Map.into()],
+                &(len as u64).to_be_bytes(),
+            ])?;
+            return Ok(SeqSerializer::new(self, wb, true).keyed_by(keys));
+        }
+        let len = fields_len(len)?;
+        let wb = self.write_byte_matrix(&[
+            &[Tag::StructVariant.into()],
+            &variant_index.to_be_bytes(),
+            &len.to_be_bytes(),
+        ])?;
         Ok(SeqSerializer::new(self, wb, true))
     }
 
-    fn collect_str<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn collect_str<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: fmt::Display,
+        T: ?Sized + fmt::Display,
     {
+        #[cfg(feature = "alloc")]
+        if self.config.canonical {
+            return self.serialize_str(&value.to_string());
+        }
         // unknown str length marker
         let mut wb = self.write_tag(Tag::NullTerminatedString)?;
-        let mut collector = StrCollector::new(&mut self.writer);
-        fmt::write(&mut collector, format_args!("{}", value))?;
+        let mut collector = StrCollector::new(&mut *self);
+        if let Err(err) = fmt::write(&mut collector, format_args!("{}", value)) {
+            return Err(collector.error.unwrap_or(err.into()));
+        }
         wb += collector.written_bytes;
         // "null" terminated str
-        wb += self.writer.write_bytes(&UNSIZED_STRING_END_MARKER)?;
+        wb += self.write_bytes(&UNSIZED_STRING_END_MARKER)?;
         Ok(wb)
     }
 }
 
-pub struct SeqSerializer<'a, W> {
-    serializer: &'a mut Serializer<W>,
+pub struct SeqSerializer<'a, W, P = NoProgress> {
+    serializer: &'a mut Serializer<W, P>,
     written_bytes: usize,
     known_size: bool,
+    // checksum of the enclosing container, restored when this one is finished
+    parent_crc: Option<Crc32>,
+    // with `canonical`, the tag and the values of a sequence or map written once they are all known
+    #[cfg(feature = "alloc")]
+    buffered: Option<(Tag, BufferedValues)>,
+    // with `sparse_structs` or `hashed_field_names`, the key written before the value of each field of a struct
+    field_keys: Option<FieldKeys>,
+    // with `hashed_field_names`, the hashes of the fields given so far, to find the collisions
+    #[cfg(feature = "alloc")]
+    field_hashes: Vec<(u32, &'static str)>,
+    // with `field_filter`, the filter and the name of the struct
+    #[cfg(feature = "alloc")]
+    filter: Option<(Filter, &'static str)>,
+    // index of a buffered struct variant, written in its header
+    #[cfg(feature = "alloc")]
+    variant_index: Option<u32>,
 }
 
-impl<'a, W: Write> SeqSerializer<'a, W> {
-    pub fn new(serializer: &'a mut Serializer<W>, written_bytes: usize, known_size: bool) -> Self {
+/// Key of the fields of a struct serialized as a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKeys {
+    /// The index of the field, holding the index of the next one.
+    Index(u32),
+    /// The `field_hash` of the name of the field.
+    Hash,
+}
+
+impl<'a, W: Write, P: ProgressCallback> SeqSerializer<'a, W, P> {
+    pub fn new(
+        serializer: &'a mut Serializer<W, P>,
+        written_bytes: usize,
+        known_size: bool,
+    ) -> Self {
+        let parent_crc = serializer
+            .config
+            .container_checksums
+            .then(|| core::mem::replace(&mut serializer.crc, Crc32::new()));
         Self {
             serializer,
             written_bytes,
             known_size,
+            parent_crc,
+            #[cfg(feature = "alloc")]
+            buffered: None,
+            field_keys: None,
+            #[cfg(feature = "alloc")]
+            field_hashes: Vec::new(),
+            #[cfg(feature = "alloc")]
+            filter: None,
+            #[cfg(feature = "alloc")]
+            variant_index: None,
         }
     }
 
-    pub fn ser_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn keyed_by(mut self, keys: FieldKeys) -> Self {
+        self.field_keys = Some(keys);
+        self
+    }
+
+    fn ser_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
+    {
+        #[cfg(feature = "alloc")]
+        if let Some((filter, name)) = self.filter {
+            match filter.action(name, key) {
+                FieldAction::Keep => {}
+                FieldAction::Skip => return self.skip_field(key),
+                FieldAction::Redact => return self.write_field(key, &None::<()>),
+            }
+        }
+        self.write_field(key, value)
+    }
+
+    fn write_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
     {
+        match self.field_keys {
+            Some(FieldKeys::Index(index)) => {
+                self.field_keys = Some(FieldKeys::Index(index + 1));
+                self.ser_value(&index)?;
+            }
+            Some(FieldKeys::Hash) => {
+                let hash = self.hash_field(key)?;
+                self.ser_value(&hash)?;
+            }
+            None => {}
+        }
+        self.ser_value(value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), W::Error> {
+        match self.field_keys {
+            Some(FieldKeys::Index(index)) => self.field_keys = Some(FieldKeys::Index(index + 1)),
+            // a skipped field could be written by another value of the struct
+            Some(FieldKeys::Hash) => {
+                self.hash_field(key)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn hash_field(&mut self, name: &'static str) -> Result<u32, W::Error> {
+        let hash = field_hash(name);
+        #[cfg(feature = "alloc")]
+        {
+            if let Some(&(_, first)) = self.field_hashes.iter().find(|(h, _)| *h == hash) {
+                return Err(Error::FieldHashCollision {
+                    first,
+                    second: name,
+                });
+            }
+            self.field_hashes.push((hash, name));
+        }
+        Ok(hash)
+    }
+
+    /// Buffer the values of a sequence or map to write its length, and the map entries sorted.
+    /// The header is written, and the container opened, when it is finished.
+    #[cfg(feature = "alloc")]
+    fn buffered(serializer: &'a mut Serializer<W, P>, tag: Tag) -> Self {
+        Self {
+            serializer,
+            written_bytes: 0,
+            known_size: true,
+            parent_crc: None,
+            buffered: Some((tag, BufferedValues::default())),
+            field_keys: None,
+            field_hashes: Vec::new(),
+            filter: None,
+            variant_index: None,
+        }
+    }
+
+    /// Buffer the fields of a struct, or of a struct variant with its index, to call `filter` on each of them
+    /// and write the number of fields left in the header.
+    #[cfg(feature = "alloc")]
+    fn filtered(
+        serializer: &'a mut Serializer<W, P>,
+        filter: Filter,
+        name: &'static str,
+        variant_index: Option<u32>,
+    ) -> Self {
+        let keys = serializer.struct_field_keys();
+        let tag = match (keys, variant_index) {
+            (Some(_), _) => Tag::Map,
+            (None, Some(_)) => Tag::StructVariant,
+            (None, None) => Tag::Struct,
+        };
+        let mut seq = Self::buffered(serializer, tag);
+        seq.field_keys = keys;
+        seq.filter = Some((filter, name));
+        seq.variant_index = variant_index;
+        seq
+    }
+
+    pub fn ser_value<T>(&mut self, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        #[cfg(feature = "alloc")]
+        if let Some((_, values)) = self.buffered.as_mut() {
+            let config = self.serializer.config.for_buffered_values();
+            let res = value.serialize(&mut Serializer::with_config(values.writer(), config));
+            if let Err(err) = res {
+                values.discard_partial_value();
+                return Err(err.map_writer_error(|err| match err {}));
+            }
+            values.end_value();
+            return Ok(());
+        }
         self.written_bytes += value.serialize(&mut *self.serializer)?;
         Ok(())
     }
 
+    /// Write the header and the values of a buffered sequence or map.
+    #[cfg(feature = "alloc")]
+    fn write_buffered(&mut self, tag: Tag, values: BufferedValues) -> Result<(), W::Error> {
+        let (len, values) = match tag {
+            Tag::Map => (values.len() / 2, values.sorted_entries()?),
+            _ => (values.len(), values.values().collect()),
+        };
+        let len_bytes = (len as u64).to_be_bytes();
+        self.written_bytes += match (tag, self.variant_index) {
+            (Tag::Struct, _) => self.serializer.write_tag_then(tag, &[fields_len(len)?])?,
+            (Tag::StructVariant, Some(index)) => self.serializer.write_byte_matrix(&[
+                &[tag.into()],
+                &index.to_be_bytes(),
+                &[fields_len(len)?],
+            ])?,
+            // a struct variant serialized as a map
+            (_, Some(index)) => self.serializer.write_byte_matrix(&[
+                &[Tag::NewTypeVariant.into()],
+                &index.to_be_bytes(),
+                &[tag.into()],
+                &len_bytes,
+            ])?,
+            _ => self.serializer.write_tag_then(tag, &len_bytes)?,
+        };
+        let serializer = &mut *self.serializer;
+        self.parent_crc = serializer
+            .config
+            .container_checksums
+            .then(|| core::mem::replace(&mut serializer.crc, Crc32::new()));
+        for value in values {
+            self.written_bytes += serializer.write_raw_bytes(value)?;
+        }
+        Ok(())
+    }
+
     pub fn finish(mut self) -> Result<usize, W::Error> {
+        #[cfg(feature = "alloc")]
+        if let Some((tag, values)) = self.buffered.take() {
+            self.write_buffered(tag, values)?;
+        }
         if !self.known_size {
             self.written_bytes += self.serializer.write_tag(Tag::UnsizedSeqEnd)?;
         }
+        if let Some(parent_crc) = self.parent_crc {
+            let crc = core::mem::replace(&mut self.serializer.crc, parent_crc).finish();
+            self.written_bytes += self.serializer.write_bytes(&crc.to_be_bytes())?;
+        }
         Ok(self.written_bytes)
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeSeq for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -364,14 +1007,14 @@ impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTuple for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -381,14 +1024,14 @@ impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTupleStruct for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -398,14 +1041,14 @@ impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTupleVariant for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -415,21 +1058,24 @@ impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeMap for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), W::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        if self.serializer.config.strict_map_keys {
+            check_map_key(key, self.serializer.config)?;
+        }
         self.ser_value(key)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -439,16 +1085,20 @@ impl<'a, W: Write> ser::SerializeMap for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeStruct for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(value)
+        self.ser_field(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), W::Error> {
+        SeqSerializer::skip_field(self, key)
     }
 
     fn end(self) -> Result<Self::Ok, W::Error> {
@@ -456,16 +1106,20 @@ impl<'a, W: Write> ser::SerializeStruct for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeStructVariant for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(value)
+        self.ser_field(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), W::Error> {
+        SeqSerializer::skip_field(self, key)
     }
 
     fn end(self) -> Result<Self::Ok, W::Error> {
@@ -473,28 +1127,109 @@ impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
     }
 }
 
-struct StrCollector<'a, W> {
-    writer: &'a mut W,
+/// Number of fields of a tuple, struct or variant, encoded in a single byte.
+fn fields_len<E>(len: usize) -> Result<u8, E> {
+    u8::try_from(len).map_err(|_| Error::TooManyFields { len })
+}
+
+/// Fail with `Error::CompositeMapKey` if the leading tag of the key has several children.
+fn check_map_key<T, E>(key: &T, config: SerializerConfig) -> Result<(), E>
+where
+    T: ?Sized + Serialize,
+{
+    // the probe stops at the first byte, the buffered containers of the canonical mode write it last
+    let config = SerializerConfig {
+        canonical: false,
+        ..config
+    };
+    let mut probe = TagProbe(None);
+    let _ = key.serialize(&mut Serializer::with_config(&mut probe, config));
+    let Some(tag) = probe.0.and_then(|byte| Tag::try_from(byte).ok()) else {
+        // the key failed before writing anything, serializing it again gives the error
+        return Ok(());
+    };
+    match tag.payload_kind() {
+        PayloadKind::TaggedChildren { count, .. } if count != ChildCount::Fixed(1) => {
+            Err(Error::CompositeMapKey { tag })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writer keeping the first byte written, the tag of the value, then stopping the serialization.
+struct TagProbe(Option<u8>);
+
+impl Write for &mut TagProbe {
+    type Error = EndOfBuff;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> core::result::Result<usize, Self::Error> {
+        match bytes.first() {
+            Some(&byte) => {
+                self.0 = Some(byte);
+                Err(EndOfBuff)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Writer giving the payload of an extension to the `any` serializer, to update the checksums and the progress.
+struct PayloadWriter<'a, W: Write, P> {
+    serializer: &'a mut Serializer<W, P>,
+    // error of the writer, or cancellation, hidden behind `PayloadWriteError`
+    error: Option<Error<W::Error>>,
+}
+
+#[derive(Debug)]
+struct PayloadWriteError;
+
+impl fmt::Display for PayloadWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed to write the payload of an extension")
+    }
+}
+
+impl WriterError for PayloadWriteError {}
+
+impl<'a, 'b, W: Write, P: ProgressCallback> Write for &'a mut PayloadWriter<'b, W, P> {
+    type Error = PayloadWriteError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> core::result::Result<usize, Self::Error> {
+        self.serializer.write_bytes(bytes).map_err(|err| {
+            self.error = Some(err);
+            PayloadWriteError
+        })
+    }
+}
+
+struct StrCollector<'a, W: Write, P> {
+    serializer: &'a mut Serializer<W, P>,
     written_bytes: usize,
+    // error of the writer, or cancellation, hidden behind `fmt::Error`
+    error: Option<Error<W::Error>>,
 }
 
-impl<'a, W: Write> StrCollector<'a, W> {
-    pub fn new(writer: &'a mut W) -> Self {
+impl<'a, W: Write, P: ProgressCallback> StrCollector<'a, W, P> {
+    pub fn new(serializer: &'a mut Serializer<W, P>) -> Self {
         StrCollector {
-            writer,
+            serializer,
             written_bytes: 0,
+            error: None,
         }
     }
 }
 
-impl<'a, W: Write> fmt::Write for StrCollector<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> fmt::Write for StrCollector<'a, W, P> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        match self.writer.write_bytes(s.as_bytes()) {
+        match self.serializer.write_bytes(s.as_bytes()) {
             Ok(written_bytes) => {
                 self.written_bytes += written_bytes;
                 Ok(())
             }
-            Err(_) => Err(fmt::Error),
+            Err(err) => {
+                self.error = Some(err);
+                Err(fmt::Error)
+            }
         }
     }
 }
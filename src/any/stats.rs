@@ -0,0 +1,222 @@
+use core::fmt::{self, Display};
+
+use super::{read_len, ChildCount, PayloadKind, Tag, TagParsingError};
+use crate::error::{Error, Result};
+use crate::{is_unsized_string_end, DEFAULT_MAX_DEPTH, UNSIZED_STRING_END_MARKER};
+
+// one slot for each numbered tag, then one for all the extensions
+const EXTENSION_SLOT: usize = Tag::U128 as usize + 1;
+const SLOTS: usize = EXTENSION_SLOT + 1;
+
+/// Number of values with a tag in a payload and the bytes they take, counted by `tag_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TagCount {
+    /// Number of values with the tag.
+    pub count: usize,
+    /// Bytes of the tags themselves and of the lengths and children counts following them.
+    pub overhead: usize,
+    /// Bytes of the content of the values: numbers, strings, variant indices and extension payloads.
+    /// The children of sequences, maps, structs and tuples are counted with their own tag.
+    pub payload: usize,
+}
+
+impl TagCount {
+    pub const fn total(&self) -> usize {
+        self.overhead + self.payload
+    }
+
+    fn add(&mut self, other: TagCount) {
+        self.count += other.count;
+        self.overhead += other.overhead;
+        self.payload += other.payload;
+    }
+}
+
+/// Composition of a payload of the `any` format, the number of values and bytes of each tag, returned by `tag_stats`.
+///
+/// It is displayed as a table of the tags found, the ones taking the most bytes first.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagStats {
+    counts: [TagCount; SLOTS],
+}
+
+/// Walk over the single value of the input and count the values and bytes of each tag, to see what takes
+/// the most room in a payload.
+///
+/// ```
+/// use serde_bin::any::{self, Tag};
+///
+/// let bytes = any::to_bytes(&(1u8, "ab")).unwrap();
+/// let stats = any::tag_stats(&bytes).unwrap();
+/// let string = stats.get(Tag::String);
+/// // the tag and the length of 8 bytes, then the 2 bytes of the string
+/// assert_eq!((string.count, string.overhead, string.payload), (1, 9, 2));
+/// assert_eq!(stats.total().total(), bytes.len());
+/// ```
+///
+/// Only the tags are read, the values are not decoded, so the strings are not checked to be valid UTF-8.
+/// Fails like the deserializer on malformed inputs, nesting deeper than `DEFAULT_MAX_DEPTH`
+/// and trailing bytes.
+pub fn tag_stats(input: &[u8]) -> Result<TagStats> {
+    let mut stats = TagStats {
+        counts: [TagCount::default(); SLOTS],
+    };
+    match stats.walk(input, DEFAULT_MAX_DEPTH)?.len() {
+        0 => Ok(stats),
+        len => Err(Error::TrailingBytes(len)),
+    }
+}
+
+fn slot(tag: Tag) -> usize {
+    match tag {
+        Tag::Extension => EXTENSION_SLOT,
+        tag => u8::from(tag).into(),
+    }
+}
+
+fn slot_tag(slot: usize) -> Option<Tag> {
+    match slot {
+        EXTENSION_SLOT => Some(Tag::Extension),
+        slot => u8::try_from(slot).ok()?.try_into().ok(),
+    }
+}
+
+impl TagStats {
+    /// Counts of `tag`, all the extensions are counted together as `Tag::Extension`.
+    pub fn get(&self, tag: Tag) -> TagCount {
+        self.counts[slot(tag)]
+    }
+
+    /// Counts of the tags found, in the order of their byte.
+    pub fn iter(&self) -> impl Iterator<Item = (Tag, TagCount)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| count.count > 0)
+            .filter_map(|(slot, &count)| Some((slot_tag(slot)?, count)))
+    }
+
+    /// Sum of the counts of all the tags, the total of the bytes is the length of the input.
+    pub fn total(&self) -> TagCount {
+        let mut total = TagCount::default();
+        for count in &self.counts {
+            total.add(*count);
+        }
+        total
+    }
+
+    fn record(&mut self, tag: Tag, overhead: usize, payload: usize) {
+        self.counts[slot(tag)].add(TagCount {
+            count: 1,
+            overhead,
+            payload,
+        });
+    }
+
+    /// Count the value at the start of the input and its children, returning the input after it.
+    fn walk<'a>(&mut self, input: &'a [u8], depth: usize) -> Result<&'a [u8]> {
+        let (tag, rest) = Tag::read(input)?;
+        match tag.payload_kind() {
+            PayloadKind::Fixed(len) => {
+                let rest = rest.get(len..).ok_or(Error::Eof)?;
+                self.record(tag, 1, len);
+                Ok(rest)
+            }
+            PayloadKind::LengthPrefixed => {
+                let (len, rest) = read_len(rest)?;
+                let rest = rest.get(len..).ok_or(Error::LengthExceedsInput {
+                    len,
+                    remaining: rest.len(),
+                })?;
+                self.record(tag, 9, len);
+                Ok(rest)
+            }
+            PayloadKind::NullTerminated => {
+                let len = rest
+                    .windows(UNSIZED_STRING_END_MARKER.len())
+                    .position(is_unsized_string_end)
+                    .ok_or(Error::Eof)?;
+                self.record(tag, 1 + UNSIZED_STRING_END_MARKER.len(), len);
+                Ok(&rest[len + UNSIZED_STRING_END_MARKER.len()..])
+            }
+            PayloadKind::TaggedChildren { header, count } => {
+                let depth = depth.checked_sub(1).ok_or(Error::DepthLimitExceeded)?;
+                let rest = rest.get(header..).ok_or(Error::Eof)?;
+                let (len_bytes, children, mut rest) = match count {
+                    ChildCount::Fixed(count) => (0, Some(count), rest),
+                    ChildCount::U8Len => {
+                        let (&len, rest) = rest.split_first().ok_or(Error::Eof)?;
+                        (1, Some(len.into()), rest)
+                    }
+                    ChildCount::U64Len => {
+                        let (len, rest) = read_len(rest)?;
+                        (8, Some(len), rest)
+                    }
+                    ChildCount::U64PairsLen => {
+                        let (len, rest) = read_len(rest)?;
+                        (8, Some(len.checked_mul(2).ok_or(Error::InvalidSize)?), rest)
+                    }
+                    ChildCount::UntilEndMarker => (0, None, rest),
+                };
+                self.record(tag, 1 + len_bytes, header);
+                match children {
+                    Some(count) => {
+                        for _ in 0..count {
+                            rest = self.walk(rest, depth)?;
+                        }
+                    }
+                    None => loop {
+                        match rest.split_first() {
+                            Some((&byte, after)) if byte == Tag::UnsizedSeqEnd.into() => {
+                                self.record(Tag::UnsizedSeqEnd, 1, 0);
+                                rest = after;
+                                break;
+                            }
+                            Some(_) => rest = self.walk(rest, depth)?,
+                            None => return Err(Error::Eof),
+                        }
+                    },
+                }
+                Ok(rest)
+            }
+            PayloadKind::EndMarker => Err(TagParsingError::unexpected("a value", tag).into()),
+        }
+    }
+}
+
+impl Display for TagStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut slots: [usize; SLOTS] = core::array::from_fn(|slot| slot);
+        // the most bytes first, then in the order of the bytes of the tags
+        slots.sort_unstable_by_key(|&slot| (core::cmp::Reverse(self.counts[slot].total()), slot));
+        writeln!(
+            f,
+            "{:>8} {:>10} {:>10} {:>10}  tag",
+            "count", "overhead", "payload", "total"
+        )?;
+        let rows = slots
+            .iter()
+            .filter(|&&slot| self.counts[slot].count > 0)
+            .filter_map(|&slot| Some((slot_tag(slot)?, self.counts[slot])));
+        for (tag, count) in rows {
+            writeln!(
+                f,
+                "{:>8} {:>10} {:>10} {:>10}  {:?}",
+                count.count,
+                count.overhead,
+                count.payload,
+                count.total(),
+                tag
+            )?;
+        }
+        let total = self.total();
+        write!(
+            f,
+            "{:>8} {:>10} {:>10} {:>10}  total",
+            total.count,
+            total.overhead,
+            total.payload,
+            total.total()
+        )
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Tag, TagParsingError, EXTENSION_TAGS};
+use crate::error::{Error, Result};
+
+// Tags in the order of their byte, the 128 bits integers are unknown bytes on the platforms without them.
+const NUMBERED_TAGS: [Option<Tag>; 38] = [
+    Some(Tag::None),
+    Some(Tag::Some),
+    Some(Tag::BoolFalse),
+    Some(Tag::BoolTrue),
+    Some(Tag::I8),
+    Some(Tag::I16),
+    Some(Tag::I32),
+    Some(Tag::I64),
+    Some(Tag::U8),
+    Some(Tag::U16),
+    Some(Tag::U32),
+    Some(Tag::U64),
+    Some(Tag::F32),
+    Some(Tag::F64),
+    Some(Tag::Char1),
+    Some(Tag::Char2),
+    Some(Tag::Char3),
+    Some(Tag::Char4),
+    Some(Tag::String),
+    Some(Tag::NullTerminatedString),
+    Some(Tag::ByteArray),
+    Some(Tag::Unit),
+    Some(Tag::UnitStruct),
+    Some(Tag::UnitVariant),
+    Some(Tag::NewTypeStruct),
+    Some(Tag::NewTypeVariant),
+    Some(Tag::Seq),
+    Some(Tag::UnsizedSeq),
+    Some(Tag::UnsizedSeqEnd),
+    Some(Tag::Tuple),
+    Some(Tag::TupleStruct),
+    Some(Tag::TupleVariant),
+    Some(Tag::Map),
+    Some(Tag::UnsizedMap),
+    Some(Tag::Struct),
+    Some(Tag::StructVariant),
+    #[cfg(not(no_integer128))]
+    Some(Tag::I128),
+    #[cfg(no_integer128)]
+    None,
+    #[cfg(not(no_integer128))]
+    Some(Tag::U128),
+    #[cfg(no_integer128)]
+    None,
+];
+
+/// Table from the bytes of an input to the tags they stand for, to decode an input written with another numbering
+/// of the tags, such as a fork of the format, with `DeserializerConfig::tag_map`.
+///
+/// The map starts from the numbering of this crate with `TagMap::current()`, and is adjusted byte by byte:
+///
+/// ```
+/// use serde_bin::any::{Tag, TagMap};
+///
+/// // a fork that numbered the sequences before the newtype variants
+/// const FORK: TagMap = TagMap::current()
+///     .with(25, Tag::Seq)
+///     .with(26, Tag::NewTypeVariant);
+/// assert_eq!(FORK.get(25), Some(Tag::Seq));
+/// assert_eq!(FORK.get(24), Some(Tag::NewTypeStruct));
+/// ```
+///
+/// No documented revision of the format numbered the tags differently, so no map is provided for them.
+/// The extensions can't be moved: their byte is part of the value, and must be one of `EXTENSION_TAGS`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagMap {
+    tags: [Option<Tag>; 256],
+}
+
+impl TagMap {
+    /// Map of the numbering of this crate, with the bytes of `EXTENSION_TAGS` mapped to `Tag::Extension`.
+    pub const fn current() -> Self {
+        let mut tags = [None; 256];
+        let mut byte = 0;
+        while byte < NUMBERED_TAGS.len() {
+            tags[byte] = NUMBERED_TAGS[byte];
+            byte += 1;
+        }
+        let mut byte = *EXTENSION_TAGS.start() as usize;
+        while byte <= *EXTENSION_TAGS.end() as usize {
+            tags[byte] = Some(Tag::Extension);
+            byte += 1;
+        }
+        TagMap { tags }
+    }
+
+    /// Map with no byte, every tag is unknown until it is added with `with`.
+    pub const fn empty() -> Self {
+        TagMap { tags: [None; 256] }
+    }
+
+    /// Read `byte` as `tag`, the other bytes read as `tag` are kept.
+    ///
+    /// Panics if `tag` is `Tag::Extension`, at compile time for a constant.
+    pub const fn with(mut self, byte: u8, tag: Tag) -> Self {
+        assert!(
+            !matches!(tag, Tag::Extension),
+            "the extension tags are not mapped"
+        );
+        self.tags[byte as usize] = Some(tag);
+        self
+    }
+
+    /// Make `byte` an unknown tag.
+    pub const fn without(mut self, byte: u8) -> Self {
+        self.tags[byte as usize] = None;
+        self
+    }
+
+    /// Tag read from `byte`, `None` if it is unknown.
+    pub const fn get(&self, byte: u8) -> Option<Tag> {
+        self.tags[byte as usize]
+    }
+
+    /// Parse the tag at the start of `input` like `Tag::read`, with the bytes of this map.
+    pub fn read<'a>(&self, input: &'a [u8]) -> Result<(Tag, &'a [u8])> {
+        let (&byte, rest) = input.split_first().ok_or(Error::Eof)?;
+        match self.get(byte) {
+            Some(tag) => Ok((tag, rest)),
+            None => Err(TagParsingError::UnmappedTag(byte).into()),
+        }
+    }
+}
+
+impl Default for TagMap {
+    fn default() -> Self {
+        Self::current()
+    }
+}
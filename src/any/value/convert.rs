@@ -0,0 +1,89 @@
+//! Conversions from a `Value` to the primitive types, used by `Value::as_typed_vec`.
+
+use core::fmt::{self, Display};
+
+use super::{Number, Value};
+
+/// Error of the conversions from a `Value`, the value doesn't hold the target type or doesn't fit in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConversionError;
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the value can't be converted to the requested type")
+    }
+}
+
+fn number(value: &Value) -> Result<Number, ConversionError> {
+    value.as_number().ok_or(ConversionError)
+}
+
+macro_rules! impl_integer {
+    ($($t:ty)*) => {
+        $(
+            /// Any integer in the range of the type, floats are not converted.
+            impl TryFrom<&Value<'_>> for $t {
+                type Error = ConversionError;
+
+                fn try_from(value: &Value<'_>) -> Result<Self, Self::Error> {
+                    number(value)?
+                        .to_i128()
+                        .and_then(|n| n.try_into().ok())
+                        .ok_or(ConversionError)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8 i16 i32 i64 u8 u16 u32 u64);
+#[cfg(not(no_integer128))]
+impl_integer!(i128);
+
+/// Any integer in the range of the type, floats are not converted.
+#[cfg(not(no_integer128))]
+impl TryFrom<&Value<'_>> for u128 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value<'_>) -> Result<Self, Self::Error> {
+        match number(value)? {
+            Number::U128(n) => Ok(n),
+            n => n
+                .to_i128()
+                .and_then(|n| n.try_into().ok())
+                .ok_or(ConversionError),
+        }
+    }
+}
+
+/// Any float, the integers are not converted.
+impl TryFrom<&Value<'_>> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value<'_>) -> Result<Self, Self::Error> {
+        match number(value)? {
+            Number::F32(n) => Ok(n.into()),
+            Number::F64(n) => Ok(n),
+            _ => Err(ConversionError),
+        }
+    }
+}
+
+impl TryFrom<&Value<'_>> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value<'_>) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(boolean) => Ok(*boolean),
+            _ => Err(ConversionError),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value<'_>> for &'a str {
+    type Error = ConversionError;
+
+    fn try_from(value: &'a Value<'_>) -> Result<Self, Self::Error> {
+        value.as_str().ok_or(ConversionError)
+    }
+}
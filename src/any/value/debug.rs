@@ -0,0 +1,198 @@
+use core::fmt::{self, Debug, Write};
+
+use super::Value;
+
+/// Limits of the output of `Value::compact_debug`, so logging a large value gives a short line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugLimits {
+    max_str_len: usize,
+    max_bytes_len: usize,
+    max_elements: usize,
+    max_depth: usize,
+}
+
+impl Default for DebugLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugLimits {
+    /// Print 64 bytes of strings, 16 bytes of byte arrays, 16 elements of arrays and maps, and 8 levels of nesting.
+    pub const fn new() -> Self {
+        DebugLimits {
+            max_str_len: 64,
+            max_bytes_len: 16,
+            max_elements: 16,
+            max_depth: 8,
+        }
+    }
+
+    /// Number of bytes printed for a string, cut on a char boundary.
+    pub const fn max_str_len(mut self, max: usize) -> Self {
+        self.max_str_len = max;
+        self
+    }
+
+    pub const fn max_bytes_len(mut self, max: usize) -> Self {
+        self.max_bytes_len = max;
+        self
+    }
+
+    /// Number of elements printed for arrays, and of entries for maps.
+    pub const fn max_elements(mut self, max: usize) -> Self {
+        self.max_elements = max;
+        self
+    }
+
+    /// Number of nested arrays, maps, options and enums printed, the deeper ones are replaced by their size.
+    pub const fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = max;
+        self
+    }
+}
+
+/// `Debug` output of a value truncated to `DebugLimits`, returned by `Value::compact_debug`.
+///
+/// The output looks like the one of `Debug for Value`, with `...` and the full size where something is left out.
+#[derive(Clone, Copy)]
+pub struct CompactDebug<'a, 'de> {
+    value: &'a Value<'de>,
+    limits: DebugLimits,
+    depth: usize,
+}
+
+impl<'a, 'de> CompactDebug<'a, 'de> {
+    pub(crate) fn new(value: &'a Value<'de>, limits: DebugLimits) -> Self {
+        CompactDebug {
+            value,
+            limits,
+            depth: 0,
+        }
+    }
+
+    fn child(&self, value: &'a Value<'de>) -> Self {
+        CompactDebug {
+            value,
+            limits: self.limits,
+            depth: self.depth + 1,
+        }
+    }
+
+    fn write_str(&self, f: &mut fmt::Formatter<'_>, name: &str, string: &str) -> fmt::Result {
+        let max = self.limits.max_str_len;
+        if string.len() <= max {
+            return write!(f, "{}({:?})", name, string);
+        }
+        let mut end = max;
+        while !string.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(
+            f,
+            "{}({:?}... {} bytes)",
+            name,
+            &string[..end],
+            string.len()
+        )
+    }
+
+    fn write_bytes(&self, f: &mut fmt::Formatter<'_>, name: &str, bytes: &[u8]) -> fmt::Result {
+        write!(f, "{}(", name)?;
+        self.write_byte_list(f, bytes)?;
+        f.write_char(')')
+    }
+
+    fn write_extension(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: &str,
+        tag: u8,
+        payload: &[u8],
+    ) -> fmt::Result {
+        write!(f, "{}({}, ", name, tag)?;
+        self.write_byte_list(f, payload)?;
+        f.write_char(')')
+    }
+
+    fn write_byte_list(&self, f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+        let max = self.limits.max_bytes_len;
+        if bytes.len() <= max {
+            return Debug::fmt(bytes, f);
+        }
+        f.debug_list()
+            .entries(&bytes[..max])
+            .entry(&Omitted(bytes.len(), "bytes"))
+            .finish()
+    }
+}
+
+// placeholder of the left out part of a value, with its full size
+struct Omitted(usize, &'static str);
+
+impl Debug for Omitted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "... {} {}", self.0, self.1)
+    }
+}
+
+impl<'a, 'de> Debug for CompactDebug<'a, 'de> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let too_deep = self.depth >= self.limits.max_depth;
+        match self.value {
+            Value::String(string) => self.write_str(f, "String", string),
+            Value::OwnedString(string) => self.write_str(f, "OwnedString", string),
+            Value::Bytes(bytes) => self.write_bytes(f, "Bytes", bytes),
+            Value::OwnedBytes(bytes) => self.write_bytes(f, "OwnedBytes", bytes),
+            Value::Extension(tag, payload) => self.write_extension(f, "Extension", *tag, payload),
+            Value::OwnedExtension(tag, payload) => {
+                self.write_extension(f, "OwnedExtension", *tag, payload)
+            }
+            Value::Option(Some(_)) if too_deep => f.write_str("Option Some(...)"),
+            Value::Option(Some(value)) => {
+                f.write_str("Option ")?;
+                f.debug_tuple("Some").field(&self.child(value)).finish()
+            }
+            Value::Array(values) if too_deep => {
+                write!(f, "Array [{:?}]", Omitted(values.len(), "elements"))
+            }
+            Value::Array(values) => {
+                let max = self.limits.max_elements;
+                f.write_str("Array ")?;
+                let mut list = f.debug_list();
+                list.entries(values.iter().take(max).map(|value| self.child(value)));
+                if values.len() > max {
+                    list.entry(&Omitted(values.len(), "elements"));
+                }
+                list.finish()
+            }
+            Value::Map(map) if too_deep => {
+                write!(f, "Object {{{:?}}}", Omitted(map.len(), "entries"))
+            }
+            Value::Map(map) => {
+                let max = self.limits.max_elements;
+                f.write_str("Object {")?;
+                for (i, (key, value)) in map.iter().take(max).enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{:?}:{:?}", self.child(key), self.child(value))?;
+                }
+                if map.len() > max {
+                    if max > 0 {
+                        f.write_char(',')?;
+                    }
+                    Debug::fmt(&Omitted(map.len(), "entries"), f)?;
+                }
+                f.write_char('}')
+            }
+            Value::Enum(_) if too_deep => f.write_str("EnumValue { ... }"),
+            Value::Enum(value) => f
+                .debug_struct("EnumValue")
+                .field("variant", &self.child(&value.variant))
+                .field("value", &self.child(&value.value))
+                .finish(),
+            value => Debug::fmt(value, f),
+        }
+    }
+}
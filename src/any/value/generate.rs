@@ -0,0 +1,77 @@
+//! `Arbitrary` implementations, to generate structured values when fuzzing.
+
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+use super::{Box, EnumValue, Number, Value, ValueMap, Vec};
+
+// the values nested deeper are only generated as leaves, so a value is always accepted by the default `max_depth`
+const MAX_DEPTH: usize = 16;
+
+const NUMBER_VARIANTS: usize = if cfg!(no_integer128) { 10 } else { 12 };
+
+impl<'a> Arbitrary<'a> for Number {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.choose_index(NUMBER_VARIANTS)? {
+            0 => Number::I8(u.arbitrary()?),
+            1 => Number::I16(u.arbitrary()?),
+            2 => Number::I32(u.arbitrary()?),
+            3 => Number::I64(u.arbitrary()?),
+            4 => Number::U8(u.arbitrary()?),
+            5 => Number::U16(u.arbitrary()?),
+            6 => Number::U32(u.arbitrary()?),
+            7 => Number::U64(u.arbitrary()?),
+            8 => Number::F32(u.arbitrary()?),
+            #[cfg(not(no_integer128))]
+            10 => Number::I128(u.arbitrary()?),
+            #[cfg(not(no_integer128))]
+            11 => Number::U128(u.arbitrary()?),
+            _ => Number::F64(u.arbitrary()?),
+        })
+    }
+}
+
+/// Only generates owned strings and byte arrays, and enums identified by their variant index
+/// so every value can be serialized.
+impl<'a> Arbitrary<'a> for Value<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        arbitrary_value(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ValueMap<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        arbitrary_map(u, 0)
+    }
+}
+
+fn arbitrary_value(u: &mut Unstructured<'_>, depth: usize) -> Result<Value<'static>> {
+    let variants = if depth < MAX_DEPTH { 11 } else { 7 };
+    Ok(match u.choose_index(variants)? {
+        0 => Value::Unit,
+        1 => Value::Bool(u.arbitrary()?),
+        2 => Value::Number(u.arbitrary()?),
+        3 => Value::Char(u.arbitrary()?),
+        4 => Value::OwnedString(u.arbitrary()?),
+        5 => Value::OwnedBytes(u.arbitrary()?),
+        6 => Value::Option(None),
+        7 => Value::Option(Some(Box::new(arbitrary_value(u, depth + 1)?))),
+        8 => Value::Array(arbitrary_values(u, depth + 1)?),
+        9 => Value::Map(arbitrary_map(u, depth + 1)?),
+        _ => Value::Enum(Box::new(EnumValue {
+            variant: Value::Number(Number::U32(u.arbitrary()?)),
+            value: arbitrary_value(u, depth + 1)?,
+        })),
+    })
+}
+
+fn arbitrary_values(u: &mut Unstructured<'_>, depth: usize) -> Result<Vec<Value<'static>>> {
+    let len = u.arbitrary_len::<Value>()?;
+    (0..len).map(|_| arbitrary_value(u, depth)).collect()
+}
+
+fn arbitrary_map(u: &mut Unstructured<'_>, depth: usize) -> Result<ValueMap<'static>> {
+    let len = u.arbitrary_len::<(Value, Value)>()?;
+    (0..len)
+        .map(|_| Ok((arbitrary_value(u, depth)?, arbitrary_value(u, depth)?)))
+        .collect()
+}
@@ -1,14 +1,26 @@
 use super::{size_hint_caution, Value};
 use core::fmt::{self, Debug, Write};
+use serde::{ser::SerializeMap, Serialize};
 
-use super::Vec;
+use super::{vec, Vec};
 
+/// Entry of a `ValueMap`.
 #[derive(Clone, PartialEq)]
 pub struct ValueEntry<'de> {
     key: Value<'de>,
     value: Value<'de>,
 }
 
+impl<'de> ValueEntry<'de> {
+    pub fn key(&self) -> &Value<'de> {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Value<'de> {
+        &self.value
+    }
+}
+
 impl<'de> Debug for ValueEntry<'de> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{:?}:{:?}", self.key, self.value)
@@ -33,15 +45,119 @@ impl<'de> Debug for ValueMap<'de> {
 }
 
 impl<'de> ValueMap<'de> {
-    pub(crate) fn from_map_access<A>(mut map: A) -> Result<Self, A::Error>
+    /// Collect the entries of `map`, of which the first key has already been read.
+    pub(crate) fn from_map_access<A>(first_key: Value<'de>, mut map: A) -> Result<Self, A::Error>
     where
         A: serde::de::MapAccess<'de>,
     {
-        let mut buff = Vec::with_capacity(size_hint_caution(map.size_hint()));
+        let mut buff = Vec::with_capacity(size_hint_caution(map.size_hint()).saturating_add(1));
+        buff.push(ValueEntry {
+            key: first_key,
+            value: map.next_value()?,
+        });
         while let Some((key, value)) = map.next_entry()? {
             buff.push(ValueEntry { key, value })
         }
         buff.shrink_to_fit();
         Ok(Self(buff))
     }
+
+    /// Number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return the value of the first entry with the given key.
+    pub fn get(&self, key: &Value<'de>) -> Option<&Value<'de>> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, value)| value)
+    }
+
+    /// Entries in the order they were decoded, including the repeated keys.
+    pub fn entries(&self) -> &[ValueEntry<'de>] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Value<'de>, &Value<'de>)> {
+        self.0.iter().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Merge the entries of `overlay` into the map, see `value::merge`.
+    pub(super) fn merge(mut self, overlay: ValueMap<'de>) -> Self {
+        for ValueEntry { key, value } in overlay.0 {
+            match self.0.iter_mut().find(|entry| same_key(&entry.key, &key)) {
+                Some(entry) => {
+                    let base = core::mem::take(&mut entry.value);
+                    entry.value = super::merge(base, value);
+                }
+                None => self.0.push(ValueEntry { key, value }),
+            }
+        }
+        self
+    }
+
+    /// Iterate over the entries with mutable access to the values.
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = (&Value<'de>, &mut Value<'de>)> {
+        self.0
+            .iter_mut()
+            .map(|entry| (&entry.key, &mut entry.value))
+    }
+}
+
+// the borrowed and owned strings with the same content are the same key
+fn same_key(a: &Value, b: &Value) -> bool {
+    match (a.as_str(), b.as_str()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl<'de> FromIterator<(Value<'de>, Value<'de>)> for ValueMap<'de> {
+    fn from_iter<T: IntoIterator<Item = (Value<'de>, Value<'de>)>>(iter: T) -> Self {
+        let entries = iter
+            .into_iter()
+            .map(|(key, value)| ValueEntry { key, value })
+            .collect();
+        Self(entries)
+    }
+}
+
+impl<'de> IntoIterator for ValueMap<'de> {
+    type Item = (Value<'de>, Value<'de>);
+    type IntoIter = IntoIter<'de>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self.0.into_iter())
+    }
+}
+
+/// Owning iterator over the entries of a `ValueMap`.
+pub struct IntoIter<'de>(vec::IntoIter<ValueEntry<'de>>);
+
+impl<'de> Iterator for IntoIter<'de> {
+    type Item = (Value<'de>, Value<'de>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|entry| (entry.key, entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'de> Serialize for ValueMap<'de> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for entry in &self.0 {
+            map.serialize_entry(&entry.key, &entry.value)?;
+        }
+        map.end()
+    }
 }
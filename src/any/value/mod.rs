@@ -1,23 +1,44 @@
-use self::map::ValueMap;
 use core::fmt::{self, Debug};
 
 extern crate alloc;
 
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
     string::{String, ToString},
-    vec::Vec,
+    vec::{self, Vec},
 };
 use serde::{
     de::{DeserializeSeed, Visitor},
-    serde_if_integer128, Deserialize,
+    ser::{self, SerializeSeq},
+    serde_if_integer128, Deserialize, Serialize,
 };
 
+mod convert;
+mod debug;
+#[cfg(feature = "arbitrary")]
+mod generate;
 mod map;
+mod parse;
+mod walk;
+
+use super::extension::{ExtensionValue, EXTENSION_TOKEN};
+use crate::write::VecWriter;
+pub use convert::ConversionError;
+pub use debug::{CompactDebug, DebugLimits};
+pub use map::{IntoIter, ValueEntry, ValueMap};
+pub use walk::{OwnedPathSegment, Path, PathBuf, PathSegment};
 
 const MAX_PREALLOC_SIZE: usize = 256;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// A number of any type.
+///
+/// Numbers are equal if they have the same mathematical value, whatever their type, so `U8(1) == U64(1)`
+/// and `I32(-1) != U32(u32::MAX)`. Floats are compared exactly: an integer equals a float only if the float
+/// holds that integer, `U64(2^53 + 1) != F64(2^53)` even if the integer converts to that float,
+/// and `F32(0.1) != F64(0.1)` as they don't hold the same value. `NaN` is never equal to anything,
+/// and `0.0 == -0.0`. Use `Number::bitwise_eq` to also compare the types.
+#[derive(Debug, Clone, Copy)]
 pub enum Number {
     I8(i8),
     I16(i16),
@@ -35,6 +56,113 @@ pub enum Number {
     U128(u128),
 }
 
+impl Number {
+    pub fn is_integer(&self) -> bool {
+        !self.is_float()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Number::F32(_) | Number::F64(_))
+    }
+
+    /// Return the value of an integer as an `i128`,
+    /// or `None` for floats and `u128` too big to fit.
+    pub fn to_i128(self) -> Option<i128> {
+        let n = match self {
+            Number::I8(n) => n.into(),
+            Number::I16(n) => n.into(),
+            Number::I32(n) => n.into(),
+            Number::I64(n) => n.into(),
+            Number::U8(n) => n.into(),
+            Number::U16(n) => n.into(),
+            Number::U32(n) => n.into(),
+            Number::U64(n) => n.into(),
+            #[cfg(not(no_integer128))]
+            Number::I128(n) => n,
+            #[cfg(not(no_integer128))]
+            Number::U128(n) => return n.try_into().ok(),
+            Number::F32(_) | Number::F64(_) => return None,
+        };
+        Some(n)
+    }
+
+    /// Return the sign and the magnitude of an integer, or of a float holding an integer that fits in an `u128`.
+    fn integer_parts(self) -> Option<(bool, u128)> {
+        let n = match self {
+            Number::F32(n) => n.into(),
+            Number::F64(n) => n,
+            #[cfg(not(no_integer128))]
+            Number::U128(n) => return Some((false, n)),
+            n => {
+                let n = n.to_i128()?;
+                return Some((n < 0, n.unsigned_abs()));
+            }
+        };
+        // 2^128 is exactly representable, and the cast saturates on NaN and infinities
+        let magnitude = n.abs();
+        if magnitude >= 340282366920938463463374607431768211456.0 {
+            return None;
+        }
+        let integer = magnitude as u128;
+        (integer as f64 == magnitude).then_some((n < 0.0, integer))
+    }
+
+    /// Compare the type and the value of the numbers, floats are compared by their bits
+    /// so `NaN` can be equal to itself but `0.0` is not equal to `-0.0`.
+    pub fn bitwise_eq(&self, other: &Number) -> bool {
+        match (*self, *other) {
+            (Number::I8(a), Number::I8(b)) => a == b,
+            (Number::I16(a), Number::I16(b)) => a == b,
+            (Number::I32(a), Number::I32(b)) => a == b,
+            (Number::I64(a), Number::I64(b)) => a == b,
+            (Number::U8(a), Number::U8(b)) => a == b,
+            (Number::U16(a), Number::U16(b)) => a == b,
+            (Number::U32(a), Number::U32(b)) => a == b,
+            (Number::U64(a), Number::U64(b)) => a == b,
+            (Number::F32(a), Number::F32(b)) => a.to_bits() == b.to_bits(),
+            (Number::F64(a), Number::F64(b)) => a.to_bits() == b.to_bits(),
+            #[cfg(not(no_integer128))]
+            (Number::I128(a), Number::I128(b)) => a == b,
+            #[cfg(not(no_integer128))]
+            (Number::U128(a), Number::U128(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Return the value as an `f64`, this is lossy for integers that can't be represented exactly.
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Number::I8(n) => n.into(),
+            Number::I16(n) => n.into(),
+            Number::I32(n) => n.into(),
+            Number::I64(n) => n as f64,
+            Number::U8(n) => n.into(),
+            Number::U16(n) => n.into(),
+            Number::U32(n) => n.into(),
+            Number::U64(n) => n as f64,
+            #[cfg(not(no_integer128))]
+            Number::I128(n) => n as f64,
+            #[cfg(not(no_integer128))]
+            Number::U128(n) => n as f64,
+            Number::F32(n) => n.into(),
+            Number::F64(n) => n,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        if self.is_float() && other.is_float() {
+            // `f32` converts exactly to `f64`
+            return self.to_f64() == other.to_f64();
+        }
+        match (self.integer_parts(), other.integer_parts()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct EnumValue<'de> {
     variant: Value<'de>,
@@ -56,6 +184,87 @@ pub enum Value<'de> {
     Array(Vec<Self>),
     Map(ValueMap<'de>),
     Enum(Box<EnumValue<'de>>),
+    /// Value of an `any::Extension`, with its tag and its payload.
+    Extension(u8, &'de [u8]),
+    OwnedExtension(u8, Vec<u8>),
+}
+
+impl<'de> Value<'de> {
+    /// Parse a value from the `any` format without recursion, so the nesting depth costs heap instead of stack.
+    ///
+    /// Gives the same value as the `Deserialize` implementation, without the nesting depth limit,
+    /// enum variants are decoded with their `u32` index.
+    /// Dropping a value is still recursive, so very deep values should be taken apart before being dropped on a small stack.
+    pub fn parse(input: &'de [u8]) -> crate::Result<Self> {
+        let mut deserializer = crate::any::Deserializer::new(input);
+        let value = parse::parse_value(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    }
+
+    /// Format the value like `Debug`, but with long strings, byte arrays, arrays and maps truncated
+    /// and deep values cut to `limits`, to log values of any size.
+    pub fn compact_debug(&self, limits: DebugLimits) -> CompactDebug<'_, 'de> {
+        CompactDebug::new(self, limits)
+    }
+
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            Value::OwnedString(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Convert a map with string keys into a `BTreeMap`, the last value is kept if a key is repeated.
+    ///
+    /// Return the value unchanged if it is not a map or if a key is not a string.
+    pub fn try_into_map(self) -> Result<BTreeMap<String, Value<'de>>, Self> {
+        match self {
+            Value::Map(map) if map.iter().all(|(key, _)| key.as_str().is_some()) => Ok(map
+                .into_iter()
+                .map(|(key, value)| match key {
+                    Value::OwnedString(key) => (key, value),
+                    Value::String(key) => (key.to_string(), value),
+                    _ => unreachable!("the keys are strings"),
+                })
+                .collect()),
+            value => Err(value),
+        }
+    }
+
+    /// Convert every element of an array to `T`, such as an integer type for an array of numbers.
+    ///
+    /// Return `None` if the value is not an array or if an element can't be converted, an empty array gives an empty `Vec`.
+    pub fn as_typed_vec<'a, T>(&'a self) -> Option<Vec<T>>
+    where
+        T: TryFrom<&'a Value<'de>>,
+    {
+        match self {
+            Value::Array(values) => values.iter().map(|value| T::try_from(value).ok()).collect(),
+            _ => None,
+        }
+    }
+
+    /// Return the elements of an array of integers that all fit in an `u64`, see `as_typed_vec`.
+    pub fn as_u64_vec(&self) -> Option<Vec<u64>> {
+        self.as_typed_vec()
+    }
+
+    /// Return the values of an array, or the value unchanged if it is not an array.
+    pub fn try_into_array(self) -> Result<Vec<Value<'de>>, Self> {
+        match self {
+            Value::Array(values) => Ok(values),
+            value => Err(value),
+        }
+    }
 }
 
 impl<'de> Debug for Value<'de> {
@@ -82,10 +291,107 @@ impl<'de> Debug for Value<'de> {
                 Debug::fmt(map, f)
             }
             Value::Enum(e) => Debug::fmt(e, f),
+            Value::Extension(tag, payload) => write!(f, "Extension({}, {:?})", tag, payload),
+            Value::OwnedExtension(tag, payload) => {
+                write!(f, "OwnedExtension({}, {:?})", tag, payload)
+            }
+        }
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match *self {
+            Number::I8(n) => serializer.serialize_i8(n),
+            Number::I16(n) => serializer.serialize_i16(n),
+            Number::I32(n) => serializer.serialize_i32(n),
+            Number::I64(n) => serializer.serialize_i64(n),
+            Number::U8(n) => serializer.serialize_u8(n),
+            Number::U16(n) => serializer.serialize_u16(n),
+            Number::U32(n) => serializer.serialize_u32(n),
+            Number::U64(n) => serializer.serialize_u64(n),
+            Number::F32(n) => serializer.serialize_f32(n),
+            Number::F64(n) => serializer.serialize_f64(n),
+            #[cfg(not(no_integer128))]
+            Number::I128(n) => serializer.serialize_i128(n),
+            #[cfg(not(no_integer128))]
+            Number::U128(n) => serializer.serialize_u128(n),
         }
     }
 }
 
+impl<'de> Serialize for Value<'de> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Value::Unit => serializer.serialize_unit(),
+            Value::Bool(boolean) => serializer.serialize_bool(*boolean),
+            Value::Option(None) => serializer.serialize_none(),
+            Value::Option(Some(value)) => serializer.serialize_some(value),
+            Value::Number(number) => number.serialize(serializer),
+            Value::Char(c) => serializer.serialize_char(*c),
+            Value::String(string) => serializer.serialize_str(string),
+            Value::OwnedString(string) => serializer.serialize_str(string),
+            Value::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            Value::OwnedBytes(bytes) => serializer.serialize_bytes(bytes),
+            Value::Array(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Value::Map(map) => map.serialize(serializer),
+            Value::Enum(e) => match (&e.variant, &e.value) {
+                (Value::Number(Number::U32(index)), Value::Unit) => {
+                    serializer.serialize_unit_variant("", *index, "")
+                }
+                (Value::Number(Number::U32(index)), value) => {
+                    serializer.serialize_newtype_variant("", *index, "", value)
+                }
+                _ => Err(ser::Error::custom(
+                    "Only enums identified by their variant index can be serialized",
+                )),
+            },
+            Value::Extension(tag, payload) => {
+                ExtensionValue { tag: *tag, payload }.serialize(serializer)
+            }
+            Value::OwnedExtension(tag, payload) => {
+                ExtensionValue { tag: *tag, payload }.serialize(serializer)
+            }
+        }
+    }
+}
+
+/// Merge two values, for layered configurations where `overlay` overrides `base`.
+///
+/// When both values are maps, the entries of `overlay` replace the entries of `base` with the same key,
+/// or are merged with them if both are maps, and the keys missing from `base` are added after its entries.
+/// Any other value, arrays included, is replaced by `overlay` as a whole.
+/// Borrowed and owned strings with the same content are the same key.
+pub fn merge<'de>(base: Value<'de>, overlay: Value<'de>) -> Value<'de> {
+    match (base, overlay) {
+        (Value::Map(base), Value::Map(overlay)) => Value::Map(base.merge(overlay)),
+        (_, overlay) => overlay,
+    }
+}
+
+/// Serialize a value with the compact format.
+///
+/// `Value` does not keep track of the type of sequences and maps, tuples are decoded as arrays and structs as maps,
+/// so they are serialized with their length (and keys for maps) and can't be deserialized back from the compact format.
+/// Values containing only sequences, maps, options, strings, bytes and primitives convert without loss.
+pub fn to_compact_bytes(value: &Value) -> crate::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    crate::compact::to_writer(value, VecWriter(&mut output))?;
+    Ok(output)
+}
+
 impl<'de> Deserialize<'de> for Value<'de> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -225,18 +531,34 @@ impl<'de> Visitor<'de> for ValueVisitor {
         Ok(Value::Array(buff))
     }
 
-    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
     where
         A: serde::de::MapAccess<'de>,
     {
-        let map = ValueMap::from_map_access(map)?;
-        Ok(Value::Map(map))
+        let Some(key) = map.next_key::<Value>()? else {
+            return Ok(Value::Map(core::iter::empty().collect()));
+        };
+        if key.as_str() != Some(EXTENSION_TOKEN) {
+            let map = ValueMap::from_map_access(key, map)?;
+            return Ok(Value::Map(map));
+        }
+        // extensions are given as a map with a single entry, see `ExtensionAccess`
+        match map.next_value::<(u8, Value)>()? {
+            (tag, Value::Bytes(payload)) => Ok(Value::Extension(tag, payload)),
+            (tag, Value::OwnedBytes(payload)) => Ok(Value::OwnedExtension(tag, payload)),
+            _ => Err(serde::de::Error::custom(
+                "the payload of an extension must be a byte array",
+            )),
+        }
     }
 
-    fn visit_enum<A>(self, _data: A) -> Result<Self::Value, A::Error>
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
     where
         A: serde::de::EnumAccess<'de>,
     {
-        todo!()
+        // the `any` deserializer gives the payload of every variant as a newtype
+        let (variant, payload) = data.variant()?;
+        let value = serde::de::VariantAccess::newtype_variant(payload)?;
+        Ok(Value::Enum(Box::new(EnumValue { variant, value })))
     }
 }
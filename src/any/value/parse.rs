@@ -0,0 +1,258 @@
+//! Iterative parser for `Value`, keeping the values being built on the heap instead of the stack.
+
+use serde::Deserialize;
+
+use super::{size_hint_caution, Box, EnumValue, Number, Value, Vec};
+use crate::any::{Deserializer, PayloadKind, Tag, TagParsingError};
+use crate::error::{Error, Result};
+
+// Value being built, waiting for its children.
+enum Frame<'de> {
+    Some,
+    Variant(u32),
+    Seq {
+        values: Vec<Value<'de>>,
+        // `None` for unsized sequences
+        remaining: Option<usize>,
+    },
+    Map {
+        entries: Vec<(Value<'de>, Value<'de>)>,
+        key: Option<Value<'de>>,
+        // `None` for unsized maps
+        remaining: Option<usize>,
+    },
+    Struct {
+        entries: Vec<(Value<'de>, Value<'de>)>,
+        remaining: usize,
+    },
+}
+
+impl<'de> Frame<'de> {
+    fn seq(len: Option<usize>) -> Self {
+        Frame::Seq {
+            values: Vec::with_capacity(size_hint_caution(len)),
+            remaining: len,
+        }
+    }
+
+    fn map(len: Option<usize>) -> Self {
+        Frame::Map {
+            entries: Vec::with_capacity(size_hint_caution(len)),
+            key: None,
+            remaining: len,
+        }
+    }
+
+    fn structure(len: usize) -> Self {
+        Frame::Struct {
+            entries: Vec::with_capacity(len),
+            remaining: len,
+        }
+    }
+
+    fn is_unsized(&self) -> bool {
+        matches!(
+            self,
+            Frame::Seq {
+                remaining: None,
+                ..
+            } | Frame::Map {
+                remaining: None,
+                ..
+            }
+        )
+    }
+
+    // Whether the next tag may be the end marker, so an unsized sequence or map between two elements.
+    fn at_element_boundary(&self) -> bool {
+        matches!(
+            self,
+            Frame::Seq {
+                remaining: None,
+                ..
+            } | Frame::Map {
+                remaining: None,
+                key: None,
+                ..
+            }
+        )
+    }
+
+    /// Add a child, and return the finished value if it was the last one.
+    fn push(&mut self, value: Value<'de>) -> Option<Value<'de>> {
+        let remaining = match self {
+            Frame::Some => return Some(Value::Option(Some(Box::new(value)))),
+            Frame::Variant(index) => {
+                return Some(Value::Enum(Box::new(EnumValue {
+                    variant: Value::Number(Number::U32(*index)),
+                    value,
+                })))
+            }
+            Frame::Seq { values, remaining } => {
+                values.push(value);
+                remaining.as_mut()
+            }
+            Frame::Map {
+                entries,
+                key,
+                remaining,
+            } => match key.take() {
+                None => {
+                    *key = Some(value);
+                    return None;
+                }
+                Some(key) => {
+                    entries.push((key, value));
+                    remaining.as_mut()
+                }
+            },
+            Frame::Struct { entries, remaining } => {
+                let index = Value::Number(Number::U64(entries.len() as u64));
+                entries.push((index, value));
+                Some(remaining)
+            }
+        };
+        match remaining {
+            Some(remaining) => {
+                *remaining -= 1;
+                (*remaining == 0).then(|| self.finish())
+            }
+            None => None,
+        }
+    }
+
+    fn finish(&mut self) -> Value<'de> {
+        match self {
+            Frame::Seq { values, .. } => Value::Array(core::mem::take(values)),
+            Frame::Map { entries, .. } | Frame::Struct { entries, .. } => {
+                Value::Map(core::mem::take(entries).into_iter().collect())
+            }
+            Frame::Some | Frame::Variant(_) => unreachable!("finished with their only child"),
+        }
+    }
+}
+
+/// Parse the next value without recursion, decoding the same `Value` as its `Deserialize` implementation.
+pub(crate) fn parse_value<'de>(de: &mut Deserializer<'de>) -> Result<Value<'de>> {
+    let mut stack: Vec<Frame<'de>> = Vec::new();
+    match parse_frames(de, &mut stack) {
+        // more bytes could still complete an unsized sequence or map
+        Err(Error::Eof) if stack.iter().any(Frame::is_unsized) => Err(Error::NeedMoreData),
+        res => res,
+    }
+}
+
+fn parse_frames<'de>(
+    de: &mut Deserializer<'de>,
+    stack: &mut Vec<Frame<'de>>,
+) -> Result<Value<'de>> {
+    loop {
+        let at_element_boundary = stack.last().is_some_and(Frame::at_element_boundary);
+        let tag = de.peek_tag()?;
+        let value = match tag {
+            Tag::UnsizedSeqEnd => {
+                if !at_element_boundary {
+                    return Err(TagParsingError::unexpected(
+                        "Any tag other than end of sequence",
+                        tag,
+                    )
+                    .into());
+                }
+                de.pop_tag()?;
+                let mut frame = stack.pop().unwrap();
+                frame.finish()
+            }
+            Tag::Some => {
+                de.pop_tag()?;
+                stack.push(Frame::Some);
+                continue;
+            }
+            Tag::NewTypeStruct => {
+                de.pop_tag()?;
+                continue;
+            }
+            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
+                de.pop_tag()?;
+                let index = u32::from_be_bytes(de.pop_n()?);
+                stack.push(Frame::Variant(index));
+                match tag {
+                    Tag::UnitVariant => Value::Unit,
+                    Tag::NewTypeVariant => continue,
+                    _ => {
+                        let len = de.pop_elements_len()?;
+                        let frame = match tag {
+                            Tag::TupleVariant => Frame::seq(Some(len)),
+                            _ => Frame::structure(len),
+                        };
+                        match open(stack, frame) {
+                            Some(value) => value,
+                            None => continue,
+                        }
+                    }
+                }
+            }
+            Tag::Seq | Tag::Tuple | Tag::TupleStruct | Tag::Map | Tag::Struct => {
+                de.pop_tag()?;
+                let frame = match tag {
+                    Tag::Seq => Frame::seq(Some(de.pop_usize()?)),
+                    Tag::Map => Frame::map(Some(de.pop_usize()?)),
+                    Tag::Struct => Frame::structure(de.pop_elements_len()?),
+                    _ => Frame::seq(Some(de.pop_elements_len()?)),
+                };
+                match open(stack, frame) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            }
+            Tag::UnsizedSeq => {
+                de.pop_tag()?;
+                stack.push(Frame::seq(None));
+                continue;
+            }
+            Tag::UnsizedMap => {
+                de.pop_tag()?;
+                stack.push(Frame::map(None));
+                continue;
+            }
+            // the remaining tags are leaves, the serde implementation doesn't recurse for them
+            tag => {
+                debug_assert!(!matches!(
+                    tag.payload_kind(),
+                    PayloadKind::TaggedChildren { .. }
+                ));
+                Value::deserialize(&mut *de)?
+            }
+        };
+        if let Some(value) = complete(stack, value) {
+            return Ok(value);
+        }
+    }
+}
+
+/// Push a new container, or return it directly if it is empty.
+fn open<'de>(stack: &mut Vec<Frame<'de>>, mut frame: Frame<'de>) -> Option<Value<'de>> {
+    let empty = matches!(
+        frame,
+        Frame::Seq {
+            remaining: Some(0),
+            ..
+        } | Frame::Map {
+            remaining: Some(0),
+            ..
+        } | Frame::Struct { remaining: 0, .. }
+    );
+    if empty {
+        return Some(frame.finish());
+    }
+    stack.push(frame);
+    None
+}
+
+/// Give a finished value to its parents, return the root value once it is finished.
+fn complete<'de>(stack: &mut Vec<Frame<'de>>, mut value: Value<'de>) -> Option<Value<'de>> {
+    while let Some(frame) = stack.last_mut() {
+        value = frame.push(value)?;
+        stack.pop();
+    }
+    Some(value)
+}
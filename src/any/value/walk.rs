@@ -0,0 +1,242 @@
+//! Depth-first traversal of a `Value`, using a stack on the heap instead of recursion.
+
+use core::ops::ControlFlow;
+
+use super::{Value, Vec};
+
+/// Step from a value to one of its children.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment<'a, 'de> {
+    /// Element of an array.
+    Index(usize),
+    /// Value of a map entry, with the key of the entry.
+    Key(&'a Value<'de>),
+    /// Content of an `Option`.
+    Some,
+    /// Content of an enum, with the variant.
+    Variant(&'a Value<'de>),
+}
+
+/// Position of a value from the root of a traversal.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path<'a, 'de> {
+    segments: Vec<PathSegment<'a, 'de>>,
+}
+
+impl<'a, 'de> Path<'a, 'de> {
+    pub fn segments(&self) -> &[PathSegment<'a, 'de>] {
+        &self.segments
+    }
+
+    /// Depth of the value, 0 for the root.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&PathSegment<'a, 'de>> {
+        self.segments.last()
+    }
+
+    /// Copy the keys and variants of the path, so it doesn't borrow the value it was taken from.
+    pub fn to_path_buf(&self) -> PathBuf<'de> {
+        let segments = self.segments.iter().map(|segment| match *segment {
+            PathSegment::Index(i) => OwnedPathSegment::Index(i),
+            PathSegment::Key(key) => OwnedPathSegment::Key(key.clone()),
+            PathSegment::Some => OwnedPathSegment::Some,
+            PathSegment::Variant(variant) => OwnedPathSegment::Variant(variant.clone()),
+        });
+        PathBuf {
+            segments: segments.collect(),
+        }
+    }
+
+    // Move to a child of the value at the given depth.
+    fn set(&mut self, parent_depth: usize, segment: Option<PathSegment<'a, 'de>>) {
+        self.segments.truncate(parent_depth);
+        self.segments.extend(segment);
+    }
+}
+
+/// Step of a `PathBuf`, owning the key or the variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedPathSegment<'de> {
+    /// Element of an array.
+    Index(usize),
+    /// Value of a map entry, with the key of the entry.
+    Key(Value<'de>),
+    /// Content of an `Option`.
+    Some,
+    /// Content of an enum, with the variant.
+    Variant(Value<'de>),
+}
+
+impl<'de> OwnedPathSegment<'de> {
+    pub fn as_segment(&self) -> PathSegment<'_, 'de> {
+        match self {
+            OwnedPathSegment::Index(i) => PathSegment::Index(*i),
+            OwnedPathSegment::Key(key) => PathSegment::Key(key),
+            OwnedPathSegment::Some => PathSegment::Some,
+            OwnedPathSegment::Variant(variant) => PathSegment::Variant(variant),
+        }
+    }
+}
+
+/// Position of a value from the root of a traversal, owning its keys and variants.
+///
+/// Returned by `find` and `find_all`, so the paths can be kept after the value is dropped
+/// and given to `pointer` on another value, through `as_path`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathBuf<'de> {
+    segments: Vec<OwnedPathSegment<'de>>,
+}
+
+impl<'de> PathBuf<'de> {
+    pub fn segments(&self) -> &[OwnedPathSegment<'de>] {
+        &self.segments
+    }
+
+    /// Depth of the value, 0 for the root.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&OwnedPathSegment<'de>> {
+        self.segments.last()
+    }
+
+    /// Borrow the keys and variants of the path.
+    pub fn as_path(&self) -> Path<'_, 'de> {
+        Path {
+            segments: self
+                .segments
+                .iter()
+                .map(OwnedPathSegment::as_segment)
+                .collect(),
+        }
+    }
+}
+
+// Value waiting to be visited, with the depth of its parent and the segment leading to it.
+type Pending<'a, 'de, V> = (usize, Option<PathSegment<'a, 'de>>, V);
+
+impl<'de> Value<'de> {
+    /// Return the value at the end of the path, such as the ones given by `walk`,
+    /// or by `find` and `find_all` through `PathBuf::as_path`.
+    pub fn pointer(&self, path: &[PathSegment<'_, 'de>]) -> Option<&Value<'de>> {
+        path.iter()
+            .try_fold(self, |value, segment| match (segment, value) {
+                (PathSegment::Index(i), Value::Array(values)) => values.get(*i),
+                (PathSegment::Key(key), Value::Map(map)) => map.get(key),
+                (PathSegment::Some, Value::Option(Some(inner))) => Some(&**inner),
+                (PathSegment::Variant(variant), Value::Enum(e)) => {
+                    (e.variant == **variant).then_some(&e.value)
+                }
+                _ => None,
+            })
+    }
+
+    /// Return the first value matching the predicate in depth-first order, with its path.
+    pub fn find<'a, P>(&'a self, mut predicate: P) -> Option<(PathBuf<'de>, &'a Value<'de>)>
+    where
+        P: FnMut(&Value<'de>) -> bool,
+    {
+        let mut found = None;
+        let _ = self.walk(|path, value| {
+            if predicate(value) {
+                found = Some((path.to_path_buf(), value));
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+        found
+    }
+
+    /// Return every value matching the predicate in depth-first order, with their paths.
+    pub fn find_all<'a, P>(&'a self, mut predicate: P) -> Vec<(PathBuf<'de>, &'a Value<'de>)>
+    where
+        P: FnMut(&Value<'de>) -> bool,
+    {
+        let mut found = Vec::new();
+        let _ = self.walk(|path, value| {
+            if predicate(value) {
+                found.push((path.to_path_buf(), value));
+            }
+            ControlFlow::Continue(())
+        });
+        found
+    }
+
+    /// Call `f` with every value in depth-first order, starting with this one, stopping when `f` returns `Break`.
+    ///
+    /// Map keys are not visited, they are part of the path of the values.
+    /// The traversal doesn't recurse, so it can go through values nested arbitrarily deep.
+    pub fn walk<'a, F>(&'a self, mut f: F) -> ControlFlow<()>
+    where
+        F: FnMut(&Path<'a, 'de>, &'a Value<'de>) -> ControlFlow<()>,
+    {
+        let mut path = Path::default();
+        let mut stack: Vec<Pending<'a, 'de, &'a Value<'de>>> = Vec::from([(0, None, self)]);
+        while let Some((parent_depth, segment, value)) = stack.pop() {
+            path.set(parent_depth, segment);
+            f(&path, value)?;
+            let depth = path.len();
+            // children are pushed in reverse to be visited in order
+            match value {
+                Value::Option(Some(inner)) => stack.push((depth, Some(PathSegment::Some), inner)),
+                Value::Array(values) => {
+                    let children = values.iter().enumerate().rev();
+                    stack.extend(children.map(|(i, v)| (depth, Some(PathSegment::Index(i)), v)))
+                }
+                Value::Map(map) => {
+                    let children = map.iter().rev();
+                    stack.extend(children.map(|(k, v)| (depth, Some(PathSegment::Key(k)), v)))
+                }
+                Value::Enum(e) => {
+                    stack.push((depth, Some(PathSegment::Variant(&e.variant)), &e.value))
+                }
+                _ => {}
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Same as `walk` but with mutable access to the values, a value is visited before its children
+    /// so the children of a replaced value are the ones of the new value.
+    pub fn walk_mut<'a, F>(&'a mut self, mut f: F) -> ControlFlow<()>
+    where
+        F: FnMut(&Path<'a, 'de>, &mut Value<'de>) -> ControlFlow<()>,
+    {
+        let mut path = Path::default();
+        let mut stack: Vec<Pending<'a, 'de, &'a mut Value<'de>>> = Vec::from([(0, None, self)]);
+        while let Some((parent_depth, segment, value)) = stack.pop() {
+            path.set(parent_depth, segment);
+            f(&path, value)?;
+            let depth = path.len();
+            match value {
+                Value::Option(Some(inner)) => stack.push((depth, Some(PathSegment::Some), inner)),
+                Value::Array(values) => {
+                    let children = values.iter_mut().enumerate().rev();
+                    stack.extend(children.map(|(i, v)| (depth, Some(PathSegment::Index(i)), v)))
+                }
+                Value::Map(map) => {
+                    let children = map.iter_mut().rev();
+                    stack.extend(children.map(|(k, v)| (depth, Some(PathSegment::Key(k)), v)))
+                }
+                Value::Enum(e) => {
+                    let e = &mut **e;
+                    stack.push((depth, Some(PathSegment::Variant(&e.variant)), &mut e.value))
+                }
+                _ => {}
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Integers restricted to a range, checked when they are serialized and when they are deserialized.
+//!
+//! A field can be wrapped in a [`Bounded`], or keep its integer type and use the functions of this module
+//! with the bounds given as const parameters:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Endpoint {
+//!     #[serde(
+//!         serialize_with = "serde_bin::bounded::serialize::<_, _, 1, 65535>",
+//!         deserialize_with = "serde_bin::bounded::deserialize::<_, _, 1, 65535>"
+//!     )]
+//!     port: u16,
+//! }
+//!
+//! let err = serde_bin::compact::to_bytes(&Endpoint { port: 0 }).unwrap_err();
+//! assert_eq!(err.to_string(), "0 is out of the range 1..=65535");
+//! ```
+//!
+//! The value is encoded as its integer type, the bounds are not part of the encoding.
+
+use core::fmt::{self, Display};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Integer types handled by this module.
+pub trait Integer: Copy + Display {
+    /// `None` if the value doesn't fit in an `i128`, it is then out of any range.
+    fn to_i128(self) -> Option<i128>;
+}
+
+macro_rules! impl_integer {
+    ($($t:ty)*) => {
+        $(
+            impl Integer for $t {
+                fn to_i128(self) -> Option<i128> {
+                    self.try_into().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+struct OutOfRange<T> {
+    value: T,
+    min: i128,
+    max: i128,
+}
+
+impl<T: Display> Display for OutOfRange<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is out of the range {}..={}",
+            self.value, self.min, self.max
+        )
+    }
+}
+
+fn check<T: Integer, const MIN: i128, const MAX: i128>(value: T) -> Result<T, OutOfRange<T>> {
+    match value.to_i128() {
+        Some(n) if (MIN..=MAX).contains(&n) => Ok(value),
+        _ => Err(OutOfRange {
+            value,
+            min: MIN,
+            max: MAX,
+        }),
+    }
+}
+
+pub fn serialize<T, S, const MIN: i128, const MAX: i128>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    T: Integer + Serialize,
+    S: Serializer,
+{
+    check::<T, MIN, MAX>(*value)
+        .map_err(ser::Error::custom)?
+        .serialize(serializer)
+}
+
+pub fn deserialize<'de, T, D, const MIN: i128, const MAX: i128>(
+    deserializer: D,
+) -> Result<T, D::Error>
+where
+    T: Integer + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    check::<T, MIN, MAX>(T::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+/// Integer in the range `MIN..=MAX`, encoded as `T`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bounded<T, const MIN: i128, const MAX: i128>(T);
+
+impl<T: Integer, const MIN: i128, const MAX: i128> Bounded<T, MIN, MAX> {
+    /// Return `None` if `value` is out of the range.
+    pub fn new(value: T) -> Option<Self> {
+        check::<T, MIN, MAX>(value).ok().map(Bounded)
+    }
+
+    pub fn get(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const MIN: i128, const MAX: i128> Serialize for Bounded<T, MIN, MAX>
+where
+    T: Integer + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize::<T, S, MIN, MAX>(&self.0, serializer)
+    }
+}
+
+impl<'de, T, const MIN: i128, const MAX: i128> Deserialize<'de> for Bounded<T, MIN, MAX>
+where
+    T: Integer + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize::<T, D, MIN, MAX>(deserializer).map(Bounded)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::Bounded;
+    use crate::{any, compact, Error};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Endpoint {
+        #[serde(
+            serialize_with = "crate::bounded::serialize::<_, _, 1, 65535>",
+            deserialize_with = "crate::bounded::deserialize::<_, _, 1, 65535>"
+        )]
+        port: u16,
+        retries: Bounded<i8, -1, 10>,
+    }
+
+    #[derive(Serialize)]
+    struct Unchecked {
+        port: u16,
+        retries: i8,
+    }
+
+    #[test]
+    fn test_bounded() {
+        let value = Endpoint {
+            port: 443,
+            retries: Bounded::new(-1).unwrap(),
+        };
+        let bytes = compact::to_bytes(&value).unwrap();
+        // encoded as the integers
+        let unchecked = Unchecked {
+            port: 443,
+            retries: -1,
+        };
+        assert_eq!(bytes, compact::to_bytes(&unchecked).unwrap());
+        assert_eq!(compact::from_bytes(&bytes), Ok(value));
+
+        let unchecked = Unchecked {
+            port: 443,
+            retries: 11,
+        };
+        let bytes = any::to_bytes(&unchecked).unwrap();
+        let err = any::from_bytes::<Endpoint>(&bytes).unwrap_err();
+        assert_eq!(
+            err.root_cause(),
+            &Error::Message("11 is out of the range -1..=10".into())
+        );
+        let unchecked = Unchecked {
+            port: 0,
+            retries: 1,
+        };
+        let bytes = compact::to_bytes(&unchecked).unwrap();
+        let err = compact::from_bytes::<Endpoint>(&bytes).unwrap_err();
+        assert_eq!(
+            err.root_cause(),
+            &Error::Message("0 is out of the range 1..=65535".into())
+        );
+
+        let value = Endpoint {
+            port: 0,
+            retries: Bounded::new(3).unwrap(),
+        };
+        let err = any::to_bytes(&value).unwrap_err();
+        assert_eq!(err.to_string(), "0 is out of the range 1..=65535");
+
+        assert_eq!(Bounded::<u64, 0, 10>::new(11), None);
+        assert_eq!(Bounded::<u128, 0, { i128::MAX }>::new(u128::MAX), None);
+        assert_eq!(Bounded::<u8, 0, 10>::new(10).map(Bounded::get), Some(10));
+    }
+}
@@ -0,0 +1,347 @@
+use core::fmt::{self, Display};
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use crate::write::VecWriter;
+#[cfg(feature = "alloc")]
+use crate::{Error, Result};
+
+/// Rule of the canonical encoding broken by an input deserialized with `DeserializerConfig::canonical`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanonicalRule {
+    /// A string was encoded without its length.
+    UnsizedString,
+    /// A sequence was encoded without its length, only in the `any` format.
+    UnsizedSeq,
+    /// A map was encoded without its length, only in the `any` format.
+    UnsizedMap,
+    /// The keys of a map are not in the increasing order of their encoded bytes, or a key is repeated.
+    UnsortedMapKeys,
+    /// A unit variant was encoded with its name instead of its index, only in the `any` format.
+    VariantByName,
+}
+
+impl Display for CanonicalRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CanonicalRule::UnsizedString => "string without its length",
+            CanonicalRule::UnsizedSeq => "sequence without its length",
+            CanonicalRule::UnsizedMap => "map without its length",
+            CanonicalRule::UnsortedMapKeys => "map keys not sorted by their encoded bytes",
+            CanonicalRule::VariantByName => "unit variant encoded by name",
+        })
+    }
+}
+
+/// Check that a map key comes after the previous one, comparing their encoded bytes.
+pub(crate) fn check_key_order(previous: Option<&[u8]>, key: &[u8]) -> bool {
+    previous.is_none_or(|previous| previous < key)
+}
+
+/// Values of a container serialized in their own buffer, to write them once they are all known.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default)]
+pub struct BufferedValues {
+    bytes: Vec<u8>,
+    // end of each value in `bytes`
+    ends: Vec<usize>,
+}
+
+#[cfg(feature = "alloc")]
+impl BufferedValues {
+    /// Writer for the next value, `end_value` must be called once it is written.
+    pub(crate) fn writer(&mut self) -> VecWriter<'_> {
+        VecWriter(&mut self.bytes)
+    }
+
+    pub(crate) fn end_value(&mut self) {
+        self.ends.push(self.bytes.len());
+    }
+
+    /// Drop the bytes written since the last complete value, after an error.
+    pub(crate) fn discard_partial_value(&mut self) {
+        self.bytes.truncate(self.ends.last().copied().unwrap_or(0));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = core::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(self.ends.iter().copied())
+            .map(|(start, end)| &self.bytes[start..end])
+    }
+
+    /// The values of a map, key then value, with the entries sorted by the bytes of their key.
+    ///
+    /// Fails if two keys have the same bytes, the canonical decoder would reject the map.
+    pub(crate) fn sorted_entries<E>(&self) -> Result<Vec<&[u8]>, E> {
+        let values: Vec<&[u8]> = self.values().collect();
+        let mut entries: Vec<&[&[u8]]> = values.chunks(2).collect();
+        entries.sort_by_key(|entry| entry[0]);
+        if entries.windows(2).any(|pair| pair[0][0] == pair[1][0]) {
+            return Err(Error::DuplicateMapKey);
+        }
+        Ok(entries.concat())
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::CanonicalRule;
+    use crate::{DeserializerConfig, Error, SerializerConfig};
+    use proptest::prelude::*;
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        name: String,
+        scores: HashMap<String, u32>,
+        ids: Vec<u16>,
+        parent: Option<String>,
+        flags: HashMap<u8, Vec<bool>>,
+    }
+
+    fn record(names: &[&str]) -> Record {
+        Record {
+            name: "record".into(),
+            scores: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), i as u32 * 10))
+                .collect(),
+            ids: vec![3, 1, 2],
+            parent: Some("root".into()),
+            flags: (0..5)
+                .map(|i| (i * 37, vec![i % 2 == 0; i as usize]))
+                .collect(),
+        }
+    }
+
+    // map written in the given order, the serializer sorts it with `canonical`
+    struct Entries<'a>(&'a [(u8, u8)]);
+
+    impl<'a> Serialize for Entries<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+        }
+    }
+
+    // sequence or map written without a known length
+    struct Unsized<'a>(&'a [(u8, u8)], bool);
+
+    impl<'a> Serialize for Unsized<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let entries = self.0.iter().filter(|_| true);
+            if self.1 {
+                serializer.collect_map(entries.map(|(k, v)| (k, v)))
+            } else {
+                serializer.collect_seq(entries.map(|(k, _)| k))
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    enum State {
+        Idle,
+        Busy(u8),
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let value = record(&["a", "bb", "ccc", "zz"]);
+
+        let bytes = crate::compact::to_bytes_canonical(&value).unwrap();
+        assert_eq!(crate::compact::from_bytes_canonical(&bytes), Ok(value));
+
+        let value = record(&["a", "bb", "ccc", "zz"]);
+        let bytes = crate::any::to_bytes_canonical(&value).unwrap();
+        assert_eq!(crate::any::from_bytes_canonical(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn test_canonical_map_order() {
+        let names: Vec<String> = (0..50).map(|i| format!("key{}", i * 7)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut reversed = names.clone();
+        reversed.reverse();
+        let value = record(&names);
+        let mut other = record(&reversed);
+        other.scores = HashMap::with_capacity(1000);
+        other
+            .scores
+            .extend(value.scores.iter().map(|(k, v)| (k.clone(), *v)));
+
+        assert_eq!(
+            crate::compact::to_bytes_canonical(&value).unwrap(),
+            crate::compact::to_bytes_canonical(&other).unwrap()
+        );
+        assert_eq!(
+            crate::any::to_bytes_canonical(&value).unwrap(),
+            crate::any::to_bytes_canonical(&other).unwrap()
+        );
+
+        // the entries are sorted by their encoded bytes, the length of a string comes first
+        let entries = Entries(&[(2, 0), (1, 5)]);
+        let bytes = crate::compact::to_bytes_canonical(&entries).unwrap();
+        assert_eq!(bytes, [0, 0, 0, 0, 0, 0, 0, 2, 1, 5, 2, 0]);
+    }
+
+    #[test]
+    fn test_canonical_duplicate_keys() {
+        // the repeated key is not next to its first occurrence before sorting
+        let entries = Entries(&[(1, 0), (2, 3), (1, 5)]);
+        assert_eq!(
+            crate::compact::to_bytes_canonical(&entries),
+            Err(Error::DuplicateMapKey)
+        );
+        assert_eq!(
+            crate::any::to_bytes_canonical(&entries),
+            Err(Error::DuplicateMapKey)
+        );
+        // also in a map written in a sequence
+        assert_eq!(
+            crate::compact::to_bytes_canonical(&[entries]),
+            Err(Error::DuplicateMapKey)
+        );
+    }
+
+    fn non_canonical<T>(rule: CanonicalRule, offset: usize) -> crate::Result<T> {
+        Err(Error::NonCanonical { rule, offset })
+    }
+
+    #[test]
+    fn test_canonical_rejections() {
+        // strings of unknown length
+        let bytes = crate::compact::to_bytes(&(1u8, format_args!("{}", 7))).unwrap();
+        assert_eq!(
+            crate::compact::from_bytes_canonical::<(u8, String)>(&bytes),
+            non_canonical(CanonicalRule::UnsizedString, 1)
+        );
+        let bytes = crate::any::to_bytes(&(1u8, format_args!("{}", 7))).unwrap();
+        assert_eq!(
+            crate::any::from_bytes_canonical::<(u8, String)>(&bytes),
+            non_canonical(CanonicalRule::UnsizedString, 4)
+        );
+        let bytes = crate::compact::to_bytes_canonical(&(1u8, format_args!("{}", 7))).unwrap();
+        assert_eq!(
+            crate::compact::from_bytes_canonical(&bytes),
+            Ok((1u8, "7".to_string()))
+        );
+
+        // sequences and maps of unknown length
+        let bytes = crate::any::to_bytes(&Unsized(&[(1, 0), (2, 0)], false)).unwrap();
+        assert_eq!(
+            crate::any::from_bytes_canonical::<Vec<u8>>(&bytes),
+            non_canonical(CanonicalRule::UnsizedSeq, 0)
+        );
+        let bytes = crate::any::to_bytes(&Unsized(&[(1, 2)], true)).unwrap();
+        assert_eq!(
+            crate::any::from_bytes_canonical::<HashMap<u8, u8>>(&bytes),
+            non_canonical(CanonicalRule::UnsizedMap, 0)
+        );
+
+        // unsorted and repeated keys
+        let bytes = crate::compact::to_bytes(&Entries(&[(2, 0), (1, 5)])).unwrap();
+        assert_eq!(
+            crate::compact::from_bytes_canonical::<HashMap<u8, u8>>(&bytes),
+            non_canonical(CanonicalRule::UnsortedMapKeys, 10)
+        );
+        let bytes = crate::compact::to_bytes(&Entries(&[(1, 0), (1, 5)])).unwrap();
+        assert_eq!(
+            crate::compact::from_bytes_canonical::<HashMap<u8, u8>>(&bytes),
+            non_canonical(CanonicalRule::UnsortedMapKeys, 10)
+        );
+        let bytes = crate::any::to_bytes(&Entries(&[(2, 0), (1, 5)])).unwrap();
+        assert!(matches!(
+            crate::any::from_bytes_canonical::<HashMap<u8, u8>>(&bytes),
+            Err(Error::NonCanonical {
+                rule: CanonicalRule::UnsortedMapKeys,
+                ..
+            })
+        ));
+        // the map is rejected when skipped too
+        let config = DeserializerConfig::new().canonical(true);
+        assert!(matches!(
+            crate::any::from_bytes_with_config::<serde::de::IgnoredAny>(&bytes, config),
+            Err(Error::NonCanonical {
+                rule: CanonicalRule::UnsortedMapKeys,
+                ..
+            })
+        ));
+
+        // unit variants by name
+        let mut bytes = Vec::new();
+        let config = SerializerConfig::new().unit_variants_by_name(true);
+        State::Idle
+            .serialize(&mut crate::any::Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        assert_eq!(
+            crate::any::from_bytes_canonical::<State>(&bytes),
+            non_canonical(CanonicalRule::VariantByName, 0)
+        );
+        let mut bytes = Vec::new();
+        let config = config.canonical(true);
+        State::Idle
+            .serialize(&mut crate::any::Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        assert_eq!(crate::any::from_bytes_canonical(&bytes), Ok(State::Idle));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Small {
+        ids: Vec<u32>,
+        names: HashMap<u16, String>,
+        state: Option<State>,
+        active: bool,
+    }
+
+    fn small() -> impl Strategy<Value = Small> {
+        (
+            prop::collection::vec(any::<u32>(), 0..6),
+            prop::collection::hash_map(any::<u16>(), ".{0,6}", 0..5),
+            prop::option::of(prop_oneof![
+                Just(State::Idle),
+                any::<u8>().prop_map(State::Busy)
+            ]),
+            any::<bool>(),
+        )
+            .prop_map(|(ids, names, state, active)| Small {
+                ids,
+                names,
+                state,
+                active,
+            })
+    }
+
+    proptest! {
+        // a value has a single accepted encoding, so changing a byte can't decode to the same value
+        #[test]
+        fn test_canonical_mutations(
+            value in small(),
+            index in any::<prop::sample::Index>(),
+            mask in 1..=u8::MAX,
+        ) {
+            let mut bytes = crate::compact::to_bytes_canonical(&value).unwrap();
+            let index = index.index(bytes.len());
+            bytes[index] ^= mask;
+            if let Ok(res) = crate::compact::from_bytes_canonical::<Small>(&bytes) {
+                prop_assert_ne!(&res, &value);
+            }
+
+            let mut bytes = crate::any::to_bytes_canonical(&value).unwrap();
+            let index = index.min(bytes.len() - 1);
+            bytes[index] ^= mask;
+            if let Ok(res) = crate::any::from_bytes_canonical::<Small>(&bytes) {
+                prop_assert_ne!(&res, &value);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::write::Write;
+
+/// Stream cipher encrypting the bytes written by `EncryptingWriter` and decrypting the ones read by `DecryptingReader`.
+///
+/// Implement it over the stream cipher of your choice, the crate only provides `XorCipher` as a reference.
+pub trait StreamCipher {
+    /// Encrypt or decrypt the bytes in place, continuing the keystream where the previous call stopped.
+    fn apply_keystream(&mut self, bytes: &mut [u8]);
+}
+
+/// XOR of the bytes with a repeating key, as a reference `StreamCipher` for tests: it is not secure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XorCipher<'a> {
+    key: &'a [u8],
+    position: usize,
+}
+
+impl<'a> XorCipher<'a> {
+    /// Panics if `key` is empty.
+    pub fn new(key: &'a [u8]) -> Self {
+        assert!(!key.is_empty(), "the key of a XorCipher can't be empty");
+        XorCipher { key, position: 0 }
+    }
+}
+
+impl StreamCipher for XorCipher<'_> {
+    fn apply_keystream(&mut self, bytes: &mut [u8]) {
+        for byte in bytes {
+            *byte ^= self.key[self.position];
+            self.position = (self.position + 1) % self.key.len();
+        }
+    }
+}
+
+// the bytes are encrypted on the stack by chunks of this size before being given to the writer
+const CHUNK_LEN: usize = 64;
+
+/// Writer encrypting the bytes with a `StreamCipher` before giving them to the inner writer.
+///
+/// The bytes are encrypted on the stack, so it is available without `alloc`.
+///
+/// ```
+/// use serde_bin::{compact, EncryptingWriter, XorCipher};
+///
+/// let mut output = Vec::new();
+/// let mut writer = EncryptingWriter::new(&mut output, XorCipher::new(b"key"));
+/// compact::to_writer(&(1u8, 2u8), &mut writer).unwrap();
+/// assert_eq!(output, [1 ^ b'k', 2 ^ b'e']);
+/// ```
+#[derive(Debug)]
+pub struct EncryptingWriter<W, C> {
+    writer: W,
+    cipher: C,
+}
+
+impl<W: Write, C: StreamCipher> EncryptingWriter<W, C> {
+    pub fn new(writer: W, cipher: C) -> Self {
+        EncryptingWriter { writer, cipher }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> (W, C) {
+        (self.writer, self.cipher)
+    }
+}
+
+impl<W: Write, C: StreamCipher> Write for &mut EncryptingWriter<W, C> {
+    type Error = W::Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        let mut buff = [0; CHUNK_LEN];
+        for chunk in bytes.chunks(CHUNK_LEN) {
+            let buff = &mut buff[..chunk.len()];
+            buff.copy_from_slice(chunk);
+            self.cipher.apply_keystream(buff);
+            self.writer.write_bytes(buff)?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.writer.flush()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.writer.reserve(additional);
+    }
+}
+
+/// Reader decrypting the bytes read from the inner reader with a `StreamCipher`, to read back
+/// the output of an `EncryptingWriter` before deserializing it.
+///
+/// Without `std`, decrypt the input in place with `StreamCipher::apply_keystream` instead.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct DecryptingReader<R, C> {
+    reader: R,
+    cipher: C,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, C: StreamCipher> DecryptingReader<R, C> {
+    pub fn new(reader: R, cipher: C) -> Self {
+        DecryptingReader { reader, cipher }
+    }
+
+    pub fn into_inner(self) -> (R, C) {
+        (self.reader, self.cipher)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, C: StreamCipher> io::Read for DecryptingReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.cipher.apply_keystream(&mut buf[..len]);
+        Ok(len)
+    }
+}
@@ -0,0 +1,24 @@
+//! The compact format, values are written without their type so the consumer must know it.
+//!
+//! The tagged format, written by the functions of the `tagged` module, can't be read with these functions,
+//! deserializing it fails with `Error::WrongFormat` when the input looks like a tagged value.
+
+#[cfg(feature = "erased")]
+pub use crate::ser::{to_bytes_erased, to_writer_erased};
+#[cfg(feature = "pool")]
+pub use crate::ser::{to_local_pooled_bytes, to_pooled_bytes};
+#[cfg(feature = "alloc")]
+pub use crate::{
+    de::from_bytes_batch,
+    ser::{to_bytes, to_bytes_batch, to_bytes_canonical, to_hybrid, to_writer_batch},
+};
+pub use crate::{
+    de::{
+        for_each_element, from_bytes, from_bytes_canonical, from_bytes_partial, from_bytes_seed,
+        from_bytes_seed_with_config, from_bytes_with_config, from_bytes_with_limit, Deserializer,
+    },
+    ser::{
+        get_serialized_size, get_serialized_size_and_crc, to_array, to_array_ref, to_buff,
+        to_writer, to_writer_complete, Serializer, Writer,
+    },
+};
@@ -0,0 +1,244 @@
+//! Keep the enum variants added by newer producers instead of failing to deserialize them.
+//!
+//! `#[serde(other)]` maps the unknown variants to a unit variant, losing their content.
+//! With the `any` format, a newtype variant holding an `UnknownVariant` and renamed to `UNKNOWN_VARIANT`
+//! captures the bytes of the unknown variants, and serializes them back verbatim:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use serde_bin::compat::UnknownVariant;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! enum Message {
+//!     Ping(u32),
+//!     Text(String),
+//!     #[serde(rename = "$serde_bin::UnknownVariant")]
+//!     Unknown(UnknownVariant),
+//! }
+//! ```
+//!
+//! The fallback must be the last variant, as every variant index from its own is unknown to this version of the enum,
+//! and the name given to `rename` must be the value of `UNKNOWN_VARIANT`.
+//! The captured bytes include the checksums of the containers when `container_checksums` is enabled,
+//! so they must be serialized again with the same option.
+//!
+//! Only unknown variants encoded with their index are captured, unit variants serialized by name with
+//! `SerializerConfig::unit_variants_by_name` are still rejected.
+//! The compact format can't find where an unknown variant ends, it decodes and encodes the fallback
+//! as a newtype variant holding a byte array.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "alloc")]
+use crate::any::{RawBytes, Tag};
+
+/// Name to give to the fallback variant holding an `UnknownVariant` with `#[serde(rename = "...")]`.
+pub const UNKNOWN_VARIANT: &str = "$serde_bin::UnknownVariant";
+
+/// Find the index of the fallback variant, the variants from this index are captured as unknown.
+pub(crate) fn unknown_variant_index(variants: &[&str]) -> Option<u32> {
+    variants
+        .iter()
+        .position(|variant| *variant == UNKNOWN_VARIANT)
+        .map(|index| index as u32)
+}
+
+/// Enum variant of the `any` format unknown to the enum deserializing it, kept as encoded.
+///
+/// The bytes hold the whole variant, tag and variant index included.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownVariant(Vec<u8>);
+
+#[cfg(feature = "alloc")]
+impl UnknownVariant {
+    /// Index of the variant in the enum of the producer.
+    pub fn index(&self) -> u32 {
+        u32::from_be_bytes(self.0[1..5].try_into().unwrap())
+    }
+
+    /// Tag of the variant, telling if it is a unit, newtype, tuple or struct variant.
+    pub fn tag(&self) -> Tag {
+        Tag::try_from(self.0[0]).unwrap()
+    }
+
+    /// Encoded content of the variant, after the variant index.
+    pub fn payload(&self) -> &[u8] {
+        &self.0[5..]
+    }
+
+    /// Encoded variant, that can be decoded with `any::from_bytes` by a version of the enum knowing it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Serialize for UnknownVariant {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        RawBytes(&self.0).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+struct UnknownVariantVisitor;
+
+#[cfg(feature = "alloc")]
+impl<'de> de::Visitor<'de> for UnknownVariantVisitor {
+    type Value = UnknownVariant;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the bytes of an enum variant of the any format")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_byte_buf(v.to_vec())
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // the accessors rely on the tag and the variant index being there
+        match v.first().map(|&byte| Tag::try_from(byte)) {
+            Some(Ok(
+                Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant,
+            )) if v.len() >= 5 => Ok(UnknownVariant(v)),
+            _ => Err(E::invalid_value(de::Unexpected::Bytes(&v), &self)),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'de> Deserialize<'de> for UnknownVariant {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(UnknownVariantVisitor)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::UnknownVariant;
+    use crate::any::Tag;
+    use crate::SerializerConfig;
+    use crate::{DeserializerConfig, Error};
+    use serde::{Deserialize, Serialize};
+
+    // second version of the message, the first one only knows `Ping` and `Text`
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    enum MessageV2 {
+        Ping(u32),
+        Text(String),
+        Join { user: String, channels: Vec<u16> },
+        Ack,
+        Move(i8, (char, Option<u64>)),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum MessageV1 {
+        Ping(u32),
+        Text(String),
+        #[serde(rename = "$serde_bin::UnknownVariant")]
+        Unknown(UnknownVariant),
+    }
+
+    fn messages() -> Vec<MessageV2> {
+        vec![
+            MessageV2::Ping(3),
+            MessageV2::Join {
+                user: "john".into(),
+                channels: vec![1, 2],
+            },
+            MessageV2::Text("hello".into()),
+            MessageV2::Ack,
+            MessageV2::Move(-1, ('x', Some(7))),
+        ]
+    }
+
+    #[test]
+    fn test_unknown_variant_round_trip() {
+        let messages = messages();
+        let bytes = crate::any::to_bytes(&messages).unwrap();
+
+        let res: Vec<MessageV1> = crate::any::from_bytes(&bytes).unwrap();
+        assert_eq!(res.len(), 5);
+        assert_eq!(res[0], MessageV1::Ping(3));
+        assert_eq!(res[2], MessageV1::Text("hello".into()));
+        let MessageV1::Unknown(join) = &res[1] else {
+            panic!("expected an unknown variant, got {:?}", res[1]);
+        };
+        assert_eq!(join.index(), 2);
+        assert_eq!(join.tag(), Tag::StructVariant);
+        let MessageV1::Unknown(ack) = &res[3] else {
+            panic!("expected an unknown variant, got {:?}", res[3]);
+        };
+        assert_eq!((ack.index(), ack.tag()), (3, Tag::UnitVariant));
+        assert!(ack.payload().is_empty());
+        assert_eq!(
+            crate::any::from_bytes::<MessageV2>(join.as_bytes()),
+            Ok(messages[1].clone())
+        );
+
+        // the unknown variants are written back verbatim
+        let reserialized = crate::any::to_bytes(&res).unwrap();
+        assert_eq!(reserialized, bytes);
+        let res: Vec<MessageV2> = crate::any::from_bytes(&reserialized).unwrap();
+        assert_eq!(res, messages);
+    }
+
+    #[test]
+    fn test_unknown_variant_checksums() {
+        let messages = messages();
+        let ser_config = SerializerConfig::new().container_checksums(true);
+        let de_config = DeserializerConfig::new().container_checksums(true);
+        let mut bytes = Vec::new();
+        messages
+            .serialize(&mut crate::any::Serializer::with_config(
+                &mut bytes, ser_config,
+            ))
+            .unwrap();
+
+        let res: Vec<MessageV1> = crate::any::from_bytes_with_config(&bytes, de_config).unwrap();
+        let mut reserialized = Vec::new();
+        res.serialize(&mut crate::any::Serializer::with_config(
+            &mut reserialized,
+            ser_config,
+        ))
+        .unwrap();
+        assert_eq!(reserialized, bytes);
+    }
+
+    #[test]
+    fn test_unknown_variant_invalid_bytes() {
+        // `UnknownVariant` only accepts the bytes of a variant from other formats
+        let bytes = crate::compact::to_bytes(&MessageV1::Text("hi".into())).unwrap();
+        let mut unknown = bytes.clone();
+        unknown[3] = 2;
+        assert!(matches!(
+            crate::compact::from_bytes::<MessageV1>(&unknown),
+            Err(Error::Message(_))
+        ));
+    }
+}
@@ -0,0 +1,498 @@
+use crate::any::{Extension, TagMap};
+#[cfg(feature = "alloc")]
+use crate::redact::{FieldFilter, Filter};
+
+/// Default limit of `DeserializerConfig::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// How the `any` deserializer handles a number encoded with a different type than the requested one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NumberCoercion {
+    /// The tag must match the requested type.
+    #[default]
+    Exact,
+    /// Accept integers of a type whose every value fits in the requested type (`u8` into `u64` or `i16`, `i16` into `i64`...)
+    /// and `f32` into `f64`.
+    Widen,
+    /// Accept integers of any type if the value fits in the requested type, and `f32` into `f64`.
+    Fit,
+}
+
+/// How the `any` deserializer handles a byte array where a string is expected, and a string where a byte array is expected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StrCoercion {
+    /// The tag must match the requested type.
+    #[default]
+    Exact,
+    /// Accept byte arrays containing valid UTF-8 as strings, and strings as byte arrays.
+    Utf8,
+    /// Like `Utf8`, but the invalid UTF-8 sequences of a byte array are replaced with `U+FFFD`,
+    /// the string is then allocated and can't be borrowed.
+    #[cfg(feature = "alloc")]
+    Lossy,
+}
+
+/// Number of bytes used to encode the index of an enum variant in the compact format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum VariantIndexWidth {
+    /// Up to 256 variants.
+    U8,
+    /// Up to 65536 variants.
+    U16,
+    /// Any variant, serde gives the indices as `u32`.
+    #[default]
+    U32,
+}
+
+impl VariantIndexWidth {
+    pub const fn bytes(self) -> usize {
+        match self {
+            VariantIndexWidth::U8 => 1,
+            VariantIndexWidth::U16 => 2,
+            VariantIndexWidth::U32 => 4,
+        }
+    }
+}
+
+/// Options for the deserializers of both formats, `from_bytes` uses `DeserializerConfig::new()`.
+///
+/// Every option is disabled by default, except the nesting depth which is limited to `DEFAULT_MAX_DEPTH`
+/// so malicious inputs can't overflow the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeserializerConfig {
+    pub(crate) max_total_elements: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) lenient_sequences: bool,
+    pub(crate) missing_fields: bool,
+    pub(crate) number_coercion: NumberCoercion,
+    pub(crate) structs_as_sequences: bool,
+    pub(crate) container_checksums: bool,
+    pub(crate) str_coercion: StrCoercion,
+    pub(crate) deny_any: bool,
+    pub(crate) unwrap_some: bool,
+    pub(crate) top_level_tag: bool,
+    pub(crate) canonical: bool,
+    pub(crate) strict_integers: bool,
+    pub(crate) hashed_field_names: bool,
+    pub(crate) extensions: &'static [Extension],
+    pub(crate) variant_index_width: VariantIndexWidth,
+    pub(crate) projection: Option<&'static [&'static str]>,
+    pub(crate) tag_map: Option<&'static TagMap>,
+    #[cfg(feature = "alloc")]
+    pub(crate) trace: Option<usize>,
+}
+
+impl Default for DeserializerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeserializerConfig {
+    pub const fn new() -> Self {
+        DeserializerConfig {
+            max_total_elements: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            lenient_sequences: false,
+            missing_fields: false,
+            number_coercion: NumberCoercion::Exact,
+            structs_as_sequences: false,
+            container_checksums: false,
+            str_coercion: StrCoercion::Exact,
+            deny_any: false,
+            unwrap_some: false,
+            top_level_tag: false,
+            canonical: false,
+            strict_integers: false,
+            hashed_field_names: false,
+            extensions: &[],
+            variant_index_width: VariantIndexWidth::U32,
+            projection: None,
+            tag_map: None,
+            #[cfg(feature = "alloc")]
+            trace: None,
+        }
+    }
+
+    /// Limit the number of elements decoded across the whole input, counting the elements of all sequences,
+    /// the entries of all maps and the fields of all structs and tuples.
+    ///
+    /// Going over the limit fails with `Error::ElementBudgetExceeded`.
+    pub const fn max_total_elements(mut self, max: usize) -> Self {
+        self.max_total_elements = Some(max);
+        self
+    }
+
+    /// Limit how deeply values can be nested, each sequence, map, struct, tuple, enum variant, newtype struct
+    /// and `Some` is one level.
+    ///
+    /// Going over the limit fails with `Error::DepthLimitExceeded`.
+    pub const fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// Remove the nesting depth limit, only use it with trusted inputs as every level of nesting uses stack space.
+    pub const fn unlimited_depth(mut self) -> Self {
+        self.max_depth = None;
+        self
+    }
+
+    /// Accept sequences where a tuple, a tuple struct, an array or a struct is expected if they have the expected number
+    /// of elements, and accept tuples and tuple structs where a sequence or a struct is expected, so a field can change
+    /// from a `Vec` to an array. The elements of a sequence or a tuple are given to a struct as its fields in order.
+    ///
+    /// Only used by the `any` format, the compact format already encodes them the same way apart from the length.
+    pub const fn lenient_sequences(mut self, enabled: bool) -> Self {
+        self.lenient_sequences = enabled;
+        self
+    }
+
+    /// Accept structs and struct variants encoded with fewer fields than they have, so fields can be added at the end
+    /// of a struct: the fields after the encoded ones are missing, and need `#[serde(default)]` or an `Option` type.
+    /// A struct encoded with more fields than it has is still rejected with `Error::SeqSizeMismatch`.
+    ///
+    /// Only used by the `any` format, the compact format doesn't know the number of fields.
+    pub const fn missing_fields(mut self, enabled: bool) -> Self {
+        self.missing_fields = enabled;
+        self
+    }
+
+    /// Accept numbers encoded with another type than the requested one, so a field can grow from `u16` to `u32`.
+    ///
+    /// Only used by the `any` format, the compact format doesn't know how the numbers were encoded.
+    pub const fn number_coercion(mut self, coercion: NumberCoercion) -> Self {
+        self.number_coercion = coercion;
+        self
+    }
+
+    /// Accept byte arrays where a string is expected and strings where a byte array is expected,
+    /// for producers that don't distinguish them.
+    ///
+    /// Only used by the `any` format, the compact format already encodes them the same way.
+    pub const fn str_coercion(mut self, coercion: StrCoercion) -> Self {
+        self.str_coercion = coercion;
+        self
+    }
+
+    /// Give structs to `deserialize_any` as sequences of their fields instead of maps indexed by the position of the fields,
+    /// this is needed to deserialize internally tagged enums (`#[serde(tag = "...")]`), as the names of the fields are not encoded
+    /// but the tag is always the first field.
+    ///
+    /// Only used by the `any` format, the compact format doesn't support `deserialize_any`.
+    pub const fn structs_as_sequences(mut self, enabled: bool) -> Self {
+        self.structs_as_sequences = enabled;
+        self
+    }
+
+    /// Read the `U32` keys of the structs serialized as maps as the `any::field_hash` of the field names,
+    /// as written by `SerializerConfig::hashed_field_names`, instead of the index of the fields.
+    ///
+    /// The keys matching no field are unknown fields, skipped unless the struct denies them,
+    /// and a key matching two fields of the struct fails with `Error::FieldHashCollision`.
+    ///
+    /// Only used by the `any` format.
+    pub const fn hashed_field_names(mut self, enabled: bool) -> Self {
+        self.hashed_field_names = enabled;
+        self
+    }
+
+    /// Decode the newtype structs registered as an `any::Extension` from the values with the tag of the extension,
+    /// as written by `SerializerConfig::extensions`. The newtype structs written without the extension are still accepted.
+    ///
+    /// Only used by the `any` format.
+    pub const fn extensions(mut self, extensions: &'static [Extension]) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Expect the checksum written after each container by `SerializerConfig::container_checksums` and verify it,
+    /// a mismatch fails with `Error::ChecksumMismatch`.
+    ///
+    /// Only used by the `any` format, and must match the option used to serialize the input.
+    pub const fn container_checksums(mut self, enabled: bool) -> Self {
+        self.container_checksums = enabled;
+        self
+    }
+
+    /// Fail with `Error::AnyDenied` when a `Deserialize` implementation asks for a value without giving its type,
+    /// for services that only decode fully typed messages from untrusted inputs.
+    ///
+    /// This rejects `Value`, untagged and internally tagged enums, flattened fields and `IgnoredAny`.
+    ///
+    /// Only used by the `any` format, the compact format never supports `deserialize_any`.
+    pub const fn deny_any(mut self, enabled: bool) -> Self {
+        self.deny_any = enabled;
+        self
+    }
+
+    /// Accept a `Some` where a value that is not an option is expected, and deserialize the value it wraps,
+    /// for producers using an `Option` for a field the consumer requires.
+    ///
+    /// Only a single `Some` is unwrapped, and `None` still fails.
+    ///
+    /// Only used by the `any` format, the compact format can't tell an option from the value.
+    pub const fn unwrap_some(mut self, enabled: bool) -> Self {
+        self.unwrap_some = enabled;
+        self
+    }
+
+    /// Expect the tag written before the top-level value by `SerializerConfig::top_level_tag`,
+    /// and fail with `Error::TagParsingError` if it doesn't match the type being deserialized.
+    ///
+    /// Only used by the compact format, and must match the option used to serialize the input.
+    pub const fn top_level_tag(mut self, enabled: bool) -> Self {
+        self.top_level_tag = enabled;
+        self
+    }
+
+    /// Only accept the canonical encoding written by `SerializerConfig::canonical`, so a value has a single accepted
+    /// representation, for data that is hashed or signed.
+    ///
+    /// Fails with `Error::NonCanonical` on strings, and in the `any` format sequences and maps, encoded without
+    /// their length, on map keys not in the increasing order of their encoded bytes, and in the `any` format
+    /// on unit variants encoded by name. Trailing bytes are always rejected by `from_bytes`.
+    pub const fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    /// Only accept the integers written with the narrowest tag holding their value, `5` must be tagged `U8` and not `U64`,
+    /// `-1` must be tagged `I8` and not `I32`. The signedness is kept, a positive value tagged `I8` is accepted.
+    /// Any other tag fails with `Error::NonCanonicalInteger`, even for the values that are skipped.
+    ///
+    /// The serializers write the tag of the type of the value, so the producers must narrow the integers themselves,
+    /// and the consumers need `NumberCoercion::Widen` to decode them as wider types.
+    ///
+    /// Only used by the `any` format.
+    pub const fn strict_integers(mut self, enabled: bool) -> Self {
+        self.strict_integers = enabled;
+        self
+    }
+
+    /// Only decode the given fields of the top-level struct, the others are skipped with their tags without being decoded,
+    /// for reading a few fields of a large record. The skipped fields are missing for the struct,
+    /// so they need `#[serde(default)]` or an `Option` type.
+    ///
+    /// Works with the structs written positionally, by `SerializerConfig::sparse_structs` and by `hashed_field_names`.
+    /// The structs inside a sequence, a map, a tuple or another struct are decoded as usual.
+    ///
+    /// Only used by the `any` format, the compact format doesn't know where the fields end.
+    pub const fn projection(mut self, fields: &'static [&'static str]) -> Self {
+        self.projection = Some(fields);
+        self
+    }
+
+    /// Read the tags with the bytes of `map` instead of the numbering of this crate, to decode an input written
+    /// with another numbering of the tags. The bytes unknown to the map fail with `TagParsingError::UnmappedTag`.
+    ///
+    /// The raw values and the resynchronization still read the tags with the numbering of this crate.
+    ///
+    /// Only used by the `any` format.
+    pub const fn tag_map(mut self, map: &'static TagMap) -> Self {
+        self.tag_map = Some(map);
+        self
+    }
+
+    /// Record the last `len` steps of the deserialization, the tags read, the lengths decoded and the struct fields entered,
+    /// with their offset in the input, and attach them to the error if it fails, see `Error::trace`.
+    /// The alternate format of the error (`{:#}`) includes them.
+    ///
+    /// The steps are kept in a buffer of `len` events, allocated once.
+    ///
+    /// Only used by the `any` format.
+    #[cfg(feature = "alloc")]
+    pub const fn trace(mut self, len: usize) -> Self {
+        self.trace = Some(len);
+        self
+    }
+
+    /// Read the variant indices on the number of bytes written by `SerializerConfig::variant_index_width`.
+    ///
+    /// Only used by the compact format, and must match the option used to serialize the input.
+    pub const fn variant_index_width(mut self, width: VariantIndexWidth) -> Self {
+        self.variant_index_width = width;
+        self
+    }
+}
+
+/// Options for the serializers.
+///
+/// Every option is disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SerializerConfig {
+    pub(crate) unit_variants_by_name: bool,
+    pub(crate) container_checksums: bool,
+    pub(crate) element_index: Option<usize>,
+    pub(crate) top_level_tag: bool,
+    pub(crate) canonical: bool,
+    pub(crate) sparse_structs: bool,
+    pub(crate) hashed_field_names: bool,
+    pub(crate) extensions: &'static [Extension],
+    pub(crate) variant_index_width: VariantIndexWidth,
+    pub(crate) strict_map_keys: bool,
+    #[cfg(feature = "alloc")]
+    pub(crate) field_filter: Option<Filter>,
+}
+
+impl SerializerConfig {
+    pub const fn new() -> Self {
+        SerializerConfig {
+            unit_variants_by_name: false,
+            container_checksums: false,
+            element_index: None,
+            top_level_tag: false,
+            canonical: false,
+            sparse_structs: false,
+            hashed_field_names: false,
+            extensions: &[],
+            variant_index_width: VariantIndexWidth::U32,
+            strict_map_keys: false,
+            #[cfg(feature = "alloc")]
+            field_filter: None,
+        }
+    }
+
+    /// Serialize unit variants as a string containing the name of the variant instead of the variant index,
+    /// so the variants can be reordered without breaking already serialized data.
+    ///
+    /// Only used by the `any` format.
+    pub const fn unit_variants_by_name(mut self, enabled: bool) -> Self {
+        self.unit_variants_by_name = enabled;
+        self
+    }
+
+    /// Write the CRC-32 of each sequence, tuple, map, struct and tuple or struct variant after its last element,
+    /// so corrupted data fails to deserialize instead of silently shifting the following values.
+    ///
+    /// The checksum covers the bytes of the elements, a nested container is covered by its header and its own checksum,
+    /// and the header of a container is covered by the checksum of its parent.
+    /// This changes the encoding, the input must be deserialized with `DeserializerConfig::container_checksums`.
+    ///
+    /// Only used by the `any` format.
+    pub const fn container_checksums(mut self, enabled: bool) -> Self {
+        self.container_checksums = enabled;
+        self
+    }
+
+    /// Write the offsets of every block of `block_len` elements after a sequence with a known length serialized
+    /// as the top-level value, so `indexed::IndexedSeq` can decode its elements without decoding the whole sequence.
+    ///
+    /// This changes the encoding, see the `indexed` module. Values that are not a sequence are serialized as usual.
+    ///
+    /// Only used by the compact format.
+    #[cfg(feature = "alloc")]
+    pub const fn element_index(mut self, block_len: usize) -> Self {
+        self.element_index = Some(block_len);
+        self
+    }
+
+    /// Write the `any` format tag of the top-level value before it, so the deserializer can check
+    /// that the input holds the expected type before decoding it positionally.
+    /// Only the top-level value is tagged, so it costs a single byte but doesn't catch changes in nested values.
+    ///
+    /// This changes the encoding, the input must be deserialized (or opened by `indexed::IndexedSeq`)
+    /// with `DeserializerConfig::top_level_tag`.
+    ///
+    /// Only used by the compact format, the `any` format already tags every value.
+    pub const fn top_level_tag(mut self, enabled: bool) -> Self {
+        self.top_level_tag = enabled;
+        self
+    }
+
+    /// Write the single encoding accepted by `DeserializerConfig::canonical`: strings, sequences and maps
+    /// are always written with their length, the entries of maps are sorted by the encoded bytes of their key,
+    /// and unit variants are written with their index even with `unit_variants_by_name`.
+    ///
+    /// The strings given to `collect_str`, the sequences with an unknown length in the `any` format
+    /// and all the maps are buffered before being written. A map with the same key twice has no canonical encoding
+    /// and fails with `Error::DuplicateMapKey`.
+    #[cfg(feature = "alloc")]
+    pub const fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    /// Serialize structs as maps from the index of each field to its value, so the fields skipped
+    /// with `#[serde(skip_serializing_if = "...")]` are left out without shifting the following fields.
+    /// Struct variants are serialized as newtype variants holding such a map.
+    ///
+    /// Combined with `#[serde(default)]`, a struct with mostly default fields only writes the other ones,
+    /// the deserializer fills the missing fields with their default. The `any` deserializer accepts
+    /// these maps where a struct is expected without any option.
+    ///
+    /// Only used by the `any` format, the compact format can't tell which fields are missing.
+    pub const fn sparse_structs(mut self, enabled: bool) -> Self {
+        self.sparse_structs = enabled;
+        self
+    }
+
+    /// Serialize structs as maps from the `any::field_hash` of the name of each field to its value,
+    /// like `sparse_structs` but keyed by a 4 bytes hash of the name instead of the index of the field,
+    /// so the fields can be reordered, added or removed, but not renamed, without breaking already serialized data.
+    /// This takes precedence over `sparse_structs`.
+    ///
+    /// Two fields of a struct with the same hash fail with `Error::FieldHashCollision`. The collisions are found
+    /// with the `alloc` feature, without it they are only found by the deserializer.
+    /// The input must be deserialized with `DeserializerConfig::hashed_field_names`.
+    ///
+    /// Only used by the `any` format.
+    pub const fn hashed_field_names(mut self, enabled: bool) -> Self {
+        self.hashed_field_names = enabled;
+        self
+    }
+
+    /// Serialize the newtype structs registered as an `any::Extension` with the tag of the extension,
+    /// followed by the length of their content encoded with the compact format and the content,
+    /// or of the payload given by the encoder of the extension for those registered with `Extension::with_codec`.
+    ///
+    /// Only used by the `any` format.
+    pub const fn extensions(mut self, extensions: &'static [Extension]) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Write the variant indices on 1 or 2 bytes instead of 4, to shrink the payloads holding many enums.
+    /// Serializing a variant whose index doesn't fit fails with `Error::VariantIndexTooLarge`.
+    ///
+    /// This changes the encoding, the input must be deserialized with `DeserializerConfig::variant_index_width`.
+    ///
+    /// Only used by the compact format, the tags of the `any` format give the size of the index following them.
+    pub const fn variant_index_width(mut self, width: VariantIndexWidth) -> Self {
+        self.variant_index_width = width;
+        self
+    }
+
+    /// Reject the map keys holding other values, sequences, tuples, maps and structs, with `Error::CompositeMapKey`,
+    /// for the data bridged to formats only accepting scalar and string keys, such as JSON.
+    /// The key is checked by its leading tag, a newtype struct or `Some` around a sequence is accepted.
+    ///
+    /// Only used by the `any` format.
+    pub const fn strict_map_keys(mut self, enabled: bool) -> Self {
+        self.strict_map_keys = enabled;
+        self
+    }
+
+    /// Call `filter` with the name of the struct, or of the enum for a struct variant, and the name of the field
+    /// before serializing each field, to leave out or hide some fields, like passwords in a debug dump,
+    /// without defining another type.
+    ///
+    /// A redacted field is serialized as `None`, so it is deserialized back as `None` by an `Option` field.
+    /// A skipped field is left out like with `#[serde(skip_serializing_if = "...")]`, the `any` format buffers
+    /// the structs to write the number of fields left. Only the structs serialized as maps by `sparse_structs`
+    /// or `hashed_field_names` can then be deserialized, the missing field gets its default value.
+    #[cfg(feature = "alloc")]
+    pub const fn field_filter(mut self, filter: FieldFilter) -> Self {
+        self.field_filter = Some(Filter(filter));
+        self
+    }
+
+    /// Config of the serializers writing the values buffered by the canonical mode,
+    /// only the top-level serializer handles the top-level value.
+    #[cfg(feature = "alloc")]
+    pub(crate) const fn for_buffered_values(mut self) -> Self {
+        self.element_index = None;
+        self.top_level_tag = false;
+        self
+    }
+}
@@ -0,0 +1,121 @@
+//! Serialize a `Cow<[u8]>` as a byte array.
+//!
+//! Like `&[u8]`, the derived `Serialize` implementation of `Cow<[u8]>` serialize it as a sequence, one element at the time.
+//! Use `#[serde(with = "serde_bin::cow_bytes", borrow)]` to serialize it with `serialize_bytes` instead, as [`Bytes`](crate::Bytes)
+//! and [`ByteBuf`](crate::ByteBuf) do. The bytes are borrowed from the input when the deserializer gives them borrowed,
+//! as both formats of this crate do, and copied in a `Cow::Owned` otherwise.
+//!
+//! The `borrow` attribute is needed for the derived `Deserialize` implementation to borrow from the input,
+//! the deserialized `Cow` can't outlive the input.
+
+use core::fmt;
+
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+
+extern crate alloc;
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(bytes)
+}
+
+pub fn deserialize<'de: 'a, 'a, D>(deserializer: D) -> Result<Cow<'a, [u8]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(CowBytesVisitor)
+}
+
+struct CowBytesVisitor;
+
+impl<'de> Visitor<'de> for CowBytesVisitor {
+    type Value = Cow<'de, [u8]>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Cow::Owned(Vec::from(v)))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Cow::Owned(v))
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde::de::value::{self, BytesDeserializer};
+    use serde::{Deserialize, Serialize};
+
+    use crate::any::Tag;
+    use crate::{any, compact, Bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Frame<'a> {
+        id: u8,
+        #[serde(with = "crate::cow_bytes", borrow)]
+        payload: Cow<'a, [u8]>,
+    }
+
+    #[derive(Serialize)]
+    struct BytesFrame<'a> {
+        id: u8,
+        payload: Bytes<'a>,
+    }
+
+    #[test]
+    fn test_cow_bytes() {
+        for payload in [Cow::Borrowed(&b"payload"[..]), Cow::Owned(vec![1, 2, 3])] {
+            let value = Frame { id: 7, payload };
+            let as_bytes = BytesFrame {
+                id: 7,
+                payload: Bytes(&value.payload),
+            };
+
+            let bytes = compact::to_bytes(&value).unwrap();
+            assert_eq!(bytes, compact::to_bytes(&as_bytes).unwrap());
+            let res: Frame = compact::from_bytes(&bytes).unwrap();
+            assert_eq!(res, value);
+            assert!(matches!(res.payload, Cow::Borrowed(_)));
+
+            let bytes = any::to_bytes(&value).unwrap();
+            assert_eq!(bytes, any::to_bytes(&as_bytes).unwrap());
+            let payload_tag = bytes.len() - value.payload.len() - 9;
+            assert_eq!(bytes[payload_tag], u8::from(Tag::ByteArray));
+            let res: Frame = any::from_bytes(&bytes).unwrap();
+            assert_eq!(res, value);
+            assert!(matches!(res.payload, Cow::Borrowed(_)));
+        }
+    }
+
+    #[test]
+    fn test_cow_bytes_owned() {
+        // the bytes are copied when the deserializer can't lend them
+        let deserializer = BytesDeserializer::<value::Error>::new(b"owned");
+        let res = crate::cow_bytes::deserialize(deserializer).unwrap();
+        assert_eq!(res, Cow::<[u8]>::Owned(b"owned".to_vec()));
+        assert!(matches!(res, Cow::Owned(_)));
+    }
+}
@@ -1,37 +1,396 @@
 use serde::{
-    de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+    de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
     serde_if_integer128, Deserialize,
 };
 
+use core::marker::PhantomData;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::ops::Range;
+
 use crate::{
-    error::{Error, NoWriterError, Result},
+    any::{Tag, TagParsingError},
+    canonical::{check_key_order, CanonicalRule},
+    config::{DeserializerConfig, VariantIndexWidth},
+    error::{Error, ForEachError, NoWriterError, Result},
+    is_unsized_string_end,
+    progress::{NoProgress, ProgressCallback, ProgressTracker},
     UNSIZED_STRING_END_MARKER,
 };
 
-pub struct Deserializer<'de> {
+/// Convert a length read from the input to `L`, `usize` outside of the tests, failing with `Error::InvalidSize`
+/// if it doesn't fit, as on the targets where `usize` is smaller than the `u64` of the encoded lengths.
+pub(crate) fn narrow_len<L: TryFrom<u64>>(len: u64) -> Result<L> {
+    L::try_from(len).map_err(|_| Error::InvalidSize)
+}
+
+pub struct Deserializer<'de, P = NoProgress> {
     input: &'de [u8],
+    config: DeserializerConfig,
+    remaining_elements: Option<usize>,
+    remaining_depth: Option<usize>,
+    progress: ProgressTracker<P>,
+    // the tag of the top-level value is yet to be checked
+    top_level_tag: bool,
+    input_len: usize,
 }
 
 pub fn from_bytes<'a, T>(input: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer { input };
-    let t = T::deserialize(&mut deserializer)?;
-    let len = deserializer.input.len();
-    (len == 0).then_some(t).ok_or(Error::TrailingBytes(len))
+    from_bytes_seed(PhantomData, input)
+}
+
+/// Fail with `Error::MessageTooLarge` if the input is longer than `max_len` bytes, before decoding anything,
+/// then deserialize it like `from_bytes`.
+///
+/// This rejects an oversized frame up front, `DeserializerConfig::max_total_elements` bounds the work done while decoding.
+pub fn from_bytes_with_limit<'a, T>(input: &'a [u8], max_len: usize) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    check_message_len(input, max_len)?;
+    from_bytes(input)
+}
+
+pub(crate) fn check_message_len(input: &[u8], max_len: usize) -> Result<()> {
+    if input.len() > max_len {
+        return Err(Error::MessageTooLarge {
+            len: input.len(),
+            max: max_len,
+        });
+    }
+    Ok(())
+}
+
+pub fn from_bytes_seed<'a, S>(seed: S, input: &'a [u8]) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    from_bytes_seed_with_config(seed, input, DeserializerConfig::new())
+}
+
+pub fn from_bytes_with_config<'a, T>(input: &'a [u8], config: DeserializerConfig) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_seed_with_config(PhantomData, input, config)
+}
+
+pub fn from_bytes_seed_with_config<'a, S>(
+    seed: S,
+    input: &'a [u8],
+    config: DeserializerConfig,
+) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    let mut deserializer = Deserializer::with_config(input, config);
+    let res = seed
+        .deserialize(&mut deserializer)
+        .and_then(|t| deserializer.end().map(|()| t));
+    // with a top-level tag, the input of a single number is also a tagged value
+    #[cfg(feature = "alloc")]
+    let res = match res {
+        Err(err) if !config.top_level_tag => {
+            Err(err.with_format_hint(input, crate::Format::Compact))
+        }
+        res => res,
+    };
+    res
+}
+
+/// Deserialize a value with `DeserializerConfig::canonical`, rejecting any other encoding than the one
+/// written by `to_bytes_canonical`.
+pub fn from_bytes_canonical<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_with_config(input, DeserializerConfig::new().canonical(true))
+}
+
+/// Deserialize a sequence, calling `f` with each element as soon as it is decoded instead of collecting them,
+/// to process a large sequence without holding all of it.
+///
+/// The first error returned by `f` stops the decoding and is returned as `ForEachError::Callback`.
+///
+/// ```
+/// let bytes = serde_bin::compact::to_bytes(&vec![1u32, 2, 3]).unwrap();
+/// let mut sum = 0;
+/// serde_bin::compact::for_each_element(&bytes, |n: u32| {
+///     sum += n;
+///     Ok::<_, core::convert::Infallible>(())
+/// })
+/// .unwrap();
+/// assert_eq!(sum, 6);
+/// ```
+pub fn for_each_element<'a, T, E, F>(
+    input: &'a [u8],
+    f: F,
+) -> core::result::Result<(), ForEachError<E>>
+where
+    T: Deserialize<'a>,
+    F: FnMut(T) -> core::result::Result<(), E>,
+{
+    let mut error = None;
+    let res = from_bytes_seed(ForEachElement::new(f, &mut error), input);
+    for_each_result(res, error)
+}
+
+/// Result of `for_each_element`, the error of the callback comes first as it stopped the decoding of the sequence,
+/// the deserializer may then have failed on the elements left.
+pub(crate) fn for_each_result<E>(
+    res: Result<()>,
+    error: Option<E>,
+) -> core::result::Result<(), ForEachError<E>> {
+    match error {
+        Some(err) => Err(ForEachError::Callback(err)),
+        None => res.map_err(ForEachError::Deserialize),
+    }
+}
+
+/// Seed deserializing a sequence by giving its elements to a callback, shared by both formats.
+pub(crate) struct ForEachElement<'e, T, E, F> {
+    f: F,
+    // error of the callback, the sequence is left unfinished when it is set
+    error: &'e mut Option<E>,
+    marker: PhantomData<T>,
+}
+
+impl<'e, T, E, F> ForEachElement<'e, T, E, F> {
+    pub(crate) fn new(f: F, error: &'e mut Option<E>) -> Self {
+        ForEachElement {
+            f,
+            error,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T, E, F> DeserializeSeed<'de> for ForEachElement<'_, T, E, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T) -> core::result::Result<(), E>,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T, E, F> Visitor<'de> for ForEachElement<'_, T, E, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T) -> core::result::Result<(), E>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> core::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(element) = seq.next_element()? {
+            if let Err(err) = (self.f)(element) {
+                // stop without building an error, `for_each_result` gives the one of the callback
+                *self.error = Some(err);
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize the values at the given ranges of the input, in the order of the ranges, as written by `to_bytes_batch`.
+///
+/// Fails with `Error::Eof` if a range is not in the input.
+#[cfg(feature = "alloc")]
+pub fn from_bytes_batch<'a, T>(input: &'a [u8], ranges: &[Range<usize>]) -> Result<Vec<T>>
+where
+    T: Deserialize<'a>,
+{
+    ranges
+        .iter()
+        .map(|range| {
+            input
+                .get(range.clone())
+                .ok_or(Error::Eof)
+                .and_then(from_bytes)
+        })
+        .collect()
+}
+
+/// Deserialize a value at the start of the input, returning it with the bytes left after it.
+pub fn from_bytes_partial<'a, T>(input: &'a [u8]) -> Result<(T, &'a [u8])>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(input);
+    let value = T::deserialize(&mut deserializer)?;
+    Ok((value, deserializer.remaining()))
 }
 
 impl<'de> Deserializer<'de> {
+    pub fn new(input: &'de [u8]) -> Self {
+        Self::with_config(input, DeserializerConfig::new())
+    }
+
+    pub fn with_config(input: &'de [u8], config: DeserializerConfig) -> Self {
+        Deserializer {
+            input,
+            config,
+            remaining_elements: config.max_total_elements,
+            remaining_depth: config.max_depth,
+            progress: ProgressTracker::new(),
+            top_level_tag: config.top_level_tag,
+            input_len: input.len(),
+        }
+    }
+
+    /// Call `callback` with the number of bytes read so far, at most once every `interval` bytes.
+    /// Returning `ControlFlow::Break(())` stops the deserialization with `Error::Cancelled`.
+    pub fn with_progress<P: ProgressCallback>(
+        self,
+        interval: usize,
+        callback: P,
+    ) -> Deserializer<'de, P> {
+        Deserializer {
+            input: self.input,
+            config: self.config,
+            remaining_elements: self.remaining_elements,
+            remaining_depth: self.remaining_depth,
+            progress: ProgressTracker::with_callback(interval, callback),
+            top_level_tag: self.top_level_tag,
+            input_len: self.input_len,
+        }
+    }
+}
+
+impl<'de, P: ProgressCallback> Deserializer<'de, P> {
+    pub fn config(&self) -> &DeserializerConfig {
+        &self.config
+    }
+
+    /// Input not consumed yet, to read the next value of a stream.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.input
+    }
+
+    /// Erase the type of the deserializer, to give it to `erased_serde::deserialize`.
+    /// The erased deserializer reads a single value, call this again for the next one of a stream.
+    ///
+    /// The errors are then `erased_serde::Error`s with the message of the error of this crate.
+    /// The input is not checked to be fully consumed, see `remaining`.
+    #[cfg(feature = "erased")]
+    pub fn erased(&mut self) -> impl erased_serde::Deserializer<'de> + '_ {
+        <dyn erased_serde::Deserializer>::erase(self)
+    }
+
+    /// Fail if the input is not fully consumed.
+    pub(crate) fn end(&self) -> Result<()> {
+        match self.input.len() {
+            0 => Ok(()),
+            len => Err(Error::TrailingBytes(len)),
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if let Some(remaining) = self.remaining_depth.as_mut() {
+            *remaining = remaining.checked_sub(1).ok_or(Error::DepthLimitExceeded)?;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        if let Some(remaining) = self.remaining_depth.as_mut() {
+            *remaining += 1;
+        }
+    }
+
+    /// Run `f` one nesting level deeper.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.enter()?;
+        let res = f(self);
+        self.leave();
+        res
+    }
+
+    fn consume_element(&mut self) -> Result<()> {
+        if let Some(remaining) = self.remaining_elements.as_mut() {
+            *remaining = remaining
+                .checked_sub(1)
+                .ok_or(Error::ElementBudgetExceeded)?;
+        }
+        Ok(())
+    }
+
+    /// Pop the tag of the top-level value and check it, if enabled and not checked yet.
+    fn check_top_level_tag(
+        &mut self,
+        expected: &'static str,
+        matches: fn(Tag) -> bool,
+    ) -> Result<()> {
+        if !core::mem::take(&mut self.top_level_tag) {
+            return Ok(());
+        }
+        let [byte] = self.pop_n()?;
+        let tag = Tag::try_from(byte)?;
+        if !matches(tag) {
+            return Err(TagParsingError::unexpected(expected, tag).into());
+        }
+        Ok(())
+    }
+
     fn pop_slice(&mut self, len: usize) -> Result<&'de [u8]> {
         if self.input.len() < len {
             return Err(Error::Eof);
         }
         let (bytes, rem) = self.input.split_at(len);
         self.input = rem;
+        self.progress.advance(len)?;
         Ok(bytes)
     }
 
+    /// Offset of the remaining input in the whole input.
+    fn offset(&self) -> usize {
+        self.input_len - self.input.len()
+    }
+
+    /// Read a map key with `read`, and with `canonical` check that it comes after the previous key.
+    fn map_key<T>(
+        &mut self,
+        previous_key: &mut Option<&'de [u8]>,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if !self.config.canonical {
+            return read(self);
+        }
+        let input = self.input;
+        let offset = self.offset();
+        let value = read(self)?;
+        let key = &input[..input.len() - self.input.len()];
+        if !check_key_order(*previous_key, key) {
+            return Err(Error::NonCanonical {
+                rule: CanonicalRule::UnsortedMapKeys,
+                offset,
+            });
+        }
+        *previous_key = Some(key);
+        Ok(value)
+    }
+
     fn pop_n<const N: usize>(&mut self) -> Result<[u8; N]> {
         let bytes = self.pop_slice(N)?;
         let mut buff = [0; N];
@@ -39,11 +398,17 @@ impl<'de> Deserializer<'de> {
         Ok(buff)
     }
 
+    fn pop_variant_index(&mut self) -> Result<u32> {
+        Ok(match self.config.variant_index_width {
+            VariantIndexWidth::U8 => u8::from_be_bytes(self.pop_n()?).into(),
+            VariantIndexWidth::U16 => u16::from_be_bytes(self.pop_n()?).into(),
+            VariantIndexWidth::U32 => u32::from_be_bytes(self.pop_n()?),
+        })
+    }
+
     fn pop_usize(&mut self) -> Result<usize> {
         let bytes = self.pop_n()?;
-        u64::from_be_bytes(bytes)
-            .try_into()
-            .map_err(|_| Error::InvalidSize)
+        narrow_len(u64::from_be_bytes(bytes))
     }
 
     fn pop_bytes_seq(&mut self) -> Result<&'de [u8]> {
@@ -52,16 +417,23 @@ impl<'de> Deserializer<'de> {
     }
 
     fn parse_str(&mut self) -> Result<&'de str> {
+        let offset = self.offset();
         let len_bytes = self.pop_n()?;
         let len = u64::from_be_bytes(len_bytes);
         let len = if len == u64::MAX {
+            if self.config.canonical {
+                return Err(Error::NonCanonical {
+                    rule: CanonicalRule::UnsizedString,
+                    offset,
+                });
+            }
             // unknown str length, "null" terminated
             self.input
                 .windows(UNSIZED_STRING_END_MARKER.len())
-                .position(|bytes| bytes == UNSIZED_STRING_END_MARKER)
+                .position(is_unsized_string_end)
                 .ok_or(Error::Eof)?
         } else {
-            len.try_into().map_err(|_| Error::InvalidSize)?
+            narrow_len(len)?
         };
 
         let bytes = self.pop_slice(len)?;
@@ -70,19 +442,102 @@ impl<'de> Deserializer<'de> {
     }
 }
 
+macro_rules! read_number {
+    ($($fn_name:ident: $t:ident),*) => {
+        $(
+            #[doc = concat!("Read a `", stringify!($t), "` written in big endian.")]
+            pub fn $fn_name(&mut self) -> Result<$t> {
+                self.pop_n().map($t::from_be_bytes)
+            }
+        )*
+    };
+}
+
+/// Reading of the values of a known layout one by one, without going through a `Deserialize` implementation,
+/// for the hot paths where the visitors cost too much.
+///
+/// Each method reads the bytes written by the serializer for a value of its type and moves past them,
+/// failing with `Error::Eof` if the input ends before. They don't check the top-level tag of
+/// `DeserializerConfig::top_level_tag` and don't count the elements of `max_total_elements`.
+/// The rest of the input can still be deserialized, by giving the deserializer to a `Deserialize` implementation
+/// or `remaining` to `from_bytes_partial`.
+///
+/// ```
+/// use serde_bin::compact::{self, Deserializer};
+///
+/// let bytes = compact::to_bytes(&(7u32, "abc", 0.5f64)).unwrap();
+/// let mut deserializer = Deserializer::new(&bytes);
+/// assert_eq!(deserializer.read_u32(), Ok(7));
+/// assert_eq!(deserializer.read_str(), Ok("abc"));
+/// assert_eq!(deserializer.read_f64(), Ok(0.5));
+/// assert!(deserializer.remaining().is_empty());
+/// ```
+impl<'de, P: ProgressCallback> Deserializer<'de, P> {
+    read_number!(
+        read_u8: u8,
+        read_u16: u16,
+        read_u32: u32,
+        read_u64: u64,
+        read_i8: i8,
+        read_i16: i16,
+        read_i32: i32,
+        read_i64: i64,
+        read_f32: f32,
+        read_f64: f64
+    );
+
+    /// Read a `bool`, failing with `Error::InvalidBool` if the byte is not 0 or 1.
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.pop_n()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [byte] => Err(Error::InvalidBool(byte)),
+        }
+    }
+
+    /// Read a string, its `u64` length then its bytes, or the bytes up to the end marker of a string written
+    /// without its length. Fails with `Error::InvalidStr` if they are not UTF-8.
+    pub fn read_str(&mut self) -> Result<&'de str> {
+        self.parse_str()
+    }
+
+    /// Read the `u64` length of a sequence, a map or a byte array, failing with `Error::InvalidSize`
+    /// if it doesn't fit in a `usize`. The elements that follow are left to read.
+    pub fn read_len(&mut self) -> Result<usize> {
+        self.pop_usize()
+    }
+
+    /// Read the next `len` bytes, such as the content of a byte array after its length.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        self.pop_slice(len)
+    }
+
+    /// Move past the next `len` bytes without reading them.
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.pop_slice(len).map(|_| ())
+    }
+}
+
+macro_rules! check_top_level_tag {
+    ($de:expr, $tag:pat, $expected:expr) => {
+        $de.check_top_level_tag($expected, |tag| matches!(tag, $tag))?
+    };
+}
+
 macro_rules! implement_number {
-    ($fn_name:ident, $visitor_fn_name:ident, $t:ident) => {
+    ($fn_name:ident, $visitor_fn_name:ident, $t:ident, $tag:pat, $expected:expr) => {
         fn $fn_name<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
         {
+            check_top_level_tag!(self, $tag, $expected);
             let bytes = self.pop_n()?;
             visitor.$visitor_fn_name($t::from_be_bytes(bytes))
         }
     };
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de, P: ProgressCallback> de::Deserializer<'de> for &mut Deserializer<'de, P> {
     type Error = Error<NoWriterError>;
 
     fn is_human_readable(&self) -> bool {
@@ -102,6 +557,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::BoolFalse | Tag::BoolTrue, "Boolean");
         let [byte] = self.pop_n::<1>()?;
         match byte {
             0 => visitor.visit_bool(false),
@@ -110,26 +566,27 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         }
     }
 
-    implement_number!(deserialize_i8, visit_i8, i8);
-    implement_number!(deserialize_i16, visit_i16, i16);
-    implement_number!(deserialize_i32, visit_i32, i32);
-    implement_number!(deserialize_i64, visit_i64, i64);
-    implement_number!(deserialize_u8, visit_u8, u8);
-    implement_number!(deserialize_u16, visit_u16, u16);
-    implement_number!(deserialize_u32, visit_u32, u32);
-    implement_number!(deserialize_u64, visit_u64, u64);
-    implement_number!(deserialize_f32, visit_f32, f32);
-    implement_number!(deserialize_f64, visit_f64, f64);
+    implement_number!(deserialize_i8, visit_i8, i8, Tag::I8, "i8");
+    implement_number!(deserialize_i16, visit_i16, i16, Tag::I16, "i16");
+    implement_number!(deserialize_i32, visit_i32, i32, Tag::I32, "i32");
+    implement_number!(deserialize_i64, visit_i64, i64, Tag::I64, "i64");
+    implement_number!(deserialize_u8, visit_u8, u8, Tag::U8, "u8");
+    implement_number!(deserialize_u16, visit_u16, u16, Tag::U16, "u16");
+    implement_number!(deserialize_u32, visit_u32, u32, Tag::U32, "u32");
+    implement_number!(deserialize_u64, visit_u64, u64, Tag::U64, "u64");
+    implement_number!(deserialize_f32, visit_f32, f32, Tag::F32, "f32");
+    implement_number!(deserialize_f64, visit_f64, f64, Tag::F64, "f64");
 
     serde_if_integer128! {
-        implement_number!(deserialize_i128, visit_i128, i128);
-        implement_number!(deserialize_u128, visit_u128, u128);
+        implement_number!(deserialize_i128, visit_i128, i128, Tag::I128, "i128");
+        implement_number!(deserialize_u128, visit_u128, u128, Tag::U128, "u128");
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::Char4, "char");
         let bytes = self.pop_n()?;
         let c = u32::from_be_bytes(bytes);
         let c = char::from_u32(c).ok_or(Error::InvalidChar(c))?;
@@ -140,6 +597,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::String, "String");
         let s = self.parse_str()?;
         visitor.visit_borrowed_str(s)
     }
@@ -155,10 +613,22 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::ByteArray, "ByteArray");
+        let bytes = self.pop_bytes_seq()?;
+        visitor.visit_borrowed_bytes(bytes)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        check_top_level_tag!(self, Tag::ByteArray, "ByteArray");
         let bytes = self.pop_bytes_seq()?;
-        visitor.visit_bytes(bytes)
+        visitor.visit_byte_buf(bytes.to_vec())
     }
 
+    #[cfg(not(feature = "alloc"))]
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -170,10 +640,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::None | Tag::Some, "Option");
         let [byte] = self.pop_n()?;
         match byte {
             0 => visitor.visit_none(),
-            1 => visitor.visit_some(self),
+            1 => self.nested(|de| visitor.visit_some(de)),
             _ => Err(Error::InvalidOptionTag(byte)),
         }
     }
@@ -182,6 +653,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        check_top_level_tag!(self, Tag::Unit, "Unit");
         visitor.visit_unit()
     }
 
@@ -189,29 +661,31 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_unit(visitor)
+        check_top_level_tag!(self, Tag::UnitStruct, "UnitStruct");
+        visitor.visit_unit()
     }
 
     fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        self.nested(|de| visitor.visit_newtype_struct(de))
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let seq_des = SeqDeserializer::new(self)?;
-        visitor.visit_seq(seq_des)
+        check_top_level_tag!(self, Tag::Seq, "Sequence");
+        SeqDeserializer::new(self)?.visit_seq(visitor)
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        check_top_level_tag!(self, Tag::Tuple, "Tuple");
+        SeqDeserializer::new_with_len(self, len).visit_seq(visitor)
     }
 
     fn deserialize_tuple_struct<V>(
@@ -223,15 +697,16 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        check_top_level_tag!(self, Tag::TupleStruct, "TupleStruct");
+        SeqDeserializer::new_with_len(self, len).visit_seq(visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let seq_des = SeqDeserializer::new(self)?;
-        visitor.visit_map(seq_des)
+        check_top_level_tag!(self, Tag::Map, "Map");
+        SeqDeserializer::new(self)?.visit_map(visitor)
     }
 
     fn deserialize_struct<V>(
@@ -243,7 +718,8 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, fields.len()))
+        check_top_level_tag!(self, Tag::Struct, "Struct");
+        SeqDeserializer::new_struct(self, fields.len()).visit_seq(visitor)
     }
 
     fn deserialize_enum<V>(
@@ -255,14 +731,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(self)
+        check_top_level_tag!(
+            self,
+            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant,
+            "Enum"
+        );
+        self.nested(|de| visitor.visit_enum(de))
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_u32(visitor)
+        // only asked for the variant indices, the structs are decoded as sequences
+        visitor.visit_u32(self.pop_variant_index()?)
     }
 
     fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
@@ -275,23 +757,74 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-struct SeqDeserializer<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct SeqDeserializer<'a, 'de: 'a, P> {
+    de: &'a mut Deserializer<'de, P>,
     remaining: usize,
+    // number of fields when deserializing a struct, used to report the field on errors
+    struct_len: Option<usize>,
+    // bytes of the previous key of a map, only kept with `canonical`
+    previous_key: Option<&'de [u8]>,
 }
 
-impl<'a, 'de> SeqDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Result<Self> {
+impl<'a, 'de, P: ProgressCallback> SeqDeserializer<'a, 'de, P> {
+    fn new(de: &'a mut Deserializer<'de, P>) -> Result<Self> {
         let len = de.pop_usize()?;
         Ok(Self::new_with_len(de, len))
     }
 
-    fn new_with_len(de: &'a mut Deserializer<'de>, len: usize) -> Self {
-        Self { de, remaining: len }
+    fn new_with_len(de: &'a mut Deserializer<'de, P>, len: usize) -> Self {
+        Self {
+            de,
+            remaining: len,
+            struct_len: None,
+            previous_key: None,
+        }
+    }
+
+    fn new_struct(de: &'a mut Deserializer<'de, P>, len: usize) -> Self {
+        Self {
+            de,
+            remaining: len,
+            struct_len: Some(len),
+            previous_key: None,
+        }
+    }
+
+    fn visit_seq<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.enter()?;
+        let value = visitor.visit_seq(&mut self);
+        self.de.leave();
+        let value = value?;
+        self.end()?;
+        Ok(value)
+    }
+
+    fn visit_map<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.enter()?;
+        let value = visitor.visit_map(&mut self);
+        self.de.leave();
+        let value = value?;
+        self.end()?;
+        Ok(value)
+    }
+
+    /// The compact format can't skip a value without knowing its type,
+    /// so the visitor stopping early is reported here instead of leaving the input in the middle of the sequence.
+    fn end(self) -> Result<()> {
+        match self.remaining {
+            0 => Ok(()),
+            remaining => Err(Error::UnconsumedElements(remaining)),
+        }
     }
 }
 
-impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
+impl<'de, 'a, P: ProgressCallback> SeqAccess<'de> for SeqDeserializer<'a, 'de, P> {
     type Error = Error<NoWriterError>;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -303,8 +836,14 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
         }
 
         self.remaining -= 1;
+        self.de.consume_element()?;
 
-        seed.deserialize(&mut *self.de).map(Some)
+        let res = seed.deserialize(&mut *self.de);
+        match self.struct_len {
+            Some(len) => res.map_err(|err| err.in_field(len - self.remaining - 1, None)),
+            None => res,
+        }
+        .map(Some)
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -312,7 +851,7 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for SeqDeserializer<'a, 'de> {
+impl<'de, 'a, P: ProgressCallback> MapAccess<'de> for SeqDeserializer<'a, 'de, P> {
     type Error = Error<NoWriterError>;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -324,8 +863,11 @@ impl<'de, 'a> MapAccess<'de> for SeqDeserializer<'a, 'de> {
         }
 
         self.remaining -= 1;
+        self.de.consume_element()?;
 
-        seed.deserialize(&mut *self.de).map(Some)
+        self.de
+            .map_key(&mut self.previous_key, |de| seed.deserialize(de))
+            .map(Some)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
@@ -340,7 +882,7 @@ impl<'de, 'a> MapAccess<'de> for SeqDeserializer<'a, 'de> {
     }
 }
 
-impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de, P: ProgressCallback> EnumAccess<'de> for &mut Deserializer<'de, P> {
     type Error = Error<NoWriterError>;
     type Variant = Self;
 
@@ -353,7 +895,7 @@ impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-impl<'a, 'de> VariantAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de, P: ProgressCallback> VariantAccess<'de> for &mut Deserializer<'de, P> {
     type Error = Error<NoWriterError>;
 
     fn unit_variant(self) -> Result<()> {
@@ -371,13 +913,13 @@ impl<'a, 'de> VariantAccess<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, len))
+        SeqDeserializer::new_with_len(self, len).visit_seq(visitor)
     }
 
     fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new_with_len(self, fields.len()))
+        SeqDeserializer::new_with_len(self, fields.len()).visit_seq(visitor)
     }
 }
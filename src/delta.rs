@@ -0,0 +1,207 @@
+//! Serialize a sorted `Vec<u64>`, such as a posting list, as the differences between consecutive values.
+//!
+//! The first value and then the difference of every value with the one before it are written as varints,
+//! 7 bits per byte with the high bit set on all the bytes but the last, in a byte array.
+//! Close values then take a byte each instead of 8:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Postings {
+//!     #[serde(with = "serde_bin::delta")]
+//!     documents: Vec<u64>,
+//! }
+//!
+//! let value = Postings { documents: vec![1000, 1003, 1010, 1100] };
+//! let bytes = serde_bin::compact::to_bytes(&value).unwrap();
+//! // the length of the byte array, 2 bytes for 1000 and 1 for each difference
+//! assert_eq!(bytes.len(), 8 + 5);
+//! assert_eq!(serde_bin::compact::from_bytes(&bytes), Ok(value));
+//! ```
+//!
+//! The values must be non-decreasing, serializing a value smaller than the one before it fails.
+
+use core::fmt;
+
+use serde::de::{self, Visitor};
+use serde::{ser, Deserializer, Serializer};
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+// a `u64` takes at most 10 bytes of 7 bits
+const MAX_VARINT_LEN: usize = 10;
+
+fn write_varint(output: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        output.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    output.push(n as u8);
+}
+
+/// Read the varint at the start of the input, returning it with the rest of the input,
+/// or `None` if the input ends before it or it doesn't fit in a `u64`.
+fn read_varint(input: &[u8]) -> Option<(u64, &[u8])> {
+    let mut n = 0u64;
+    for (i, &byte) in input.iter().enumerate().take(MAX_VARINT_LEN) {
+        let bits = u64::from(byte & 0x7F);
+        n |= bits
+            .checked_shl(7 * i as u32)
+            .filter(|n| n >> (7 * i) == bits)?;
+        if byte & 0x80 == 0 {
+            return Some((n, &input[i + 1..]));
+        }
+    }
+    None
+}
+
+pub fn serialize<S>(values: &[u64], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut bytes = Vec::with_capacity(values.len());
+    let mut previous = 0;
+    for &value in values {
+        let delta = value.checked_sub(previous).ok_or_else(|| {
+            ser::Error::custom(format_args!(
+                "the values of a delta encoded sequence must be non-decreasing, {} comes after {}",
+                value, previous
+            ))
+        })?;
+        write_varint(&mut bytes, delta);
+        previous = value;
+    }
+    serializer.serialize_bytes(&bytes)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(DeltaVisitor)
+}
+
+struct DeltaVisitor;
+
+impl<'de> Visitor<'de> for DeltaVisitor {
+    type Value = Vec<u64>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array of delta encoded integers")
+    }
+
+    fn visit_bytes<E>(self, mut v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // every value takes at least a byte
+        let mut values = Vec::with_capacity(v.len());
+        let mut previous = 0u64;
+        while !v.is_empty() {
+            let (delta, rest) = read_varint(v)
+                .ok_or_else(|| E::custom("invalid varint in a delta encoded sequence"))?;
+            previous = previous
+                .checked_add(delta)
+                .ok_or_else(|| E::custom("a value of a delta encoded sequence overflows a u64"))?;
+            values.push(previous);
+            v = rest;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{read_varint, write_varint};
+    use crate::{any, compact, Error};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Postings {
+        #[serde(with = "crate::delta")]
+        documents: Vec<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct Plain<'a> {
+        documents: &'a [u64],
+    }
+
+    #[test]
+    fn test_varint() {
+        for n in [
+            0,
+            1,
+            0x7F,
+            0x80,
+            300,
+            u32::MAX.into(),
+            u64::MAX - 1,
+            u64::MAX,
+        ] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, n);
+            assert_eq!(read_varint(&bytes), Some((n, &[][..])));
+        }
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, u64::MAX);
+        assert_eq!(bytes.len(), 10);
+        // truncated, and more than 64 bits
+        assert_eq!(read_varint(&bytes[..9]), None);
+        bytes[9] = 0x02;
+        assert_eq!(read_varint(&bytes), None);
+        assert_eq!(read_varint(&[0x80; 11]), None);
+    }
+
+    #[test]
+    fn test_delta() {
+        let documents: Vec<u64> = (0..1000).map(|i| 1_000_000 + i * 3 + i / 7).collect();
+        let value = Postings { documents };
+        let bytes = compact::to_bytes(&value).unwrap();
+        let plain = compact::to_bytes(&Plain {
+            documents: &value.documents,
+        })
+        .unwrap();
+        assert_eq!(plain.len(), 8 + 8 * 1000);
+        // the length, 3 bytes for the first value and one per difference
+        assert_eq!(bytes.len(), 8 + 3 + 999);
+        assert_eq!(compact::from_bytes(&bytes), Ok(value));
+
+        let value = Postings {
+            documents: vec![0, 0, 5, u64::MAX],
+        };
+        let bytes = any::to_bytes(&value).unwrap();
+        assert_eq!(any::from_bytes(&bytes), Ok(value));
+
+        let empty = Postings { documents: vec![] };
+        let bytes = compact::to_bytes(&empty).unwrap();
+        assert_eq!(bytes, [0; 8]);
+        assert_eq!(compact::from_bytes(&bytes), Ok(empty));
+
+        let unsorted = Postings {
+            documents: vec![3, 2],
+        };
+        assert_eq!(
+            compact::to_bytes(&unsorted).unwrap_err().to_string(),
+            "the values of a delta encoded sequence must be non-decreasing, 2 comes after 3"
+        );
+
+        // the sum of the differences overflows
+        let bytes = compact::to_bytes(&Postings {
+            documents: vec![u64::MAX],
+        })
+        .unwrap();
+        let mut overflowing = bytes.clone();
+        overflowing.push(1);
+        overflowing[7] += 1;
+        assert_eq!(
+            compact::from_bytes::<Postings>(&overflowing)
+                .unwrap_err()
+                .root_cause(),
+            &Error::Message("a value of a delta encoded sequence overflows a u64".into())
+        );
+    }
+}
@@ -8,16 +8,29 @@ use std::error;
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
 use alloc::string::{String, ToString};
 
-use crate::any::TagParsingError;
+use crate::any::{Tag, TagParsingError};
+use crate::canonical::CanonicalRule;
+use crate::config::VariantIndexWidth;
+use crate::schema::SchemaError;
+#[cfg(feature = "alloc")]
+use crate::trace::Trace;
 
 pub type Result<T, We = NoWriterError> = core::result::Result<T, Error<We>>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NoWriterError {}
 
-pub trait WriterError: Debug + Display {}
+pub trait WriterError: Debug + Display {
+    /// Kind of the `io::Error` reporting this error through an `IoAdapter`.
+    #[cfg(feature = "std")]
+    fn io_error_kind(&self) -> std::io::ErrorKind {
+        std::io::ErrorKind::Other
+    }
+}
 
 impl WriterError for NoWriterError {}
 
@@ -29,6 +42,24 @@ impl Display for NoWriterError {
     }
 }
 
+/// Format of the serializers and deserializers, used to hint that an input was serialized with the other one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// The format of the `compact` module.
+    Compact,
+    /// The format of the `tagged` module.
+    Tagged,
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Compact => "compact",
+            Format::Tagged => "tagged",
+        })
+    }
+}
+
 #[cfg(not(feature = "alloc"))]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum ErrorKind {
@@ -59,6 +90,266 @@ pub enum Error<T> {
         expected: usize,
         got: usize,
     },
+    SchemaError(SchemaError),
+    ElementBudgetExceeded,
+    DepthLimitExceeded,
+    /// A visitor stopped reading a sequence before its end in the compact format, with the number of elements left.
+    UnconsumedElements(usize),
+    /// The checksum of a container doesn't match its content in the `any` format with `container_checksums`,
+    /// with the offset of the checksum in the input.
+    ChecksumMismatch {
+        offset: usize,
+    },
+    /// `deserialize_any` or `deserialize_ignored_any` was called in the `any` format with `deny_any`.
+    AnyDenied,
+    /// The progress callback returned `ControlFlow::Break`.
+    Cancelled,
+    /// Tried to access the element at `index` of an `IndexedSeq` of `len` elements.
+    IndexOutOfRange {
+        index: usize,
+        len: usize,
+    },
+    /// The input ended inside an unsized sequence or map in the `any` format, before its end marker,
+    /// so more bytes could still complete it.
+    NeedMoreData,
+    /// The input breaks a rule of the canonical encoding with `DeserializerConfig::canonical`,
+    /// with the offset in the input of the value breaking it.
+    NonCanonical {
+        rule: CanonicalRule,
+        offset: usize,
+    },
+    /// The header of a tuple, struct or variant in the `any` format announces more elements than the bytes left
+    /// in the input, as each element takes at least the byte of its tag.
+    /// Like `Eof`, the rest of a truncated input could still complete it.
+    LengthExceedsInput {
+        len: usize,
+        remaining: usize,
+    },
+    /// Two fields of a struct have the same `any::field_hash` with `hashed_field_names`.
+    FieldHashCollision {
+        first: &'static str,
+        second: &'static str,
+    },
+    /// An integer is not encoded with the `narrowest` tag holding its value in the `any` format with
+    /// `DeserializerConfig::strict_integers`, with the offset in the input of its tag.
+    NonCanonicalInteger {
+        tag: Tag,
+        narrowest: Tag,
+        offset: usize,
+    },
+    /// The index of a variant doesn't fit in the `SerializerConfig::variant_index_width` of the compact format.
+    VariantIndexTooLarge {
+        index: u32,
+        width: VariantIndexWidth,
+    },
+    /// The input of `from_bytes_with_limit` is `len` bytes long, more than the `max` accepted.
+    MessageTooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// A map key is a sequence, a tuple, a map or a struct with `SerializerConfig::strict_map_keys`,
+    /// with the tag of the key.
+    CompositeMapKey {
+        tag: Tag,
+    },
+    /// A tuple, struct or variant has `len` fields in the `any` format, more than the 255 that its header can hold.
+    TooManyFields {
+        len: usize,
+    },
+    /// A map has the same key twice with `SerializerConfig::canonical`, which has no canonical encoding.
+    DuplicateMapKey,
+    /// Error while decoding the value of a struct field, with the index of the field
+    /// and in the `any` format the tag of the value.
+    #[cfg(feature = "alloc")]
+    FieldError {
+        index: usize,
+        tag: Option<Tag>,
+        error: Box<Error<T>>,
+    },
+    /// Deserializing failed and the input looks like it was serialized with the `likely` format,
+    /// such as a tagged value given to `compact::from_bytes`.
+    #[cfg(feature = "alloc")]
+    WrongFormat {
+        likely: Format,
+        error: Box<Error<T>>,
+    },
+    /// Deserializing failed with `DeserializerConfig::trace`, with the last steps before the error.
+    #[cfg(feature = "alloc")]
+    Traced {
+        trace: Box<Trace>,
+        error: Box<Error<T>>,
+    },
+}
+
+impl<T> Error<T> {
+    /// Return the error without the context added by `FieldError`, `WrongFormat` and `Traced`.
+    pub fn root_cause(&self) -> &Self {
+        match self {
+            #[cfg(feature = "alloc")]
+            Error::FieldError { error, .. }
+            | Error::WrongFormat { error, .. }
+            | Error::Traced { error, .. } => error.root_cause(),
+            err => err,
+        }
+    }
+
+    /// Steps of the deserialization before the error, recorded with `DeserializerConfig::trace`.
+    #[cfg(feature = "alloc")]
+    pub fn trace(&self) -> Option<&Trace> {
+        match self {
+            Error::Traced { trace, .. } => Some(trace),
+            Error::FieldError { error, .. } | Error::WrongFormat { error, .. } => error.trace(),
+            _ => None,
+        }
+    }
+
+    /// Stable number identifying the kind of the error, to pass it through an FFI boundary.
+    ///
+    /// The codes never change between releases, new variants get new codes and `0` is never used:
+    ///
+    /// | Code | Variant |
+    /// |------|---------|
+    /// | 1 | `WriterError` |
+    /// | 2 | `Message` |
+    /// | 3 | `Custom` |
+    /// | 4 | `UnknownSeqLength` |
+    /// | 5 | `Eof` |
+    /// | 6 | `InvalidBool` |
+    /// | 7 | `InvalidChar` |
+    /// | 8 | `InvalidStr` |
+    /// | 9 | `InvalidSize` |
+    /// | 10 | `InvalidOptionTag` |
+    /// | 11 | `TrailingBytes` |
+    /// | 12 | `Unimplemented` |
+    /// | 13 | `FormattingError` |
+    /// | 14 | `TagParsingError` |
+    /// | 15 | `SeqSizeMismatch` |
+    /// | 16 | `SchemaError` |
+    /// | 17 | `ElementBudgetExceeded` |
+    /// | 18 | `DepthLimitExceeded` |
+    /// | 19 | `UnconsumedElements` |
+    /// | 20 | `ChecksumMismatch` |
+    /// | 21 | `AnyDenied` |
+    /// | 22 | `Cancelled` |
+    /// | 23 | `IndexOutOfRange` |
+    /// | 24 | `NeedMoreData` |
+    /// | 25 | `NonCanonical` |
+    /// | 26 | `LengthExceedsInput` |
+    /// | 27 | `FieldHashCollision` |
+    /// | 28 | `VariantIndexTooLarge` |
+    /// | 29 | `NonCanonicalInteger` |
+    /// | 30 | `MessageTooLarge` |
+    /// | 31 | `CompositeMapKey` |
+    /// | 32 | `TooManyFields` |
+    /// | 33 | `DuplicateMapKey` |
+    ///
+    /// `FieldError`, `WrongFormat` and `Traced` only add context, their code is the one of the error they wrap.
+    pub fn code(&self) -> u32 {
+        match self.root_cause() {
+            Error::WriterError(_) => 1,
+            #[cfg(feature = "alloc")]
+            Error::Message(_) => 2,
+            #[cfg(not(feature = "alloc"))]
+            Error::Custom(_) => 3,
+            #[cfg(any(not(feature = "alloc"), feature = "no-unsized-seq"))]
+            Error::UnknownSeqLength => 4,
+            Error::Eof => 5,
+            Error::InvalidBool(_) => 6,
+            Error::InvalidChar(_) => 7,
+            Error::InvalidStr(_) => 8,
+            Error::InvalidSize => 9,
+            Error::InvalidOptionTag(_) => 10,
+            Error::TrailingBytes(_) => 11,
+            Error::Unimplemented(_) => 12,
+            Error::FormattingError => 13,
+            Error::TagParsingError(_) => 14,
+            Error::SeqSizeMismatch { .. } => 15,
+            Error::SchemaError(_) => 16,
+            Error::ElementBudgetExceeded => 17,
+            Error::DepthLimitExceeded => 18,
+            Error::UnconsumedElements(_) => 19,
+            Error::ChecksumMismatch { .. } => 20,
+            Error::AnyDenied => 21,
+            Error::Cancelled => 22,
+            Error::IndexOutOfRange { .. } => 23,
+            Error::NeedMoreData => 24,
+            Error::NonCanonical { .. } => 25,
+            Error::LengthExceedsInput { .. } => 26,
+            Error::FieldHashCollision { .. } => 27,
+            Error::VariantIndexTooLarge { .. } => 28,
+            Error::NonCanonicalInteger { .. } => 29,
+            Error::MessageTooLarge { .. } => 30,
+            Error::CompositeMapKey { .. } => 31,
+            Error::TooManyFields { .. } => 32,
+            Error::DuplicateMapKey => 33,
+            #[cfg(feature = "alloc")]
+            Error::FieldError { .. } | Error::WrongFormat { .. } | Error::Traced { .. } => {
+                unreachable!("root_cause never returns a FieldError, a WrongFormat or a Traced")
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn in_field(self, index: usize, tag: Option<Tag>) -> Self {
+        Error::FieldError {
+            index,
+            tag,
+            error: Box::new(self),
+        }
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn in_field(self, _index: usize, _tag: Option<Tag>) -> Self {
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn with_trace(self, trace: Trace) -> Self {
+        Error::Traced {
+            trace: Box::new(trace),
+            error: Box::new(self),
+        }
+    }
+
+    /// Wrap the error of a top-level deserialization in `WrongFormat` if the input looks like
+    /// it was serialized with the other format than `used`.
+    ///
+    /// Only the errors of malformed inputs get the hint, the limits, the options and the errors of the types
+    /// are returned as is.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn with_format_hint(self, input: &[u8], used: Format) -> Self {
+        if !matches!(
+            self.root_cause(),
+            Error::Eof
+                | Error::TrailingBytes(_)
+                | Error::LengthExceedsInput { .. }
+                | Error::InvalidSize
+                | Error::InvalidBool(_)
+                | Error::InvalidOptionTag(_)
+                | Error::TagParsingError(_)
+        ) {
+            return self;
+        }
+        let is_tagged_value = crate::any::is_single_value(input);
+        let likely = match used {
+            Format::Compact if is_tagged_value => Format::Tagged,
+            // a length in big endian, or a byte that is not a tag
+            Format::Tagged
+                if !is_tagged_value
+                    && (input.starts_with(&[0, 0])
+                        || input
+                            .first()
+                            .is_some_and(|byte| Tag::try_from(*byte).is_err())) =>
+            {
+                Format::Compact
+            }
+            _ => return self,
+        };
+        Error::WrongFormat {
+            likely,
+            error: Box::new(self),
+        }
+    }
 }
 
 impl<W: WriterError> Error<W> {
@@ -86,6 +377,54 @@ impl<W: WriterError> Error<W> {
             Error::FormattingError => Error::FormattingError,
             Error::TagParsingError(err) => Error::TagParsingError(err),
             Error::SeqSizeMismatch { expected, got } => Error::SeqSizeMismatch { expected, got },
+            Error::SchemaError(err) => Error::SchemaError(err),
+            Error::ElementBudgetExceeded => Error::ElementBudgetExceeded,
+            Error::DepthLimitExceeded => Error::DepthLimitExceeded,
+            Error::UnconsumedElements(x) => Error::UnconsumedElements(x),
+            Error::ChecksumMismatch { offset } => Error::ChecksumMismatch { offset },
+            Error::AnyDenied => Error::AnyDenied,
+            Error::Cancelled => Error::Cancelled,
+            Error::IndexOutOfRange { index, len } => Error::IndexOutOfRange { index, len },
+            Error::NeedMoreData => Error::NeedMoreData,
+            Error::NonCanonical { rule, offset } => Error::NonCanonical { rule, offset },
+            Error::LengthExceedsInput { len, remaining } => {
+                Error::LengthExceedsInput { len, remaining }
+            }
+            Error::FieldHashCollision { first, second } => {
+                Error::FieldHashCollision { first, second }
+            }
+            Error::VariantIndexTooLarge { index, width } => {
+                Error::VariantIndexTooLarge { index, width }
+            }
+            Error::MessageTooLarge { len, max } => Error::MessageTooLarge { len, max },
+            Error::CompositeMapKey { tag } => Error::CompositeMapKey { tag },
+            Error::TooManyFields { len } => Error::TooManyFields { len },
+            Error::DuplicateMapKey => Error::DuplicateMapKey,
+            Error::NonCanonicalInteger {
+                tag,
+                narrowest,
+                offset,
+            } => Error::NonCanonicalInteger {
+                tag,
+                narrowest,
+                offset,
+            },
+            #[cfg(feature = "alloc")]
+            Error::FieldError { index, tag, error } => Error::FieldError {
+                index,
+                tag,
+                error: Box::new(error.map_writer_error(map_fn)),
+            },
+            #[cfg(feature = "alloc")]
+            Error::WrongFormat { likely, error } => Error::WrongFormat {
+                likely,
+                error: Box::new(error.map_writer_error(map_fn)),
+            },
+            #[cfg(feature = "alloc")]
+            Error::Traced { trace, error } => Error::Traced {
+                trace,
+                error: Box::new(error.map_writer_error(map_fn)),
+            },
         }
     }
 
@@ -140,6 +479,100 @@ impl<T: Display> Display for Error<T> {
             Error::FormattingError => f.write_str("An error occured while formatting a value."),
             Error::TagParsingError(err) => Display::fmt(err, f),
             Error::SeqSizeMismatch { expected, got } => f.write_fmt(format_args!("Error deserializing a sequence, expected size was {} but encoded sequence size was {}", expected, got)),
+            Error::SchemaError(err) => Display::fmt(err, f),
+            Error::ElementBudgetExceeded => {
+                f.write_str("Reached the maximum number of elements allowed in the input")
+            }
+            Error::DepthLimitExceeded => {
+                f.write_str("Reached the maximum nesting depth allowed in the input")
+            }
+            Error::UnconsumedElements(remaining) => f.write_fmt(format_args!(
+                "A sequence was not fully deserialized, {} elements were left and can't be skipped in the compact format",
+                remaining
+            )),
+            Error::ChecksumMismatch { offset } => f.write_fmt(format_args!(
+                "The checksum of a container doesn't match its content, the checksum is at byte {}",
+                offset
+            )),
+            Error::AnyDenied => f.write_str(
+                "Tried to deserialize a value without knowing its type, but this is disabled by `deny_any`",
+            ),
+            Error::Cancelled => f.write_str("Cancelled by the progress callback"),
+            Error::IndexOutOfRange { index, len } => f.write_fmt(format_args!(
+                "Index {} is out of range for a sequence of {} elements",
+                index, len
+            )),
+            Error::NeedMoreData => {
+                f.write_str("Reached EOF inside an unsized sequence, before its end marker")
+            }
+            Error::NonCanonical { rule, offset } => f.write_fmt(format_args!(
+                "The input is not canonical, found a {} at byte {}",
+                rule, offset
+            )),
+            Error::LengthExceedsInput { len, remaining } => f.write_fmt(format_args!(
+                "The header announces {} elements but only {} bytes are left, each element takes at least 1 byte",
+                len, remaining
+            )),
+            Error::FieldHashCollision { first, second } => f.write_fmt(format_args!(
+                "The fields {:?} and {:?} have the same hash, rename one of them",
+                first, second
+            )),
+            Error::NonCanonicalInteger {
+                tag,
+                narrowest,
+                offset,
+            } => f.write_fmt(format_args!(
+                "The integer at byte {} is tagged {:?} but its value fits in {:?}",
+                offset, tag, narrowest
+            )),
+            Error::VariantIndexTooLarge { index, width } => f.write_fmt(format_args!(
+                "The variant index {} doesn't fit on the {} bytes of the variant indices",
+                index,
+                width.bytes()
+            )),
+            Error::MessageTooLarge { len, max } => f.write_fmt(format_args!(
+                "The message is {} bytes long, more than the limit of {} bytes",
+                len, max
+            )),
+            Error::CompositeMapKey { tag } => f.write_fmt(format_args!(
+                "Map keys must be scalars or strings, got a key tagged {:?}",
+                tag
+            )),
+            Error::TooManyFields { len } => f.write_fmt(format_args!(
+                "The value has {} fields, more than the 255 the any format can encode",
+                len
+            )),
+            Error::DuplicateMapKey => {
+                f.write_str("The map has the same key twice, it has no canonical encoding")
+            }
+            #[cfg(feature = "alloc")]
+            Error::FieldError {
+                index,
+                tag: Some(tag),
+                error,
+            } => f.write_fmt(format_args!(
+                "while decoding field {} ({:?}): {}",
+                index, tag, error
+            )),
+            #[cfg(feature = "alloc")]
+            Error::FieldError {
+                index,
+                tag: None,
+                error,
+            } => f.write_fmt(format_args!("while decoding field {}: {}", index, error)),
+            #[cfg(feature = "alloc")]
+            Error::WrongFormat { likely, error } => f.write_fmt(format_args!(
+                "{} (the input looks like it was serialized with the {} format)",
+                error, likely
+            )),
+            #[cfg(feature = "alloc")]
+            Error::Traced { trace, error } => {
+                Display::fmt(error, f)?;
+                if f.alternate() {
+                    f.write_fmt(format_args!(" (trace: {})", trace))?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -147,6 +580,30 @@ impl<T: Display> Display for Error<T> {
 #[cfg(feature = "std")]
 impl<We: Display + Debug> error::Error for Error<We> {}
 
+/// Error of `for_each_element`, from the input or from the callback.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForEachError<E> {
+    /// The input could not be deserialized.
+    Deserialize(Error<NoWriterError>),
+    /// The error returned by the callback, which stopped the decoding.
+    Callback(E),
+}
+
+impl<E: Display> Display for ForEachError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForEachError::Deserialize(err) => Display::fmt(err, f),
+            ForEachError::Callback(err) => f.write_fmt(format_args!(
+                "The callback of for_each_element failed: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Display + Debug> error::Error for ForEachError<E> {}
+
 impl<We: Display + Debug> ser::Error for Error<We> {
     #[cfg(feature = "alloc")]
     fn custom<T>(msg: T) -> Self
@@ -202,4 +659,8 @@ impl<We> From<fmt::Error> for Error<We> {
 }
 
 #[cfg(feature = "std")]
-impl WriterError for std::io::Error {}
+impl WriterError for std::io::Error {
+    fn io_error_kind(&self) -> std::io::ErrorKind {
+        self.kind()
+    }
+}
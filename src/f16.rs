@@ -0,0 +1,224 @@
+//! Serialize `half::f16` and `half::bf16` with 2 bytes instead of widening them to `f32`.
+//!
+//! Use it with `#[serde(with = "serde_bin::f16")]`, the value is serialized as the `u16` of its bits,
+//! so every value, including NaN payloads and subnormals, round trips exactly.
+//!
+//! Sequences of half floats can use `#[serde(with = "serde_bin::f16::packed")]`,
+//! they are then serialized as a byte array of the big endian bits of each value.
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Half precision float types handled by this module.
+pub trait HalfFloat: Copy {
+    fn to_bits(self) -> u16;
+
+    fn from_bits(bits: u16) -> Self;
+}
+
+impl HalfFloat for half::f16 {
+    fn to_bits(self) -> u16 {
+        half::f16::to_bits(self)
+    }
+
+    fn from_bits(bits: u16) -> Self {
+        half::f16::from_bits(bits)
+    }
+}
+
+impl HalfFloat for half::bf16 {
+    fn to_bits(self) -> u16 {
+        half::bf16::to_bits(self)
+    }
+
+    fn from_bits(bits: u16) -> Self {
+        half::bf16::from_bits(bits)
+    }
+}
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: HalfFloat,
+    S: Serializer,
+{
+    serializer.serialize_u16(value.to_bits())
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: HalfFloat,
+    D: Deserializer<'de>,
+{
+    u16::deserialize(deserializer).map(T::from_bits)
+}
+
+#[cfg(feature = "alloc")]
+pub mod packed {
+    //! Serialize a sequence of half floats as a byte array, 2 bytes per value.
+
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    extern crate alloc;
+    use alloc::vec::Vec;
+
+    use super::HalfFloat;
+
+    pub fn serialize<T, S>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: HalfFloat,
+        S: Serializer,
+    {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_bits().to_be_bytes())
+            .collect();
+        serializer.serialize_bytes(&bytes)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: HalfFloat,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(PackedVisitor(PhantomData))
+    }
+
+    struct PackedVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: HalfFloat> Visitor<'de> for PackedVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a byte array of packed half floats")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            let chunks = v.chunks_exact(2);
+            if !chunks.remainder().is_empty() {
+                return Err(E::invalid_length(v.len(), &self));
+            }
+            let values = chunks
+                .map(|bytes| T::from_bits(u16::from_be_bytes([bytes[0], bytes[1]])))
+                .collect();
+            Ok(values)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use half::{bf16, f16};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Scalars {
+        #[serde(with = "crate::f16")]
+        a: f16,
+        #[serde(with = "crate::f16")]
+        b: bf16,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Tensor {
+        #[serde(with = "crate::f16::packed")]
+        values: Vec<f16>,
+        #[serde(with = "crate::f16::packed")]
+        brain_values: Vec<bf16>,
+    }
+
+    const F16_VALUES: [f16; 9] = [
+        f16::ZERO,
+        f16::NEG_ZERO,
+        f16::ONE,
+        f16::MAX,
+        f16::MIN_POSITIVE_SUBNORMAL,
+        f16::from_bits(0x03FF), // biggest subnormal
+        f16::INFINITY,
+        f16::NAN,
+        f16::from_bits(0xFE01), // negative NaN with a payload
+    ];
+
+    const BF16_VALUES: [bf16; 9] = [
+        bf16::ZERO,
+        bf16::NEG_ZERO,
+        bf16::ONE,
+        bf16::MAX,
+        bf16::MIN_POSITIVE_SUBNORMAL,
+        bf16::from_bits(0x007F), // biggest subnormal
+        bf16::NEG_INFINITY,
+        bf16::NAN,
+        bf16::from_bits(0xFFC1), // negative NaN with a payload
+    ];
+
+    fn tensor() -> Tensor {
+        Tensor {
+            values: F16_VALUES.to_vec(),
+            brain_values: BF16_VALUES.to_vec(),
+        }
+    }
+
+    fn assert_same_bits(res: &Tensor, expected: &Tensor) {
+        let bits = |values: &[f16]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        let brain_bits = |values: &[bf16]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&res.values), bits(&expected.values));
+        assert_eq!(
+            brain_bits(&res.brain_values),
+            brain_bits(&expected.brain_values)
+        );
+    }
+
+    #[test]
+    fn test_half_scalars() {
+        for (a, b) in F16_VALUES.into_iter().zip(BF16_VALUES) {
+            let value = Scalars { a, b };
+
+            let bytes = crate::compact::to_bytes(&value).unwrap();
+            assert_eq!(bytes.len(), 4);
+            let res: Scalars = crate::compact::from_bytes(&bytes).unwrap();
+            assert_eq!(res.a.to_bits(), a.to_bits());
+            assert_eq!(res.b.to_bits(), b.to_bits());
+
+            let bytes = crate::any::to_bytes(&value).unwrap();
+            let res: Scalars = crate::any::from_bytes(&bytes).unwrap();
+            assert_eq!(res.a.to_bits(), a.to_bits());
+            assert_eq!(res.b.to_bits(), b.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_half_packed() {
+        let value = tensor();
+
+        let bytes = crate::compact::to_bytes(&value).unwrap();
+        // len + 2 bytes per value, for both fields
+        assert_eq!(bytes.len(), 2 * (8 + 2 * F16_VALUES.len()));
+        let res: Tensor = crate::compact::from_bytes(&bytes).unwrap();
+        assert_same_bits(&res, &value);
+
+        let bytes = crate::any::to_bytes(&value).unwrap();
+        let res: Tensor = crate::any::from_bytes(&bytes).unwrap();
+        assert_same_bits(&res, &value);
+        assert!(res.values[7].is_nan());
+        assert!(res.brain_values[7].is_nan());
+    }
+
+    #[test]
+    fn test_half_packed_odd_len() {
+        let bytes = crate::compact::to_bytes(&crate::Bytes(&[0, 1, 2])).unwrap();
+        let err = crate::compact::from_bytes::<Tensor>(&bytes).unwrap_err();
+        // the error of the first field, the packed values
+        assert!(matches!(err, crate::Error::FieldError { index: 0, .. }));
+        assert_eq!(
+            err.root_cause(),
+            &crate::Error::Message(
+                "invalid length 3, expected a byte array of packed half floats".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Checks run by the targets of the `fuzz` directory, the inputs of `fuzz/regressions` are replayed by the tests.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::any::value::Value;
+use crate::{any, compact};
+
+/// Decode the bytes with both formats, which must fail without panicking on invalid inputs.
+pub fn decode(data: &[u8]) {
+    let _ = any::from_bytes::<Value>(data);
+    let _ = Value::parse(data);
+    let _ = any::from_bytes::<(String, Vec<u64>, Option<BTreeMap<u32, char>>)>(data);
+    let _ = compact::from_bytes::<(String, Vec<u64>, Option<BTreeMap<u32, char>>)>(data);
+    let _ = compact::from_bytes::<(f64, Vec<(bool, i8)>, Option<&[u8]>)>(data);
+}
+
+/// Serialize the value with the `any` format and decode it back, which must give the same bytes.
+///
+/// The bytes are compared instead of the values, as the decoded strings are borrowed and `NaN` is never equal.
+pub fn round_trip(value: &Value) {
+    let bytes = any::to_bytes(value).expect("generated values can be serialized");
+    let decoded: Value = any::from_bytes(&bytes).expect("serialized values can be decoded");
+    assert_eq!(any::to_bytes(&decoded).unwrap(), bytes);
+    let parsed = Value::parse(&bytes).expect("serialized values can be parsed");
+    assert_eq!(any::to_bytes(&parsed).unwrap(), bytes);
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use std::path::Path;
+
+    use super::*;
+
+    fn regressions(target: &str) -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/regressions")
+            .join(target);
+        let mut inputs: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+            .collect();
+        inputs.sort();
+        inputs
+    }
+
+    #[test]
+    fn test_decode_regressions() {
+        let inputs = regressions("decode");
+        assert!(!inputs.is_empty());
+        for input in inputs {
+            decode(&input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_regressions() {
+        let inputs = regressions("round_trip");
+        assert!(!inputs.is_empty());
+        for input in inputs {
+            let value = Value::arbitrary_take_rest(Unstructured::new(&input)).unwrap();
+            round_trip(&value);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_generated() {
+        // xorshift, so the inputs are the same on every run
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut input = vec![0; 512];
+        for _ in 0..200 {
+            for byte in input.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = state as u8;
+            }
+            let value = Value::arbitrary(&mut Unstructured::new(&input)).unwrap();
+            round_trip(&value);
+            decode(&any::to_bytes(&value).unwrap());
+        }
+    }
+}
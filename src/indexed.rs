@@ -0,0 +1,532 @@
+//! Random access to the elements of a sequence serialized with the compact format.
+//!
+//! With `SerializerConfig::element_index`, a sequence with a known length serialized as the top-level value
+//! (or inside newtype structs) is followed by an index of the byte offset of the first element of every block of elements:
+//!
+//! | Sequence | Offsets | Block length | Magic |
+//! |----------|---------|--------------|-------|
+//! | the usual encoding | one `u64` per block | `u64` | `INDEX_MAGIC` |
+//!
+//! The offsets are counted from the start of the sequence, after the tag written by `SerializerConfig::top_level_tag`,
+//! and the last block can be shorter than the others.
+//! `IndexedSeq` uses it to decode an element by only decoding the elements before it in the same block,
+//! and to decode the blocks independently, in parallel with the `rayon` feature.
+//!
+//! The index is optional: `IndexedSeq` also opens sequences serialized without it, they are then a single block.
+//! `from_bytes` doesn't expect the index and fails with `Error::TrailingBytes` on an indexed sequence,
+//! and a sequence without an index whose last bytes happen to be `INDEX_MAGIC` is taken for an indexed one.
+
+use core::marker::PhantomData;
+
+use serde::Deserialize;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::any::{Tag, TagParsingError};
+use crate::config::DeserializerConfig;
+use crate::de::{narrow_len, Deserializer};
+use crate::error::{Error, Result};
+
+/// Bytes ending a sequence followed by its element index.
+pub const INDEX_MAGIC: [u8; 8] = *b"SBINIDX1";
+
+// length of the block length and of the magic
+const TRAILER_LEN: usize = 16;
+// length of the length of the sequence
+const HEADER_LEN: usize = 8;
+
+/// Return `true` if the bytes end with an element index.
+pub fn has_index(bytes: &[u8]) -> bool {
+    bytes.ends_with(&INDEX_MAGIC)
+}
+
+/// Sequence of `T` serialized with the compact format, whose elements can be decoded independently.
+pub struct IndexedSeq<'de, T> {
+    // the sequence without its index
+    bytes: &'de [u8],
+    offsets: Option<&'de [u8]>,
+    len: usize,
+    block_len: usize,
+    config: DeserializerConfig,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> Clone for IndexedSeq<'de, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'de, T> Copy for IndexedSeq<'de, T> {}
+
+impl<'de, T> IndexedSeq<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    pub fn open(bytes: &'de [u8]) -> Result<Self> {
+        Self::open_with_config(bytes, DeserializerConfig::new())
+    }
+
+    /// Open the sequence, the elements are decoded with the given config.
+    ///
+    /// With `DeserializerConfig::top_level_tag`, the sequence must start with the `Seq` tag.
+    ///
+    /// Fails with `Error::InvalidSize` if the index doesn't match the sequence.
+    pub fn open_with_config(mut bytes: &'de [u8], mut config: DeserializerConfig) -> Result<Self> {
+        // only the sequence is tagged, not its elements
+        if core::mem::take(&mut config.top_level_tag) {
+            let (&byte, rest) = bytes.split_first().ok_or(Error::Eof)?;
+            let tag = Tag::try_from(byte)?;
+            if tag != Tag::Seq {
+                return Err(TagParsingError::unexpected("Seq", tag).into());
+            }
+            bytes = rest;
+        }
+        let header = bytes.get(..HEADER_LEN).ok_or(Error::Eof)?;
+        let len = read_usize(header)?;
+        if !has_index(bytes) {
+            return Ok(IndexedSeq {
+                bytes,
+                offsets: None,
+                len,
+                block_len: len.max(1),
+                config,
+                marker: PhantomData,
+            });
+        }
+
+        let trailer_start = bytes
+            .len()
+            .checked_sub(TRAILER_LEN)
+            .ok_or(Error::InvalidSize)?;
+        let block_len = read_usize(&bytes[trailer_start..trailer_start + 8])?;
+        if block_len == 0 {
+            return Err(Error::InvalidSize);
+        }
+        let offsets_len = len
+            .div_ceil(block_len)
+            .checked_mul(8)
+            .ok_or(Error::InvalidSize)?;
+        let offsets_start = trailer_start
+            .checked_sub(offsets_len)
+            .filter(|&start| start >= HEADER_LEN)
+            .ok_or(Error::InvalidSize)?;
+        let offsets = &bytes[offsets_start..trailer_start];
+
+        // the offsets must be increasing and inside the sequence, so decoding a block never reads the index
+        let mut previous = HEADER_LEN;
+        for offset in offsets.chunks_exact(8) {
+            let offset = read_usize(offset)?;
+            if offset < previous || offset > offsets_start {
+                return Err(Error::InvalidSize);
+            }
+            previous = offset;
+        }
+
+        Ok(IndexedSeq {
+            bytes: &bytes[..offsets_start],
+            offsets: Some(offsets),
+            len,
+            block_len,
+            config,
+            marker: PhantomData,
+        })
+    }
+
+    /// Number of elements of the sequence.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return `false` if the sequence was serialized without an index, every access then decodes it from the start.
+    pub fn has_index(&self) -> bool {
+        self.offsets.is_some()
+    }
+
+    /// Number of elements of each block, the last one can be shorter.
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Decode the element at `index`, only the elements before it in the same block are decoded.
+    ///
+    /// Fails with `Error::IndexOutOfRange` if `index` is not less than the length of the sequence.
+    pub fn get(&self, index: usize) -> Result<T> {
+        if index >= self.len {
+            return Err(Error::IndexOutOfRange {
+                index,
+                len: self.len,
+            });
+        }
+        let mut elements = self.block(index / self.block_len);
+        for _ in 0..index % self.block_len {
+            elements.next().transpose()?;
+        }
+        elements.next().unwrap_or(Err(Error::Eof))
+    }
+
+    /// Iterate over the elements in order, decoding the sequence once.
+    pub fn iter(&self) -> impl Iterator<Item = Result<T>> + 'de
+    where
+        T: 'de,
+    {
+        let seq = *self;
+        (0..self.blocks()).flat_map(move |block| seq.block(block))
+    }
+
+    /// Iterate over the elements in parallel, each block is decoded by a single task.
+    ///
+    /// Collecting the iterator keeps the elements in order.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = Result<T>> + 'de
+    where
+        T: Send + 'de,
+    {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let seq = *self;
+        (0..self.blocks())
+            .into_par_iter()
+            .flat_map_iter(move |block| seq.block(block))
+    }
+
+    fn blocks(&self) -> usize {
+        self.len.div_ceil(self.block_len)
+    }
+
+    /// Iterator over the elements of a block.
+    fn block(&self, block: usize) -> Block<'de, T> {
+        let start = match self.offsets {
+            // the offsets have been checked when opening the sequence
+            Some(offsets) => read_usize(&offsets[block * 8..block * 8 + 8]).unwrap_or(HEADER_LEN),
+            None => HEADER_LEN,
+        };
+        Block {
+            de: Deserializer::with_config(&self.bytes[start..], self.config),
+            remaining: self.block_len.min(self.len - block * self.block_len),
+            marker: PhantomData,
+        }
+    }
+}
+
+struct Block<'de, T> {
+    de: Deserializer<'de>,
+    remaining: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> Iterator for Block<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.remaining = self.remaining.checked_sub(1)?;
+        let res = T::deserialize(&mut self.de);
+        if res.is_err() {
+            // the position of the next element is unknown
+            self.remaining = 0;
+        }
+        Some(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+fn read_usize(bytes: &[u8]) -> Result<usize> {
+    let mut buff = [0; 8];
+    buff.copy_from_slice(bytes);
+    narrow_len(u64::from_be_bytes(buff))
+}
+
+// Record the offsets of the elements of the top-level sequence while it is serialized.
+#[cfg(feature = "alloc")]
+pub(crate) enum IndexBuilder {
+    Disabled,
+    // nothing has been serialized yet
+    Pending {
+        block_len: usize,
+    },
+    Indexing {
+        block_len: usize,
+        offsets: Vec<u64>,
+        position: u64,
+        count: usize,
+        in_element: bool,
+    },
+}
+
+#[cfg(feature = "alloc")]
+impl IndexBuilder {
+    pub(crate) fn new(block_len: Option<usize>) -> Self {
+        match block_len {
+            Some(block_len) => IndexBuilder::Pending {
+                block_len: block_len.max(1),
+            },
+            None => IndexBuilder::Disabled,
+        }
+    }
+
+    /// A sequence with a known length starts after a header of `header_len` bytes.
+    pub(crate) fn start_seq(&mut self, header_len: usize) {
+        if let IndexBuilder::Pending { block_len } = *self {
+            *self = IndexBuilder::Indexing {
+                block_len,
+                offsets: Vec::new(),
+                position: header_len as u64,
+                count: 0,
+                in_element: false,
+            };
+        }
+    }
+
+    /// A value that is not the sequence to index starts.
+    pub(crate) fn skip(&mut self) {
+        if let IndexBuilder::Pending { .. } = self {
+            *self = IndexBuilder::Disabled;
+        }
+    }
+
+    /// Return `true` if the element starting is an element of the indexed sequence.
+    pub(crate) fn start_element(&mut self) -> bool {
+        let IndexBuilder::Indexing {
+            block_len,
+            offsets,
+            position,
+            count,
+            in_element: in_element @ false,
+        } = self
+        else {
+            return false;
+        };
+        if *count % *block_len == 0 {
+            offsets.push(*position);
+        }
+        *in_element = true;
+        true
+    }
+
+    pub(crate) fn end_element(&mut self, written_bytes: usize) {
+        if let IndexBuilder::Indexing {
+            position,
+            count,
+            in_element,
+            ..
+        } = self
+        {
+            *position += written_bytes as u64;
+            *count += 1;
+            *in_element = false;
+        }
+    }
+
+    /// Return the index to write if the container ending is the indexed sequence.
+    pub(crate) fn finish(&mut self) -> Option<Vec<u8>> {
+        let IndexBuilder::Indexing {
+            block_len,
+            offsets,
+            in_element: false,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let mut index = Vec::with_capacity(offsets.len() * 8 + TRAILER_LEN);
+        for offset in offsets.iter() {
+            index.extend_from_slice(&offset.to_be_bytes());
+        }
+        index.extend_from_slice(&(*block_len as u64).to_be_bytes());
+        index.extend_from_slice(&INDEX_MAGIC);
+        *self = IndexBuilder::Disabled;
+        Some(index)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use crate::{compact::Serializer, SerializerConfig};
+    use rayon::iter::ParallelIterator;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+        name: String,
+        values: Vec<u16>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Records(Vec<Record>);
+
+    fn records(len: u32) -> Vec<Record> {
+        (0..len)
+            .map(|id| Record {
+                id,
+                name: format!("record {}", id),
+                values: (0..id as u16 % 5).collect(),
+            })
+            .collect()
+    }
+
+    fn to_indexed_bytes<T: Serialize>(value: &T, block_len: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let config = SerializerConfig::new().element_index(block_len);
+        let written_bytes = value
+            .serialize(&mut Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        assert_eq!(written_bytes, bytes.len());
+        bytes
+    }
+
+    #[test]
+    fn test_indexed_random_access() {
+        let value = records(1000);
+        let plain = crate::compact::to_bytes(&value).unwrap();
+        for block_len in [1, 7, 16, 1000, 5000] {
+            let bytes = to_indexed_bytes(&value, block_len);
+            assert!(has_index(&bytes));
+            // the sequence itself is unchanged, followed by one offset per block and the trailer
+            assert_eq!(bytes[..plain.len()], plain);
+            assert_eq!(
+                bytes.len() - plain.len(),
+                1000usize.div_ceil(block_len) * 8 + 16
+            );
+
+            let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+            assert!(seq.has_index());
+            assert_eq!(seq.len(), 1000);
+            for index in [0, 1, 6, 7, 15, 16, 17, 500, 998, 999] {
+                assert_eq!(seq.get(index).unwrap(), value[index]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexed_out_of_range() {
+        let bytes = to_indexed_bytes(&records(10), 4);
+        let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+        assert_eq!(
+            seq.get(10).unwrap_err(),
+            Error::IndexOutOfRange { index: 10, len: 10 }
+        );
+
+        let bytes = to_indexed_bytes(&Vec::<Record>::new(), 4);
+        let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+        assert!(seq.is_empty());
+        assert_eq!(seq.iter().count(), 0);
+        assert!(seq.get(0).is_err());
+    }
+
+    #[test]
+    fn test_indexed_matches_sequential() {
+        let value = records(300);
+        let bytes = to_indexed_bytes(&value, 32);
+        let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+
+        let sequential: Vec<Record> =
+            crate::compact::from_bytes(&crate::compact::to_bytes(&value).unwrap()).unwrap();
+        let iterated: Vec<Record> = seq.iter().collect::<Result<_>>().unwrap();
+        let parallel: Vec<Record> = seq.par_iter().collect::<Result<_>>().unwrap();
+        assert_eq!(iterated, sequential);
+        assert_eq!(parallel, sequential);
+        assert_eq!(sequential, value);
+    }
+
+    #[test]
+    fn test_index_is_optional() {
+        let value = records(50);
+        let bytes = crate::compact::to_bytes(&value).unwrap();
+        assert!(!has_index(&bytes));
+        let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+        assert!(!seq.has_index());
+        assert_eq!(seq.get(42).unwrap(), value[42]);
+        assert_eq!(seq.par_iter().collect::<Result<Vec<_>>>().unwrap(), value);
+
+        // indexed sequences can't be read by `from_bytes`
+        let indexed = to_indexed_bytes(&value, 8);
+        assert!(matches!(
+            crate::compact::from_bytes::<Vec<Record>>(&indexed),
+            Err(Error::TrailingBytes(_))
+        ));
+    }
+
+    #[test]
+    fn test_index_top_level_only() {
+        // newtype structs are transparent
+        let value = Records(records(20));
+        let bytes = to_indexed_bytes(&value, 4);
+        let seq = IndexedSeq::<Record>::open(&bytes).unwrap();
+        assert_eq!(seq.get(13).unwrap(), value.0[13]);
+
+        // other values and nested sequences are serialized as usual
+        let value = (records(3), 4u8);
+        assert_eq!(
+            to_indexed_bytes(&value, 1),
+            crate::compact::to_bytes(&value).unwrap()
+        );
+        let value = Some(records(3));
+        assert_eq!(
+            to_indexed_bytes(&value, 1),
+            crate::compact::to_bytes(&value).unwrap()
+        );
+        let value = vec![vec![1u8, 2], vec![3]];
+        let bytes = to_indexed_bytes(&value, 1);
+        assert_eq!(
+            bytes.len(),
+            crate::compact::to_bytes(&value).unwrap().len() + 2 * 8 + 16
+        );
+    }
+
+    #[test]
+    fn test_index_top_level_tag() {
+        let value = records(20);
+        let mut bytes = Vec::new();
+        let config = SerializerConfig::new().element_index(4).top_level_tag(true);
+        value
+            .serialize(&mut Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        assert_eq!(bytes[0], Tag::Seq.into());
+        assert_eq!(bytes[1..], to_indexed_bytes(&value, 4));
+
+        let config = DeserializerConfig::new().top_level_tag(true);
+        let seq = IndexedSeq::<Record>::open_with_config(&bytes, config).unwrap();
+        assert!(seq.has_index());
+        assert_eq!(seq.get(13).unwrap(), value[13]);
+        assert_eq!(seq.iter().collect::<Result<Vec<_>>>().unwrap(), value);
+
+        let plain = to_indexed_bytes(&value, 4);
+        assert!(IndexedSeq::<Record>::open_with_config(&plain, config).is_err());
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        let value = records(20);
+        let mut bytes = to_indexed_bytes(&value, 4);
+        let len = bytes.len();
+        // offset of the last block past the end of the sequence
+        bytes[len - 24..len - 16].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            IndexedSeq::<Record>::open(&bytes),
+            Err(Error::InvalidSize)
+        ));
+
+        // block length of 0
+        let mut bytes = to_indexed_bytes(&value, 4);
+        bytes[len - 16..len - 8].copy_from_slice(&0u64.to_be_bytes());
+        assert!(matches!(
+            IndexedSeq::<Record>::open(&bytes),
+            Err(Error::InvalidSize)
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+use core::borrow::Borrow;
+use core::fmt;
+
+use serde::de::{DeserializeSeed, Visitor};
+
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+
+/// Cache of shared strings, used to deserialize many occurences of the same string into a single allocation.
+///
+/// A `&mut StringInterner` is a `DeserializeSeed`, so it can be used with `from_bytes_seed`
+/// or from inside a custom `DeserializeSeed`/`Visitor` to deserialize strings that are often repeated.
+///
+/// The shared pointer type can be `Arc<str>` (the default) or `Rc<str>`.
+#[derive(Debug, Clone)]
+pub struct StringInterner<P = Arc<str>> {
+    cache: BTreeSet<P>,
+}
+
+impl<P> Default for StringInterner<P> {
+    fn default() -> Self {
+        StringInterner {
+            cache: BTreeSet::new(),
+        }
+    }
+}
+
+impl<P> StringInterner<P>
+where
+    P: Clone + Ord + Borrow<str> + for<'a> From<&'a str>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached instance of the string, allocating it if it was not already in the cache.
+    pub fn intern(&mut self, s: &str) -> P {
+        if let Some(shared) = self.cache.get(s) {
+            return shared.clone();
+        }
+        let shared = P::from(s);
+        self.cache.insert(shared.clone());
+        shared
+    }
+
+    /// Number of distinct strings in the cache.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    pub fn contains(&self, s: &str) -> bool {
+        self.cache.contains(s)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        self.cache.iter()
+    }
+
+    /// Empty the cache, the strings already handed out stay valid.
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+}
+
+impl<'de, P> DeserializeSeed<'de> for &mut StringInterner<P>
+where
+    P: Clone + Ord + Borrow<str> + for<'b> From<&'b str>,
+{
+    type Value = P;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, P> Visitor<'de> for &mut StringInterner<P>
+where
+    P: Clone + Ord + Borrow<str> + for<'b> From<&'b str>,
+{
+    type Value = P;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(self.intern(v))
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::de::SeqAccess;
+    use std::rc::Rc;
+
+    // Deserialize a sequence of strings through the interner.
+    struct InternedSeq<'a, P>(&'a mut StringInterner<P>);
+
+    impl<'a, 'de, P> DeserializeSeed<'de> for InternedSeq<'a, P>
+    where
+        P: Clone + Ord + Borrow<str> + for<'b> From<&'b str>,
+    {
+        type Value = Vec<P>;
+
+        fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_seq(self)
+        }
+    }
+
+    impl<'a, 'de, P> Visitor<'de> for InternedSeq<'a, P>
+    where
+        P: Clone + Ord + Borrow<str> + for<'b> From<&'b str>,
+    {
+        type Value = Vec<P>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a sequence of strings")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut buff = Vec::new();
+            while let Some(s) = seq.next_element_seed(&mut *self.0)? {
+                buff.push(s);
+            }
+            Ok(buff)
+        }
+    }
+
+    const TAGS: [&str; 12] = [
+        "created", "updated", "deleted", "archived", "restored", "locked", "unlocked", "moved",
+        "copied", "renamed", "shared", "unshared",
+    ];
+
+    fn corpus() -> Vec<String> {
+        (0..10_000)
+            .map(|i| TAGS[(i * 7) % TAGS.len()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_intern_seed() {
+        let bytes = crate::compact::to_bytes(&"created").unwrap();
+        let mut interner: StringInterner = StringInterner::new();
+
+        let first = crate::compact::from_bytes_seed(&mut interner, &bytes).unwrap();
+        let second = crate::compact::from_bytes_seed(&mut interner, &bytes).unwrap();
+
+        assert_eq!(&*first, "created");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(interner.len(), 1);
+
+        interner.clear();
+        assert!(interner.is_empty());
+
+        let third = crate::compact::from_bytes_seed(&mut interner, &bytes).unwrap();
+        assert!(!Arc::ptr_eq(&first, &third));
+        assert_eq!(first, third);
+    }
+
+    #[test]
+    fn test_intern_corpus() {
+        let corpus = corpus();
+        let bytes = crate::any::to_bytes(&corpus).unwrap();
+
+        let mut interner: StringInterner<Rc<str>> = StringInterner::new();
+        let res = crate::any::from_bytes_seed(InternedSeq(&mut interner), &bytes).unwrap();
+
+        assert_eq!(res.len(), corpus.len());
+        assert!(res.iter().zip(&corpus).all(|(a, b)| &**a == b));
+
+        // only one allocation per distinct string
+        assert_eq!(interner.len(), TAGS.len());
+        assert!(TAGS.iter().all(|tag| interner.contains(tag)));
+
+        for tag in TAGS {
+            let mut occurences = res.iter().filter(|s| &***s == tag);
+            let first = occurences.next().unwrap();
+            assert!(occurences.all(|s| Rc::ptr_eq(first, s)));
+            // shared by the cache and every occurence
+            assert!(Rc::strong_count(first) > 2);
+        }
+
+        let interned_bytes: usize = interner.iter().map(|s| s.len()).sum();
+        let owned_bytes: usize = corpus.iter().map(String::len).sum();
+        assert!(interned_bytes * 100 < owned_bytes);
+    }
+}
@@ -0,0 +1,167 @@
+//! The compact format at the crate root, from before the `compact` module.
+//!
+//! These names don't tell which format they use, so they were often used with tagged inputs by mistake.
+
+use serde::de::{Deserialize, DeserializeSeed};
+use serde::Serialize;
+
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::{compact, BuffWriter, DeserializerConfig, EndOfBuff, Result, Write};
+
+#[deprecated(note = "use `compact::Serializer`, or `tagged::Serializer` for the tagged format")]
+pub type Serializer<W> = compact::Serializer<W>;
+
+#[deprecated(note = "use `compact::Deserializer`, or `tagged::Deserializer` for the tagged format")]
+pub type Deserializer<'de> = compact::Deserializer<'de>;
+
+#[deprecated(note = "use `compact::from_bytes`, or `tagged::from_bytes` for the tagged format")]
+pub fn from_bytes<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    compact::from_bytes(input)
+}
+
+#[deprecated(
+    note = "use `compact::from_bytes_seed`, or `tagged::from_bytes_seed` for the tagged format"
+)]
+pub fn from_bytes_seed<'a, S>(seed: S, input: &'a [u8]) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    compact::from_bytes_seed(seed, input)
+}
+
+#[deprecated(
+    note = "use `compact::from_bytes_with_config`, or `tagged::from_bytes_with_config` for the tagged format"
+)]
+pub fn from_bytes_with_config<'a, T>(input: &'a [u8], config: DeserializerConfig) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    compact::from_bytes_with_config(input, config)
+}
+
+#[deprecated(
+    note = "use `compact::from_bytes_seed_with_config`, or `tagged::from_bytes_seed_with_config` for the tagged format"
+)]
+pub fn from_bytes_seed_with_config<'a, S>(
+    seed: S,
+    input: &'a [u8],
+    config: DeserializerConfig,
+) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    compact::from_bytes_seed_with_config(seed, input, config)
+}
+
+#[deprecated(
+    note = "use `compact::from_bytes_canonical`, or `tagged::from_bytes_canonical` for the tagged format"
+)]
+pub fn from_bytes_canonical<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    compact::from_bytes_canonical(input)
+}
+
+#[deprecated(note = "use `compact::to_writer`, or `tagged::to_writer` for the tagged format")]
+pub fn to_writer<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    compact::to_writer(value, writer)
+}
+
+#[deprecated(
+    note = "use `compact::to_writer_complete`, or `tagged::to_writer_complete` for the tagged format"
+)]
+pub fn to_writer_complete<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    compact::to_writer_complete(value, writer)
+}
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+#[deprecated(note = "use `compact::to_bytes`, or `tagged::to_bytes` for the tagged format")]
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    compact::to_bytes(value)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `compact::to_bytes`, or `tagged::to_bytes` for the tagged format")]
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>, io::Error>
+where
+    T: Serialize,
+{
+    compact::to_bytes(value)
+}
+
+#[cfg(feature = "alloc")]
+#[deprecated(
+    note = "use `compact::to_bytes_canonical`, or `tagged::to_bytes_canonical` for the tagged format"
+)]
+pub fn to_bytes_canonical<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    compact::to_bytes_canonical(value)
+}
+
+#[deprecated(note = "use `compact::to_buff`, or `tagged::to_buff` for the tagged format")]
+pub fn to_buff<'a, T>(value: &T, buff: &'a mut [u8]) -> Result<BuffWriter<'a>, EndOfBuff>
+where
+    T: Serialize,
+{
+    compact::to_buff(value, buff)
+}
+
+#[deprecated(note = "use `compact::to_array`, or `tagged::to_array` for the tagged format")]
+pub fn to_array<const N: usize, T>(value: &T) -> Result<([u8; N], usize), EndOfBuff>
+where
+    T: Serialize,
+{
+    compact::to_array(value)
+}
+
+#[deprecated(note = "use `compact::to_array_ref`, or `tagged::to_array_ref` for the tagged format")]
+pub fn to_array_ref<const N: usize, T>(value: &T, buff: &mut [u8; N]) -> Result<usize, EndOfBuff>
+where
+    T: Serialize,
+{
+    compact::to_array_ref(value, buff)
+}
+
+#[deprecated(
+    note = "use `compact::get_serialized_size`, or `tagged::get_serialized_size` for the tagged format"
+)]
+pub fn get_serialized_size<T>(value: &T) -> Result<usize>
+where
+    T: Serialize,
+{
+    compact::get_serialized_size(value)
+}
+
+#[deprecated(
+    note = "use `compact::get_serialized_size_and_crc`, or `tagged::get_serialized_size_and_crc` for the tagged format"
+)]
+pub fn get_serialized_size_and_crc<T>(value: &T) -> Result<(usize, u32)>
+where
+    T: Serialize,
+{
+    compact::get_serialized_size_and_crc(value)
+}
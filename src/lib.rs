@@ -1,24 +1,107 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
 pub mod any;
+pub mod bounded;
+mod bytes;
+mod canonical;
+mod cipher;
+pub mod compact;
+pub mod compat;
+mod config;
+#[cfg(feature = "alloc")]
+pub mod cow_bytes;
 mod de;
+#[cfg(feature = "alloc")]
+pub mod delta;
 mod error;
+#[cfg(feature = "half")]
+pub mod f16;
+#[cfg(feature = "arbitrary")]
+#[doc(hidden)]
+pub mod fuzz;
+pub mod indexed;
+#[cfg(feature = "alloc")]
+mod intern;
+mod legacy;
+#[cfg(feature = "alloc")]
+pub mod option_vec;
+#[cfg(feature = "pool")]
+mod pool;
+pub mod prelude;
+mod progress;
+#[cfg(feature = "alloc")]
+mod redact;
+mod schema;
 mod ser;
+pub mod sized_flatten;
+pub mod tagged;
+#[cfg(all(test, feature = "test-utils"))]
+mod test_fixtures;
+#[cfg(feature = "test-utils")]
+pub mod testing;
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod timestamp;
+mod trace;
+#[cfg(feature = "alloc")]
+mod unborrowed;
 mod write;
 
-pub use de::{from_bytes, Deserializer};
-pub use error::{Error, NoWriterError, Result, WriterError};
 #[cfg(feature = "alloc")]
-pub use ser::to_bytes;
+pub use bytes::ByteBuf;
+pub use bytes::Bytes;
+pub use canonical::CanonicalRule;
 #[cfg(feature = "std")]
-pub use ser::to_writer;
-pub use ser::{get_serialized_size, to_buff, Serializer};
-pub use write::{BuffWriter, EndOfBuff, Write};
+pub use cipher::DecryptingReader;
+pub use cipher::{EncryptingWriter, StreamCipher, XorCipher};
+pub use config::{
+    DeserializerConfig, NumberCoercion, SerializerConfig, StrCoercion, VariantIndexWidth,
+    DEFAULT_MAX_DEPTH,
+};
+pub use error::{Error, ForEachError, Format, NoWriterError, Result, WriterError};
+#[cfg(feature = "alloc")]
+pub use intern::StringInterner;
+pub use legacy::*;
+#[cfg(feature = "pool")]
+pub use pool::{BufferPool, PooledBytes, DEFAULT_MAX_BUFFERS, DEFAULT_MAX_CAPACITY};
+pub use progress::{NoProgress, ProgressCallback};
+#[cfg(feature = "alloc")]
+pub use redact::{FieldAction, FieldFilter};
+pub use schema::{field_offset, patch_field, Field, FieldSpan, Schema, SchemaError};
+#[cfg(feature = "alloc")]
+pub use trace::Trace;
+pub use trace::{TraceEvent, TraceStep};
+#[cfg(feature = "std")]
+pub use write::IoAdapter;
+pub use write::{
+    BoundedExtend, BuffWriter, CheckedExtendWriter, EndOfBuff, ExtendWriter, SizeAndCrcWriter,
+    Write,
+};
+#[cfg(feature = "alloc")]
+pub use write::{HybridWriter, VecWriter};
+
+/// Bytes ending the strings serialized without knowing their length, in both formats.
+///
+/// `0xD800` is a surrogate, so these bytes can't appear in valid UTF-8 and can't be mistaken for the content of the string.
+pub const UNSIZED_STRING_END_MARKER: [u8; 2] = [0xD8, 0x00];
 
-const UNSIZED_STRING_END_MARKER: [u8; 2] = [0xD8, 0x00];
+/// Return `true` if the bytes start with the `UNSIZED_STRING_END_MARKER`.
+pub fn is_unsized_string_end(bytes: &[u8]) -> bool {
+    bytes.starts_with(&UNSIZED_STRING_END_MARKER)
+}
 
 #[cfg(all(test, feature = "test-utils"))]
 mod tests {
 
     use super::*;
+    use crate::compact::{
+        from_bytes, from_bytes_partial, from_bytes_with_config, get_serialized_size,
+        get_serialized_size_and_crc, to_array, to_array_ref, to_bytes, to_writer_complete,
+        Deserializer, Serializer,
+    };
+    use crate::test_fixtures::{
+        assert_borrow_own_symmetry, Compact, Empty, HeadAndRest, WithPhantom, EMPTY_COLLECTIONS,
+    };
+    use core::ops::ControlFlow;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -36,6 +119,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(invalid_from_utf8)]
     fn test_invalid_utf8_char() {
         let [byte1, byte2] = UNSIZED_STRING_END_MARKER;
         assert_eq!(
@@ -43,6 +127,24 @@ mod tests {
             None
         );
         assert!(core::str::from_utf8(&UNSIZED_STRING_END_MARKER).is_err());
+
+        assert!(is_unsized_string_end(&UNSIZED_STRING_END_MARKER));
+        assert!(is_unsized_string_end(&[0xD8, 0x00, b'a']));
+        assert!(!is_unsized_string_end(&[0xD8]));
+        assert!(!is_unsized_string_end(&[b'a', 0xD8, 0x00]));
+        // the marker is never found in a valid string
+        let s = "a\u{D7FF}\u{E000}\u{10000}\0";
+        assert!(!s.as_bytes().windows(2).any(is_unsized_string_end));
+    }
+
+    #[test]
+    fn test_deserialize_invalid_char() {
+        // surrogates and values above char::MAX are not chars
+        for c in [0xD800u32, 0xDFFF, 0x110000, u32::MAX] {
+            let bytes = c.to_be_bytes();
+            assert_eq!(from_bytes::<char>(&bytes), Err(Error::InvalidChar(c)));
+        }
+        assert_eq!(from_bytes::<char>(&[0, 0, 0]), Err(Error::Eof));
     }
 
     #[test]
@@ -209,4 +311,1863 @@ mod tests {
 
         assert_eq!(value, res);
     }
+
+    #[test]
+    fn test_borrowed_owned_str() {
+        assert_borrow_own_symmetry(Compact, &"Hello", &String::from("Hello"));
+        assert_borrow_own_symmetry(Compact, &"", &String::new());
+
+        let bytes = to_bytes(&String::from("Hello")).unwrap();
+        let res: &str = from_bytes(&bytes).unwrap();
+        assert_eq!(res, "Hello");
+    }
+
+    #[test]
+    fn test_borrowed_owned_bytes() {
+        assert_borrow_own_symmetry(Compact, &Bytes(b"Hello"), &ByteBuf(b"Hello".to_vec()));
+        assert_borrow_own_symmetry(Compact, &Bytes(&[]), &ByteBuf(Vec::new()));
+
+        let bytes = to_bytes(&ByteBuf(b"Hello".to_vec())).unwrap();
+        let res: Bytes = from_bytes(&bytes).unwrap();
+        assert_eq!(res, Bytes(b"Hello"));
+    }
+
+    // Records which visitor method received the bytes.
+    #[derive(Debug, PartialEq)]
+    enum BytesOrigin {
+        Borrowed,
+        Owned,
+    }
+
+    struct BytesOriginVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesOriginVisitor {
+        type Value = BytesOrigin;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a byte array")
+        }
+
+        fn visit_borrowed_bytes<E>(self, _v: &'de [u8]) -> core::result::Result<Self::Value, E> {
+            Ok(BytesOrigin::Borrowed)
+        }
+
+        fn visit_byte_buf<E>(self, _v: Vec<u8>) -> core::result::Result<Self::Value, E> {
+            Ok(BytesOrigin::Owned)
+        }
+    }
+
+    #[test]
+    fn test_bytes_visitor_methods() {
+        let bytes = to_bytes(&Bytes(b"Hello")).unwrap();
+
+        let mut de = de::Deserializer::new(&bytes);
+        let res = serde::Deserializer::deserialize_bytes(&mut de, BytesOriginVisitor);
+        assert_eq!(res, Ok(BytesOrigin::Borrowed));
+
+        let mut de = de::Deserializer::new(&bytes);
+        let res = serde::Deserializer::deserialize_byte_buf(&mut de, BytesOriginVisitor);
+        assert_eq!(res, Ok(BytesOrigin::Owned));
+
+        let res: ByteBuf = from_bytes(&bytes).unwrap();
+        assert_eq!(res, ByteBuf(b"Hello".to_vec()));
+    }
+
+    #[test]
+    fn test_borrowed_owned_slice() {
+        let slice: &[u16] = &[3, 7, 1, 8];
+        assert_borrow_own_symmetry(Compact, &slice, &slice.to_vec());
+        let strs: &[&str] = &["a", "bc"];
+        assert_borrow_own_symmetry(Compact, &strs, &vec![String::from("a"), String::from("bc")]);
+        assert_borrow_own_symmetry(Compact, &&[0u8; 0][..], &Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_boxed_slices() {
+        let numbers: Box<[u32]> = (0..100).collect();
+        let bytes = compact::to_bytes(&numbers).unwrap();
+        let before = allocations();
+        let res: Box<[u32]> = compact::from_bytes(&bytes).unwrap();
+        // the length of the sequence is known, the `Vec` collecting the elements is boxed without reallocating
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(res, numbers);
+        let bytes = tagged::to_bytes(&numbers).unwrap();
+        let before = allocations();
+        let res: Box<[u32]> = tagged::from_bytes(&bytes).unwrap();
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(res, numbers);
+        assert_eq!(compact::from_bytes(&[0; 8]), Ok(Box::<[u32]>::from([])));
+
+        let boxed: Box<[u8]> = (0..=255).collect();
+        let bytes = compact::to_bytes(&boxed).unwrap();
+        assert_eq!(compact::from_bytes(&bytes), Ok(boxed.clone()));
+        let bytes = tagged::to_bytes(&boxed).unwrap();
+        assert_eq!(tagged::from_bytes(&bytes), Ok(boxed.clone()));
+        // a tag per element as a sequence, a single length and the raw bytes as a byte array
+        let seq = tagged::to_bytes(&boxed).unwrap();
+        let array = tagged::to_bytes(&Bytes(&boxed)).unwrap();
+        assert_eq!(seq.len(), 1 + 8 + 2 * 256);
+        assert_eq!(array.len(), 1 + 8 + 256);
+        let before = allocations();
+        let buf: ByteBuf = tagged::from_bytes(&array).unwrap();
+        let res = Box::<[u8]>::from(buf);
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(res, boxed);
+        let array = compact::to_bytes(&Bytes(&boxed)).unwrap();
+        assert_eq!(array.len(), 8 + 256);
+        let buf: ByteBuf = compact::from_bytes(&array).unwrap();
+        assert_eq!(ByteBuf::from(boxed), buf);
+    }
+
+    #[cfg(not(no_integer128))]
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Wide {
+        a: i128,
+        b: u128,
+    }
+
+    #[cfg(not(no_integer128))]
+    #[test]
+    fn test_serialize_deserialize_128() {
+        for (a, b) in [
+            (0, 0),
+            (-1, 1),
+            (i128::MIN, u128::MAX),
+            (i128::MAX, 1 << 64),
+            (
+                -0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10,
+                0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10,
+            ),
+        ] {
+            let value = Wide { a, b };
+            let bytes = to_bytes(&value).unwrap();
+            assert_eq!(bytes.len(), 32);
+            assert_eq!(bytes[..16], a.to_be_bytes());
+            assert_eq!(bytes[16..], b.to_be_bytes());
+            assert_eq!(from_bytes::<Wide>(&bytes), Ok(value));
+        }
+
+        let bytes = to_bytes(&u128::MAX).unwrap();
+        assert_eq!(from_bytes::<u128>(&bytes[..15]), Err(Error::Eof));
+        assert_eq!(from_bytes::<i128>(&bytes), Ok(-1));
+    }
+
+    #[test]
+    fn test_non_zero() {
+        use core::num::{NonZeroI64, NonZeroU32, NonZeroU8};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct NonZeros {
+            a: NonZeroU8,
+            b: NonZeroU32,
+            c: NonZeroI64,
+        }
+
+        let value = NonZeros {
+            a: NonZeroU8::MAX,
+            b: NonZeroU32::new(42).unwrap(),
+            c: NonZeroI64::new(i64::MIN).unwrap(),
+        };
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes, to_bytes(&(u8::MAX, 42u32, i64::MIN)).unwrap());
+        assert_eq!(from_bytes::<NonZeros>(&bytes), Ok(value));
+
+        let bytes = to_bytes(&(1u8, 0u32, -1i64)).unwrap();
+        let err = from_bytes::<NonZeros>(&bytes).unwrap_err();
+        assert!(matches!(err, Error::FieldError { index: 1, .. }));
+        assert!(matches!(err.root_cause(), Error::Message(msg) if msg.contains("nonzero")));
+    }
+
+    #[test]
+    fn test_max_total_elements() {
+        // 10 outer elements + 10 * 3 inner elements
+        let value: Vec<Vec<u8>> = vec![vec![1, 2, 3]; 10];
+        let bytes = to_bytes(&value).unwrap();
+
+        let config = DeserializerConfig::new().max_total_elements(39);
+        let mut deserializer = Deserializer::with_config(&bytes, config);
+        let res = Vec::<Vec<u8>>::deserialize(&mut deserializer);
+
+        assert_eq!(res, Err(Error::ElementBudgetExceeded));
+
+        let config = DeserializerConfig::new().max_total_elements(40);
+        let mut deserializer = Deserializer::with_config(&bytes, config);
+        let res = Vec::<Vec<u8>>::deserialize(&mut deserializer);
+
+        assert_eq!(res, Ok(value));
+    }
+
+    #[test]
+    fn test_deserializer_config() {
+        // depth 2, 10 outer elements + 10 * 3 inner elements
+        let value: Vec<Vec<u8>> = vec![vec![1, 2, 3]; 10];
+        let bytes = to_bytes(&value).unwrap();
+
+        let config = DeserializerConfig::new()
+            .max_total_elements(40)
+            .max_depth(1);
+        let res = from_bytes_with_config::<Vec<Vec<u8>>>(&bytes, config);
+        assert_eq!(res, Err(Error::DepthLimitExceeded));
+
+        let config = config.max_depth(2);
+        let res = from_bytes_with_config::<Vec<Vec<u8>>>(&bytes, config);
+        assert_eq!(res, Ok(value));
+    }
+
+    // every level is a newtype struct and an option
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Nested(Option<Box<Nested>>);
+
+    fn nested(depth: usize) -> Nested {
+        (0..depth).fold(Nested(None), |inner, _| Nested(Some(Box::new(inner))))
+    }
+
+    #[test]
+    fn test_default_max_depth() {
+        let value = nested(DEFAULT_MAX_DEPTH / 2 - 1);
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(from_bytes::<Nested>(&bytes), Ok(value));
+
+        let value = nested(DEFAULT_MAX_DEPTH / 2);
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(from_bytes::<Nested>(&bytes), Err(Error::DepthLimitExceeded));
+
+        let config = DeserializerConfig::new().unlimited_depth();
+        assert_eq!(from_bytes_with_config::<Nested>(&bytes, config), Ok(value));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum FutureTestEnum {
+        Unit,
+        NewType(u8),
+        Tuple(f32, String),
+        Struct { a: f64, b: Vec<u16> },
+        FutureUnit,
+        FutureNewType(u8),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum FallbackTestEnum {
+        Unit,
+        NewType(u8),
+        Tuple(f32, String),
+        Struct {
+            a: f64,
+            b: Vec<u16>,
+        },
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[test]
+    fn test_unknown_variant_fallback() {
+        let value = vec![
+            FutureTestEnum::NewType(3),
+            FutureTestEnum::FutureUnit,
+            FutureTestEnum::Unit,
+        ];
+        let bytes = to_bytes(&value).unwrap();
+
+        let res: Vec<FallbackTestEnum> = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            res,
+            [
+                FallbackTestEnum::NewType(3),
+                FallbackTestEnum::Unknown,
+                FallbackTestEnum::Unit
+            ]
+        );
+
+        let res: Result<Vec<TestEnum>> = from_bytes(&bytes);
+        assert!(res.is_err());
+
+        // the compact format doesn't know the size of the payload, only unit variants can fall back.
+        let bytes = to_bytes(&FutureTestEnum::FutureNewType(1)).unwrap();
+        let res: Result<FallbackTestEnum> = from_bytes(&bytes);
+        assert_eq!(res, Err(Error::TrailingBytes(1)));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct WideStruct {
+        a: u8,
+        b: u16,
+        c: String,
+        d: bool,
+        e: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Outer {
+        id: u64,
+        inner: WideStruct,
+    }
+
+    #[test]
+    fn test_field_error_context() {
+        let value = WideStruct {
+            a: 1,
+            b: 2,
+            c: "c".into(),
+            d: true,
+            e: 5,
+        };
+        let mut bytes = to_bytes(&value).unwrap();
+        // 1 byte for a + 2 for b + 8 + 1 for c
+        bytes[12] = 2;
+
+        let err = from_bytes::<WideStruct>(&bytes).unwrap_err();
+        assert_eq!(
+            err,
+            Error::FieldError {
+                index: 3,
+                tag: None,
+                error: Box::new(Error::InvalidBool(2))
+            }
+        );
+        assert!(err.to_string().starts_with("while decoding field 3: "));
+        assert_eq!(err.root_cause(), &Error::InvalidBool(2));
+
+        let mut bytes = to_bytes(&Outer {
+            id: 0,
+            inner: value,
+        })
+        .unwrap();
+        bytes[20] = 2;
+
+        let err = from_bytes::<Outer>(&bytes).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("while decoding field 1: while decoding field 3: "));
+        assert_eq!(err.root_cause(), &Error::InvalidBool(2));
+    }
+
+    #[cfg(not(feature = "no-unsized-seq"))]
+    struct UnsizedMap<'a>(&'a [(&'a str, u8)]);
+
+    #[cfg(not(feature = "no-unsized-seq"))]
+    impl<'a> Serialize for UnsizedMap<'a> {
+        fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            // a filtered iterator does not give an exact size hint
+            serializer.collect_map(self.0.iter().filter(|_| true).map(|(k, v)| (k, v)))
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn test_serialize_unsized_map() {
+        let entries = [("a", 1), ("b", 2), ("c", 3)];
+        let bytes = to_bytes(&UnsizedMap(&entries)).unwrap();
+
+        // entries count, not keys and values count
+        assert_eq!(bytes[..8], 3u64.to_be_bytes());
+
+        let res: std::collections::BTreeMap<String, u8> = from_bytes(&bytes).unwrap();
+        assert!(res.iter().map(|(k, v)| (k.as_str(), *v)).eq(entries));
+    }
+
+    #[test]
+    fn test_serialize_empty_collections() {
+        for empty in EMPTY_COLLECTIONS {
+            #[cfg(feature = "no-unsized-seq")]
+            if empty.len.is_none() {
+                assert!(matches!(
+                    to_bytes(&(&empty, 5u8)),
+                    Err(Error::UnknownSeqLength)
+                ));
+                continue;
+            }
+            // only the length, whether it was known or not
+            let bytes = to_bytes(&(&empty, 5u8)).unwrap();
+            assert_eq!(bytes, [0, 0, 0, 0, 0, 0, 0, 0, 5]);
+
+            let res: (Vec<u8>, u8) = from_bytes(&bytes).unwrap();
+            assert_eq!(res, (vec![], 5));
+            let res: (std::collections::BTreeMap<String, u8>, u8) = from_bytes(&bytes).unwrap();
+            assert_eq!(res, (Default::default(), 5));
+        }
+    }
+
+    // Counts the allocations made by the current thread, so the other tests running in parallel are not counted.
+    struct CountingAllocator;
+
+    std::thread_local! {
+        static ALLOCATIONS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+        static ALLOCATED_BYTES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    fn count_allocation(size: usize) {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        let _ = ALLOCATED_BYTES.try_with(|count| count.set(count.get() + size));
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            count_allocation(layout.size());
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(
+            &self,
+            ptr: *mut u8,
+            layout: std::alloc::Layout,
+            new_size: usize,
+        ) -> *mut u8 {
+            count_allocation(new_size);
+            std::alloc::System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn allocated_bytes() -> usize {
+        ALLOCATED_BYTES.with(|count| count.get())
+    }
+
+    struct UnsizedSeq<T>(Vec<T>);
+
+    impl<T: Serialize> Serialize for UnsizedSeq<T> {
+        fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            // a filtered iterator does not give an exact size hint
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn test_unsized_seq_scratch_buffer() {
+        let sized: Vec<Vec<Vec<u16>>> = (0..200)
+            .map(|i| vec![vec![i, i + 1], vec![], vec![i; 3]])
+            .collect();
+        let value: Vec<UnsizedSeq<UnsizedSeq<u16>>> = sized
+            .iter()
+            .map(|seqs| UnsizedSeq(seqs.iter().cloned().map(UnsizedSeq).collect()))
+            .collect();
+        let expected = to_bytes(&sized).unwrap();
+
+        let mut buff = vec![0; expected.len()];
+        let before = allocations();
+        let written = compact::to_buff(&value, &mut buff).unwrap().len();
+        let allocated = allocations() - before;
+
+        assert_eq!(buff[..written], expected);
+        // 800 sequences of unknown size, the scratch buffer only allocates when it grows.
+        assert!(allocated < 10, "{} allocations", allocated);
+    }
+
+    #[cfg(not(feature = "no-unsized-seq"))]
+    struct Failing;
+
+    #[cfg(not(feature = "no-unsized-seq"))]
+    impl Serialize for Failing {
+        fn serialize<S>(&self, _serializer: S) -> core::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            Err(serde::ser::Error::custom("failing"))
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn test_unsized_seq_error_recovery() {
+        let mut v: Vec<u8> = Vec::new();
+        let mut serializer = Serializer::new(&mut v);
+
+        let failing = UnsizedSeq(vec![UnsizedSeq(vec![Failing])]);
+        assert!(failing.serialize(&mut serializer).is_err());
+
+        let value = UnsizedSeq(vec![1u8, 2]);
+        value.serialize(&mut serializer).unwrap();
+
+        assert_eq!(v, to_bytes(&vec![1u8, 2]).unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn test_unsized_seq_aborted() {
+        // Fails after writing an element, without going through `SerializeSeq::end`.
+        struct AbortedSeq;
+
+        impl Serialize for AbortedSeq {
+            fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use serde::ser::SerializeSeq;
+                let mut seq = serializer.serialize_seq(None)?;
+                seq.serialize_element(&1u8)?;
+                Err(serde::ser::Error::custom("aborted"))
+            }
+        }
+
+        let mut output = Vec::new();
+        let mut writer = compact::Writer::new(&mut output);
+
+        assert!(writer.write(&AbortedSeq).is_err());
+        assert!(writer.write(&UnsizedSeq(vec![AbortedSeq])).is_err());
+        assert_eq!(writer.write(&5u8).unwrap(), 1);
+        assert_eq!(writer.write(&UnsizedSeq(vec![2u8])).unwrap(), 9);
+        assert_eq!(writer.total(), 10);
+
+        assert_eq!(output, [5, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsized-seq"))]
+    fn test_nested_unsized_seq_copies() {
+        let sized: Vec<Vec<Vec<u64>>> = (0..20)
+            .map(|i| {
+                (0..20)
+                    .map(|j| (0..50).map(|k| i * j * k).collect())
+                    .collect()
+            })
+            .collect();
+        let value = UnsizedSeq(
+            sized
+                .iter()
+                .map(|seqs| UnsizedSeq(seqs.iter().cloned().map(UnsizedSeq).collect()))
+                .collect(),
+        );
+        let expected = to_bytes(&sized).unwrap();
+
+        let mut buff = vec![0; expected.len()];
+        let before = allocated_bytes();
+        let written = compact::to_buff(&value, &mut buff).unwrap().len();
+        let allocated = allocated_bytes() - before;
+
+        assert_eq!(buff[..written], expected);
+        // Every level is written once in the scratch buffer, which doubles its capacity when it grows,
+        // so the sum of its allocations stays under 4 times the output size.
+        // Buffering each level separately copies the inner levels again in their parent and allocates around 9 times the output size.
+        assert!(allocated < 4 * written, "{} bytes allocated", allocated);
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Point(u8, u8, u8);
+
+    // Reads only the first 2 fields of a `Point`.
+    #[derive(Debug, PartialEq)]
+    struct Prefix(u8, u8);
+
+    impl<'de> Deserialize<'de> for Prefix {
+        fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_tuple_struct("Point", 3, PrefixVisitor)
+        }
+    }
+
+    struct PrefixVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for PrefixVisitor {
+        type Value = Prefix;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a Point")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let a = seq.next_element()?.unwrap_or_default();
+            let b = seq.next_element()?.unwrap_or_default();
+            Ok(Prefix(a, b))
+        }
+    }
+
+    #[test]
+    fn test_partial_seq_consumption() {
+        let bytes = to_bytes(&(Point(1, 2, 3), 4u8)).unwrap();
+        // the third field can't be skipped without knowing its type,
+        // the error is reported by the sequence instead of as trailing bytes.
+        let err = from_bytes::<(Prefix, u8)>(&bytes).unwrap_err();
+        assert_eq!(err, Error::UnconsumedElements(1));
+    }
+
+    #[test]
+    fn test_seq_access_deserializer() {
+        let bytes = to_bytes(&(vec![1u32, 2, 3, 4], 5u8)).unwrap();
+        let res: (HeadAndRest, u8) = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            res.0,
+            HeadAndRest {
+                head: 1,
+                rest: vec![2, 3, 4]
+            }
+        );
+        assert_eq!(res.1, 5);
+
+        let bytes = to_bytes(&vec![1u32]).unwrap();
+        let res: HeadAndRest = from_bytes(&bytes).unwrap();
+        assert!(res.rest.is_empty());
+    }
+
+    // bit by bit CRC-32, to check the table driven one
+    fn reference_crc32(bytes: &[u8]) -> u32 {
+        let mut crc = u32::MAX;
+        for &byte in bytes {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn test_to_array() {
+        let value = (300u16, "Hello");
+
+        // exact fit
+        let (buff, len) = to_array::<15, _>(&value).unwrap();
+        assert_eq!(len, 15);
+        assert_eq!(from_bytes::<(u16, &str)>(&buff), Ok(value));
+
+        let (buff, len) = to_array::<20, _>(&value).unwrap();
+        assert_eq!(len, 15);
+        assert_eq!(buff[len..], [0; 5]);
+        assert_eq!(from_bytes::<(u16, &str)>(&buff[..len]), Ok(value));
+
+        assert_eq!(
+            to_array::<14, _>(&value),
+            Err(Error::WriterError(EndOfBuff))
+        );
+        assert_eq!(to_array::<0, _>(&value), Err(Error::WriterError(EndOfBuff)));
+
+        let mut buff = [0; 15];
+        assert_eq!(to_array_ref(&value, &mut buff), Ok(15));
+        assert_eq!(buff, to_array::<15, _>(&value).unwrap().0);
+    }
+
+    #[test]
+    fn test_size_and_crc_writer() {
+        let mut writer = SizeAndCrcWriter::new();
+        (&mut writer).write_bytes(b"123456789").unwrap();
+        assert_eq!(writer.finish(), (9, 0xCBF4_3926));
+        assert_eq!(SizeAndCrcWriter::new().finish(), (0, 0));
+
+        let value = TestStruct {
+            a: 42,
+            b: "Hello, World!".into(),
+        };
+
+        let bytes = to_bytes(&value).unwrap();
+        let (size, crc) = get_serialized_size_and_crc(&value).unwrap();
+        assert_eq!(size, get_serialized_size(&value).unwrap());
+        assert_eq!(size, bytes.len());
+        assert_eq!(crc, reference_crc32(&bytes));
+
+        let bytes = any::to_bytes(&value).unwrap();
+        let (size, crc) = any::get_serialized_size_and_crc(&value).unwrap();
+        assert_eq!(size, any::get_serialized_size(&value).unwrap());
+        assert_eq!(size, bytes.len());
+        assert_eq!(crc, reference_crc32(&bytes));
+    }
+
+    #[test]
+    fn test_phantom_data_and_empty_struct() {
+        let bytes = to_bytes(&core::marker::PhantomData::<u32>).unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(from_bytes(&bytes), Ok(core::marker::PhantomData::<u32>));
+
+        let bytes = to_bytes(&Empty {}).unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(from_bytes(&bytes), Ok(Empty {}));
+
+        let value = WithPhantom {
+            a: 1,
+            marker: core::marker::PhantomData,
+            b: Empty {},
+        };
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes, [1]);
+        assert_eq!(from_bytes(&bytes), Ok(value));
+    }
+
+    #[test]
+    #[allow(invalid_from_utf8)]
+    fn test_error_codes() {
+        let invalid_str = core::str::from_utf8(&[0xFF]).unwrap_err();
+        let errors: [(Error<&str>, u32); 32] = [
+            (Error::WriterError("disk full"), 1),
+            (Error::Message("custom".into()), 2),
+            (Error::Eof, 5),
+            (Error::InvalidBool(2), 6),
+            (Error::InvalidChar(0xD800), 7),
+            (Error::InvalidStr(invalid_str), 8),
+            (Error::InvalidSize, 9),
+            (Error::InvalidOptionTag(2), 10),
+            (Error::TrailingBytes(1), 11),
+            (Error::Unimplemented("deserialize_any"), 12),
+            (Error::FormattingError, 13),
+            (
+                Error::TagParsingError(any::TagParsingError::InvalidTag(255)),
+                14,
+            ),
+            (
+                Error::SeqSizeMismatch {
+                    expected: 1,
+                    got: 2,
+                },
+                15,
+            ),
+            (Error::SchemaError(SchemaError::UnknownField(0)), 16),
+            (Error::ElementBudgetExceeded, 17),
+            (Error::DepthLimitExceeded, 18),
+            (Error::UnconsumedElements(1), 19),
+            (Error::ChecksumMismatch { offset: 4 }, 20),
+            (Error::AnyDenied, 21),
+            (Error::Cancelled, 22),
+            (Error::IndexOutOfRange { index: 2, len: 1 }, 23),
+            (Error::NeedMoreData, 24),
+            (
+                Error::NonCanonical {
+                    rule: CanonicalRule::UnsortedMapKeys,
+                    offset: 0,
+                },
+                25,
+            ),
+            (
+                Error::LengthExceedsInput {
+                    len: 3,
+                    remaining: 2,
+                },
+                26,
+            ),
+            (
+                Error::FieldHashCollision {
+                    first: "a",
+                    second: "b",
+                },
+                27,
+            ),
+            (
+                Error::VariantIndexTooLarge {
+                    index: 256,
+                    width: VariantIndexWidth::U8,
+                },
+                28,
+            ),
+            (
+                Error::NonCanonicalInteger {
+                    tag: any::Tag::U64,
+                    narrowest: any::Tag::U8,
+                    offset: 0,
+                },
+                29,
+            ),
+            (Error::MessageTooLarge { len: 5, max: 4 }, 30),
+            (Error::CompositeMapKey { tag: any::Tag::Seq }, 31),
+            (Error::TooManyFields { len: 256 }, 32),
+            (Error::DuplicateMapKey, 33),
+            (Error::Eof.in_field(3, None), 5),
+        ];
+        for (error, code) in errors {
+            assert_eq!(error.code(), code, "{:?}", error);
+        }
+
+        assert_eq!(from_bytes::<bool>(&[]).unwrap_err().code(), 5);
+        assert_eq!(from_bytes::<bool>(&[1, 0]).unwrap_err().code(), 11);
+        assert_eq!(any::from_bytes::<u8>(&[255]).unwrap_err().code(), 14);
+    }
+
+    #[test]
+    fn test_hybrid_writer() {
+        // 8 bytes of length then the elements
+        let exact = vec![7u8; 8];
+        let before = allocations();
+        let writer = compact::to_hybrid::<16, _>(&exact).unwrap();
+        assert_eq!(allocations(), before);
+        assert!(!writer.is_spilled());
+        assert_eq!(writer.as_slice(), to_bytes(&exact).unwrap());
+
+        let over = vec![7u8; 9];
+        let writer = compact::to_hybrid::<16, _>(&over).unwrap();
+        assert!(writer.is_spilled());
+        assert_eq!(writer.len(), 17);
+        assert_eq!(writer.as_slice(), to_bytes(&over).unwrap());
+        assert_eq!(writer.into_vec(), to_bytes(&over).unwrap());
+
+        // spilled in the middle of the value
+        let value = TestStruct {
+            a: 3,
+            b: "a string longer than the inline storage".into(),
+        };
+        let writer = compact::to_hybrid::<16, _>(&value).unwrap();
+        assert!(writer.is_spilled());
+        assert_eq!(from_bytes(&writer), Ok(value));
+
+        let before = allocations();
+        let writer = any::to_hybrid::<32, _>(&(1u8, "ab", Some(2i64))).unwrap();
+        assert_eq!(allocations(), before);
+        assert!(!writer.is_spilled());
+        assert_eq!(any::from_bytes(&writer), Ok((1u8, "ab", Some(2i64))));
+
+        let mut writer = HybridWriter::<4>::new();
+        assert!(writer.is_empty());
+        (&mut writer).write_bytes(&[1, 2, 3, 4]).unwrap();
+        assert!(!writer.is_spilled());
+        (&mut writer).write_bytes(&[5]).unwrap();
+        assert!(writer.is_spilled());
+        assert_eq!(&*writer, [1, 2, 3, 4, 5]);
+    }
+
+    struct Capped {
+        bytes: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Extend<u8> for Capped {
+        fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+            self.bytes.extend(iter);
+        }
+    }
+
+    impl BoundedExtend for Capped {
+        fn remaining_capacity(&self) -> usize {
+            self.capacity.saturating_sub(self.bytes.len())
+        }
+    }
+
+    #[test]
+    fn test_extend_writer() {
+        let value = TestStruct {
+            a: 1,
+            b: "foo".into(),
+        };
+        let mut writer = ExtendWriter(Vec::new());
+        let len = compact::to_writer(&value, &mut writer).unwrap();
+        any::to_writer(&value, &mut writer).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes[..len], to_bytes(&value).unwrap());
+        assert_eq!(bytes[len..], any::to_bytes(&value).unwrap());
+
+        let size = get_serialized_size(&value).unwrap();
+        let mut writer = CheckedExtendWriter(Capped {
+            bytes: Vec::new(),
+            capacity: size,
+        });
+        assert_eq!(compact::to_writer(&value, &mut writer), Ok(size));
+        assert_eq!(writer.0.remaining_capacity(), 0);
+        assert_eq!(
+            compact::to_writer(&1u8, &mut writer),
+            Err(Error::WriterError(EndOfBuff))
+        );
+        let capped = writer.into_inner();
+        assert_eq!(capped.bytes, to_bytes(&value).unwrap());
+
+        // nothing past the capacity is given to the container
+        let mut writer = CheckedExtendWriter(Capped {
+            bytes: Vec::new(),
+            capacity: size - 1,
+        });
+        assert_eq!(
+            compact::to_writer(&value, &mut writer),
+            Err(Error::WriterError(EndOfBuff))
+        );
+        assert!(writer.0.bytes.len() < size);
+    }
+
+    #[test]
+    fn test_buffer_pool() {
+        let value = TestStruct {
+            a: 1,
+            b: "foo".into(),
+        };
+        let pool = BufferPool::default();
+        let bytes = compact::to_pooled_bytes(&value, &pool).unwrap();
+        assert_eq!(*bytes, to_bytes(&value).unwrap());
+        let ptr = bytes.as_ptr();
+        assert!(pool.is_empty());
+        drop(bytes);
+        assert_eq!(pool.len(), 1);
+
+        // the same buffer is reused without allocating
+        let before = allocations();
+        let bytes = compact::to_pooled_bytes(&value, &pool).unwrap();
+        assert_eq!(allocations(), before);
+        assert_eq!(bytes.as_ptr(), ptr);
+        assert_eq!(from_bytes(&bytes), Ok(value));
+        // two buffers in use at the same time
+        let other = any::to_pooled_bytes(&[1u8, 2], &pool).unwrap();
+        assert_ne!(other.as_ptr(), ptr);
+        drop((bytes, other));
+        assert_eq!(pool.len(), 2);
+        let vec = any::to_pooled_bytes(&3u8, &pool).unwrap().into_vec();
+        assert_eq!(any::from_bytes(&vec), Ok(3u8));
+        assert_eq!(pool.len(), 1);
+
+        // the buffer of a failed serialization goes back to the pool
+        struct Failing;
+        impl Serialize for Failing {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                _: S,
+            ) -> core::result::Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("failing"))
+            }
+        }
+        assert!(compact::to_pooled_bytes(&(1u8, Failing), &pool).is_err());
+        assert_eq!(pool.len(), 1);
+
+        // the buffers over the limits are not kept
+        let pool = BufferPool::new(1, 8);
+        drop(compact::to_pooled_bytes(&[0u8; 16], &pool).unwrap());
+        assert!(pool.is_empty());
+        let first = compact::to_pooled_bytes(&1u8, &pool).unwrap();
+        let second = compact::to_pooled_bytes(&2u8, &pool).unwrap();
+        drop((first, second));
+        assert_eq!(pool.len(), 1);
+        pool.clear();
+        assert!(pool.is_empty());
+
+        // the pool of the thread, filled by a first value
+        drop(any::to_local_pooled_bytes(&(7u32, true)).unwrap());
+        let before = allocations();
+        let bytes = any::to_local_pooled_bytes(&(7u32, true)).unwrap();
+        assert_eq!(allocations(), before);
+        assert_eq!(any::from_bytes(&bytes), Ok((7u32, true)));
+    }
+
+    #[test]
+    fn test_encrypting_writer() {
+        use std::io::Read;
+
+        let value = (
+            TestStruct {
+                a: 7,
+                b: "secret".repeat(20),
+            },
+            TestEnum::Struct {
+                a: 0.25,
+                b: vec![1, 2, 3],
+            },
+        );
+        let plain = to_bytes(&value).unwrap();
+        assert!(plain.len() > 64);
+
+        let mut encrypted = Vec::new();
+        let mut writer = EncryptingWriter::new(&mut encrypted, XorCipher::new(b"k3y"));
+        let len = compact::to_writer(&value, &mut writer).unwrap();
+        assert_eq!(len, plain.len());
+        assert_eq!(encrypted.len(), plain.len());
+        assert_ne!(encrypted, plain);
+
+        let mut reader = DecryptingReader::new(&encrypted[..], XorCipher::new(b"k3y"));
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, plain);
+        assert_eq!(from_bytes(&decrypted), Ok(value));
+
+        // the keystream continues across the values
+        let mut encrypted = Vec::new();
+        let mut writer = EncryptingWriter::new(&mut encrypted, XorCipher::new(b"k3y"));
+        any::to_writer(&1u8, &mut writer).unwrap();
+        any::to_writer(&"ab", &mut writer).unwrap();
+        let mut cipher = XorCipher::new(b"k3y");
+        cipher.apply_keystream(&mut encrypted);
+        let mut deserializer = any::Deserializer::new(&encrypted);
+        assert_eq!(u8::deserialize(&mut deserializer), Ok(1));
+        assert_eq!(<&str>::deserialize(&mut deserializer), Ok("ab"));
+    }
+
+    #[test]
+    fn test_manual_reading() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Frame {
+            id: u32,
+            ok: bool,
+            ratio: f64,
+            name: String,
+            payload: ByteBuf,
+            samples: Vec<i16>,
+            next: TestEnum,
+        }
+
+        let frame = Frame {
+            id: 42,
+            ok: true,
+            ratio: -1.5,
+            name: "sensor".into(),
+            payload: ByteBuf(vec![1, 2, 3]),
+            samples: vec![-1, 300],
+            next: TestEnum::NewType(9),
+        };
+        let bytes = to_bytes(&frame).unwrap();
+
+        let mut deserializer = Deserializer::new(&bytes);
+        assert_eq!(deserializer.read_u32(), Ok(frame.id));
+        assert_eq!(deserializer.read_bool(), Ok(frame.ok));
+        assert_eq!(deserializer.read_f64(), Ok(frame.ratio));
+        assert_eq!(deserializer.read_str(), Ok("sensor"));
+        let len = deserializer.read_len().unwrap();
+        assert_eq!(deserializer.read_bytes(len), Ok(&frame.payload.0[..]));
+        let len = deserializer.read_len().unwrap();
+        let samples: Vec<i16> = (0..len).map(|_| deserializer.read_i16().unwrap()).collect();
+        assert_eq!(samples, frame.samples);
+
+        // the rest through serde, from the deserializer or its remaining input
+        let rest = deserializer.remaining();
+        assert_eq!(
+            from_bytes_partial(rest),
+            Ok((TestEnum::NewType(9), &[][..]))
+        );
+        assert_eq!(
+            TestEnum::deserialize(&mut deserializer),
+            Ok(TestEnum::NewType(9))
+        );
+        assert!(deserializer.remaining().is_empty());
+        assert_eq!(deserializer.read_u8(), Err(Error::Eof));
+
+        // skipping to a field
+        let mut deserializer = Deserializer::new(&bytes);
+        deserializer.skip(4 + 1 + 8).unwrap();
+        assert_eq!(deserializer.read_str(), Ok("sensor"));
+        assert_eq!(
+            Deserializer::new(&[2]).read_bool(),
+            Err(Error::InvalidBool(2))
+        );
+        assert_eq!(Deserializer::new(&[0, 1]).read_u32(), Err(Error::Eof));
+
+        let bytes = [any::Tag::U8.into(), 5, 1];
+        assert_eq!(any::from_bytes_partial::<u8>(&bytes), Ok((5, &[1][..])));
+    }
+
+    #[test]
+    fn test_from_bytes_with_limit() {
+        let value = TestStruct {
+            a: 1,
+            b: "foo".into(),
+        };
+        let bytes = to_bytes(&value).unwrap();
+        let len = bytes.len();
+        assert_eq!(
+            compact::from_bytes_with_limit::<TestStruct>(&bytes, len - 1),
+            Err(Error::MessageTooLarge { len, max: len - 1 })
+        );
+        assert_eq!(compact::from_bytes_with_limit(&bytes, len), Ok(value));
+
+        // checked before decoding, even an invalid input is only too large
+        let garbage = [255; 64];
+        let err = any::from_bytes_with_limit::<TestStruct>(&garbage, 16).unwrap_err();
+        assert_eq!(err, Error::MessageTooLarge { len: 64, max: 16 });
+        assert_eq!(
+            err.to_string(),
+            "The message is 64 bytes long, more than the limit of 16 bytes"
+        );
+        let bytes = any::to_bytes(&TestEnum::NewType(3)).unwrap();
+        assert_eq!(
+            any::from_bytes_with_limit(&bytes, 64),
+            Ok(TestEnum::NewType(3))
+        );
+    }
+
+    const LARGE_LEN: usize = 100_000;
+
+    fn cancel_halfway(bytes: usize) -> ControlFlow<()> {
+        if bytes >= LARGE_LEN * 8 / 2 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let value: Vec<u64> = (0..LARGE_LEN as u64).collect();
+        let full = to_bytes(&value).unwrap();
+
+        // the callback can keep its own state
+        let mut reports = 0;
+        let mut v: Vec<u8> = Vec::new();
+        let mut serializer = Serializer::new(&mut v).with_progress(4096, |_| {
+            reports += 1;
+            ControlFlow::Continue(())
+        });
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(v, full);
+        assert_eq!(reports, full.len() / 4096);
+
+        let mut v: Vec<u8> = Vec::new();
+        let mut serializer = Serializer::new(&mut v).with_progress(4096, cancel_halfway);
+        let err = value.serialize(&mut serializer).unwrap_err();
+        assert!(matches!(err, Error::Cancelled));
+        assert!(v.len() >= full.len() / 2 && v.len() < full.len());
+        assert!(full.starts_with(&v));
+
+        let mut deserializer = Deserializer::new(&full).with_progress(4096, cancel_halfway);
+        let err = Vec::<u64>::deserialize(&mut deserializer).unwrap_err();
+        assert_eq!(err, Error::Cancelled);
+
+        // cancelled from outside of the callback
+        let cancelled = core::cell::Cell::new(false);
+        let mut deserializer = Deserializer::new(&full).with_progress(4096, |_| {
+            if cancelled.get() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        cancelled.set(true);
+        let err = Vec::<u64>::deserialize(&mut deserializer).unwrap_err();
+        assert_eq!(err, Error::Cancelled);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Login {
+        user: String,
+        password: Option<String>,
+        session: Session,
+        token: Option<u64>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Session {
+        id: u32,
+        token: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct PublicLogin<'a> {
+        user: &'a str,
+        session: Session,
+    }
+
+    fn hide_secrets(name: &'static str, field: &'static str) -> FieldAction {
+        match (name, field) {
+            ("Login", "password") => FieldAction::Redact,
+            (_, "token") => FieldAction::Skip,
+            _ => FieldAction::Keep,
+        }
+    }
+
+    fn redact_secrets(name: &'static str, field: &'static str) -> FieldAction {
+        match hide_secrets(name, field) {
+            FieldAction::Skip => FieldAction::Redact,
+            action => action,
+        }
+    }
+
+    fn login() -> Login {
+        Login {
+            user: "admin".into(),
+            password: Some("hunter2".into()),
+            session: Session {
+                id: 3,
+                token: Some(42),
+            },
+            token: Some(7),
+        }
+    }
+
+    #[test]
+    fn test_field_filter() {
+        let serialize = |filter: FieldFilter| {
+            let config = SerializerConfig::new().field_filter(filter);
+            let mut v: Vec<u8> = Vec::new();
+            login()
+                .serialize(&mut Serializer::with_config(&mut v, config))
+                .unwrap();
+            v
+        };
+
+        // the redacted fields are deserialized as `None`
+        let bytes = serialize(redact_secrets);
+        let res: Login = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            res,
+            Login {
+                user: "admin".into(),
+                password: None,
+                session: Session { id: 3, token: None },
+                token: None,
+            }
+        );
+
+        // the skipped fields are redacted to keep the position of the next fields,
+        // the filter is called with the name of the nested structs
+        assert_eq!(serialize(hide_secrets), bytes);
+        let public = PublicLogin {
+            user: "admin",
+            session: Session { id: 3, token: None },
+        };
+        let mut expected = to_bytes(&public).unwrap();
+        // `None` for the password, after the user
+        expected.insert(8 + 5, 0);
+        // and for the token of the login
+        expected.push(0);
+        assert_eq!(bytes, expected);
+    }
+
+    #[derive(Default)]
+    struct FlushRecorder {
+        bytes: Vec<u8>,
+        flushed_len: Option<usize>,
+    }
+
+    impl std::io::Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushed_len = Some(self.bytes.len());
+            Ok(())
+        }
+    }
+
+    fn to_tagged_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let config = SerializerConfig::new().top_level_tag(true);
+        let written_bytes = value
+            .serialize(&mut Serializer::with_config(&mut bytes, config))
+            .unwrap();
+        assert_eq!(written_bytes, bytes.len());
+        bytes
+    }
+
+    #[test]
+    fn test_top_level_tag() {
+        let config = DeserializerConfig::new().top_level_tag(true);
+
+        let bytes = to_tagged_bytes(&5u32);
+        assert_eq!(bytes[0], any::Tag::U32.into());
+        assert_eq!(bytes[1..], to_bytes(&5u32).unwrap());
+        assert_eq!(from_bytes_with_config::<u32>(&bytes, config), Ok(5));
+        assert_eq!(
+            from_bytes_with_config::<String>(&bytes, config),
+            Err(Error::TagParsingError(any::TagParsingError::unexpected(
+                "String",
+                any::Tag::U32
+            )))
+        );
+        // without the tag the bytes are decoded as a string length
+        assert!(from_bytes::<String>(&to_bytes(&5u32).unwrap()).is_err());
+
+        let value = TestStruct {
+            a: 1,
+            b: "foo".into(),
+        };
+        let bytes = to_tagged_bytes(&value);
+        assert_eq!(bytes[0], any::Tag::Struct.into());
+        assert_eq!(from_bytes_with_config(&bytes, config), Ok(value));
+        assert!(from_bytes_with_config::<(usize, String)>(&bytes, config).is_err());
+
+        // the tag is written before the buffered sequences of unknown length
+        #[cfg(not(feature = "no-unsized-seq"))]
+        {
+            // only the top-level value is tagged
+            let value = (Some(TestEnum::NewType(2)), UnsizedSeq(vec![3u16, 4]));
+            let bytes = to_tagged_bytes(&value);
+            assert_eq!(bytes[0], any::Tag::Tuple.into());
+            assert_eq!(bytes[1..], to_bytes(&value).unwrap());
+            let res: (Option<TestEnum>, Vec<u16>) = from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(res, (Some(TestEnum::NewType(2)), vec![3, 4]));
+
+            let bytes = to_tagged_bytes(&UnsizedSeq(vec!['a', 'b']));
+            assert_eq!(bytes[0], any::Tag::Seq.into());
+            assert_eq!(
+                from_bytes_with_config::<Vec<char>>(&bytes, config),
+                Ok(vec!['a', 'b'])
+            );
+        }
+
+        let bytes = to_tagged_bytes(&TestEnum::Unit);
+        assert_eq!(bytes[0], any::Tag::UnitVariant.into());
+        assert_eq!(
+            from_bytes_with_config::<TestEnum>(&bytes, config),
+            Ok(TestEnum::Unit)
+        );
+        assert!(from_bytes_with_config::<TestEnum>(&[255, 0, 0, 0, 0], config).is_err());
+    }
+
+    #[test]
+    fn test_variant_index_width() {
+        fn to_bytes_with_width<T: Serialize>(
+            value: &T,
+            width: VariantIndexWidth,
+        ) -> Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            let config = SerializerConfig::new().variant_index_width(width);
+            value.serialize(&mut Serializer::with_config(
+                write::VecWriter(&mut bytes),
+                config,
+            ))?;
+            Ok(bytes)
+        }
+
+        let values = [
+            TestEnum::Unit,
+            TestEnum::NewType(2),
+            TestEnum::Tuple(1.5, "foo".into()),
+            TestEnum::Struct {
+                a: 0.25,
+                b: vec![3, 4],
+            },
+        ];
+        for width in [
+            VariantIndexWidth::U8,
+            VariantIndexWidth::U16,
+            VariantIndexWidth::U32,
+        ] {
+            let config = DeserializerConfig::new().variant_index_width(width);
+            for (index, value) in values.iter().enumerate() {
+                let bytes = to_bytes_with_width(value, width).unwrap();
+                let default_bytes = to_bytes(value).unwrap();
+                assert_eq!(bytes.len(), default_bytes.len() - 4 + width.bytes());
+                assert_eq!(
+                    bytes[..width.bytes()],
+                    (index as u32).to_be_bytes()[4 - width.bytes()..]
+                );
+                assert_eq!(bytes[width.bytes()..], default_bytes[4..]);
+                assert_eq!(from_bytes_with_config(&bytes, config).as_ref(), Ok(value));
+            }
+        }
+
+        let bytes = to_bytes_with_width(&TestEnum::Unit, VariantIndexWidth::U8).unwrap();
+        assert_eq!(bytes, [0]);
+        let bytes =
+            to_bytes_with_width(&Some(TestEnum::NewType(7)), VariantIndexWidth::U8).unwrap();
+        assert_eq!(bytes, [1, 1, 7]);
+
+        // the indices of serde are `u32`, a manual implementation can give any of them
+        struct Variant(u32);
+
+        impl Serialize for Variant {
+            fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_unit_variant("Variant", self.0, "Variant")
+            }
+        }
+
+        assert_eq!(
+            to_bytes_with_width(&Variant(255), VariantIndexWidth::U8),
+            Ok(vec![255])
+        );
+        assert_eq!(
+            to_bytes_with_width(&Variant(256), VariantIndexWidth::U8),
+            Err(Error::VariantIndexTooLarge {
+                index: 256,
+                width: VariantIndexWidth::U8
+            })
+        );
+        assert_eq!(
+            to_bytes_with_width(&Variant(65536), VariantIndexWidth::U16),
+            Err(Error::VariantIndexTooLarge {
+                index: 65536,
+                width: VariantIndexWidth::U16
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_writer_complete() {
+        let value = (
+            TestStruct {
+                a: 1,
+                b: "foo".into(),
+            },
+            UnsizedSeq(vec![2u8, 3]),
+        );
+        let mut writer = FlushRecorder::default();
+        #[cfg(not(feature = "no-unsized-seq"))]
+        {
+            let len = to_writer_complete(&value, &mut writer).unwrap();
+            assert_eq!(len, writer.bytes.len());
+            assert_eq!(writer.flushed_len, Some(len));
+            let res: (TestStruct, Vec<u8>) = from_bytes(&writer.bytes).unwrap();
+            assert_eq!(res.0, value.0);
+            assert_eq!(res.1, value.1 .0);
+        }
+        // the error is returned before flushing
+        #[cfg(feature = "no-unsized-seq")]
+        {
+            assert!(matches!(
+                to_writer_complete(&value, &mut writer),
+                Err(Error::UnknownSeqLength)
+            ));
+            assert_eq!(writer.flushed_len, None);
+        }
+
+        let mut writer = FlushRecorder::default();
+        let len = any::to_writer_complete(&value, &mut writer).unwrap();
+        assert_eq!(writer.flushed_len, Some(len));
+        let res: (TestStruct, Vec<u8>) = any::from_bytes(&writer.bytes).unwrap();
+        assert_eq!(res.0, value.0);
+        assert_eq!(res.1, value.1 .0);
+    }
+
+    #[test]
+    fn test_sets() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let ordered: BTreeSet<String> = ["b", "c", "a"].into_iter().map(Into::into).collect();
+        let bytes = to_bytes(&ordered).unwrap();
+        // written in order, as the sorted sequence
+        assert_eq!(bytes, to_bytes(&["a", "b", "c"].to_vec()).unwrap());
+        assert_eq!(from_bytes(&bytes), Ok(ordered.clone()));
+        let bytes = any::to_bytes(&ordered).unwrap();
+        assert_eq!(bytes, any::to_bytes(&["a", "b", "c"].to_vec()).unwrap());
+        assert_eq!(any::from_bytes(&bytes), Ok(ordered.clone()));
+        assert_eq!(any::from_bytes_canonical(&bytes), Ok(ordered));
+
+        // the order of a `HashSet` is not deterministic, only the content is compared
+        let unordered: HashSet<u32> = (0..100).map(|i| i * 7).collect();
+        let bytes = to_bytes(&unordered).unwrap();
+        assert_eq!(from_bytes(&bytes), Ok(unordered.clone()));
+        let bytes = any::to_bytes(&unordered).unwrap();
+        assert_eq!(any::from_bytes(&bytes), Ok(unordered.clone()));
+        let decoded: Vec<u32> = any::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), unordered.len());
+        assert!(decoded.iter().all(|i| unordered.contains(i)));
+
+        // sets of unknown length, through an iterator without a size hint
+        let seq = UnsizedSeq(unordered.iter().copied().collect());
+        #[cfg(not(feature = "no-unsized-seq"))]
+        {
+            let bytes = to_bytes(&seq).unwrap();
+            assert_eq!(from_bytes(&bytes), Ok(unordered.clone()));
+        }
+        #[cfg(feature = "no-unsized-seq")]
+        assert!(matches!(to_bytes(&seq), Err(Error::UnknownSeqLength)));
+        let bytes = any::to_bytes(&seq).unwrap();
+        assert_eq!(any::from_bytes(&bytes), Ok(unordered));
+    }
+
+    #[test]
+    fn test_narrow_len() {
+        use crate::de::narrow_len;
+
+        // `u16` stands for the `usize` of a 16 bits target
+        assert_eq!(narrow_len::<u16>(u16::MAX.into()), Ok(u16::MAX));
+        assert_eq!(narrow_len::<u16>(1 << 16), Err(Error::InvalidSize));
+        assert_eq!(narrow_len::<u32>(u64::MAX), Err(Error::InvalidSize));
+        assert_eq!(narrow_len::<usize>(7), Ok(7));
+    }
+
+    // The lengths of every kind of collection fail the same way when they don't fit in a `usize`.
+    #[test]
+    #[cfg(not(target_pointer_width = "64"))]
+    fn test_len_overflow() {
+        let len = (u64::from(u32::MAX) + 1).to_be_bytes();
+        let tagged = |tag: any::Tag| [&[tag.into()][..], &len].concat();
+
+        assert_eq!(from_bytes::<Vec<u8>>(&len), Err(Error::InvalidSize));
+        assert_eq!(from_bytes::<&str>(&len), Err(Error::InvalidSize));
+        assert_eq!(from_bytes::<&[u8]>(&len), Err(Error::InvalidSize));
+        assert_eq!(
+            from_bytes::<std::collections::HashMap<u8, u8>>(&len),
+            Err(Error::InvalidSize)
+        );
+        for tag in [
+            any::Tag::Seq,
+            any::Tag::Map,
+            any::Tag::String,
+            any::Tag::ByteArray,
+        ] {
+            assert_eq!(
+                any::from_bytes::<serde::de::IgnoredAny>(&tagged(tag)),
+                Err(Error::InvalidSize)
+            );
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let first = TestStruct {
+            a: 1,
+            b: "foo".into(),
+        };
+        let mut output = Vec::new();
+        let mut writer = compact::Writer::new(&mut output);
+        let len = get_serialized_size(&first).unwrap();
+        assert_eq!(writer.write(&first).unwrap(), len);
+        writer.write(&TestEnum::NewType(2)).unwrap();
+        writer.write(&[3u16, 4]).unwrap();
+        let total = writer.total();
+        assert_eq!(total, output.len());
+
+        let mut deserializer = Deserializer::new(&output);
+        assert_eq!(TestStruct::deserialize(&mut deserializer), Ok(first));
+        assert_eq!(
+            TestEnum::deserialize(&mut deserializer),
+            Ok(TestEnum::NewType(2))
+        );
+        assert_eq!(<[u16; 2]>::deserialize(&mut deserializer), Ok([3, 4]));
+        assert_eq!(deserializer.end(), Ok(()));
+
+        // every value gets its top-level tag
+        let config = SerializerConfig::new().top_level_tag(true);
+        let mut output = Vec::new();
+        let mut writer = compact::Writer::with_config(&mut output, config);
+        writer.write(&1u8).unwrap();
+        writer.write(&2u8).unwrap();
+        assert_eq!(writer.total(), 4);
+        assert_eq!(output, [any::Tag::U8.into(), 1, any::Tag::U8.into(), 2]);
+
+        let mut writer = any::Writer::new(Vec::new());
+        writer.write(&TestEnum::Unit).unwrap();
+        writer.write("bar").unwrap();
+        writer.write(&Some(5i8)).unwrap();
+        let total = writer.total();
+        let output = writer.into_inner();
+        assert_eq!(total, output.len());
+
+        let mut deserializer = any::Deserializer::new(&output);
+        assert_eq!(TestEnum::deserialize(&mut deserializer), Ok(TestEnum::Unit));
+        assert_eq!(<&str>::deserialize(&mut deserializer), Ok("bar"));
+        assert_eq!(Option::<i8>::deserialize(&mut deserializer), Ok(Some(5)));
+        assert!(deserializer.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_for_each_element() {
+        let values: Vec<u32> = (0..1000).collect();
+        let expected: u32 = values.iter().sum();
+        let bytes = to_bytes(&values).unwrap();
+        let mut sum = 0;
+        let before = allocations();
+        compact::for_each_element(&bytes, |n: u32| {
+            sum += n;
+            Ok::<_, core::convert::Infallible>(())
+        })
+        .unwrap();
+        assert_eq!(allocations(), before);
+        assert_eq!(sum, expected);
+
+        // the error of the callback stops the decoding, and is given back without allocating
+        #[derive(Debug, PartialEq)]
+        struct TooLarge(u32);
+
+        let mut seen = 0;
+        let before = allocations();
+        let res = compact::for_each_element(&bytes, |n: u32| {
+            seen += 1;
+            if n == 10 {
+                return Err(TooLarge(n));
+            }
+            Ok(())
+        });
+        assert_eq!(allocations(), before);
+        assert_eq!(res, Err(ForEachError::Callback(TooLarge(10))));
+        assert_eq!(seen, 11);
+        // also on the last element, when there is nothing left to decode
+        let res = compact::for_each_element(&bytes, |n: u32| match n {
+            999 => Err(TooLarge(n)),
+            _ => Ok(()),
+        });
+        assert_eq!(res, Err(ForEachError::Callback(TooLarge(999))));
+
+        let bytes = any::to_bytes(&UnsizedSeq(vec!["a", "b"])).unwrap();
+        let mut strings = Vec::new();
+        any::for_each_element(&bytes, |s: &str| {
+            strings.push(s);
+            Ok::<_, core::convert::Infallible>(())
+        })
+        .unwrap();
+        assert_eq!(strings, ["a", "b"]);
+        let res = any::for_each_element(&bytes, |s: &str| match s {
+            "a" => Err(TooLarge(0)),
+            _ => Ok(()),
+        });
+        assert_eq!(res, Err(ForEachError::Callback(TooLarge(0))));
+        let res = any::for_each_element(&bytes, |_: u8| Ok::<_, TooLarge>(()));
+        assert!(matches!(res, Err(ForEachError::Deserialize(_))));
+    }
+
+    #[test]
+    fn test_batch() {
+        let values = [
+            TestEnum::Unit,
+            TestEnum::NewType(1),
+            TestEnum::Tuple(0.5, "foo".into()),
+            TestEnum::Struct {
+                a: 2.0,
+                b: vec![3, 4],
+            },
+        ];
+        let (bytes, ranges) = compact::to_bytes_batch(&values).unwrap();
+        assert_eq!(ranges.len(), values.len());
+        assert_eq!(ranges.first().map(|range| range.start), Some(0));
+        assert_eq!(ranges.last().map(|range| range.end), Some(bytes.len()));
+        for (value, range) in values.iter().zip(&ranges) {
+            assert_eq!(
+                compact::from_bytes(&bytes[range.clone()]).as_ref(),
+                Ok(value)
+            );
+        }
+        let selected = [ranges[3].clone(), ranges[1].clone()];
+        let decoded: Vec<TestEnum> = compact::from_bytes_batch(&bytes, &selected).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((&decoded[0], &decoded[1]), (&values[3], &values[1]));
+        // a range past the end of the input
+        let selected = [ranges[0].clone(), bytes.len()..bytes.len() + 1];
+        assert_eq!(
+            compact::from_bytes_batch::<TestEnum>(&bytes, &selected),
+            Err(Error::Eof)
+        );
+
+        let mut output = Vec::new();
+        let ranges = any::to_writer_batch(&values, &mut output).unwrap();
+        let decoded: Vec<TestEnum> = any::from_bytes_batch(&output, &ranges).unwrap();
+        assert_eq!(decoded, values);
+        assert_eq!(any::to_bytes_batch(&values), Ok((output, ranges)));
+
+        let (bytes, ranges) = any::to_bytes_batch::<TestEnum, _>(&[]).unwrap();
+        assert!(bytes.is_empty() && ranges.is_empty());
+        assert_eq!(
+            any::from_bytes_batch::<TestEnum>(&bytes, &ranges),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn test_erased() {
+        let values: [Box<dyn erased_serde::Serialize>; 3] = [
+            Box::new(TestStruct {
+                a: 4,
+                b: "foo".into(),
+            }),
+            Box::new(TestEnum::Tuple(0.5, "bar".into())),
+            Box::new(Some(vec![1u8, 2])),
+        ];
+
+        let erased = compact::to_bytes_erased(values[0].as_ref()).unwrap();
+        let first = TestStruct {
+            a: 4,
+            b: "foo".into(),
+        };
+        assert_eq!(erased, to_bytes(&first).unwrap());
+        let mut deserializer = Deserializer::new(&erased);
+        let res: TestStruct = erased_serde::deserialize(&mut deserializer.erased()).unwrap();
+        assert_eq!(res, first);
+        assert!(deserializer.remaining().is_empty());
+
+        let mut output = Vec::new();
+        for value in &values {
+            any::to_writer_erased(value.as_ref(), &mut output).unwrap();
+        }
+        let mut expected = any::to_bytes(&first).unwrap();
+        expected.extend(any::to_bytes(&TestEnum::Tuple(0.5, "bar".into())).unwrap());
+        expected.extend(any::to_bytes(&Some(vec![1u8, 2])).unwrap());
+        assert_eq!(output, expected);
+
+        let mut deserializer = any::Deserializer::new(&output);
+        let res: TestStruct = erased_serde::deserialize(&mut deserializer.erased()).unwrap();
+        assert_eq!(res, first);
+        let res: TestEnum = erased_serde::deserialize(&mut deserializer.erased()).unwrap();
+        assert_eq!(res, TestEnum::Tuple(0.5, "bar".into()));
+        let res: Option<Vec<u8>> = erased_serde::deserialize(&mut deserializer.erased()).unwrap();
+        assert_eq!(res, Some(vec![1, 2]));
+        assert!(deserializer.remaining().is_empty());
+
+        // the errors keep their message
+        let mut deserializer = any::Deserializer::new(&output);
+        let err = erased_serde::deserialize::<u8>(&mut deserializer.erased()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            any::from_bytes::<u8>(&output).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_root_functions() {
+        let value = TestStruct {
+            a: 3,
+            b: "foo".into(),
+        };
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(bytes, compact::to_bytes(&value).unwrap());
+        assert_eq!(crate::from_bytes::<TestStruct>(&bytes), Ok(value));
+        assert_eq!(
+            crate::get_serialized_size(&(1u8, 2u16)),
+            compact::get_serialized_size(&(1u8, 2u16))
+        );
+        let (buff, len) = crate::to_array::<8, _>(&7u32).unwrap();
+        assert_eq!(&buff[..len], [0, 0, 0, 7]);
+        let mut deserializer = crate::Deserializer::new(&buff[..len]);
+        assert_eq!(u32::deserialize(&mut deserializer), Ok(7));
+    }
+
+    #[test]
+    fn test_prelude() {
+        use crate::prelude::*;
+
+        let bytes = tagged::to_bytes(&ByteBuf(vec![1, 2])).unwrap();
+        let config = DeserializerConfig::new().str_coercion(StrCoercion::Utf8);
+        let res: Result<String> = tagged::from_bytes_with_config(&bytes, config);
+        assert_eq!(res, Ok("\u{1}\u{2}".to_string()));
+        let mut buff = [0; 4];
+        let mut writer = BuffWriter::new(&mut buff);
+        assert_eq!(compact::to_writer_complete(&1u16, &mut writer), Ok(2));
+    }
+
+    #[test]
+    fn test_wrong_format_hint() {
+        let value = (12u32, "foo".to_string());
+
+        let tagged = any::to_bytes(&value).unwrap();
+        let err = compact::from_bytes::<(u32, String)>(&tagged).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::WrongFormat {
+                likely: Format::Tagged,
+                ..
+            }
+        ));
+        assert!(err
+            .to_string()
+            .ends_with("(the input looks like it was serialized with the tagged format)"));
+        assert_eq!(err.code(), err.root_cause().code());
+
+        let compact = to_bytes(&vec![value.clone()]).unwrap();
+        let err = any::from_bytes::<Vec<(u32, String)>>(&compact).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::WrongFormat {
+                likely: Format::Compact,
+                ..
+            }
+        ));
+
+        // a tagged value of the wrong type is not a hint
+        let err = any::from_bytes::<u8>(&tagged).unwrap_err();
+        assert!(matches!(err, Error::TagParsingError(_)));
+        // neither are the limits
+        let config = DeserializerConfig::new().max_depth(0);
+        let err = compact::from_bytes_with_config::<(u32, String)>(&tagged, config).unwrap_err();
+        assert_eq!(err, Error::DepthLimitExceeded);
+        // even in a field
+        #[derive(Debug, Deserialize)]
+        struct Outer {
+            _a: u8,
+            _inner: (u8, u8),
+        }
+        let nested = any::to_bytes(&(1u8, (2u8, 3u8))).unwrap();
+        let config = DeserializerConfig::new().max_depth(1);
+        let err = compact::from_bytes_with_config::<Outer>(&nested, config).unwrap_err();
+        assert!(matches!(err, Error::FieldError { index: 1, .. }));
+        assert_eq!(err.root_cause(), &Error::DepthLimitExceeded);
+        assert!(!matches!(err, Error::WrongFormat { .. }));
+        // nor the errors of the types
+        let bytes = any::to_bytes(&0u8).unwrap();
+        let err = compact::from_bytes::<(u8, core::num::NonZeroU8)>(&bytes).unwrap_err();
+        assert!(matches!(err, Error::Message(_)));
+    }
+
+    #[test]
+    fn test_to_bytes_exact_capacity() {
+        let value: Vec<(u64, String, Option<Vec<u8>>)> = (0..10_000)
+            .map(|i| (i, i.to_string(), (i % 3 == 0).then(|| vec![i as u8; 5])))
+            .collect();
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes.len(), bytes.capacity());
+        assert_eq!(
+            from_bytes::<Vec<(u64, String, Option<Vec<u8>>)>>(&bytes).unwrap(),
+            value
+        );
+
+        let bytes = any::to_bytes(&value).unwrap();
+        assert_eq!(bytes.len(), bytes.capacity());
+    }
+
+    #[test]
+    fn test_to_writer() {
+        let value = (42u8, "hello");
+        let mut buff = [0; 64];
+        let mut writer = BuffWriter::new(&mut buff);
+        let written = compact::to_writer(&value, &mut writer).unwrap();
+        let written = written + tagged::to_writer(&value, &mut writer).unwrap();
+        assert_eq!(writer.len(), written);
+        let (compact_bytes, tagged_bytes) = buff[..written].split_at(1 + 8 + 5);
+        assert_eq!(from_bytes::<(u8, &str)>(compact_bytes).unwrap(), value);
+        assert_eq!(
+            tagged::from_bytes::<(u8, &str)>(tagged_bytes).unwrap(),
+            value
+        );
+
+        let mut output = Vec::new();
+        let written = compact::to_writer(&value, &mut output).unwrap();
+        assert_eq!(output.len(), written);
+        assert_eq!(output, compact_bytes);
+    }
+
+    #[test]
+    fn test_writer_reserve() {
+        let value: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        let size = get_serialized_size(&value).unwrap();
+        let mut output = Vec::new();
+        let mut writer = crate::VecWriter::new(&mut output);
+        writer.reserve(size);
+        let capacity = writer.get_ref().capacity();
+        assert!(capacity >= size);
+        compact::to_writer(&value, writer).unwrap();
+        assert_eq!(output.len(), size);
+        assert_eq!(output.capacity(), capacity);
+
+        // `to_bytes` reserves the serialized size
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes.capacity(), bytes.len());
+        let bytes = any::to_bytes(&value).unwrap();
+        assert_eq!(bytes.capacity(), bytes.len());
+
+        // the default does nothing
+        let mut buff = [0; 8];
+        let mut writer = BuffWriter::new(&mut buff);
+        (&mut writer).reserve(1024);
+        assert_eq!(writer.len(), 0);
+    }
+
+    #[test]
+    fn test_io_adapter() {
+        use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+        use std::io::{ErrorKind, Read};
+
+        let value = TestStruct {
+            a: 42,
+            b: "Hello, World!".repeat(16),
+        };
+
+        let mut buff = [0; 256];
+        let mut writer = BuffWriter::new(&mut buff);
+        let mut encoder = DeflateEncoder::new(IoAdapter::new(&mut writer), Compression::default());
+        compact::to_writer(&value, &mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let compressed = writer.get();
+        assert!(compressed.len() < get_serialized_size(&value).unwrap());
+
+        let mut bytes = Vec::new();
+        DeflateDecoder::new(compressed)
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(from_bytes::<TestStruct>(&bytes), Ok(value));
+
+        let mut buff = [0; 4];
+        let mut writer = BuffWriter::new(&mut buff);
+        let err = std::io::Write::write_all(&mut IoAdapter::new(&mut writer), &[0; 8]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WriteZero);
+    }
 }
@@ -0,0 +1,229 @@
+//! Serialize a `Vec<Option<T>>` as a bitmap of the present values followed by only the `Some` values.
+//!
+//! Each element of an optional vector is serialized with its own tag, a byte in the compact format
+//! and a tag in the `any` format, so a sparse vector mostly made of `None` spends most of its bytes on tags.
+//! Use `#[serde(with = "serde_bin::option_vec")]` to serialize it as a tuple of the number of elements,
+//! a byte array with one bit per element set for the `Some` (least significant bit first),
+//! and a tuple of the `Some` values.
+//!
+//! This costs a constant header of about 16 bytes in the compact format, so it only pays off for vectors
+//! with enough `None`.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bytes::{ByteBuf, Bytes};
+
+pub fn serialize<T, S>(values: &[Option<T>], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    let mut bitmap = vec![0u8; values.len().div_ceil(8)];
+    for (index, value) in values.iter().enumerate() {
+        if value.is_some() {
+            bitmap[index / 8] |= 1 << (index % 8);
+        }
+    }
+    let mut tuple = serializer.serialize_tuple(3)?;
+    tuple.serialize_element(&(values.len() as u64))?;
+    tuple.serialize_element(&Bytes(&bitmap))?;
+    tuple.serialize_element(&PresentValues(values))?;
+    tuple.end()
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_tuple(3, OptionVecVisitor(PhantomData))
+}
+
+// The `Some` values, as a tuple as their number is given by the bitmap.
+struct PresentValues<'a, T>(&'a [Option<T>]);
+
+impl<'a, T: Serialize> Serialize for PresentValues<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let len = self.0.iter().filter(|value| value.is_some()).count();
+        let mut tuple = serializer.serialize_tuple(len)?;
+        for value in self.0.iter().flatten() {
+            tuple.serialize_element(value)?;
+        }
+        tuple.end()
+    }
+}
+
+struct OptionVecVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for OptionVecVisitor<T> {
+    type Value = Vec<Option<T>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a bitmap prefixed vector of options")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let len: u64 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let bitmap: ByteBuf = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| len.div_ceil(8) == bitmap.len())
+            .ok_or_else(|| de::Error::invalid_length(bitmap.len(), &"one bit per element"))?;
+        // the padding bits must be unset, so each vector has a single encoding
+        if len % 8 != 0 && bitmap[len / 8] >> (len % 8) != 0 {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Bytes(&bitmap),
+                &"a bitmap with unset padding bits",
+            ));
+        }
+        seq.next_element_seed(PresentValuesSeed {
+            len,
+            bitmap: &bitmap,
+            marker: PhantomData,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(2, &self))
+    }
+}
+
+struct PresentValuesSeed<'a, T> {
+    len: usize,
+    bitmap: &'a [u8],
+    marker: PhantomData<T>,
+}
+
+impl<'a, 'de, T: Deserialize<'de>> DeserializeSeed<'de> for PresentValuesSeed<'a, T> {
+    type Value = Vec<Option<T>>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let count = self
+            .bitmap
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum();
+        deserializer.deserialize_tuple(count, self)
+    }
+}
+
+impl<'a, 'de, T: Deserialize<'de>> Visitor<'de> for PresentValuesSeed<'a, T> {
+    type Value = Vec<Option<T>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the values present in the bitmap")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::with_capacity(self.len);
+        let mut count = 0;
+        for index in 0..self.len {
+            if self.bitmap[index / 8] & (1 << (index % 8)) == 0 {
+                values.push(None);
+                continue;
+            }
+            let value = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(count, &self))?;
+            values.push(Some(value));
+            count += 1;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Sparse {
+        #[serde(with = "crate::option_vec")]
+        values: Vec<Option<u32>>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Tagged {
+        values: Vec<Option<u32>>,
+    }
+
+    fn values(len: usize) -> Vec<Option<u32>> {
+        (0..len as u32)
+            .map(|i| (i % 7 == 3).then_some(i * 1000))
+            .collect()
+    }
+
+    #[test]
+    fn test_option_vec() {
+        for len in [0, 1, 4, 8, 9, 100] {
+            let value = Sparse {
+                values: values(len),
+            };
+
+            let bytes = crate::compact::to_bytes(&value).unwrap();
+            let res: Sparse = crate::compact::from_bytes(&bytes).unwrap();
+            assert_eq!(res, value);
+
+            let bytes = crate::any::to_bytes(&value).unwrap();
+            let res: Sparse = crate::any::from_bytes(&bytes).unwrap();
+            assert_eq!(res, value);
+        }
+    }
+
+    #[test]
+    fn test_option_vec_size() {
+        let value = Sparse {
+            values: values(100),
+        };
+        let tagged = Tagged {
+            values: values(100),
+        };
+        let size = crate::compact::get_serialized_size(&value).unwrap();
+        // len, bitmap len and 13 bytes of bitmap, then 14 values
+        assert_eq!(size, 8 + 8 + 13 + 14 * 4);
+        assert!(size < crate::compact::get_serialized_size(&tagged).unwrap());
+        assert!(
+            crate::any::get_serialized_size(&value).unwrap()
+                < crate::any::get_serialized_size(&tagged).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_option_vec_invalid_bitmap() {
+        let value = Sparse { values: values(4) };
+        let bytes = crate::compact::to_bytes(&value).unwrap();
+        assert_eq!(bytes[16], 0b1000);
+
+        // a padding bit set
+        let mut invalid = bytes.clone();
+        invalid[16] |= 0x80;
+        assert!(crate::compact::from_bytes::<Sparse>(&invalid).is_err());
+
+        // a bitmap too short for the number of elements
+        let mut invalid = bytes;
+        invalid[7] = 9;
+        assert!(crate::compact::from_bytes::<Sparse>(&invalid).is_err());
+    }
+}
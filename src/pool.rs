@@ -0,0 +1,161 @@
+use core::fmt;
+use core::ops::Deref;
+use std::sync::{Mutex, PoisonError};
+
+/// Number of buffers kept by `BufferPool::default()` and the pool of each thread.
+pub const DEFAULT_MAX_BUFFERS: usize = 16;
+
+/// Largest capacity of the buffers kept by `BufferPool::default()` and the pool of each thread,
+/// the buffers grown past it by a large value are freed instead of holding that memory.
+pub const DEFAULT_MAX_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static LOCAL_POOL: BufferPool = const { BufferPool::new(DEFAULT_MAX_BUFFERS, DEFAULT_MAX_CAPACITY) };
+}
+
+/// Free list of the buffers used by `to_pooled_bytes`, so serializing in a hot loop reuses the same allocations
+/// instead of allocating and freeing a `Vec` for every value.
+///
+/// ```
+/// use serde_bin::{compact, BufferPool};
+///
+/// let pool = BufferPool::default();
+/// let bytes = compact::to_pooled_bytes(&(1u8, 2u8), &pool).unwrap();
+/// assert_eq!(*bytes, [1, 2]);
+/// drop(bytes);
+/// // the buffer is back in the pool for the next value
+/// assert_eq!(pool.len(), 1);
+/// ```
+///
+/// The pool can be shared between threads, `to_local_pooled_bytes` uses a pool owned by the current thread instead,
+/// so the threads never wait for each other.
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// Pool keeping at most `max_buffers` buffers, of at most `max_capacity` bytes each.
+    pub const fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    /// Number of buffers waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Free the buffers waiting to be reused.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub(crate) fn take(&self) -> PooledBytes<'_> {
+        PooledBytes {
+            buffer: self.pop(),
+            pool: Some(self),
+        }
+    }
+
+    /// Take a buffer from the pool of the current thread, returned to the pool of the thread dropping it.
+    pub(crate) fn take_local() -> PooledBytes<'static> {
+        let buffer = LOCAL_POOL.try_with(BufferPool::pop).unwrap_or_default();
+        PooledBytes { buffer, pool: None }
+    }
+
+    fn pop(&self) -> Vec<u8> {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    fn put(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > self.max_capacity {
+            return;
+        }
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_buffers {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+
+    // a panic can't leave the list of buffers half updated, so a poisoned lock is still usable
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BUFFERS, DEFAULT_MAX_CAPACITY)
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("len", &self.len())
+            .field("max_buffers", &self.max_buffers)
+            .field("max_capacity", &self.max_capacity)
+            .finish()
+    }
+}
+
+/// Bytes serialized by `to_pooled_bytes` or `to_local_pooled_bytes`, the buffer goes back to its pool when dropped.
+pub struct PooledBytes<'a> {
+    buffer: Vec<u8>,
+    // `None` for the pool of the thread dropping the bytes
+    pool: Option<&'a BufferPool>,
+}
+
+impl PooledBytes<'_> {
+    pub(crate) fn vec_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+
+    /// Take the buffer out of the pool, it is not reused anymore.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        core::mem::take(&mut self.buffer)
+    }
+}
+
+impl Deref for PooledBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl AsRef<[u8]> for PooledBytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl fmt::Debug for PooledBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.buffer, f)
+    }
+}
+
+impl Drop for PooledBytes<'_> {
+    fn drop(&mut self) {
+        let buffer = core::mem::take(&mut self.buffer);
+        match self.pool {
+            Some(pool) => pool.put(buffer),
+            // the pool of the thread is already gone if the bytes are dropped while the thread exits
+            None => {
+                let _ = LOCAL_POOL.try_with(|pool| pool.put(buffer));
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! The modules of both formats and the types and traits used with them, to import with `use serde_bin::prelude::*`.
+
+#[cfg(feature = "alloc")]
+pub use crate::ByteBuf;
+pub use crate::{
+    compact, tagged, BuffWriter, Bytes, DeserializerConfig, Error, SerializerConfig, Write,
+    WriterError,
+};
@@ -0,0 +1,74 @@
+use core::ops::ControlFlow;
+
+use crate::error::Error;
+
+/// Callback receiving the number of bytes written or read so far, returning `ControlFlow::Break(())` cancels the operation.
+///
+/// Implemented by the closures, which can capture a counter or a cancellation flag.
+pub trait ProgressCallback {
+    fn report(&mut self, processed: usize) -> ControlFlow<()>;
+}
+
+impl<F: FnMut(usize) -> ControlFlow<()>> ProgressCallback for F {
+    fn report(&mut self, processed: usize) -> ControlFlow<()> {
+        self(processed)
+    }
+}
+
+/// Progress callback of the (de)serializers without `with_progress`, never called.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NoProgress;
+
+impl ProgressCallback for NoProgress {
+    fn report(&mut self, _processed: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+// Count the bytes processed by a (de)serializer and call the progress callback every `interval` bytes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ProgressTracker<P> {
+    callback: P,
+    // `None` without a callback
+    interval: Option<usize>,
+    processed: usize,
+    next_report: usize,
+}
+
+impl ProgressTracker<NoProgress> {
+    pub(crate) fn new() -> Self {
+        ProgressTracker {
+            callback: NoProgress,
+            interval: None,
+            processed: 0,
+            next_report: 0,
+        }
+    }
+}
+
+impl<P: ProgressCallback> ProgressTracker<P> {
+    pub(crate) fn with_callback(interval: usize, callback: P) -> Self {
+        ProgressTracker {
+            callback,
+            interval: Some(interval),
+            processed: 0,
+            next_report: interval,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn advance<E>(&mut self, len: usize) -> Result<(), Error<E>> {
+        let Some(interval) = self.interval else {
+            return Ok(());
+        };
+        self.processed += len;
+        if self.processed < self.next_report {
+            return Ok(());
+        }
+        self.next_report = self.processed.saturating_add(interval);
+        match self.callback.report(self.processed) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(Error::Cancelled),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use core::hash::{Hash, Hasher};
+
+/// What the serializer does with a field of a struct, returned by the filter of `SerializerConfig::field_filter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldAction {
+    /// Serialize the field as usual.
+    Keep,
+    /// Leave the field out, like `#[serde(skip_serializing_if = "...")]`.
+    ///
+    /// The compact format identifies the fields by their position, so it redacts the field instead.
+    Skip,
+    /// Serialize `None` instead of the value of the field.
+    Redact,
+}
+
+/// Filter called with the name of the struct and the name of the field, for every field serialized.
+pub type FieldFilter = fn(&'static str, &'static str) -> FieldAction;
+
+// The configs compare the filters by address, like the progress callbacks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Filter(pub(crate) FieldFilter);
+
+impl Filter {
+    pub(crate) fn action(self, name: &'static str, field: &'static str) -> FieldAction {
+        (self.0)(name, field)
+    }
+}
+
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 as usize == other.0 as usize
+    }
+}
+
+impl Eq for Filter {}
+
+impl Hash for Filter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0 as usize).hash(state)
+    }
+}
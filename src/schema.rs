@@ -0,0 +1,357 @@
+use core::fmt::Display;
+use core::ops::Range;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::ser::{get_serialized_size, Serializer};
+use crate::write::BuffWriter;
+
+/// Description of the layout of a value serialized with the compact format.
+///
+/// Only the layout matters, so a schema doesn't need to know about the actual rust types,
+/// for example `usize` and `u64` share the same schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    F32,
+    F64,
+    Char,
+    /// Unit, unit structs and any other zero sized value.
+    Unit,
+    Str,
+    Bytes,
+    Option(&'static Schema),
+    Seq(&'static Schema),
+    Map(&'static Schema, &'static Schema),
+    /// Tuples and tuple structs, fields are adressed by their index.
+    Tuple(&'static [Schema]),
+    Struct(&'static [Field]),
+    Enum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub schema: Schema,
+}
+
+impl Field {
+    pub const fn new(name: &'static str, schema: Schema) -> Self {
+        Field { name, schema }
+    }
+}
+
+impl Schema {
+    /// Return the number of bytes taken by a value with this schema,
+    /// or `None` if that number depend on the value.
+    pub fn fixed_size(&self) -> Option<usize> {
+        let size = match self {
+            Schema::Unit => 0,
+            Schema::Bool | Schema::I8 | Schema::U8 => 1,
+            Schema::I16 | Schema::U16 => 2,
+            Schema::I32 | Schema::U32 | Schema::F32 | Schema::Char => 4,
+            Schema::I64 | Schema::U64 | Schema::F64 => 8,
+            Schema::I128 | Schema::U128 => 16,
+            Schema::Tuple(schemas) => return schemas.iter().map(Schema::fixed_size).sum(),
+            Schema::Struct(fields) => {
+                return fields.iter().map(|field| field.schema.fixed_size()).sum()
+            }
+            Schema::Str
+            | Schema::Bytes
+            | Schema::Option(_)
+            | Schema::Seq(_)
+            | Schema::Map(_, _)
+            | Schema::Enum => return None,
+        };
+        Some(size)
+    }
+}
+
+/// Location of a fixed size field inside a serialized value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldSpan {
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl FieldSpan {
+    /// Range of the field in the bytes, `None` if its end overflows a `usize`.
+    pub fn range(&self) -> Option<Range<usize>> {
+        Some(self.offset..self.offset.checked_add(self.len)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchemaError {
+    /// The segment at the given depth of the path does not name a field.
+    UnknownField(usize),
+    /// The field at the given depth of the path, or one of the fields before it,
+    /// has a size that depends on its value.
+    VariableLength(usize),
+    /// The new value does not have the same serialized size as the field.
+    SizeMismatch { expected: usize, got: usize },
+    /// The span of the field ends after the end of the bytes being patched,
+    /// `end` is `usize::MAX` if it overflows.
+    SpanOutOfRange { end: usize, len: usize },
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SchemaError::UnknownField(depth) => f.write_fmt(format_args!(
+                "No field found for the path segment at depth {}",
+                depth
+            )),
+            SchemaError::VariableLength(depth) => f.write_fmt(format_args!(
+                "Can't compute the field offset, a variable length field was found at depth {}",
+                depth
+            )),
+            SchemaError::SizeMismatch { expected, got } => f.write_fmt(format_args!(
+                "Error patching field: the field takes {} bytes but the new value takes {}",
+                expected, got
+            )),
+            SchemaError::SpanOutOfRange { end, len } => f.write_fmt(format_args!(
+                "Error patching field: the field ends at byte {} but only {} bytes were given",
+                end, len
+            )),
+        }
+    }
+}
+
+impl<We> From<SchemaError> for Error<We> {
+    fn from(value: SchemaError) -> Self {
+        Error::SchemaError(value)
+    }
+}
+
+/// Compute the location of a field in a value serialized with the compact format.
+///
+/// Each segment of the path is a struct field name, or the index of the element for tuples.
+/// This fails if the targeted field, or any field serialized before it, does not have a fixed size.
+pub fn field_offset(schema: &Schema, path: &[&str]) -> Result<FieldSpan> {
+    let mut offset = 0;
+    let mut schema = schema;
+    for (depth, segment) in path.iter().enumerate() {
+        let (preceding, field): (&mut dyn Iterator<Item = &Schema>, _) = match schema {
+            Schema::Struct(fields) => {
+                let index = fields
+                    .iter()
+                    .position(|field| field.name == *segment)
+                    .ok_or(SchemaError::UnknownField(depth))?;
+                (
+                    &mut fields[..index].iter().map(|field| &field.schema),
+                    &fields[index].schema,
+                )
+            }
+            Schema::Tuple(schemas) => {
+                let index: usize = segment
+                    .parse()
+                    .map_err(|_| SchemaError::UnknownField(depth))?;
+                let field = schemas.get(index).ok_or(SchemaError::UnknownField(depth))?;
+                (&mut schemas[..index].iter(), field)
+            }
+            _ => return Err(SchemaError::UnknownField(depth).into()),
+        };
+        for schema in preceding {
+            offset += schema
+                .fixed_size()
+                .ok_or(SchemaError::VariableLength(depth))?;
+        }
+        schema = field;
+    }
+    let len = schema
+        .fixed_size()
+        .ok_or(SchemaError::VariableLength(path.len()))?;
+    Ok(FieldSpan { offset, len })
+}
+
+/// Overwrite the field at the given span with the new value, leaving the rest of the bytes untouched.
+///
+/// The value must have the same serialized size as the field and the span must lie inside the bytes,
+/// both are checked before any byte is written.
+pub fn patch_field<T>(bytes: &mut [u8], span: FieldSpan, value: &T) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    let len = bytes.len();
+    let field =
+        span.range()
+            .and_then(|range| bytes.get_mut(range))
+            .ok_or(SchemaError::SpanOutOfRange {
+                end: span.offset.saturating_add(span.len),
+                len,
+            })?;
+    let size = get_serialized_size(value)?;
+    if size != span.len {
+        return Err(SchemaError::SizeMismatch {
+            expected: span.len,
+            got: size,
+        }
+        .into());
+    }
+    let mut writer = BuffWriter::new(field);
+    Serializer::to_writer(value, &mut writer).map_err(Error::unwrap_writer_error)?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use crate::compact::{from_bytes, to_bytes};
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Header {
+        version: u8,
+        flags: (bool, u16),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        id: u64,
+        header: Header,
+        counter: u32,
+        name: String,
+        checksum: u32,
+    }
+
+    const RECORD_SCHEMA: Schema = Schema::Struct(&[
+        Field::new("id", Schema::U64),
+        Field::new(
+            "header",
+            Schema::Struct(&[
+                Field::new("version", Schema::U8),
+                Field::new("flags", Schema::Tuple(&[Schema::Bool, Schema::U16])),
+            ]),
+        ),
+        Field::new("counter", Schema::U32),
+        Field::new("name", Schema::Str),
+        Field::new("checksum", Schema::U32),
+    ]);
+
+    fn record() -> Record {
+        Record {
+            id: 42,
+            header: Header {
+                version: 3,
+                flags: (true, 0xBEEF),
+            },
+            counter: 17,
+            name: "john".into(),
+            checksum: 0xDEADBEEF,
+        }
+    }
+
+    #[test]
+    fn test_field_offset() {
+        let span = field_offset(&RECORD_SCHEMA, &["counter"]).unwrap();
+        assert_eq!(span, FieldSpan { offset: 12, len: 4 });
+
+        let span = field_offset(&RECORD_SCHEMA, &["header", "flags", "1"]).unwrap();
+        assert_eq!(span, FieldSpan { offset: 10, len: 2 });
+
+        let span = field_offset(&RECORD_SCHEMA, &["header"]).unwrap();
+        assert_eq!(span, FieldSpan { offset: 8, len: 4 });
+    }
+
+    #[test]
+    fn test_field_offset_errors() {
+        assert_eq!(
+            field_offset(&RECORD_SCHEMA, &["checksum"]),
+            Err(SchemaError::VariableLength(0).into())
+        );
+        assert_eq!(
+            field_offset(&RECORD_SCHEMA, &["name"]),
+            Err(SchemaError::VariableLength(1).into())
+        );
+        assert_eq!(
+            field_offset(&RECORD_SCHEMA, &["header", "unknown"]),
+            Err(SchemaError::UnknownField(1).into())
+        );
+        assert_eq!(
+            field_offset(&RECORD_SCHEMA, &["header", "flags", "2"]),
+            Err(SchemaError::UnknownField(2).into())
+        );
+    }
+
+    #[test]
+    fn test_patch_field() {
+        let value = record();
+        let mut bytes = to_bytes(&value).unwrap();
+
+        let span = field_offset(&RECORD_SCHEMA, &["counter"]).unwrap();
+        patch_field(&mut bytes, span, &18u32).unwrap();
+
+        let span = field_offset(&RECORD_SCHEMA, &["header", "flags", "1"]).unwrap();
+        patch_field(&mut bytes, span, &0xCAFEu16).unwrap();
+
+        // unsized values, an empty slice is only its 8 bytes length
+        let span = field_offset(&RECORD_SCHEMA, &["id"]).unwrap();
+        let empty: &[u32] = &[];
+        patch_field(&mut bytes, span, empty).unwrap();
+
+        let res: Record = from_bytes(&bytes).unwrap();
+
+        let mut expected = value;
+        expected.id = 0;
+        expected.counter = 18;
+        expected.header.flags.1 = 0xCAFE;
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_patch_field_size_mismatch() {
+        let mut bytes = to_bytes(&record()).unwrap();
+        let untouched = bytes.clone();
+
+        let span = field_offset(&RECORD_SCHEMA, &["counter"]).unwrap();
+        assert_eq!(
+            patch_field(&mut bytes, span, &18u64),
+            Err(SchemaError::SizeMismatch {
+                expected: 4,
+                got: 8
+            }
+            .into())
+        );
+        assert_eq!(bytes, untouched);
+    }
+
+    #[test]
+    fn test_patch_field_out_of_range() {
+        let value = record();
+        let mut bytes = to_bytes(&value).unwrap();
+
+        // the span of a field computed for a longer record
+        let span = field_offset(&RECORD_SCHEMA, &["counter"]).unwrap();
+        let truncated = &mut bytes[..14];
+        assert_eq!(
+            patch_field(truncated, span, &18u32),
+            Err(SchemaError::SpanOutOfRange { end: 16, len: 14 }.into())
+        );
+        let huge = FieldSpan {
+            offset: 2,
+            len: usize::MAX,
+        };
+        assert_eq!(huge.range(), None);
+        assert_eq!(
+            patch_field(&mut bytes, huge, &18u32),
+            Err(SchemaError::SpanOutOfRange {
+                end: usize::MAX,
+                len: bytes.len()
+            }
+            .into())
+        );
+        assert_eq!(from_bytes(&bytes), Ok(value));
+    }
+}
@@ -3,35 +3,213 @@ use serde::{ser, serde_if_integer128, Serialize};
 #[cfg(feature = "std")]
 use std::io;
 
+use crate::any::Tag;
+use crate::config::{SerializerConfig, VariantIndexWidth};
 use crate::error::{Error, Result};
-use crate::write::{BuffWriter, DummyWriter, EndOfBuff, Write};
+#[cfg(feature = "alloc")]
+use crate::indexed::IndexBuilder;
+use crate::progress::{NoProgress, ProgressCallback, ProgressTracker};
+use crate::write::{BuffWriter, DummyWriter, EndOfBuff, SizeAndCrcWriter, Write};
 use crate::UNSIZED_STRING_END_MARKER;
 use core::fmt;
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::{string::ToString, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use crate::canonical::BufferedValues;
+#[cfg(feature = "pool")]
+use crate::pool::{BufferPool, PooledBytes};
+#[cfg(feature = "alloc")]
+use crate::redact::FieldAction;
+#[cfg(feature = "alloc")]
+use crate::write::{HybridWriter, VecWriter};
+
+pub struct Serializer<T, P = NoProgress> {
+    writer: Output<T, P>,
+    config: SerializerConfig,
+    // the tag of the top-level value is yet to be written
+    top_level_tag: bool,
+    #[cfg(feature = "alloc")]
+    index: IndexBuilder,
+    // with `field_filter`, the name of the struct being serialized
+    #[cfg(feature = "alloc")]
+    struct_name: &'static str,
+}
 
-pub struct Serializer<T> {
-    writer: T,
+// Writer of the serializer, the writes are redirected to the scratch buffer
+// while a sequence of unknown size is being buffered.
+struct Output<W, P> {
+    writer: W,
+    // Shared by all the sequences of unknown size, nested sequences are appended after their parent,
+    // and their count is patched in place once they end.
+    #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+    scratch: Vec<u8>,
+    // Number of sequences of unknown size currently being serialized.
+    #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+    buffering: usize,
+    // only counts the bytes given to the writer
+    progress: ProgressTracker<P>,
+}
+
+impl<W: Write, P: ProgressCallback> Output<W, P> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, W::Error> {
+        #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+        if self.buffering > 0 {
+            self.scratch.extend_from_slice(bytes);
+            return Ok(bytes.len());
+        }
+        self.write_to_writer(bytes)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<usize, W::Error> {
+        self.write_bytes(core::slice::from_ref(&byte))
+    }
+
+    fn write_to_writer(&mut self, bytes: &[u8]) -> Result<usize, W::Error> {
+        let written_bytes = self.writer.write_bytes(bytes)?;
+        self.progress.advance(written_bytes)?;
+        Ok(written_bytes)
+    }
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new(writer: W) -> Self {
-        Serializer { writer }
+        Self::with_config(writer, SerializerConfig::new())
+    }
+
+    /// Only `SerializerConfig::element_index`, `SerializerConfig::top_level_tag`
+    /// and `SerializerConfig::canonical` are used by the compact format.
+    pub fn with_config(writer: W, config: SerializerConfig) -> Self {
+        Serializer {
+            writer: Output {
+                writer,
+                #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+                scratch: Vec::new(),
+                #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+                buffering: 0,
+                progress: ProgressTracker::new(),
+            },
+            config,
+            top_level_tag: config.top_level_tag,
+            #[cfg(feature = "alloc")]
+            index: IndexBuilder::new(config.element_index),
+            #[cfg(feature = "alloc")]
+            struct_name: "",
+        }
     }
+
     pub fn to_writer<T>(value: &T, writer: W) -> Result<usize, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let mut serializer = Serializer::new(writer);
 
         value.serialize(&mut serializer)
     }
+
+    /// Call `callback` with the number of bytes given to the writer so far, at most once every `interval` bytes.
+    /// Returning `ControlFlow::Break(())` stops the serialization with `Error::Cancelled`,
+    /// the writer then holds an incomplete value.
+    pub fn with_progress<P: ProgressCallback>(
+        self,
+        interval: usize,
+        callback: P,
+    ) -> Serializer<W, P> {
+        let Serializer {
+            writer,
+            config,
+            top_level_tag,
+            #[cfg(feature = "alloc")]
+            index,
+            #[cfg(feature = "alloc")]
+            struct_name,
+        } = self;
+        Serializer {
+            writer: Output {
+                writer: writer.writer,
+                #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+                scratch: writer.scratch,
+                #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+                buffering: writer.buffering,
+                progress: ProgressTracker::with_callback(interval, callback),
+            },
+            config,
+            top_level_tag,
+            #[cfg(feature = "alloc")]
+            index,
+            #[cfg(feature = "alloc")]
+            struct_name,
+        }
+    }
 }
 
-#[cfg(feature = "std")]
+impl<W: Write, P: ProgressCallback> Serializer<W, P> {
+    pub fn config(&self) -> &SerializerConfig {
+        &self.config
+    }
+
+    /// Write the tag of the top-level value, if enabled and not written yet.
+    fn write_top_level_tag(&mut self, tag: Tag) -> Result<usize, W::Error> {
+        if !core::mem::take(&mut self.top_level_tag) {
+            return Ok(0);
+        }
+        self.writer.write_byte(tag.into())
+    }
+
+    fn write_variant_index(&mut self, index: u32) -> Result<usize, W::Error> {
+        let width = self.config.variant_index_width;
+        let too_large = |_| Error::VariantIndexTooLarge { index, width };
+        match width {
+            VariantIndexWidth::U8 => {
+                let index = u8::try_from(index).map_err(too_large)?;
+                self.writer.write_byte(index)
+            }
+            VariantIndexWidth::U16 => {
+                let index = u16::try_from(index).map_err(too_large)?;
+                self.writer.write_bytes(&index.to_be_bytes())
+            }
+            VariantIndexWidth::U32 => self.writer.write_bytes(&index.to_be_bytes()),
+        }
+    }
+
+    /// Serialize an element of a sequence, recording its offset if it belongs to the indexed sequence.
+    fn serialize_element<T>(&mut self, value: &T) -> Result<usize, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        #[cfg(feature = "alloc")]
+        if self.index.start_element() {
+            let written_bytes = value.serialize(&mut *self)?;
+            self.index.end_element(written_bytes);
+            return Ok(written_bytes);
+        }
+        value.serialize(self)
+    }
+
+    /// A value that can't be the sequence to index starts.
+    fn skip_index(&mut self) {
+        #[cfg(feature = "alloc")]
+        self.index.skip();
+    }
+
+    /// Write the element index if the container ending is the indexed sequence.
+    fn finish_index(&mut self) -> Result<usize, W::Error> {
+        #[cfg(feature = "alloc")]
+        if let Some(index) = self.index.finish() {
+            return self.writer.write_bytes(&index);
+        }
+        Ok(0)
+    }
+}
+
+/// Serialize the value into the writer, returning the number of bytes written.
+///
+/// Available without `std`, the writer can be a `BuffWriter` or, with `alloc`, a `&mut Vec<u8>`.
 pub fn to_writer<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
 where
     T: Serialize,
@@ -40,23 +218,139 @@ where
     Serializer::to_writer(value, writer)
 }
 
+/// Serialize a value behind an `erased_serde::Serialize` trait object into the writer,
+/// returning the number of bytes written. Gives the same bytes as `to_writer` with the value before its type was erased.
+#[cfg(feature = "erased")]
+pub fn to_writer_erased<W>(
+    value: &dyn erased_serde::Serialize,
+    writer: W,
+) -> Result<usize, W::Error>
+where
+    W: Write,
+{
+    erased_serde::serialize(value, &mut Serializer::new(writer))
+}
+
+/// Serialize the value then flush the writer, returning the number of bytes written.
+///
+/// Once it returns the writer holds exactly one complete value of that size and nothing is buffered,
+/// so the caller can close the frame containing it.
+pub fn to_writer_complete<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    let mut serializer = Serializer::new(writer);
+    let written_bytes = value.serialize(&mut serializer)?;
+    serializer.writer.writer.flush()?;
+    Ok(written_bytes)
+}
+
+/// Writer of several values one after the other into the same sink, counting the bytes written.
+///
+/// Every value is serialized as if given alone to `to_writer`, with the tag of `SerializerConfig::top_level_tag`
+/// and the index of `SerializerConfig::element_index` written for each of them.
+///
+/// ```
+/// use serde_bin::compact::{self, Writer};
+///
+/// let mut output = Vec::new();
+/// let mut writer = Writer::new(&mut output);
+/// writer.write(&1u8).unwrap();
+/// writer.write("a").unwrap();
+/// assert_eq!(writer.total(), output.len());
+/// ```
+pub struct Writer<W> {
+    serializer: Serializer<W>,
+    total: usize,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, SerializerConfig::new())
+    }
+
+    pub fn with_config(writer: W, config: SerializerConfig) -> Self {
+        Writer {
+            serializer: Serializer::with_config(writer, config),
+            total: 0,
+        }
+    }
+
+    /// Serialize the value after the ones already written, returning the number of bytes written for it.
+    pub fn write<T>(&mut self, value: &T) -> Result<usize, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let serializer = &mut self.serializer;
+        serializer.top_level_tag = serializer.config.top_level_tag;
+        #[cfg(feature = "alloc")]
+        {
+            serializer.index = IndexBuilder::new(serializer.config.element_index);
+        }
+        let written_bytes = value.serialize(&mut *serializer)?;
+        self.total += written_bytes;
+        Ok(written_bytes)
+    }
+
+    /// Number of bytes written by all the calls to `write`.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn flush(&mut self) -> Result<(), W::Error> {
+        self.serializer.writer.writer.flush().map_err(Into::into)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.serializer.writer.writer
+    }
+}
+
 #[cfg(all(feature = "alloc", not(feature = "std")))]
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
     let mut output = Vec::new();
-    Serializer::to_writer(value, &mut output)?;
+    let mut writer = VecWriter(&mut output);
+    writer.reserve(get_serialized_size(value)?);
+    Serializer::to_writer(value, writer)?;
     Ok(output)
 }
 
+/// The value is serialized twice, first to compute its size, so the returned `Vec` is allocated once
+/// with the exact capacity.
 #[cfg(feature = "std")]
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>, io::Error>
 where
     T: Serialize,
 {
+    let never = |err: Error<_>| err.map_writer_error(|err| match err {});
     let mut output = Vec::new();
-    Serializer::to_writer(value, &mut output)?;
+    let mut writer = VecWriter(&mut output);
+    writer.reserve(get_serialized_size(value).map_err(never)?);
+    Serializer::to_writer(value, writer).map_err(never)?;
+    Ok(output)
+}
+
+/// Serialize a value behind an `erased_serde::Serialize` trait object into a new `Vec`.
+#[cfg(feature = "erased")]
+pub fn to_bytes_erased(value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    to_writer_erased(value, VecWriter(&mut output))?;
+    Ok(output)
+}
+
+/// Serialize the value with `SerializerConfig::canonical`, so equal values always give the same bytes.
+#[cfg(feature = "alloc")]
+pub fn to_bytes_canonical<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut output = Vec::new();
+    let config = SerializerConfig::new().canonical(true);
+    value.serialize(&mut Serializer::with_config(VecWriter(&mut output), config))?;
     Ok(output)
 }
 
@@ -69,88 +363,203 @@ where
     Ok(buff_writer)
 }
 
-pub fn get_serialized_size<T>(value: &T) -> Result<usize>
+/// Serialize the value into a new array, returning the array and the number of bytes written.
+///
+/// This is the simplest way to serialize without allocating:
+///
+/// ```
+/// let (buff, len) = serde_bin::compact::to_array::<16, _>(&(1u8, 'c')).unwrap();
+/// let value: (u8, char) = serde_bin::compact::from_bytes(&buff[..len]).unwrap();
+/// assert_eq!(value, (1, 'c'));
+/// ```
+pub fn to_array<const N: usize, T>(value: &T) -> Result<([u8; N], usize), EndOfBuff>
+where
+    T: Serialize,
+{
+    let mut buff = [0; N];
+    let len = to_array_ref(value, &mut buff)?;
+    Ok((buff, len))
+}
+
+/// Serialize the value into an existing array, returning the number of bytes written.
+pub fn to_array_ref<const N: usize, T>(value: &T, buff: &mut [u8; N]) -> Result<usize, EndOfBuff>
+where
+    T: Serialize,
+{
+    to_buff(value, buff).map(|writer| writer.len())
+}
+
+/// Serialize the values one after the other with a `Writer`, returning the range of the bytes of each of them,
+/// counted from the first byte written.
+///
+/// Any value can then be decoded alone with `from_bytes_batch`, without decoding the ones before it.
+#[cfg(feature = "alloc")]
+pub fn to_writer_batch<'a, W, T, I>(items: I, writer: W) -> Result<Vec<Range<usize>>, W::Error>
+where
+    W: Write,
+    T: 'a + ?Sized + Serialize,
+    I: IntoIterator<Item = &'a T>,
+{
+    let items = items.into_iter();
+    let mut ranges = Vec::with_capacity(items.size_hint().0);
+    let mut writer = Writer::new(writer);
+    for item in items {
+        let start = writer.total();
+        writer.write(item)?;
+        ranges.push(start..writer.total());
+    }
+    Ok(ranges)
+}
+
+/// Serialize the values one after the other into a new `Vec`, returning it with the range of the bytes of each value.
+///
+/// ```
+/// use serde_bin::compact;
+///
+/// let (bytes, ranges) = compact::to_bytes_batch(&["a", "bc", "def"]).unwrap();
+/// let values: Vec<&str> = compact::from_bytes_batch(&bytes, &ranges[1..]).unwrap();
+/// assert_eq!(values, ["bc", "def"]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn to_bytes_batch<'a, T, I>(items: I) -> Result<(Vec<u8>, Vec<Range<usize>>)>
+where
+    T: 'a + ?Sized + Serialize,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut output = Vec::new();
+    let ranges = to_writer_batch(items, VecWriter(&mut output))?;
+    Ok((output, ranges))
+}
+
+/// Serialize the value into a buffer taken from the pool, given back to the pool when the returned bytes are dropped.
+#[cfg(feature = "pool")]
+pub fn to_pooled_bytes<'a, T>(value: &T, pool: &'a BufferPool) -> Result<PooledBytes<'a>>
+where
+    T: Serialize,
+{
+    let mut bytes = pool.take();
+    Serializer::to_writer(value, VecWriter(bytes.vec_mut()))?;
+    Ok(bytes)
+}
+
+/// Serialize the value into a buffer taken from a pool owned by the current thread, like `to_pooled_bytes`
+/// without sharing the pool between threads.
+#[cfg(feature = "pool")]
+pub fn to_local_pooled_bytes<T>(value: &T) -> Result<PooledBytes<'static>>
 where
     T: Serialize,
+{
+    let mut bytes = BufferPool::take_local();
+    Serializer::to_writer(value, VecWriter(bytes.vec_mut()))?;
+    Ok(bytes)
+}
+
+/// Serialize the value into a `HybridWriter`, written inline if it takes at most `N` bytes.
+#[cfg(feature = "alloc")]
+pub fn to_hybrid<const N: usize, T>(value: &T) -> Result<HybridWriter<N>>
+where
+    T: Serialize,
+{
+    let mut writer = HybridWriter::new();
+    Serializer::to_writer(value, &mut writer)?;
+    Ok(writer)
+}
+
+pub fn get_serialized_size<T>(value: &T) -> Result<usize>
+where
+    T: ?Sized + Serialize,
 {
     Serializer::to_writer(value, DummyWriter)
 }
 
+/// Return the serialized size of the value and the CRC-32 of the serialized bytes, without storing them.
+pub fn get_serialized_size_and_crc<T>(value: &T) -> Result<(usize, u32)>
+where
+    T: Serialize,
+{
+    let mut writer = SizeAndCrcWriter::new();
+    Serializer::to_writer(value, &mut writer)?;
+    Ok(writer.finish())
+}
+
 macro_rules! implement_number {
-    ($fn_name:ident, $t:ident) => {
+    ($fn_name:ident, $t:ident, $tag:expr) => {
         fn $fn_name(self, value: $t) -> Result<Self::Ok, W::Error> {
+            let written_bytes = self.write_top_level_tag($tag)?;
             self.writer
                 .write_bytes(&value.to_be_bytes())
-                .map_err(Into::into)
+                .map(|wb| wb + written_bytes)
         }
     };
 }
 
-impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+impl<'a, W: Write, P: ProgressCallback> ser::Serializer for &'a mut Serializer<W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    type SerializeSeq = SeqSerializer<'a, W>;
-    type SerializeTuple = SeqSerializer<'a, W>;
-    type SerializeTupleStruct = SeqSerializer<'a, W>;
-    type SerializeTupleVariant = SeqSerializer<'a, W>;
-    type SerializeMap = SeqSerializer<'a, W>;
-    type SerializeStruct = SeqSerializer<'a, W>;
-    type SerializeStructVariant = SeqSerializer<'a, W>;
+    type SerializeSeq = SeqSerializer<'a, W, P>;
+    type SerializeTuple = SeqSerializer<'a, W, P>;
+    type SerializeTupleStruct = SeqSerializer<'a, W, P>;
+    type SerializeTupleVariant = SeqSerializer<'a, W, P>;
+    type SerializeMap = MapSerializer<'a, W, P>;
+    type SerializeStruct = SeqSerializer<'a, W, P>;
+    type SerializeStructVariant = SeqSerializer<'a, W, P>;
 
     fn is_human_readable(&self) -> bool {
         false
     }
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, W::Error> {
+        let tag = if v { Tag::BoolTrue } else { Tag::BoolFalse };
+        let writted_bytes = self.write_top_level_tag(tag)?;
         let byte: u8 = v.into();
-        let writted_bytes = self.writer.write_byte(byte)?;
-        Ok(writted_bytes)
+        self.writer.write_byte(byte).map(|wb| wb + writted_bytes)
     }
 
-    implement_number!(serialize_i8, i8);
-    implement_number!(serialize_i16, i16);
-    implement_number!(serialize_i32, i32);
-    implement_number!(serialize_i64, i64);
-    implement_number!(serialize_u8, u8);
-    implement_number!(serialize_u16, u16);
-    implement_number!(serialize_u32, u32);
-    implement_number!(serialize_u64, u64);
-    implement_number!(serialize_f32, f32);
-    implement_number!(serialize_f64, f64);
+    implement_number!(serialize_i8, i8, Tag::I8);
+    implement_number!(serialize_i16, i16, Tag::I16);
+    implement_number!(serialize_i32, i32, Tag::I32);
+    implement_number!(serialize_i64, i64, Tag::I64);
+    implement_number!(serialize_u8, u8, Tag::U8);
+    implement_number!(serialize_u16, u16, Tag::U16);
+    implement_number!(serialize_u32, u32, Tag::U32);
+    implement_number!(serialize_u64, u64, Tag::U64);
+    implement_number!(serialize_f32, f32, Tag::F32);
+    implement_number!(serialize_f64, f64, Tag::F64);
 
     serde_if_integer128! {
-        implement_number!(serialize_i128, i128);
-        implement_number!(serialize_u128, u128);
+        implement_number!(serialize_i128, i128, Tag::I128);
+        implement_number!(serialize_u128, u128, Tag::U128);
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, W::Error> {
+        // always encoded on 4 bytes
+        let writted_bytes = self.write_top_level_tag(Tag::Char4)?;
         let bytes: u32 = v.into();
         self.writer
             .write_bytes(&bytes.to_be_bytes())
-            .map_err(Error::WriterError)
+            .map(|wb| wb + writted_bytes)
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, W::Error> {
-        Self::serialize_bytes(self, v.as_bytes())
+        let writted_bytes = self.write_top_level_tag(Tag::String)?;
+        Self::serialize_bytes(self, v.as_bytes()).map(|wb| wb + writted_bytes)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, W::Error> {
+        let mut writted_bytes = self.write_top_level_tag(Tag::ByteArray)?;
         let len = v.len() as u64;
-        let writted_bytes = self.writer.write_bytes(&len.to_be_bytes())?;
-        self.writer
-            .write_bytes(v)
-            .map(|wb| wb + writted_bytes)
-            .map_err(Error::WriterError)
+        writted_bytes += self.writer.write_bytes(&len.to_be_bytes())?;
+        self.writer.write_bytes(v).map(|wb| wb + writted_bytes)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, W::Error> {
-        Ok(0)
+        self.write_top_level_tag(Tag::Unit)
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, W::Error> {
-        Self::serialize_unit(self)
+        self.write_top_level_tag(Tag::UnitStruct)
     }
 
     fn serialize_unit_variant(
@@ -159,21 +568,23 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         variant_index: u32,
         _variant: &'static str,
     ) -> Result<Self::Ok, W::Error> {
-        Self::serialize_u32(self, variant_index)
+        let writted_bytes = self.write_top_level_tag(Tag::UnitVariant)?;
+        self.write_variant_index(variant_index)
+            .map(|wb| wb + writted_bytes)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         variant_index: u32,
@@ -181,37 +592,46 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let written_bytes = self.writer.write_bytes(&variant_index.to_be_bytes())?;
+        self.skip_index();
+        let mut written_bytes = self.write_top_level_tag(Tag::NewTypeVariant)?;
+        written_bytes += self.write_variant_index(variant_index)?;
         value.serialize(self).map(|wb| wb + written_bytes)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, W::Error> {
+        let written_bytes = self.write_top_level_tag(Tag::Seq)?;
         match len {
             Some(len) => {
                 let len: u64 = len as u64;
-                let written_bytes = self.writer.write_bytes(&len.to_be_bytes())?;
-                Ok(SeqSerializer::new_known(self, written_bytes))
+                let header_len = self.writer.write_bytes(&len.to_be_bytes())?;
+                // the offsets are counted after the top-level tag, `IndexedSeq` skips it
+                #[cfg(feature = "alloc")]
+                self.index.start_seq(header_len);
+                Ok(SeqSerializer::new_known(self, written_bytes + header_len))
             }
-            None => SeqSerializer::new_unknown(self),
+            None => SeqSerializer::new_unknown(self, written_bytes),
         }
     }
 
     fn serialize_none(self) -> Result<Self::Ok, W::Error> {
-        self.writer.write_byte(0).map_err(Error::WriterError)
+        let written_bytes = self.write_top_level_tag(Tag::None)?;
+        self.writer.write_byte(0).map(|wb| wb + written_bytes)
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let written_bytes = self.writer.write_byte(1)?;
+        self.skip_index();
+        let written_bytes = self.write_top_level_tag(Tag::Some)? + self.writer.write_byte(1)?;
         value.serialize(self).map(|wb| wb + written_bytes)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, W::Error> {
-        Ok(SeqSerializer::new_known(self, 0))
+        let written_bytes = self.write_top_level_tag(Tag::Tuple)?;
+        Ok(SeqSerializer::new_known(self, written_bytes))
     }
 
     fn serialize_tuple_struct(
@@ -219,7 +639,8 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct, W::Error> {
-        Ok(SeqSerializer::new_known(self, 0))
+        let written_bytes = self.write_top_level_tag(Tag::TupleStruct)?;
+        Ok(SeqSerializer::new_known(self, written_bytes))
     }
 
     fn serialize_tuple_variant(
@@ -229,48 +650,83 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, W::Error> {
-        let written_bytes = self.writer.write_bytes(&variant_index.to_be_bytes())?;
+        let written_bytes = self.write_top_level_tag(Tag::TupleVariant)?
+            + self.write_variant_index(variant_index)?;
         Ok(SeqSerializer::new_known(self, written_bytes))
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, W::Error> {
+        let written_bytes = self.write_top_level_tag(Tag::Map)?;
+        #[cfg(feature = "alloc")]
+        if self.config.canonical {
+            self.skip_index();
+            return Ok(MapSerializer::Sorted {
+                serializer: self,
+                written_bytes,
+                entries: BufferedValues::default(),
+            });
+        }
         match len {
             Some(len) => {
                 let len: u64 = len as u64;
-                let written_bytes = self.writer.write_bytes(&len.to_be_bytes())?;
-                Ok(SeqSerializer::new_known(self, written_bytes))
+                let written_bytes = written_bytes + self.writer.write_bytes(&len.to_be_bytes())?;
+                Ok(MapSerializer::Seq(SeqSerializer::new_known(
+                    self,
+                    written_bytes,
+                )))
             }
-            None => SeqSerializer::new_unknown(self),
+            None => SeqSerializer::new_unknown(self, written_bytes).map(MapSerializer::Seq),
         }
     }
 
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, W::Error> {
-        Ok(SeqSerializer::new_known(self, 0))
+        #[cfg(feature = "alloc")]
+        {
+            self.struct_name = name;
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = name;
+        let written_bytes = self.write_top_level_tag(Tag::Struct)?;
+        Ok(SeqSerializer::new_known(self, written_bytes))
     }
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, W::Error> {
-        let written_bytes = self.writer.write_bytes(&variant_index.to_be_bytes())?;
+        #[cfg(feature = "alloc")]
+        {
+            self.struct_name = name;
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = name;
+        let written_bytes = self.write_top_level_tag(Tag::StructVariant)?
+            + self.write_variant_index(variant_index)?;
         Ok(SeqSerializer::new_known(self, written_bytes))
     }
 
-    fn collect_str<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn collect_str<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: fmt::Display,
+        T: ?Sized + fmt::Display,
     {
+        #[cfg(feature = "alloc")]
+        if self.config.canonical {
+            return self.serialize_str(&value.to_string());
+        }
+        let mut written_bytes = self.write_top_level_tag(Tag::String)?;
         // unknown str length marker
-        let mut written_bytes = self.writer.write_bytes(&u64::MAX.to_be_bytes())?;
+        written_bytes += self.writer.write_bytes(&u64::MAX.to_be_bytes())?;
         let mut collector = StrCollector::new(&mut self.writer);
-        fmt::write(&mut collector, format_args!("{}", value))?;
+        if let Err(err) = fmt::write(&mut collector, format_args!("{}", value)) {
+            return Err(collector.error.unwrap_or(err.into()));
+        }
         written_bytes += collector.written_bytes;
         // "null" terminated str
         written_bytes += self.writer.write_bytes(&UNSIZED_STRING_END_MARKER)?;
@@ -279,59 +735,127 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 }
 
 #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
-pub enum SeqSerializer<'a, W> {
+pub enum SeqSerializer<'a, W, P = NoProgress> {
     KnownSize {
-        serializer: &'a mut Serializer<W>,
+        serializer: &'a mut Serializer<W, P>,
         written_bytes: usize,
     },
-    UnknownSize {
-        serializer: &'a mut Serializer<W>,
-        count: u64,
-        bytes: Vec<u8>,
-    },
+    UnknownSize(BufferedSeq<'a, W, P>),
+}
+
+/// Sequence of unknown length, its elements are buffered in the scratch buffer of the serializer
+/// until the count is known.
+///
+/// Dropping it before `finish`, when the serialization of an element failed,
+/// discards what was buffered so the serializer stays usable.
+#[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+pub struct BufferedSeq<'a, W, P = NoProgress> {
+    serializer: &'a mut Serializer<W, P>,
+    count: u64,
+    // offset of the count in the scratch buffer, the elements are written after it
+    start: usize,
+    // bytes written before the count, the tag of the top-level value
+    written_bytes: usize,
+    finished: bool,
+}
+
+#[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+impl<W: Write, P: ProgressCallback> BufferedSeq<'_, W, P> {
+    fn finish(&mut self) -> Result<usize, W::Error> {
+        self.finished = true;
+        let output = &mut self.serializer.writer;
+        let start = self.start;
+        output.scratch[start..start + 8].copy_from_slice(&self.count.to_be_bytes());
+        output.buffering -= 1;
+        if output.buffering > 0 {
+            // the parent sequence is still being buffered
+            return Ok(output.scratch.len() - start);
+        }
+        let scratch = core::mem::take(&mut output.scratch);
+        let res = output.write_to_writer(&scratch[start..]);
+        output.scratch = scratch;
+        output.scratch.clear();
+        res.map(|wb| wb + self.written_bytes)
+    }
+}
+
+#[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
+impl<W, P> Drop for BufferedSeq<'_, W, P> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let output = &mut self.serializer.writer;
+            output.scratch.truncate(self.start);
+            output.buffering -= 1;
+        }
+    }
 }
 
 #[cfg(any(not(feature = "alloc"), feature = "no-unsized-seq"))]
-pub struct SeqSerializer<'a, W> {
-    serializer: &'a mut Serializer<W>,
+pub struct SeqSerializer<'a, W, P = NoProgress> {
+    serializer: &'a mut Serializer<W, P>,
     written_bytes: usize,
 }
 
 #[cfg(all(feature = "alloc", not(feature = "no-unsized-seq")))]
-impl<'a, W: Write> SeqSerializer<'a, W> {
-    pub fn new_known(serializer: &'a mut Serializer<W>, written_bytes: usize) -> Self {
+impl<'a, W: Write, P: ProgressCallback> SeqSerializer<'a, W, P> {
+    pub fn new_known(serializer: &'a mut Serializer<W, P>, written_bytes: usize) -> Self {
+        serializer.skip_index();
         Self::KnownSize {
             serializer,
             written_bytes,
         }
     }
 
-    pub fn new_unknown(serializer: &'a mut Serializer<W>) -> Result<Self, W::Error> {
-        Ok(Self::UnknownSize {
-            count: 0,
-            bytes: Vec::new(),
+    fn serializer(&mut self) -> &mut Serializer<W, P> {
+        match self {
+            SeqSerializer::KnownSize { serializer, .. } => serializer,
+            SeqSerializer::UnknownSize(seq) => seq.serializer,
+        }
+    }
+
+    pub fn new_unknown(
+        serializer: &'a mut Serializer<W, P>,
+        written_bytes: usize,
+    ) -> Result<Self, W::Error> {
+        serializer.skip_index();
+        let output = &mut serializer.writer;
+        let start = output.scratch.len();
+        output.scratch.extend_from_slice(&0u64.to_be_bytes());
+        output.buffering += 1;
+        Ok(Self::UnknownSize(BufferedSeq {
             serializer,
-        })
+            count: 0,
+            start,
+            written_bytes,
+            finished: false,
+        }))
     }
 
-    pub fn ser_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    pub fn ser_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
+    {
+        if let SeqSerializer::UnknownSize(seq) = self {
+            seq.count += 1;
+        }
+        self.ser_map_value(value)
+    }
+
+    /// Serialize the value of a map entry, the entry is counted with its key.
+    pub fn ser_map_value<T>(&mut self, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
     {
         match self {
             SeqSerializer::KnownSize {
                 serializer,
                 written_bytes,
             } => {
-                *written_bytes += value.serialize(&mut **serializer)?;
+                *written_bytes += serializer.serialize_element(value)?;
                 Ok(())
             }
-            SeqSerializer::UnknownSize { count, bytes, .. } => {
-                let mut serializer = Serializer { writer: bytes };
-                *count += 1;
-                value
-                    .serialize(&mut serializer)
-                    .map_err(Error::unwrap_writer_error)?;
+            SeqSerializer::UnknownSize(seq) => {
+                value.serialize(&mut *seq.serializer)?;
                 Ok(())
             }
         }
@@ -339,57 +863,98 @@ impl<'a, W: Write> SeqSerializer<'a, W> {
 
     pub fn finish(self) -> Result<usize, W::Error> {
         match self {
-            SeqSerializer::KnownSize { written_bytes, .. } => Ok(written_bytes),
-            SeqSerializer::UnknownSize {
-                count,
-                bytes,
+            SeqSerializer::KnownSize {
                 serializer,
-            } => {
-                let written_bytes = serializer.writer.write_bytes(&count.to_be_bytes())?;
-                serializer
-                    .writer
-                    .write_bytes(&bytes)
-                    .map(|wb| wb + written_bytes)
-                    .map_err(Error::WriterError)
-            }
+                written_bytes,
+            } => Ok(written_bytes + serializer.finish_index()?),
+            SeqSerializer::UnknownSize(mut seq) => seq.finish(),
         }
     }
 }
 
 #[cfg(any(not(feature = "alloc"), feature = "no-unsized-seq"))]
-impl<'a, W: Write> SeqSerializer<'a, W> {
-    pub fn new_known(serializer: &'a mut Serializer<W>, written_bytes: usize) -> Self {
+impl<'a, W: Write, P: ProgressCallback> SeqSerializer<'a, W, P> {
+    pub fn new_known(serializer: &'a mut Serializer<W, P>, written_bytes: usize) -> Self {
+        serializer.skip_index();
         Self {
             serializer,
             written_bytes,
         }
     }
 
-    pub fn new_unknown(_serializer: &'a mut Serializer<W>) -> Result<Self, W::Error> {
+    pub fn new_unknown(
+        serializer: &'a mut Serializer<W, P>,
+        _written_bytes: usize,
+    ) -> Result<Self, W::Error> {
+        serializer.skip_index();
         Err(Error::UnknownSeqLength)
     }
 
-    pub fn ser_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    #[cfg(feature = "alloc")]
+    fn serializer(&mut self) -> &mut Serializer<W, P> {
+        self.serializer
+    }
+
+    pub fn ser_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.written_bytes += value.serialize(&mut *self.serializer)?;
+        self.written_bytes += self.serializer.serialize_element(value)?;
         Ok(())
     }
 
+    pub fn ser_map_value<T>(&mut self, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser_value(value)
+    }
+
     pub fn finish(self) -> Result<usize, W::Error> {
-        Ok(self.written_bytes)
+        Ok(self.written_bytes + self.serializer.finish_index()?)
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> SeqSerializer<'a, W, P> {
+    /// Serialize a field of a struct, after calling the filter of `SerializerConfig::field_filter`.
+    #[cfg(feature = "alloc")]
+    fn ser_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let serializer = self.serializer();
+        let name = serializer.struct_name;
+        let action = serializer
+            .config
+            .field_filter
+            .map(|filter| filter.action(name, key));
+        let res = match action {
+            // the fields are identified by their position, leaving one out would shift the next ones
+            Some(FieldAction::Skip | FieldAction::Redact) => self.ser_value(&None::<()>),
+            _ => self.ser_value(value),
+        };
+        // the structs in the value set their own name
+        self.serializer().struct_name = name;
+        res
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn ser_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser_value(value)
+    }
+}
+
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeSeq for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -399,14 +964,14 @@ impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTuple for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -416,14 +981,14 @@ impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTupleStruct for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -433,14 +998,14 @@ impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeTupleVariant for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -450,40 +1015,93 @@ impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for SeqSerializer<'a, W> {
+pub enum MapSerializer<'a, W, P = NoProgress> {
+    Seq(SeqSerializer<'a, W, P>),
+    // with `canonical`, the entries are written sorted by their key once they are all known
+    #[cfg(feature = "alloc")]
+    Sorted {
+        serializer: &'a mut Serializer<W, P>,
+        written_bytes: usize,
+        entries: BufferedValues,
+    },
+}
+
+impl<'a, W: Write, P: ProgressCallback> MapSerializer<'a, W, P> {
+    fn ser_entry_value<T>(&mut self, value: &T, is_key: bool) -> Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self {
+            MapSerializer::Seq(seq) if is_key => seq.ser_value(value),
+            MapSerializer::Seq(seq) => seq.ser_map_value(value),
+            #[cfg(feature = "alloc")]
+            MapSerializer::Sorted {
+                serializer,
+                entries,
+                ..
+            } => {
+                let config = serializer.config.for_buffered_values();
+                let res = value.serialize(&mut Serializer::with_config(entries.writer(), config));
+                if let Err(err) = res {
+                    entries.discard_partial_value();
+                    return Err(err.map_writer_error(|err| match err {}));
+                }
+                entries.end_value();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeMap for MapSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), W::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(key)
+        self.ser_entry_value(key, true)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(value)
+        self.ser_entry_value(value, false)
     }
 
     fn end(self) -> Result<Self::Ok, W::Error> {
-        self.finish()
+        match self {
+            MapSerializer::Seq(seq) => seq.finish(),
+            #[cfg(feature = "alloc")]
+            MapSerializer::Sorted {
+                serializer,
+                mut written_bytes,
+                entries,
+            } => {
+                let len = (entries.len() / 2) as u64;
+                written_bytes += serializer.writer.write_bytes(&len.to_be_bytes())?;
+                for value in entries.sorted_entries()? {
+                    written_bytes += serializer.writer.write_bytes(value)?;
+                }
+                Ok(written_bytes)
+            }
+        }
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeStruct for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(value)
+        self.ser_field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, W::Error> {
@@ -491,16 +1109,16 @@ impl<'a, W: Write> ser::SerializeStruct for SeqSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> ser::SerializeStructVariant for SeqSerializer<'a, W, P> {
     type Ok = usize;
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser_value(value)
+        self.ser_field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, W::Error> {
@@ -508,28 +1126,34 @@ impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
     }
 }
 
-struct StrCollector<'a, W> {
-    writer: &'a mut W,
+struct StrCollector<'a, W: Write, P> {
+    writer: &'a mut Output<W, P>,
     written_bytes: usize,
+    // error of the writer, or cancellation, hidden behind `fmt::Error`
+    error: Option<Error<W::Error>>,
 }
 
-impl<'a, W: Write> StrCollector<'a, W> {
-    pub fn new(writer: &'a mut W) -> Self {
+impl<'a, W: Write, P: ProgressCallback> StrCollector<'a, W, P> {
+    fn new(writer: &'a mut Output<W, P>) -> Self {
         StrCollector {
             writer,
             written_bytes: 0,
+            error: None,
         }
     }
 }
 
-impl<'a, W: Write> fmt::Write for StrCollector<'a, W> {
+impl<'a, W: Write, P: ProgressCallback> fmt::Write for StrCollector<'a, W, P> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         match self.writer.write_bytes(s.as_bytes()) {
             Ok(written_bytes) => {
                 self.written_bytes += written_bytes;
                 Ok(())
             }
-            Err(_) => Err(fmt::Error),
+            Err(err) => {
+                self.error = Some(err);
+                Err(fmt::Error)
+            }
         }
     }
 }
@@ -0,0 +1,375 @@
+//! Serialize structs using `#[serde(flatten)]` as a map with a known length.
+//!
+//! Serde doesn't know the number of fields of a struct with flattened fields, so it serializes it
+//! as a map with an unknown length, which needs to be buffered with the compact format
+//! (or fails without the `alloc` feature or with the `no-unsized-seq` feature),
+//! and is encoded as an `UnsizedMap` with the `any` format, which gives no size hint when deserializing.
+//!
+//! Use `#[serde(with = "serde_bin::sized_flatten")]` on a field, or call `serialize` directly,
+//! to first count the entries of the map and then serialize it with its length.
+//! Counting the entries runs the `Serialize` implementation of the value twice: the first time, flattened fields still run
+//! their own `Serialize` implementation to list their entries, but the keys and values are only counted, not serialized.
+//! Values that are not serialized as a map are serialized as is.
+
+use core::fmt;
+
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+
+type CountError = Error<crate::NoWriterError>;
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: ?Sized + Serialize,
+    S: Serializer,
+{
+    match value.serialize(EntryCounter) {
+        Ok(len) => value.serialize(SizedMapSerializer {
+            serializer,
+            len: Some(len),
+        }),
+        Err(_) => value.serialize(serializer),
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer)
+}
+
+// Serializer counting the entries of a map, fails for any other type.
+struct EntryCounter;
+
+struct EntryCount(usize);
+
+macro_rules! not_a_map {
+    ($($fn_name:ident($($arg:ident: $t:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $fn_name(self, $(_: $t),*) -> Result<$ret, CountError> {
+                Err(CountError::Unimplemented(concat!("EntryCounter::", stringify!($fn_name))))
+            }
+        )*
+    };
+}
+
+impl Serializer for EntryCounter {
+    type Ok = usize;
+    type Error = CountError;
+    type SerializeSeq = ser::Impossible<usize, CountError>;
+    type SerializeTuple = ser::Impossible<usize, CountError>;
+    type SerializeTupleStruct = ser::Impossible<usize, CountError>;
+    type SerializeTupleVariant = ser::Impossible<usize, CountError>;
+    type SerializeMap = EntryCount;
+    type SerializeStruct = ser::Impossible<usize, CountError>;
+    type SerializeStructVariant = ser::Impossible<usize, CountError>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    not_a_map! {
+        serialize_bool(v: bool) -> usize;
+        serialize_i8(v: i8) -> usize;
+        serialize_i16(v: i16) -> usize;
+        serialize_i32(v: i32) -> usize;
+        serialize_i64(v: i64) -> usize;
+        serialize_i128(v: i128) -> usize;
+        serialize_u8(v: u8) -> usize;
+        serialize_u16(v: u16) -> usize;
+        serialize_u32(v: u32) -> usize;
+        serialize_u64(v: u64) -> usize;
+        serialize_u128(v: u128) -> usize;
+        serialize_f32(v: f32) -> usize;
+        serialize_f64(v: f64) -> usize;
+        serialize_char(v: char) -> usize;
+        serialize_str(v: &str) -> usize;
+        serialize_bytes(v: &[u8]) -> usize;
+        serialize_none() -> usize;
+        serialize_unit() -> usize;
+        serialize_unit_struct(name: &'static str) -> usize;
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> usize;
+        serialize_seq(len: Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(len: usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeTupleVariant;
+        serialize_struct(name: &'static str, len: usize) -> Self::SerializeStruct;
+        serialize_struct_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<usize, CountError>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(CountError::Unimplemented("EntryCounter::serialize_some"))
+    }
+
+    fn collect_str<T>(self, _value: &T) -> Result<usize, CountError>
+    where
+        T: ?Sized + fmt::Display,
+    {
+        Err(CountError::Unimplemented("EntryCounter::collect_str"))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<usize, CountError>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(CountError::Unimplemented(
+            "EntryCounter::serialize_newtype_struct",
+        ))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<usize, CountError>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(CountError::Unimplemented(
+            "EntryCounter::serialize_newtype_variant",
+        ))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<EntryCount, CountError> {
+        Ok(EntryCount(0))
+    }
+}
+
+impl ser::SerializeMap for EntryCount {
+    type Ok = usize;
+    type Error = CountError;
+
+    fn serialize_key<T>(&mut self, _key: &T) -> Result<(), CountError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.0 += 1;
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, _value: &T) -> Result<(), CountError>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(())
+    }
+
+    fn end(self) -> Result<usize, CountError> {
+        Ok(self.0)
+    }
+}
+
+// Forward everything to the inner serializer, but give the length to the first map with an unknown length.
+struct SizedMapSerializer<S> {
+    serializer: S,
+    len: Option<usize>,
+}
+
+macro_rules! forward {
+    ($($fn_name:ident($($arg:ident: $t:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $fn_name(self, $($arg: $t),*) -> Result<$ret, S::Error> {
+                self.serializer.$fn_name($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for SizedMapSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = S::SerializeSeq;
+    type SerializeTuple = S::SerializeTuple;
+    type SerializeTupleStruct = S::SerializeTupleStruct;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = S::SerializeStruct;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    fn is_human_readable(&self) -> bool {
+        self.serializer.is_human_readable()
+    }
+
+    forward! {
+        serialize_bool(v: bool) -> S::Ok;
+        serialize_i8(v: i8) -> S::Ok;
+        serialize_i16(v: i16) -> S::Ok;
+        serialize_i32(v: i32) -> S::Ok;
+        serialize_i64(v: i64) -> S::Ok;
+        serialize_i128(v: i128) -> S::Ok;
+        serialize_u8(v: u8) -> S::Ok;
+        serialize_u16(v: u16) -> S::Ok;
+        serialize_u32(v: u32) -> S::Ok;
+        serialize_u64(v: u64) -> S::Ok;
+        serialize_u128(v: u128) -> S::Ok;
+        serialize_f32(v: f32) -> S::Ok;
+        serialize_f64(v: f64) -> S::Ok;
+        serialize_char(v: char) -> S::Ok;
+        serialize_str(v: &str) -> S::Ok;
+        serialize_bytes(v: &[u8]) -> S::Ok;
+        serialize_none() -> S::Ok;
+        serialize_unit() -> S::Ok;
+        serialize_unit_struct(name: &'static str) -> S::Ok;
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> S::Ok;
+        serialize_seq(len: Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(len: usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeTupleVariant;
+        serialize_struct(name: &'static str, len: usize) -> Self::SerializeStruct;
+        serialize_struct_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serializer.serialize_some(value)
+    }
+
+    fn collect_str<T>(self, value: &T) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + fmt::Display,
+    {
+        self.serializer.collect_str(value)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serializer.serialize_newtype_struct(name, value)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serializer
+            .serialize_newtype_variant(name, variant_index, variant, value)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<S::SerializeMap, S::Error> {
+        self.serializer.serialize_map(len.or(self.len))
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use crate::any::Tag;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct FlattenTestInner {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        age: Option<u32>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct FlattenTest {
+        a: char,
+        b: String,
+        #[serde(flatten)]
+        c: FlattenTestInner,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Wrapper {
+        id: u8,
+        #[serde(with = "crate::sized_flatten")]
+        value: FlattenTest,
+    }
+
+    fn value(age: Option<u32>) -> FlattenTest {
+        FlattenTest {
+            a: 'c',
+            b: "foo".into(),
+            c: FlattenTestInner {
+                name: "john".into(),
+                age,
+            },
+        }
+    }
+
+    #[test]
+    fn test_sized_flatten_any() {
+        for (age, len) in [(Some(32), 4u64), (None, 3)] {
+            let value = value(age);
+
+            let mut v: Vec<u8> = Vec::new();
+            super::serialize(&value, &mut crate::any::Serializer::new(&mut v)).unwrap();
+
+            assert_eq!(v[0], Tag::Map.into());
+            assert_eq!(v[1..9], len.to_be_bytes());
+
+            let res: FlattenTest = crate::any::from_bytes(&v).unwrap();
+            assert_eq!(res, value);
+
+            let unsized_bytes = crate::any::to_bytes(&value).unwrap();
+            assert_eq!(unsized_bytes[0], Tag::UnsizedMap.into());
+            assert_eq!(unsized_bytes.last(), Some(&Tag::UnsizedSeqEnd.into()));
+            // same entries
+            assert_eq!(v[9..], unsized_bytes[1..unsized_bytes.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn test_sized_flatten_with() {
+        let value = Wrapper {
+            id: 3,
+            value: value(Some(32)),
+        };
+
+        let bytes = crate::compact::to_bytes(&value).unwrap();
+        assert_eq!(bytes[1..9], 4u64.to_be_bytes());
+        let unsized_bytes = crate::compact::to_bytes(&(3u8, &value.value));
+        // the compact format writes the same bytes whether the length is known or not
+        #[cfg(not(feature = "no-unsized-seq"))]
+        assert_eq!(bytes, unsized_bytes.unwrap());
+        // only the sized flatten can be written without the unknown lengths
+        #[cfg(feature = "no-unsized-seq")]
+        assert!(matches!(unsized_bytes, Err(crate::Error::UnknownSeqLength)));
+
+        let bytes = crate::any::to_bytes(&value).unwrap();
+        let res: Wrapper = crate::any::from_bytes(&bytes).unwrap();
+        assert_eq!(res, value);
+    }
+}
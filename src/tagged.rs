@@ -0,0 +1,7 @@
+//! The tagged format, each value is preceded by a tag giving its type, so it supports `deserialize_any`.
+//!
+//! This is the format of the `any` module, under a name matching the `compact` module.
+//! The compact format can't be read with these functions, deserializing it fails with `Error::WrongFormat`
+//! when the input looks like it.
+
+pub use crate::any::*;
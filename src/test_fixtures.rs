@@ -0,0 +1,138 @@
+//! Fixtures shared by the tests of the compact and tagged formats.
+
+use serde::{Deserialize, Serialize};
+
+// An empty sequence or map, serialized with or without its length.
+pub(crate) struct EmptyCollection {
+    pub(crate) map: bool,
+    pub(crate) len: Option<usize>,
+}
+
+impl Serialize for EmptyCollection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        if self.map {
+            serializer.serialize_map(self.len)?.end()
+        } else {
+            serializer.serialize_seq(self.len)?.end()
+        }
+    }
+}
+
+pub(crate) const EMPTY_COLLECTIONS: [EmptyCollection; 4] = [
+    EmptyCollection {
+        map: false,
+        len: Some(0),
+    },
+    EmptyCollection {
+        map: false,
+        len: None,
+    },
+    EmptyCollection {
+        map: true,
+        len: Some(0),
+    },
+    EmptyCollection {
+        map: true,
+        len: None,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub(crate) struct Empty {}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub(crate) struct WithPhantom {
+    pub(crate) a: u8,
+    pub(crate) marker: core::marker::PhantomData<u32>,
+    pub(crate) b: Empty,
+}
+
+// The first element of a sequence and the remaining ones.
+#[derive(Debug, PartialEq)]
+pub(crate) struct HeadAndRest {
+    pub(crate) head: u32,
+    pub(crate) rest: Vec<u32>,
+}
+
+impl<'de> Deserialize<'de> for HeadAndRest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(HeadAndRestVisitor)
+    }
+}
+
+struct HeadAndRestVisitor;
+
+impl<'de> serde::de::Visitor<'de> for HeadAndRestVisitor {
+    type Value = HeadAndRest;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a non empty sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let head = seq
+            .next_element()?
+            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let rest = Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))?;
+        Ok(HeadAndRest { head, rest })
+    }
+}
+
+// A format the tests can run against, without knowing which one.
+pub(crate) trait TestFormat {
+    fn write<T: Serialize>(&self, value: &T) -> Vec<u8>;
+
+    fn read<'de, T: Deserialize<'de>>(&self, bytes: &'de [u8]) -> T;
+}
+
+pub(crate) struct Compact;
+
+impl TestFormat for Compact {
+    fn write<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        crate::compact::to_bytes(value).unwrap()
+    }
+
+    fn read<'de, T: Deserialize<'de>>(&self, bytes: &'de [u8]) -> T {
+        crate::compact::from_bytes(bytes).unwrap()
+    }
+}
+
+pub(crate) struct Tagged;
+
+impl TestFormat for Tagged {
+    fn write<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        crate::any::to_bytes(value).unwrap()
+    }
+
+    fn read<'de, T: Deserialize<'de>>(&self, bytes: &'de [u8]) -> T {
+        crate::any::from_bytes(bytes).unwrap()
+    }
+}
+
+// Checks that a borrowed value is written like its owned counterpart,
+// and that the owned one can be read back from those bytes.
+pub(crate) fn assert_borrow_own_symmetry<F, B, O>(format: F, borrowed: &B, owned: &O)
+where
+    F: TestFormat,
+    B: Serialize,
+    O: Serialize + serde::de::DeserializeOwned + PartialEq + core::fmt::Debug,
+{
+    let borrowed_bytes = format.write(borrowed);
+    let owned_bytes = format.write(owned);
+
+    assert_eq!(borrowed_bytes, owned_bytes);
+
+    let res: O = format.read(&borrowed_bytes);
+
+    assert_eq!(&res, owned);
+}
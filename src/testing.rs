@@ -0,0 +1,294 @@
+//! Writers to test the handling of writer errors, available with the `test-utils` feature.
+//!
+//! `FailingWriter` fails after a number of bytes like a full disk or a closed connection,
+//! and `CallLog` records the calls made to a writer to check how a value is written.
+
+use std::fmt::{self, Display};
+use std::io;
+
+use crate::{Write, WriterError};
+
+/// Error of a `FailingWriter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailingWriterError<E> {
+    /// The error injected once the byte limit is reached, with its kind.
+    Injected(io::ErrorKind),
+    /// Error of the wrapped writer.
+    Writer(E),
+}
+
+impl<E: Display> Display for FailingWriterError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailingWriterError::Injected(kind) => write!(f, "injected writer error: {}", kind),
+            FailingWriterError::Writer(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl<E: WriterError> WriterError for FailingWriterError<E> {
+    fn io_error_kind(&self) -> io::ErrorKind {
+        match self {
+            FailingWriterError::Injected(kind) => *kind,
+            FailingWriterError::Writer(err) => err.io_error_kind(),
+        }
+    }
+}
+
+/// Writer giving the first `limit` bytes to the wrapped writer, then failing.
+///
+/// The write crossing the limit gives the bytes before it to the wrapped writer before failing,
+/// so the wrapped writer holds exactly `limit` bytes. By default every following write fails too,
+/// with `fail_once` the writer recovers after the first failure and accepts all the following writes.
+#[derive(Debug)]
+pub struct FailingWriter<W> {
+    writer: W,
+    limit: Option<usize>,
+    written: usize,
+    kind: io::ErrorKind,
+    fail_once: bool,
+    failures: usize,
+}
+
+impl<W: Write> FailingWriter<W> {
+    /// Fail with `io::ErrorKind::WriteZero` after `limit` bytes.
+    pub fn new(writer: W, limit: usize) -> Self {
+        FailingWriter {
+            writer,
+            limit: Some(limit),
+            written: 0,
+            kind: io::ErrorKind::WriteZero,
+            fail_once: false,
+            failures: 0,
+        }
+    }
+
+    /// Kind of the injected error.
+    pub fn error_kind(mut self, kind: io::ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Accept all the writes after the first failure.
+    pub fn fail_once(mut self) -> Self {
+        self.fail_once = true;
+        self
+    }
+
+    /// Number of bytes given to the wrapped writer.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Number of injected errors.
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn fail(&mut self) -> FailingWriterError<W::Error> {
+        self.failures += 1;
+        if self.fail_once {
+            self.limit = None;
+        }
+        FailingWriterError::Injected(self.kind)
+    }
+}
+
+impl<W: Write> Write for &mut FailingWriter<W> {
+    type Error = FailingWriterError<W::Error>;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        let accepted = match self.limit {
+            Some(limit) => bytes.len().min(limit.saturating_sub(self.written)),
+            None => bytes.len(),
+        };
+        if accepted > 0 {
+            self.writer
+                .write_bytes(&bytes[..accepted])
+                .map_err(FailingWriterError::Writer)?;
+            self.written += accepted;
+        }
+        if accepted < bytes.len() {
+            return Err(self.fail());
+        }
+        Ok(accepted)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.writer.flush().map_err(FailingWriterError::Writer)
+    }
+}
+
+/// Call made to a `CallLog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteCall {
+    Bytes(Vec<u8>),
+    Byte(u8),
+    Flush,
+    Reserve(usize),
+}
+
+/// Writer recording every call before giving it to the wrapped writer.
+#[derive(Debug)]
+pub struct CallLog<W> {
+    writer: W,
+    calls: Vec<WriteCall>,
+}
+
+impl<W: Write> CallLog<W> {
+    pub fn new(writer: W) -> Self {
+        CallLog {
+            writer,
+            calls: Vec::new(),
+        }
+    }
+
+    pub fn calls(&self) -> &[WriteCall] {
+        &self.calls
+    }
+
+    /// The bytes given to the writer by each call, the calls that don't write are left out.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        self.calls.iter().filter_map(|call| match call {
+            WriteCall::Bytes(bytes) => Some(bytes.as_slice()),
+            WriteCall::Byte(byte) => Some(core::slice::from_ref(byte)),
+            WriteCall::Flush | WriteCall::Reserve(_) => None,
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Write for &mut CallLog<W> {
+    type Error = W::Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.calls.push(WriteCall::Bytes(bytes.to_vec()));
+        self.writer.write_bytes(bytes)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<usize, Self::Error> {
+        self.calls.push(WriteCall::Byte(byte));
+        self.writer.write_byte(byte)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.calls.push(WriteCall::Flush);
+        self.writer.flush()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.calls.push(WriteCall::Reserve(additional));
+        self.writer.reserve(additional);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{any, compact, Error};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        score: Option<f32>,
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 7,
+            name: "record".into(),
+            tags: vec!["a".into(), "bc".into()],
+            score: Some(0.5),
+        }
+    }
+
+    #[test]
+    fn test_failing_writer_mid_struct() {
+        let value = record();
+        let full = compact::to_bytes(&value).unwrap();
+        for limit in 0..full.len() {
+            let mut writer = FailingWriter::new(Vec::new(), limit);
+            let err = compact::to_writer(&value, &mut writer).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::WriterError(FailingWriterError::Injected(io::ErrorKind::WriteZero))
+            ));
+            assert_eq!(writer.written(), limit);
+            assert_eq!(writer.failures(), 1);
+            assert_eq!(writer.into_inner(), full[..limit]);
+        }
+        let mut writer = FailingWriter::new(Vec::new(), full.len());
+        assert_eq!(compact::to_writer(&value, &mut writer).unwrap(), full.len());
+        assert_eq!(writer.into_inner(), full);
+
+        let full = any::to_bytes(&value).unwrap();
+        for limit in 0..full.len() {
+            let mut writer = FailingWriter::new(Vec::new(), limit);
+            assert!(any::to_writer(&value, &mut writer).is_err());
+            assert_eq!(writer.into_inner(), full[..limit]);
+        }
+    }
+
+    #[test]
+    fn test_failing_writer_recovery() {
+        let value = record();
+        let full = compact::to_bytes(&value).unwrap();
+        let limit = full.len() / 2;
+        let mut writer = FailingWriter::new(Vec::new(), limit)
+            .error_kind(io::ErrorKind::Interrupted)
+            .fail_once();
+        let mut serializer = compact::Serializer::new(&mut writer);
+        let err = value.serialize(&mut serializer).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "injected writer error: operation interrupted"
+        );
+        // the serializer keeps no state from the failed value
+        assert_eq!(value.serialize(&mut serializer).unwrap(), full.len());
+        drop(serializer);
+        assert_eq!(writer.failures(), 1);
+        let written = writer.into_inner();
+        assert_eq!(written[..limit], full[..limit]);
+        assert_eq!(written[limit..], full);
+    }
+
+    #[test]
+    fn test_call_log() {
+        let value = record();
+        let mut log = CallLog::new(Vec::new());
+        let written = compact::to_writer_complete(&value, &mut log).unwrap();
+        assert_eq!(log.chunks().map(<[u8]>::len).sum::<usize>(), written);
+        assert_eq!(log.calls().last(), Some(&WriteCall::Flush));
+        assert_eq!(log.chunks().collect::<Vec<_>>().concat(), log.get_ref()[..]);
+
+        // the fixed size values are written in a single call
+        let mut log = CallLog::new(Vec::new());
+        compact::to_writer(&(1u64, 2u32), &mut log).unwrap();
+        assert_eq!(
+            log.calls(),
+            [
+                WriteCall::Bytes(1u64.to_be_bytes().to_vec()),
+                WriteCall::Bytes(2u32.to_be_bytes().to_vec())
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Explicit encodings for timestamps, to use with `#[serde(with = "serde_bin::timestamp::...")]`.
+//!
+//! - `seconds_u64`: whole seconds since the unix epoch, the sub-second part is truncated.
+//! - `millis_u64`: whole milliseconds since the unix epoch, the sub-millisecond part is truncated.
+//! - `nanos_i128`: nanoseconds since the unix epoch, lossless and supports dates before the epoch.
+//!
+//! The unsigned encodings can't represent dates before the epoch and fail to serialize them.
+//! Decoding a timestamp outside of the range of the target type fails instead of wrapping or clamping.
+//!
+//! `chrono::DateTime<Utc>` is supported with the `chrono` feature and `time::OffsetDateTime`
+//! with the `time` feature, the offset is not encoded so an `OffsetDateTime` is always decoded in UTC.
+
+use core::fmt::Display;
+
+use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Date time types handled by this module.
+pub trait Timestamp: Sized {
+    /// Number of nanoseconds since the unix epoch.
+    fn to_unix_nanos(&self) -> i128;
+
+    /// Return `None` if the timestamp is out of the range of the type.
+    fn from_unix_nanos(nanos: i128) -> Option<Self>;
+}
+
+#[cfg(feature = "chrono")]
+impl Timestamp for chrono::DateTime<chrono::Utc> {
+    fn to_unix_nanos(&self) -> i128 {
+        i128::from(self.timestamp()) * NANOS_PER_SEC + i128::from(self.timestamp_subsec_nanos())
+    }
+
+    fn from_unix_nanos(nanos: i128) -> Option<Self> {
+        let secs = nanos.div_euclid(NANOS_PER_SEC).try_into().ok()?;
+        let nsecs = nanos.rem_euclid(NANOS_PER_SEC) as u32;
+        chrono::DateTime::from_timestamp(secs, nsecs)
+    }
+}
+
+#[cfg(feature = "time")]
+impl Timestamp for time::OffsetDateTime {
+    fn to_unix_nanos(&self) -> i128 {
+        self.unix_timestamp_nanos()
+    }
+
+    fn from_unix_nanos(nanos: i128) -> Option<Self> {
+        time::OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()
+    }
+}
+
+struct Unit {
+    nanos: i128,
+    name: &'static str,
+}
+
+const SECONDS: Unit = Unit {
+    nanos: NANOS_PER_SEC,
+    name: "seconds",
+};
+
+const MILLIS: Unit = Unit {
+    nanos: 1_000_000,
+    name: "milliseconds",
+};
+
+const NANOS: Unit = Unit {
+    nanos: 1,
+    name: "nanoseconds",
+};
+
+fn to_units<T: Timestamp>(value: &T, unit: &Unit) -> i128 {
+    value.to_unix_nanos().div_euclid(unit.nanos)
+}
+
+fn to_unsigned_units<T: Timestamp, E: ser::Error>(value: &T, unit: &Unit) -> Result<u64, E> {
+    let units = to_units(value, unit);
+    units.try_into().map_err(|_| {
+        E::custom(format_args!(
+            "timestamp out of range: {} {} since the unix epoch can't be encoded as an u64",
+            units, unit.name
+        ))
+    })
+}
+
+fn from_units<T, V, E>(value: V, unit: &Unit) -> Result<T, E>
+where
+    T: Timestamp,
+    V: Into<i128> + Display + Copy,
+    E: de::Error,
+{
+    value
+        .into()
+        .checked_mul(unit.nanos)
+        .and_then(T::from_unix_nanos)
+        .ok_or_else(|| {
+            E::custom(format_args!(
+                "timestamp out of range: {} {} since the unix epoch",
+                value, unit.name
+            ))
+        })
+}
+
+pub mod seconds_u64 {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Timestamp,
+        S: Serializer,
+    {
+        serializer.serialize_u64(to_unsigned_units(value, &SECONDS)?)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Timestamp,
+        D: Deserializer<'de>,
+    {
+        from_units(u64::deserialize(deserializer)?, &SECONDS)
+    }
+}
+
+pub mod millis_u64 {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Timestamp,
+        S: Serializer,
+    {
+        serializer.serialize_u64(to_unsigned_units(value, &MILLIS)?)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Timestamp,
+        D: Deserializer<'de>,
+    {
+        from_units(u64::deserialize(deserializer)?, &MILLIS)
+    }
+}
+
+pub mod nanos_i128 {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Timestamp,
+        S: Serializer,
+    {
+        serializer.serialize_i128(to_units(value, &NANOS))
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Timestamp,
+        D: Deserializer<'de>,
+    {
+        from_units(i128::deserialize(deserializer)?, &NANOS)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::Timestamp;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use time::OffsetDateTime;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Event<T: Timestamp> {
+        #[serde(with = "crate::timestamp::seconds_u64")]
+        seconds: T,
+        #[serde(with = "crate::timestamp::millis_u64")]
+        millis: T,
+        #[serde(with = "crate::timestamp::nanos_i128")]
+        nanos: T,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Nanos<T: Timestamp>(#[serde(with = "crate::timestamp::nanos_i128")] T);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Seconds<T: Timestamp>(#[serde(with = "crate::timestamp::seconds_u64")] T);
+
+    fn event<T: Timestamp>(nanos: i128) -> Event<T> {
+        Event {
+            seconds: T::from_unix_nanos(nanos).unwrap(),
+            millis: T::from_unix_nanos(nanos).unwrap(),
+            nanos: T::from_unix_nanos(nanos).unwrap(),
+        }
+    }
+
+    fn assert_event_round_trip<T: Timestamp + PartialEq + core::fmt::Debug>() {
+        // epoch, 1ns, 1ms and 1s after it, and an arbitrary date with every unit set
+        for nanos in [0, 1, 1_000_000, 1_000_000_000, 1_700_000_123_456_789_012] {
+            let value = event::<T>(nanos);
+            let bytes = crate::compact::to_bytes(&value).unwrap();
+            assert_eq!(bytes.len(), 8 + 8 + 16);
+            let res: Event<T> = crate::compact::from_bytes(&bytes).unwrap();
+
+            assert_eq!(
+                res.seconds.to_unix_nanos(),
+                nanos / 1_000_000_000 * 1_000_000_000
+            );
+            assert_eq!(res.millis.to_unix_nanos(), nanos / 1_000_000 * 1_000_000);
+            assert_eq!(res.nanos, value.nanos);
+
+            let bytes = crate::any::to_bytes(&value).unwrap();
+            let res: Event<T> = crate::any::from_bytes(&bytes).unwrap();
+            assert_eq!(res.nanos, value.nanos);
+        }
+    }
+
+    fn assert_before_epoch<T: Timestamp + PartialEq + core::fmt::Debug>() {
+        // 1ns and 1.5s before the epoch
+        for nanos in [-1, -1_500_000_000] {
+            let value = Nanos(T::from_unix_nanos(nanos).unwrap());
+            let bytes = crate::compact::to_bytes(&value).unwrap();
+            assert_eq!(bytes, nanos.to_be_bytes());
+            let res: Nanos<T> = crate::compact::from_bytes(&bytes).unwrap();
+            assert_eq!(res, value);
+
+            let value = Seconds(T::from_unix_nanos(nanos).unwrap());
+            assert!(crate::compact::to_bytes(&value).is_err());
+        }
+    }
+
+    fn assert_out_of_range<T: Timestamp + core::fmt::Debug>() {
+        let bytes = crate::compact::to_bytes(&u64::MAX).unwrap();
+        let err = crate::compact::from_bytes::<Seconds<T>>(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "timestamp out of range: 18446744073709551615 seconds since the unix epoch"
+        );
+
+        let bytes = crate::compact::to_bytes(&i128::MIN).unwrap();
+        assert!(crate::compact::from_bytes::<Nanos<T>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_chrono_timestamps() {
+        assert_event_round_trip::<DateTime<Utc>>();
+        assert_before_epoch::<DateTime<Utc>>();
+        assert_out_of_range::<DateTime<Utc>>();
+
+        let value = Nanos(DateTime::<Utc>::MIN_UTC);
+        let bytes = crate::compact::to_bytes(&value).unwrap();
+        assert_eq!(
+            crate::compact::from_bytes::<Nanos<DateTime<Utc>>>(&bytes),
+            Ok(value)
+        );
+    }
+
+    #[test]
+    fn test_time_timestamps() {
+        assert_event_round_trip::<OffsetDateTime>();
+        assert_before_epoch::<OffsetDateTime>();
+        assert_out_of_range::<OffsetDateTime>();
+    }
+}
@@ -0,0 +1,99 @@
+use core::fmt::{self, Display};
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::collections::VecDeque;
+
+use crate::any::Tag;
+
+/// Step of a deserialization recorded by `DeserializerConfig::trace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TraceStep {
+    /// Read the tag of a value.
+    Tag(Tag),
+    /// Failed to read the tag of a value, with the byte found, `None` at the end of the input.
+    InvalidTag(Option<u8>),
+    /// Read the length of a sequence, map, string or byte array, or the number of elements of a tuple or struct.
+    Length(usize),
+    /// Started decoding the field of a struct with the given index.
+    Field(usize),
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceStep::Tag(tag) => write!(f, "tag {:?}", tag),
+            TraceStep::InvalidTag(Some(byte)) => write!(f, "invalid tag {}", byte),
+            TraceStep::InvalidTag(None) => f.write_str("missing tag"),
+            TraceStep::Length(len) => write!(f, "length {}", len),
+            TraceStep::Field(index) => write!(f, "field {}", index),
+        }
+    }
+}
+
+/// Step of a deserialization with the offset in the input where it started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceEvent {
+    pub offset: usize,
+    pub step: TraceStep,
+}
+
+/// Last steps of a deserialization before it failed, oldest first, attached to the error by `DeserializerConfig::trace`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    events: VecDeque<TraceEvent>,
+    capacity: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl Trace {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Trace {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record an event, dropping the oldest one when the trace is full.
+    pub(crate) fn push(&mut self, event: TraceEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &TraceEvent> + ExactSizeIterator {
+        self.events.iter()
+    }
+
+    /// Last step before the error.
+    pub fn last(&self) -> Option<&TraceEvent> {
+        self.events.back()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} at byte {}", event.step, event.offset)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+//! Adapter giving a deserializer over a temporary buffer to the visitors expecting the lifetime of the input,
+//! used for the payloads rewritten by the decoder of an `any::Extension`.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+
+/// Deserializer, visitor, seed or access of the lifetime `'a` used where `'de` is expected.
+///
+/// Nothing lives for `'de`, so the borrowed strings and bytes are given to the visitors as transient ones,
+/// which fails for the types borrowing from the input.
+pub(crate) struct Unborrowed<'a, 'de, T> {
+    inner: T,
+    lifetimes: PhantomData<(&'a (), &'de ())>,
+}
+
+impl<T> Unborrowed<'_, '_, T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Unborrowed {
+            inner,
+            lifetimes: PhantomData,
+        }
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($(#[$attr:meta])* $method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            $(#[$attr])*
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.inner.$method($($arg,)* Unborrowed::new(visitor))
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, D: Deserializer<'a>> Deserializer<'de> for Unborrowed<'a, 'de, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        #[cfg(not(no_integer128))]
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        #[cfg(not(no_integer128))]
+        deserialize_u128()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($(#[$attr:meta])* $method:ident($ty:ty))*) => {
+        $(
+            $(#[$attr])*
+            fn $method<E>(self, v: $ty) -> Result<V::Value, E>
+            where
+                E: de::Error,
+            {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, V: Visitor<'de>> Visitor<'a> for Unborrowed<'a, 'de, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool)
+        visit_i8(i8)
+        visit_i16(i16)
+        visit_i32(i32)
+        visit_i64(i64)
+        #[cfg(not(no_integer128))]
+        visit_i128(i128)
+        visit_u8(u8)
+        visit_u16(u16)
+        visit_u32(u32)
+        visit_u64(u64)
+        #[cfg(not(no_integer128))]
+        visit_u128(u128)
+        visit_f32(f32)
+        visit_f64(f64)
+        visit_char(char)
+        visit_str(&str)
+        visit_string(String)
+        visit_bytes(&[u8])
+        visit_byte_buf(Vec<u8>)
+    }
+
+    fn visit_none<E>(self) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<V::Value, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        self.inner.visit_some(Unborrowed::new(deserializer))
+    }
+
+    fn visit_unit<E>(self) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_unit()
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<V::Value, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        self.inner
+            .visit_newtype_struct(Unborrowed::new(deserializer))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<V::Value, A::Error>
+    where
+        A: SeqAccess<'a>,
+    {
+        self.inner.visit_seq(Unborrowed::new(seq))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<V::Value, A::Error>
+    where
+        A: MapAccess<'a>,
+    {
+        self.inner.visit_map(Unborrowed::new(map))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<V::Value, A::Error>
+    where
+        A: EnumAccess<'a>,
+    {
+        self.inner.visit_enum(Unborrowed::new(data))
+    }
+}
+
+impl<'a, 'de, T: DeserializeSeed<'de>> DeserializeSeed<'a> for Unborrowed<'a, 'de, T> {
+    type Value = T::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T::Value, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        self.inner.deserialize(Unborrowed::new(deserializer))
+    }
+}
+
+impl<'a, 'de, A: SeqAccess<'a>> SeqAccess<'de> for Unborrowed<'a, 'de, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.inner.next_element_seed(Unborrowed::new(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A: MapAccess<'a>> MapAccess<'de> for Unborrowed<'a, 'de, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, A::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.inner.next_key_seed(Unborrowed::new(seed))
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.inner.next_value_seed(Unborrowed::new(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A: EnumAccess<'a>> EnumAccess<'de> for Unborrowed<'a, 'de, A> {
+    type Error = A::Error;
+    type Variant = Unborrowed<'a, 'de, A::Variant>;
+
+    fn variant_seed<T>(self, seed: T) -> Result<(T::Value, Self::Variant), A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let (value, variant) = self.inner.variant_seed(Unborrowed::new(seed))?;
+        Ok((value, Unborrowed::new(variant)))
+    }
+}
+
+impl<'a, 'de, A: VariantAccess<'a>> VariantAccess<'de> for Unborrowed<'a, 'de, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.inner.newtype_variant_seed(Unborrowed::new(seed))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.inner.tuple_variant(len, Unborrowed::new(visitor))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.inner.struct_variant(fields, Unborrowed::new(visitor))
+    }
+}
@@ -19,10 +19,24 @@ pub trait Write {
     fn write_byte(&mut self, byte: u8) -> Result<usize, Self::Error> {
         self.write_bytes(core::slice::from_ref(&byte))
     }
+
+    /// Make sure the bytes written so far reached their destination, does nothing by default.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Prepare for `additional` more bytes, such as the size given by `get_serialized_size`,
+    /// so a growable writer allocates once. Does nothing by default.
+    ///
+    /// With the `std` feature, the writers implementing `io::Write` go through a blanket implementation that can't reserve,
+    /// a `&mut Vec<u8>` must then be wrapped in a `VecWriter` to reserve.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
 }
 
 #[cfg(all(feature = "alloc", not(feature = "std")))]
-impl<'a> Write for &'a mut Vec<u8> {
+impl Write for &mut Vec<u8> {
     type Error = NoWriterError;
 
     fn write_byte(&mut self, byte: u8) -> Result<usize, Self::Error> {
@@ -34,6 +48,10 @@ impl<'a> Write for &'a mut Vec<u8> {
         self.extend_from_slice(bytes);
         Ok(bytes.len())
     }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
 }
 
 #[cfg(feature = "std")]
@@ -44,6 +62,53 @@ impl<W: io::Write> Write for W {
         self.write_all(bytes)?;
         Ok(bytes.len())
     }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        io::Write::flush(self)
+    }
+}
+
+/// Expose a writer of this crate as an `io::Write`, to give it to code writing into streams such as compressors.
+///
+/// The errors of the writer become `io::Error`s of the kind given by `WriterError::io_error_kind`,
+/// `EndOfBuff` becomes `io::ErrorKind::WriteZero`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IoAdapter<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: Write> IoAdapter<W> {
+    pub fn new(writer: W) -> Self {
+        IoAdapter(writer)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+#[cfg(feature = "std")]
+fn to_io_error<E: WriterError>(err: E) -> io::Error {
+    io::Error::new(err.io_error_kind(), err.to_string())
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> io::Write for IoAdapter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_bytes(buf).map_err(to_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(to_io_error)
+    }
 }
 
 pub struct BuffWriter<'a> {
@@ -94,7 +159,12 @@ impl<'a> DerefMut for BuffWriter<'a> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EndOfBuff;
 
-impl WriterError for EndOfBuff {}
+impl WriterError for EndOfBuff {
+    #[cfg(feature = "std")]
+    fn io_error_kind(&self) -> io::ErrorKind {
+        io::ErrorKind::WriteZero
+    }
+}
 
 impl Display for EndOfBuff {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -111,6 +181,212 @@ impl<'a, 'b> Write for &'a mut BuffWriter<'b> {
             .get_mut(self.head..self.head + bytes.len())
             .ok_or(EndOfBuff)?;
         spot.copy_from_slice(bytes);
+        self.head += bytes.len();
+        Ok(bytes.len())
+    }
+}
+
+/// Writer appending to a `Vec` without going through `io::Write`, so it can't fail and it can reserve
+/// with or without the `std` feature.
+///
+/// ```
+/// use serde_bin::{compact, VecWriter, Write};
+///
+/// let value = vec![1u32, 2, 3];
+/// let mut output = Vec::new();
+/// let mut writer = VecWriter::new(&mut output);
+/// writer.reserve(compact::get_serialized_size(&value).unwrap());
+/// compact::to_writer(&value, writer).unwrap();
+/// assert_eq!(output.capacity(), output.len());
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct VecWriter<'a>(pub(crate) &'a mut Vec<u8>);
+
+#[cfg(feature = "alloc")]
+impl<'a> VecWriter<'a> {
+    pub fn new(vec: &'a mut Vec<u8>) -> Self {
+        VecWriter(vec)
+    }
+
+    pub fn get_ref(&self) -> &Vec<u8> {
+        self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Write for VecWriter<'a> {
+    type Error = NoWriterError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.0.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.0.reserve(additional);
+    }
+}
+
+/// Writer storing the first `N` bytes in an inline array, and moving them to a `Vec` once they don't fit,
+/// so the messages of at most `N` bytes are written without allocating.
+///
+/// ```
+/// let small = serde_bin::compact::to_hybrid::<8, _>(&1u32).unwrap();
+/// assert!(!small.is_spilled());
+/// assert_eq!(small.as_slice(), [0, 0, 0, 1]);
+/// let large = serde_bin::compact::to_hybrid::<8, _>(&[1u32, 2, 3]).unwrap();
+/// assert!(large.is_spilled());
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct HybridWriter<const N: usize> {
+    storage: HybridStorage<N>,
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+enum HybridStorage<const N: usize> {
+    Inline { buff: [u8; N], len: usize },
+    Heap(Vec<u8>),
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> HybridWriter<N> {
+    pub const fn new() -> Self {
+        HybridWriter {
+            storage: HybridStorage::Inline {
+                buff: [0; N],
+                len: 0,
+            },
+        }
+    }
+
+    /// Bytes written so far, from the storage in use.
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.storage {
+            HybridStorage::Inline { buff, len } => &buff[..*len],
+            HybridStorage::Heap(vec) => vec,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return `true` if more than `N` bytes were written, so they were moved to the heap.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, HybridStorage::Heap(_))
+    }
+
+    /// Return the bytes written in a `Vec`, allocating it if they were still inline.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.storage {
+            HybridStorage::Inline { buff, len } => buff[..len].to_vec(),
+            HybridStorage::Heap(vec) => vec,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> Default for HybridWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> Deref for HybridWriter<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> Write for &mut HybridWriter<N> {
+    type Error = NoWriterError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        match &mut self.storage {
+            HybridStorage::Inline { buff, len } if *len + bytes.len() <= N => {
+                buff[*len..*len + bytes.len()].copy_from_slice(bytes);
+                *len += bytes.len();
+            }
+            HybridStorage::Inline { buff, len } => {
+                let mut vec = Vec::with_capacity((*len + bytes.len()).max(2 * N));
+                vec.extend_from_slice(&buff[..*len]);
+                vec.extend_from_slice(bytes);
+                self.storage = HybridStorage::Heap(vec);
+            }
+            HybridStorage::Heap(vec) => vec.extend_from_slice(bytes),
+        }
+        Ok(bytes.len())
+    }
+}
+
+/// Writer appending to any container implementing `Extend<u8>`, such as a ring buffer or an arena vector,
+/// available without `alloc`.
+///
+/// ```
+/// use std::collections::VecDeque;
+/// use serde_bin::{compact, ExtendWriter};
+///
+/// let mut writer = ExtendWriter(VecDeque::new());
+/// compact::to_writer(&(1u8, 2u8), &mut writer).unwrap();
+/// assert_eq!(writer.into_inner(), [1, 2]);
+/// ```
+///
+/// The bytes are given to `Extend::extend`, so the container can't refuse them,
+/// a container with a limited capacity can implement `BoundedExtend` and use `CheckedExtendWriter`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ExtendWriter<T>(pub T);
+
+impl<T: Extend<u8>> ExtendWriter<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Extend<u8>> Write for &mut ExtendWriter<T> {
+    type Error = NoWriterError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.0.extend(bytes.iter().copied());
+        Ok(bytes.len())
+    }
+}
+
+/// Container of bytes with a limited capacity, written by `CheckedExtendWriter`.
+pub trait BoundedExtend: Extend<u8> {
+    /// Number of bytes that can still be added.
+    fn remaining_capacity(&self) -> usize;
+}
+
+/// Writer appending to a `BoundedExtend` container, failing with `EndOfBuff` when the bytes don't fit
+/// instead of giving them to the container.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CheckedExtendWriter<T>(pub T);
+
+impl<T: BoundedExtend> CheckedExtendWriter<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: BoundedExtend> Write for &mut CheckedExtendWriter<T> {
+    type Error = EndOfBuff;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        if bytes.len() > self.0.remaining_capacity() {
+            return Err(EndOfBuff);
+        }
+        self.0.extend(bytes.iter().copied());
         Ok(bytes.len())
     }
 }
@@ -124,3 +400,82 @@ impl Write for DummyWriter {
         Ok(bytes.len())
     }
 }
+
+// CRC-32/ISO-HDLC lookup table, the checksum used by zlib, gzip and png.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Running CRC-32 of a stream of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) const fn new() -> Self {
+        Crc32(u32::MAX)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC32_TABLE[usize::from(self.0 as u8 ^ byte)] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Writer that doesn't store the bytes but counts them and computes their CRC-32,
+/// to get both the length prefix and the checksum trailer of a message before writing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SizeAndCrcWriter {
+    len: usize,
+    crc: Crc32,
+}
+
+impl Default for SizeAndCrcWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SizeAndCrcWriter {
+    pub const fn new() -> Self {
+        SizeAndCrcWriter {
+            len: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Return the number of bytes written and their CRC-32.
+    pub fn finish(self) -> (usize, u32) {
+        (self.len, self.crc.finish())
+    }
+}
+
+impl Write for &mut SizeAndCrcWriter {
+    type Error = NoWriterError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.crc.update(bytes);
+        self.len += bytes.len();
+        Ok(bytes.len())
+    }
+}